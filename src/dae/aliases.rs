@@ -0,0 +1,131 @@
+//! This module eliminates alias variables of a `Dae` and the redundant
+//! equations this reveals.
+//!
+//! Expanded connections give many equations of the form `a = b`, `a = -b` or
+//! `a = 0`. `Dae::eliminate_aliases` removes such an equation of `fx` for an
+//! algebraic variable, substitutes its other side for the variable in the
+//! remaining equations and drops the variable from `y`, until no alias
+//! equation is left. Outputs of the model are kept. It returns the
+//! substituted expression of each eliminated variable.
+//!
+//! Elimination can turn equations into tautologies, a node grounded twice
+//! gives `0 = 0`. `Dae::redundant_equations` returns the indices of these
+//! equations in `fx`, and `Dae::remove_redundant_equations` removes them. The
+//! `Redundancy` it returns carries a warning when the removal leaves a
+//! balanced system with fewer equations than unknowns.
+use crate::dae::ast::Dae;
+use crate::ir::ast::{Causality, Equation, Expression, OpUnary};
+use crate::ir::visitor::Visitable;
+use crate::ir::visitors::index_substituter::IndexSubstituter;
+use crate::ir::visitors::tautology_remover::is_tautology;
+use anyhow::Result;
+use indexmap::IndexMap;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Redundancy {
+    /// removed equations, in the order of `fx`
+    pub removed: Vec<Equation>,
+    pub warning: Option<String>,
+}
+
+impl Dae {
+    pub fn eliminate_aliases(&mut self) -> IndexMap<String, Expression> {
+        let mut aliases = IndexMap::new();
+        while let Some((index, name, value)) = self
+            .fx
+            .iter()
+            .enumerate()
+            .find_map(|(i, eq)| self.alias(eq).map(|(name, value)| (i, name, value)))
+        {
+            self.fx.remove(index);
+            // the names of the DAE are unqualified, so the substituter of
+            // loop indices replaces the variable
+            let mut substituter = IndexSubstituter {
+                index: name.clone(),
+                value: value.clone(),
+            };
+            for eq in self
+                .fx
+                .iter_mut()
+                .chain(&mut self.fz)
+                .chain(&mut self.fm)
+                .chain(&mut self.fi)
+            {
+                eq.accept(&mut substituter);
+            }
+            for stmt in self.fr.values_mut() {
+                stmt.accept(&mut substituter);
+            }
+            for expr in self.fc.values_mut().chain(aliases.values_mut()) {
+                expr.accept(&mut substituter);
+            }
+            aliases.insert(name, value);
+        }
+        self.y.retain(|comp| !aliases.contains_key(&comp.name));
+        self.index_outputs();
+        aliases
+    }
+
+    /// Returns the eliminated variable and its value when `eq` is an alias
+    /// equation.
+    fn alias(&self, eq: &Equation) -> Option<(String, Expression)> {
+        let Equation::Simple { lhs, rhs, .. } = eq else {
+            return None;
+        };
+        [(lhs, rhs), (rhs, lhs)]
+            .into_iter()
+            .find_map(|(var, value)| {
+                let Expression::ComponentReference(cref) = var else {
+                    return None;
+                };
+                let name = cref.to_string();
+                let eliminable = self.y.iter().any(|comp| {
+                    comp.name == name
+                        && !(matches!(comp.causality, Causality::Output(..))
+                            && comp.qualified_name.is_none())
+                });
+                (eliminable && is_alias_value(value, &name)).then(|| (name, value.clone()))
+            })
+    }
+
+    pub fn redundant_equations(&self) -> Vec<usize> {
+        (0..self.fx.len())
+            .filter(|&i| is_tautology(&self.fx[i]))
+            .collect()
+    }
+
+    pub fn remove_redundant_equations(&mut self) -> Result<Redundancy> {
+        let unknowns = self.x_dot.len() + self.y.len();
+        let balanced = self.to_residual_form()?.len() == unknowns;
+        let redundant = self.redundant_equations();
+        let mut removed = Vec::new();
+        for index in redundant.into_iter().rev() {
+            removed.insert(0, self.fx.remove(index));
+        }
+        self.index_outputs();
+        let equations = self.to_residual_form()?.len();
+        let warning = (balanced && !removed.is_empty() && equations != unknowns).then(|| {
+            format!(
+                "removing {} redundant equation(s) leaves {} equation(s) for {} unknown(s)",
+                removed.len(),
+                equations,
+                unknowns
+            )
+        });
+        Ok(Redundancy { removed, warning })
+    }
+}
+
+/// Tells whether `value` may replace the variable `name`, that is if it is
+/// another variable or a literal, possibly negated.
+fn is_alias_value(value: &Expression, name: &str) -> bool {
+    match value {
+        Expression::ComponentReference(cref) => cref.to_string() != name,
+        Expression::Terminal { .. } => true,
+        Expression::Unary {
+            op: OpUnary::Minus(..),
+            rhs,
+        } => is_alias_value(rhs, name),
+        _ => false,
+    }
+}
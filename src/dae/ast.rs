@@ -43,6 +43,10 @@
 //! reinit:
 //!
 //! v = fr (v, c)    : happens at event time
+//!
+//! initialization:
+//!
+//! 0 = fi (v)       : holds at the initial time, along with fx
 
 use indexmap::IndexMap;
 
@@ -69,4 +73,35 @@ pub struct Dae {
     pub fm: Vec<Equation>,                // discrete update equations
     pub fr: IndexMap<String, Statement>,  // reset expressions, condition -> assignment statements
     pub fc: IndexMap<String, Expression>, // condition updates, condition -> expression
+    pub fo: IndexMap<String, usize>,      // output equations, output variable -> index in fx
+    pub fi: Vec<Equation>,                // initial equations (ADDED)
+    // enumeration type -> literal -> ordinal
+    pub enumerations: IndexMap<String, IndexMap<String, i64>>,
+}
+
+impl Dae {
+    /// Returns the components of the DAE by name, including time.
+    pub fn components(&self) -> IndexMap<String, Component> {
+        let mut components = IndexMap::new();
+        components.insert(self.t.name.clone(), self.t.clone());
+        for list in [
+            &self.p,
+            &self.cp,
+            &self.x,
+            &self.x_dot,
+            &self.y,
+            &self.u,
+            &self.pre_z,
+            &self.pre_x,
+            &self.pre_m,
+            &self.z,
+            &self.m,
+            &self.c,
+        ] {
+            for comp in list {
+                components.insert(comp.name.clone(), comp.clone());
+            }
+        }
+        components
+    }
 }
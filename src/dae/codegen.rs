@@ -0,0 +1,576 @@
+//! This module provides code generators that emit source code for a `Dae`
+//! directly from Rust, as an alternative to rendering a user supplied
+//! template with `jinja::render_template`.
+//!
+//! # Generators
+//! - `to_mtk`: Emits a Julia script declaring the model as a ModelingToolkit
+//!   `ODESystem`, ready for `structural_simplify` and simulation with
+//!   DifferentialEquations.jl. Derivative references (`der_x`, as produced by
+//!   the `StateFinder`) are mapped to `D(x)` and `time` to the independent
+//!   variable `t`. The Integer functions `div`, `mod` and `rem` are emitted
+//!   unchanged, as the Julia functions of the same name share their Modelica
+//!   semantics, and String `+` is emitted as the Julia concatenation `*`.
+//!   Calls of `homotopy` are emitted as their actual argument. When the model
+//!   uses `homotopy`, the initialization residuals are also emitted for both
+//!   stages of the homotopy, as `init_eqs_simplified` at `lambda = 0` and
+//!   `init_eqs_actual` at `lambda = 1`.
+//!
+//!   The `when` updates of `fz` and `fm` are emitted as discrete events,
+//!   checked after each step, with one callback per branch. An `elsewhen`
+//!   branch only fires when none of the preceding conditions hold. The
+//!   assigned values refer to the variables before the event, so `pre(v)`
+//!   is emitted as `v`. Equations that cannot be expressed in the system,
+//!   such as a remaining `connect`, are reported as errors.
+//!
+//!   ODE systems have no integer states, so `Integer` states are rejected,
+//!   unless `MtkOptions::relax_integer_states` is set, which exports them
+//!   as continuous variables.
+//! - `to_c`: Emits a C function `residual` evaluating the residuals
+//!   `lhs - rhs` of the equations of `fx`, for implicit solvers such as IDA.
+//!   The variables are passed as arrays in the order of the `Dae` fields,
+//!   `x`, `x_dot`, `y`, `u`, the discrete variables `z` and `m`, and the
+//!   parameters `cp` and `p`. The conditions of `fc` are computed from the
+//!   variables. `if` expressions become conditional expressions, and `and`
+//!   and `or` the C operators `&&` and `||`, so only the taken branch is
+//!   evaluated and a guarded `1/a` is never computed when the guard is
+//!   false. Variables named like an argument of `residual`, a C keyword or a
+//!   `math.h` function get a trailing underscore, so that `r` is emitted as
+//!   `r_`. Numbers are emitted as `double` literals, Modelica division being
+//!   real division. The Integer functions `div`, `rem` and `mod` become
+//!   `trunc(a / b)`, `fmod(a, b)` and `a - floor(a / b) * b`.
+use crate::dae::ast::Dae;
+use crate::ir::ast::{Component, Equation, Expression, OpBinary, OpUnary, TerminalType};
+use crate::ir::types::{Type, type_of};
+use anyhow::{Result, anyhow};
+use indexmap::{IndexMap, IndexSet};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MtkOptions {
+    /// Export `Integer` states as continuous variables, instead of rejecting
+    /// them.
+    pub relax_integer_states: bool,
+}
+
+pub fn to_mtk(dae: &Dae) -> Result<String> {
+    to_mtk_with_options(dae, &MtkOptions::default())
+}
+
+pub fn to_mtk_with_options(dae: &Dae, options: &MtkOptions) -> Result<String> {
+    let integer_states: Vec<&Component> = dae
+        .x
+        .iter()
+        .filter(|comp| Type::from_name(&comp.type_name.to_string()) == Type::Integer)
+        .collect();
+    if let Some(comp) = integer_states.first()
+        && !options.relax_integer_states
+    {
+        return Err(anyhow!(
+            "Integer variable '{}' is a state, which an ODE system cannot represent; \
+             declare it as Real, or set relax_integer_states to export it as a continuous variable",
+            comp.name
+        ));
+    }
+
+    let ctx = MtkContext {
+        states: dae.x.iter().map(|c| c.name.clone()).collect(),
+        components: dae.components(),
+        pre: IndexSet::new(),
+    };
+    let mut s = String::new();
+    s += "using ModelingToolkit, DifferentialEquations\n\n";
+    for comp in integer_states {
+        s += &format!(
+            "# Integer state {} is relaxed to a continuous variable\n",
+            comp.name
+        );
+    }
+    s += "@independent_variables t\n";
+    s += "D = Differential(t)\n\n";
+
+    // parameters
+    let params: Vec<&Component> = dae.cp.iter().chain(dae.p.iter()).collect();
+    if !params.is_empty() {
+        s += "@parameters begin\n";
+        for comp in params {
+            s += &format!("    {}\n", mtk_declaration(comp, false, &ctx));
+        }
+        s += "end\n\n";
+    }
+
+    // variables
+    let vars: Vec<&Component> = dae
+        .x
+        .iter()
+        .chain(dae.u.iter())
+        .chain(dae.y.iter())
+        .chain(dae.z.iter())
+        .chain(dae.m.iter())
+        .collect();
+    if !vars.is_empty() {
+        s += "@variables begin\n";
+        for comp in vars {
+            s += &format!("    {}\n", mtk_declaration(comp, true, &ctx));
+        }
+        s += "end\n\n";
+    }
+
+    // equations
+    s += "eqs = [\n";
+    for eq in &dae.fx {
+        s += &format!("    {},\n", mtk_equation(eq, &ctx)?);
+    }
+    s += "]\n\n";
+
+    // two stage initialization
+    if let Some(homotopy) = dae.homotopy_residuals()? {
+        for (name, residuals) in [
+            ("init_eqs_simplified", &homotopy.simplified),
+            ("init_eqs_actual", &homotopy.actual),
+        ] {
+            s += &format!("{} = [\n", name);
+            for residual in residuals {
+                s += &format!("    0 ~ {},\n", mtk_expression(residual, &ctx));
+            }
+            s += "]\n\n";
+        }
+    }
+
+    // discrete updates, the values before the event are the current values
+    let event_ctx = MtkContext {
+        pre: dae
+            .pre_x
+            .iter()
+            .chain(&dae.pre_z)
+            .chain(&dae.pre_m)
+            .map(|c| c.name.clone())
+            .collect(),
+        ..ctx
+    };
+    let events = mtk_events(dae, &event_ctx)?;
+    if !events.is_empty() {
+        s += "events = [\n";
+        for event in &events {
+            s += &format!("    {},\n", event);
+        }
+        s += "]\n\n";
+    }
+    if events.is_empty() {
+        s += "@named sys = ODESystem(eqs, t)\n";
+    } else {
+        s += "@named sys = ODESystem(eqs, t; discrete_events = events)\n";
+    }
+    s += "sys = structural_simplify(sys)\n";
+    Ok(s)
+}
+
+/// Names used to translate expressions.
+struct MtkContext {
+    states: IndexSet<String>,
+    components: IndexMap<String, Component>,
+    /// `pre` variables emitted as the variable itself
+    pre: IndexSet<String>,
+}
+
+fn mtk_declaration(comp: &Component, time_varying: bool, ctx: &MtkContext) -> String {
+    let mut decl = comp.name.clone();
+    if time_varying {
+        decl += "(t)";
+    }
+    if comp.start != Expression::Empty {
+        decl += &format!(" = {}", mtk_expression(&comp.start, ctx));
+    }
+    if !comp.description.is_empty() {
+        let desc: Vec<String> = comp.description.iter().map(|t| t.text.clone()).collect();
+        decl += &format!(", [description = {:?}]", desc.join(""));
+    }
+    decl
+}
+
+fn mtk_equation(eq: &Equation, ctx: &MtkContext) -> Result<String> {
+    match eq {
+        Equation::Simple { lhs, rhs, .. } => Ok(format!(
+            "{} ~ {}",
+            mtk_expression(lhs, ctx),
+            mtk_expression(rhs, ctx)
+        )),
+        _ => Err(anyhow!(
+            "equation {:?} is not supported by the ModelingToolkit export",
+            eq
+        )),
+    }
+}
+
+/// Returns the discrete events of the `when` updates of `fz` and `fm`, as
+/// `condition => [affects]`.
+fn mtk_events(dae: &Dae, ctx: &MtkContext) -> Result<Vec<String>> {
+    let mut events = Vec::new();
+    for update in dae.fz.iter().chain(&dae.fm) {
+        let Equation::When(blocks) = update else {
+            return Err(anyhow!(
+                "discrete update {:?} is not supported by the ModelingToolkit export",
+                update
+            ));
+        };
+        let mut preceding: Vec<String> = Vec::new();
+        for block in blocks {
+            let cond = match &block.cond {
+                Expression::ComponentReference(cref) => match dae.fc.get(&cref.to_string()) {
+                    Some(cond) => mtk_expression(cond, ctx),
+                    None => mtk_expression(&block.cond, ctx),
+                },
+                cond => mtk_expression(cond, ctx),
+            };
+            let mut affects = Vec::new();
+            for eq in &block.eqs {
+                affects.push(match eq {
+                    Equation::FunctionCall { comp, args }
+                        if comp.to_string() == "reinit" && args.len() == 2 =>
+                    {
+                        format!(
+                            "{} ~ {}",
+                            mtk_expression(&args[0], ctx),
+                            mtk_expression(&args[1], ctx)
+                        )
+                    }
+                    _ => mtk_equation(eq, ctx)?,
+                });
+            }
+            let guard = preceding
+                .iter()
+                .map(|cond| format!(" & !{}", cond))
+                .collect::<String>();
+            events.push(format!("({}{}) => [{}]", cond, guard, affects.join(", ")));
+            preceding.push(cond);
+        }
+    }
+    Ok(events)
+}
+
+fn mtk_expression(expr: &Expression, ctx: &MtkContext) -> String {
+    match expr {
+        Expression::Empty => String::new(),
+        Expression::Terminal {
+            terminal_type,
+            token,
+        } => match terminal_type {
+            TerminalType::String => format!("{:?}", token.text),
+            _ => token.text.clone(),
+        },
+        Expression::ComponentReference(cref) => {
+            let name = cref.to_string();
+            match name.strip_prefix("der_") {
+                Some(state) if ctx.states.contains(state) => format!("D({})", state),
+                _ if ctx.pre.contains(&name) => name["pre_".len()..].to_string(),
+                _ if name == "time" => "t".to_string(),
+                _ => name,
+            }
+        }
+        Expression::Unary { op, rhs } => {
+            let op = match op {
+                OpUnary::Minus(..) | OpUnary::DotMinus(..) => "-",
+                OpUnary::Plus(..) | OpUnary::DotPlus(..) => "+",
+                OpUnary::Not(..) => "!",
+                OpUnary::Empty => "",
+            };
+            format!("{}({})", op, mtk_expression(rhs, ctx))
+        }
+        Expression::Binary { op, lhs, rhs } => {
+            let op = match op {
+                // strings are concatenated with * in Julia
+                OpBinary::Add(..) | OpBinary::AddElem(..)
+                    if type_of(lhs, &ctx.components) == Type::String
+                        || type_of(rhs, &ctx.components) == Type::String =>
+                {
+                    "*"
+                }
+                OpBinary::Add(..) => "+",
+                OpBinary::Sub(..) => "-",
+                OpBinary::Mul(..) => "*",
+                OpBinary::Div(..) => "/",
+                OpBinary::Exp(..) => "^",
+                OpBinary::AddElem(..) => ".+",
+                OpBinary::SubElem(..) => ".-",
+                OpBinary::MulElem(..) => ".*",
+                OpBinary::DivElem(..) => "./",
+                OpBinary::Eq(..) => "==",
+                OpBinary::Neq(..) => "!=",
+                OpBinary::Lt(..) => "<",
+                OpBinary::Le(..) => "<=",
+                OpBinary::Gt(..) => ">",
+                OpBinary::Ge(..) => ">=",
+                OpBinary::And(..) => "&",
+                OpBinary::Or(..) => "|",
+                OpBinary::Empty => "",
+            };
+            format!(
+                "({} {} {})",
+                mtk_expression(lhs, ctx),
+                op,
+                mtk_expression(rhs, ctx)
+            )
+        }
+        Expression::FunctionCall { comp, args }
+            if comp.to_string() == "homotopy" && args.len() == 2 =>
+        {
+            mtk_expression(&args[0], ctx)
+        }
+        Expression::FunctionCall { comp, args } => {
+            let args: Vec<String> = args.iter().map(|a| mtk_expression(a, ctx)).collect();
+            format!("{}({})", comp, args.join(", "))
+        }
+        Expression::Array { elements } => {
+            let elements: Vec<String> = elements.iter().map(|e| mtk_expression(e, ctx)).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        Expression::ArrayComprehension { expr, indices } => {
+            let indices: Vec<String> = indices
+                .iter()
+                .map(|index| {
+                    format!(
+                        "{} in {}",
+                        index.ident.text,
+                        mtk_expression(&index.range, ctx)
+                    )
+                })
+                .collect();
+            format!("[{} for {}]", mtk_expression(expr, ctx), indices.join(", "))
+        }
+        Expression::If {
+            branches,
+            else_branch,
+        } => {
+            // elseif branches become nested ifelse calls
+            let mut s = mtk_expression(else_branch, ctx);
+            for (cond, then) in branches.iter().rev() {
+                s = format!(
+                    "ifelse({}, {}, {})",
+                    mtk_expression(cond, ctx),
+                    mtk_expression(then, ctx),
+                    s
+                );
+            }
+            s
+        }
+        Expression::Range { start, step, end } => match step {
+            Some(step) => format!(
+                "{}:{}:{}",
+                mtk_expression(start, ctx),
+                mtk_expression(step, ctx),
+                mtk_expression(end, ctx)
+            ),
+            None => format!(
+                "{}:{}",
+                mtk_expression(start, ctx),
+                mtk_expression(end, ctx)
+            ),
+        },
+    }
+}
+
+pub fn to_c(dae: &Dae) -> Result<String> {
+    let components = dae.components();
+    let mut s = String::new();
+    s += &format!("/* residuals of the DAE, {} equations */\n", dae.fx.len());
+    s += "#include <math.h>\n\n";
+    s += "void residual(double t, const double *x, const double *x_dot, const double *y,\n";
+    s += "              const double *u, const double *d, const double *p, double *r)\n";
+    s += "{\n";
+    s += "    (void)t;\n";
+    for (array, comps) in [
+        ("x", dae.x.iter().collect::<Vec<_>>()),
+        ("x_dot", dae.x_dot.iter().collect()),
+        ("y", dae.y.iter().collect()),
+        ("u", dae.u.iter().collect()),
+        ("d", dae.z.iter().chain(&dae.m).collect()),
+        ("p", dae.cp.iter().chain(&dae.p).collect()),
+    ] {
+        for (i, comp) in comps.iter().enumerate() {
+            s += &format!(
+                "    const double {} = {}[{}];\n",
+                c_name(&comp.name),
+                array,
+                i
+            );
+        }
+    }
+    for comp in &dae.c {
+        if let Some(cond) = dae.fc.get(&comp.name) {
+            s += &format!(
+                "    const int {} = {};\n",
+                c_name(&comp.name),
+                c_expression(cond, &components)?
+            );
+        }
+    }
+    for (i, eq) in dae.fx.iter().enumerate() {
+        let Equation::Simple { lhs, rhs, .. } = eq else {
+            return Err(anyhow!(
+                "equation {:?} is not supported by the C export",
+                eq
+            ));
+        };
+        s += &format!(
+            "    r[{}] = {} - ({});\n",
+            i,
+            c_expression(lhs, &components)?,
+            c_expression(rhs, &components)?
+        );
+    }
+    s += "}\n";
+    Ok(s)
+}
+
+/// Names of the `residual` function, its arguments and the C keywords and
+/// `math.h` functions it may use, which variables must not shadow.
+const C_RESERVED: &[&str] = &[
+    "residual", "t", "x", "x_dot", "y", "u", "d", "p", "r", "double", "int", "const", "void", "if",
+    "else", "for", "while", "do", "return", "break", "continue", "switch", "case", "default",
+    "char", "float", "long", "short", "signed", "unsigned", "static", "extern", "struct", "union",
+    "enum", "typedef", "sizeof", "goto", "volatile", "register", "auto", "inline", "restrict",
+    "fabs", "fmin", "fmax", "fmod", "floor", "ceil", "trunc", "pow", "sin", "cos", "tan", "asin",
+    "acos", "atan", "atan2", "sinh", "cosh", "tanh", "exp", "log", "log10", "sqrt",
+];
+
+/// Returns a C identifier for a variable name, such as `x_1_` for `x[1]`.
+/// Reserved names get a trailing underscore, `r` becomes `r_`.
+fn c_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if C_RESERVED.contains(&name.as_str()) {
+        name + "_"
+    } else {
+        name
+    }
+}
+
+fn c_expression(expr: &Expression, components: &IndexMap<String, Component>) -> Result<String> {
+    match expr {
+        Expression::Terminal {
+            terminal_type,
+            token,
+        } => match terminal_type {
+            TerminalType::UnsignedInteger => Ok(format!("{}.0", token.text)),
+            TerminalType::UnsignedReal => Ok(token.text.clone()),
+            TerminalType::Bool => Ok(if token.text == "true" { "1" } else { "0" }.to_string()),
+            _ => Err(anyhow!(
+                "{:?} literal {} is not supported by the C export",
+                terminal_type,
+                token.text
+            )),
+        },
+        Expression::ComponentReference(cref) => {
+            let name = cref.to_string();
+            if name == "time" {
+                Ok("t".to_string())
+            } else if type_of(expr, components) == Type::String {
+                Err(anyhow!(
+                    "String variable '{}' is not supported by the C export",
+                    name
+                ))
+            } else {
+                Ok(c_name(&name))
+            }
+        }
+        Expression::Unary { op, rhs } => {
+            let op = match op {
+                OpUnary::Minus(..) | OpUnary::DotMinus(..) => "-",
+                OpUnary::Plus(..) | OpUnary::DotPlus(..) => "+",
+                OpUnary::Not(..) => "!",
+                OpUnary::Empty => "",
+            };
+            Ok(format!("{}({})", op, c_expression(rhs, components)?))
+        }
+        Expression::Binary { op, lhs, rhs } => {
+            if type_of(lhs, components) == Type::String || type_of(rhs, components) == Type::String
+            {
+                return Err(anyhow!(
+                    "String operation {:?} is not supported by the C export",
+                    expr
+                ));
+            }
+            let (lhs, rhs) = (
+                c_expression(lhs, components)?,
+                c_expression(rhs, components)?,
+            );
+            let op = match op {
+                OpBinary::Add(..) | OpBinary::AddElem(..) => "+",
+                OpBinary::Sub(..) | OpBinary::SubElem(..) => "-",
+                OpBinary::Mul(..) | OpBinary::MulElem(..) => "*",
+                OpBinary::Div(..) | OpBinary::DivElem(..) => "/",
+                OpBinary::Exp(..) => return Ok(format!("pow({}, {})", lhs, rhs)),
+                OpBinary::Eq(..) => "==",
+                OpBinary::Neq(..) => "!=",
+                OpBinary::Lt(..) => "<",
+                OpBinary::Le(..) => "<=",
+                OpBinary::Gt(..) => ">",
+                OpBinary::Ge(..) => ">=",
+                OpBinary::And(..) => "&&",
+                OpBinary::Or(..) => "||",
+                OpBinary::Empty => return Err(anyhow!("missing operator in {:?}", expr)),
+            };
+            Ok(format!("({} {} {})", lhs, op, rhs))
+        }
+        Expression::FunctionCall { comp, args } => {
+            let name = comp.to_string();
+            // the simplified expression of homotopy, and noEvent, are the
+            // actual argument
+            if (name == "homotopy" && args.len() == 2) || (name == "noEvent" && args.len() == 1) {
+                return c_expression(&args[0], components);
+            }
+            // the Integer functions on doubles, div truncates towards zero
+            // and mod rounds towards negative infinity
+            if let ("div" | "mod" | "rem", [lhs, rhs]) = (name.as_str(), args.as_slice()) {
+                let (lhs, rhs) = (
+                    c_expression(lhs, components)?,
+                    c_expression(rhs, components)?,
+                );
+                return Ok(match name.as_str() {
+                    "div" => format!("trunc({} / {})", lhs, rhs),
+                    "rem" => format!("fmod({}, {})", lhs, rhs),
+                    _ => format!("({} - floor({} / {}) * {})", lhs, lhs, rhs, rhs),
+                });
+            }
+            let function = match name.as_str() {
+                "abs" => "fabs",
+                "min" => "fmin",
+                "max" => "fmax",
+                "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "atan2" | "sinh" | "cosh"
+                | "tanh" | "exp" | "log" | "log10" | "sqrt" | "floor" | "ceil" => name.as_str(),
+                _ => {
+                    return Err(anyhow!(
+                        "function '{}' is not supported by the C export",
+                        name
+                    ));
+                }
+            };
+            let args = args
+                .iter()
+                .map(|arg| c_expression(arg, components))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(format!("{}({})", function, args.join(", ")))
+        }
+        Expression::If {
+            branches,
+            else_branch,
+        } => {
+            // elseif branches become nested conditional expressions, only
+            // the selected branch is evaluated
+            let mut s = c_expression(else_branch, components)?;
+            for (cond, then) in branches.iter().rev() {
+                s = format!(
+                    "({} ? {} : {})",
+                    c_expression(cond, components)?,
+                    c_expression(then, components)?,
+                    s
+                );
+            }
+            Ok(s)
+        }
+        _ => Err(anyhow!(
+            "expression {:?} is not supported by the C export",
+            expr
+        )),
+    }
+}
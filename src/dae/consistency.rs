@@ -0,0 +1,163 @@
+//! This module checks that a `Dae` respects the rules of discrete-time
+//! variables, which `create_dae` only partly enforces.
+//!
+//! `Dae::check_consistency` collects its findings as `Diagnostic`s, like
+//! `StoredDefinition::validate`:
+//! - **pre of continuous variables** (`pre-of-continuous`): the argument of
+//!   `pre` must be a discrete variable of `z` or `m`. Only the value of a
+//!   `reinit` may use `pre` of the state it resets, as in
+//!   `reinit(v, -e*pre(v))`.
+//! - **discrete updates** (`discrete-outside-when`): the discrete variables of
+//!   `z` and `m` may only be assigned in `when` equations, so they must not be
+//!   the left hand side of a continuous equation of `fx`.
+//!
+//! References to `pre(x)` are recognized by the `pre_x` names that
+//! `create_dae` substitutes for them.
+use crate::dae::ast::Dae;
+use crate::ir::ast::{ComponentReference, Equation, Expression, Location};
+use crate::ir::validate::{Diagnostic, equation_location};
+use indexmap::IndexSet;
+
+impl Dae {
+    pub fn check_consistency(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let discrete: IndexSet<&str> = self
+            .z
+            .iter()
+            .chain(&self.m)
+            .map(|comp| comp.name.as_str())
+            .collect();
+        let continuous: IndexSet<&str> = self
+            .x
+            .iter()
+            .chain(&self.y)
+            .chain(&self.u)
+            .map(|comp| comp.name.as_str())
+            .collect();
+        // a model may declare a variable named like a pre reference
+        let declared: IndexSet<&str> = self
+            .p
+            .iter()
+            .chain(&self.cp)
+            .chain(&self.x)
+            .chain(&self.y)
+            .chain(&self.u)
+            .chain(&self.z)
+            .chain(&self.m)
+            .map(|comp| comp.name.as_str())
+            .collect();
+
+        let mut expressions = Vec::new();
+        for eq in self.fx.iter().chain(&self.fz).chain(&self.fm) {
+            equation_expressions(eq, &mut expressions);
+        }
+        expressions.extend(self.fc.values());
+        for expr in expressions {
+            for cref in expr.free_variables() {
+                let name = cref.to_string();
+                if let Some(arg) = name.strip_prefix("pre_")
+                    && continuous.contains(arg)
+                    && !declared.contains(name.as_str())
+                {
+                    diagnostics.push(Diagnostic::error(
+                        "pre-of-continuous",
+                        format!("pre({}) requires '{}' to be discrete", arg, arg),
+                        location(&cref),
+                    ));
+                }
+            }
+        }
+
+        for eq in &self.fx {
+            check_continuous_update(eq, &discrete, &mut diagnostics);
+        }
+        diagnostics
+    }
+}
+
+/// Reports discrete variables assigned by a continuous equation.
+fn check_continuous_update(
+    eq: &Equation,
+    discrete: &IndexSet<&str>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    match eq {
+        Equation::Simple {
+            lhs: Expression::ComponentReference(cref),
+            ..
+        } if discrete.contains(cref.to_string().as_str()) => {
+            diagnostics.push(Diagnostic::error(
+                "discrete-outside-when",
+                format!(
+                    "discrete variable '{}' can only be assigned in a when equation",
+                    cref
+                ),
+                equation_location(eq),
+            ));
+        }
+        Equation::If {
+            cond_blocks,
+            else_block,
+        } => {
+            for eq in cond_blocks
+                .iter()
+                .flat_map(|block| &block.eqs)
+                .chain(else_block.iter().flatten())
+            {
+                check_continuous_update(eq, discrete, diagnostics);
+            }
+        }
+        Equation::For { equations, .. } => {
+            for eq in equations {
+                check_continuous_update(eq, discrete, diagnostics);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collects the expressions of an equation, leaving out the arguments of
+/// `reinit`, which may refer to `pre` of the reset state.
+fn equation_expressions<'a>(eq: &'a Equation, expressions: &mut Vec<&'a Expression>) {
+    match eq {
+        Equation::Empty | Equation::Connect { .. } => {}
+        Equation::Simple { lhs, rhs, .. } => expressions.extend([lhs, rhs]),
+        Equation::FunctionCall { comp, args } => {
+            if comp.to_string() != "reinit" {
+                expressions.extend(args);
+            }
+        }
+        Equation::For { indices, equations } => {
+            expressions.extend(indices.iter().map(|index| &index.range));
+            for eq in equations {
+                equation_expressions(eq, expressions);
+            }
+        }
+        Equation::When(blocks) => {
+            for block in blocks {
+                expressions.push(&block.cond);
+                for eq in &block.eqs {
+                    equation_expressions(eq, expressions);
+                }
+            }
+        }
+        Equation::If {
+            cond_blocks,
+            else_block,
+        } => {
+            for block in cond_blocks {
+                expressions.push(&block.cond);
+                for eq in &block.eqs {
+                    equation_expressions(eq, expressions);
+                }
+            }
+            for eq in else_block.iter().flatten() {
+                equation_expressions(eq, expressions);
+            }
+        }
+    }
+}
+
+fn location(cref: &ComponentReference) -> Location {
+    cref.parts[0].ident.location.clone()
+}
@@ -0,0 +1,49 @@
+//! This module collects the conditions a fixed-step simulator samples to
+//! detect events.
+//!
+//! A fixed-step simulator has no zero-crossing detection, it evaluates each
+//! condition once per step and fires an event when its value changes.
+//! `Dae::event_conditions` returns these conditions as Boolean expressions of
+//! the variables:
+//! - The condition of each condition variable of `c`, given by `fc`, in order.
+//! - The trigger of each `when` update of `fz` and `fm` that is not one of
+//!   them already.
+//!
+//! Conditions that are equivalent, such as `x > 0` and `0 < x`, are returned
+//! once.
+use crate::dae::ast::Dae;
+use crate::ir::ast::{Equation, Expression};
+
+impl Dae {
+    pub fn event_conditions(&self) -> Vec<Expression> {
+        let mut conditions: Vec<Expression> = Vec::new();
+        let triggers = self
+            .fz
+            .iter()
+            .chain(&self.fm)
+            .flat_map(|update| match update {
+                Equation::When(blocks) => blocks.iter().map(|block| &block.cond).collect(),
+                _ => Vec::new(),
+            });
+        for cond in self
+            .c
+            .iter()
+            .filter_map(|comp| self.fc.get(&comp.name))
+            .chain(triggers.map(|cond| self.condition(cond)))
+        {
+            if !conditions.iter().any(|known| known.equivalent(cond)) {
+                conditions.push(cond.clone());
+            }
+        }
+        conditions
+    }
+
+    /// Returns the expression of the condition `cond`, looked up in `fc`
+    /// when it is a condition variable.
+    fn condition<'a>(&'a self, cond: &'a Expression) -> &'a Expression {
+        match cond {
+            Expression::ComponentReference(cref) => self.fc.get(&cref.to_string()).unwrap_or(cond),
+            _ => cond,
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! This module provides graph exports of the `Dae` structure for debugging.
+//!
+//! `Dae::to_bipartite_dot` renders the equation-variable incidence of the DAE
+//! in the Graphviz DOT format. Equations (`fx`, `fz` and `fm`) and unknowns
+//! (`x_dot`, `y`, `z` and `m`) are drawn as two columns of nodes, with an edge
+//! wherever an unknown appears in an equation. States, parameters, inputs and
+//! time are known when solving the equations and are not drawn.
+//!
+//! `Dae::to_bipartite_dot_with_blocks` additionally colors the equations by
+//! their block in a block lower triangular (BLT) ordering, given as lists of
+//! equation indices into `Dae::equations`.
+//!
+//! `Dae::dependency_graph_json` exports, for each unknown, the variables it
+//! is computed from, as a JSON object of adjacency lists. Each unknown is
+//! assigned one equation by a maximum matching of equations and unknowns,
+//! and depends on the other variables of that equation, parameters and time
+//! aside. The unknowns are listed in computation order, after the unknowns
+//! they depend on, except within algebraic loops.
+use crate::dae::ast::Dae;
+use crate::ir::ast::Equation;
+use crate::ir::visitor::Visitable;
+use crate::ir::visitors::component_ref_collector::ComponentRefCollector;
+use indexmap::{IndexMap, IndexSet};
+
+const BLOCK_COLORS: [&str; 8] = [
+    "lightblue",
+    "lightgreen",
+    "lightpink",
+    "lightyellow",
+    "lightsalmon",
+    "lightcyan",
+    "plum",
+    "wheat",
+];
+
+impl Dae {
+    /// Returns the labeled equations of the DAE, in the order of the
+    /// incidence graph.
+    pub fn equations(&self) -> Vec<(String, &Equation)> {
+        let mut eqs = Vec::new();
+        for (name, list) in [("fx", &self.fx), ("fz", &self.fz), ("fm", &self.fm)] {
+            for (i, eq) in list.iter().enumerate() {
+                eqs.push((format!("{}[{}]", name, i), eq));
+            }
+        }
+        eqs
+    }
+
+    /// Returns the names of the unknowns of the DAE.
+    pub fn unknowns(&self) -> IndexSet<String> {
+        self.x_dot
+            .iter()
+            .chain(self.y.iter())
+            .chain(self.z.iter())
+            .chain(self.m.iter())
+            .map(|comp| comp.name.clone())
+            .collect()
+    }
+
+    /// Returns, for each equation, the unknowns it references.
+    pub fn incidence(&self) -> Vec<IndexSet<String>> {
+        let unknowns = self.unknowns();
+        self.equations()
+            .into_iter()
+            .map(|(_, eq)| {
+                references(eq)
+                    .into_iter()
+                    .filter(|name| unknowns.contains(name))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns, for each unknown assigned an equation, the variables of that
+    /// equation it depends on, in computation order.
+    pub fn dependency_graph(&self) -> IndexMap<String, Vec<String>> {
+        let equations = self.equations();
+        let assignment = assign_equations(&self.incidence());
+        let mut variables = self.components();
+        for comp in self.p.iter().chain(self.cp.iter()) {
+            variables.swap_remove(&comp.name);
+        }
+        variables.swap_remove(&self.t.name);
+
+        let mut dependencies = IndexMap::new();
+        for name in self.unknowns() {
+            if let Some(&i) = assignment.get(&name) {
+                let depends_on = references(equations[i].1)
+                    .into_iter()
+                    .filter(|other| *other != name && variables.contains_key(other))
+                    .collect();
+                dependencies.insert(name, depends_on);
+            }
+        }
+
+        let mut ordered = IndexMap::new();
+        for name in dependencies.keys() {
+            order(name, &dependencies, &mut IndexSet::new(), &mut ordered);
+        }
+        ordered
+    }
+
+    pub fn dependency_graph_json(&self) -> String {
+        serde_json::to_string(&self.dependency_graph())
+            .expect("dependency graph serializes to JSON")
+    }
+
+    pub fn to_bipartite_dot(&self) -> String {
+        self.to_bipartite_dot_with_blocks(&[])
+    }
+
+    pub fn to_bipartite_dot_with_blocks(&self, blocks: &[Vec<usize>]) -> String {
+        let equations = self.equations();
+        let mut s = String::new();
+        s += "graph dae {\n";
+        s += "    rankdir=LR;\n";
+
+        // equations
+        s += "    subgraph cluster_equations {\n";
+        s += "        label=\"equations\";\n";
+        for (i, (name, _)) in equations.iter().enumerate() {
+            match blocks.iter().position(|block| block.contains(&i)) {
+                Some(block) => {
+                    s += &format!(
+                        "        \"{}\" [shape=box, style=filled, fillcolor={}];\n",
+                        name,
+                        BLOCK_COLORS[block % BLOCK_COLORS.len()]
+                    )
+                }
+                None => s += &format!("        \"{}\" [shape=box];\n", name),
+            }
+        }
+        s += "    }\n";
+
+        // variables
+        s += "    subgraph cluster_variables {\n";
+        s += "        label=\"variables\";\n";
+        for name in self.unknowns() {
+            s += &format!("        \"{}\" [shape=ellipse];\n", name);
+        }
+        s += "    }\n";
+
+        // edges
+        for ((eq_name, _), vars) in equations.iter().zip(self.incidence()) {
+            for var in vars {
+                s += &format!("    \"{}\" -- \"{}\";\n", eq_name, var);
+            }
+        }
+        s += "}\n";
+        s
+    }
+}
+
+/// Returns the names of the variables referenced by an equation.
+fn references(eq: &Equation) -> IndexSet<String> {
+    let mut collector = ComponentRefCollector::default();
+    eq.clone().accept(&mut collector);
+    collector.names
+}
+
+/// Assigns equations to the unknowns they reference, such that each equation
+/// and unknown is assigned at most once, by a maximum bipartite matching.
+fn assign_equations(incidence: &[IndexSet<String>]) -> IndexMap<String, usize> {
+    let mut assignment = IndexMap::new();
+    for i in 0..incidence.len() {
+        augment(i, incidence, &mut assignment, &mut IndexSet::new());
+    }
+    assignment
+}
+
+/// Searches an augmenting path from equation `i`, reassigning the unknowns
+/// along the path.
+fn augment(
+    i: usize,
+    incidence: &[IndexSet<String>],
+    assignment: &mut IndexMap<String, usize>,
+    visited: &mut IndexSet<String>,
+) -> bool {
+    for name in &incidence[i] {
+        if !visited.insert(name.clone()) {
+            continue;
+        }
+        let free = match assignment.get(name) {
+            Some(&k) => augment(k, incidence, assignment, visited),
+            None => true,
+        };
+        if free {
+            assignment.insert(name.clone(), i);
+            return true;
+        }
+    }
+    false
+}
+
+/// Adds `name` to `ordered` after the unknowns it depends on. Dependencies
+/// on unknowns that are being visited, which form an algebraic loop, are
+/// skipped.
+fn order(
+    name: &str,
+    dependencies: &IndexMap<String, Vec<String>>,
+    visiting: &mut IndexSet<String>,
+    ordered: &mut IndexMap<String, Vec<String>>,
+) {
+    if ordered.contains_key(name) || !visiting.insert(name.to_string()) {
+        return;
+    }
+    for other in &dependencies[name] {
+        if dependencies.contains_key(other) {
+            order(other, dependencies, visiting, ordered);
+        }
+    }
+    ordered.insert(name.to_string(), dependencies[name].clone());
+}
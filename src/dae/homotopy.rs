@@ -0,0 +1,44 @@
+//! This module sets up the homotopy initialization of a `Dae`.
+//!
+//! A model that uses `homotopy(actual, simplified)` is initialized in two
+//! stages: the solver first solves the simplified problem, at the homotopy
+//! parameter `lambda = 0`, then ramps `lambda` up to 1, where the actual
+//! problem is solved. `Dae::homotopy_residuals` returns the residuals of `fx`
+//! at both ends, with every call of `homotopy` replaced by its simplified and
+//! by its actual argument, or `None` when the model does not use `homotopy`.
+use crate::dae::ast::Dae;
+use crate::ir::ast::Expression;
+use crate::ir::visitor::Visitable;
+use crate::ir::visitors::homotopy_resolver::HomotopyResolver;
+use anyhow::Result;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HomotopyResiduals {
+    /// residuals at `lambda = 0`
+    pub simplified: Vec<Expression>,
+    /// residuals at `lambda = 1`
+    pub actual: Vec<Expression>,
+}
+
+impl Dae {
+    pub fn homotopy_residuals(&self) -> Result<Option<HomotopyResiduals>> {
+        let residuals = self.to_residual_form()?;
+        let resolve = |simplified: bool| {
+            let mut resolver = HomotopyResolver {
+                simplified,
+                found: false,
+            };
+            let mut residuals = residuals.clone();
+            for residual in &mut residuals {
+                residual.accept(&mut resolver);
+            }
+            (residuals, resolver.found)
+        };
+        let (simplified, found) = resolve(true);
+        if !found {
+            return Ok(None);
+        }
+        let (actual, _) = resolve(false);
+        Ok(Some(HomotopyResiduals { simplified, actual }))
+    }
+}
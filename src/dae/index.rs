@@ -0,0 +1,288 @@
+//! This module computes the structural index of the continuous equations of a
+//! `Dae`, to tell users how hard their model is to solve before solving it.
+//!
+//! `Dae::structural_index` runs the algorithm of Pantelides on the residuals
+//! of `fx`. The unknowns are the highest derivatives: the derivatives `x_dot`
+//! of the states and the algebraic variables `y`. Whenever an equation cannot
+//! be matched to an unknown, the equations of the failed augmenting path are
+//! differentiated, introducing derivatives of their unknowns, until every
+//! equation is matched.
+//!
+//! The index is one more than the largest number of times an equation had to
+//! be differentiated:
+//! - an ODE, or a DAE whose algebraic variables follow directly from the
+//!   equations, has index 1,
+//! - a pendulum in cartesian coordinates, whose length constraint has to be
+//!   differentiated twice, has index 3.
+//!
+//! The index is diagnostic only, the equations of the `Dae` are not changed.
+//!
+//! `Dae::select_states` goes on with the dummy derivative method of Mattsson
+//! and Söderlind. Starting from the most differentiated equations, each level
+//! of differentiated equations selects as many of its highest derivatives as
+//! it has equations, these become dummy derivatives, algebraic variables of
+//! the reduced DAE. A state whose derivative is a dummy derivative is no
+//! longer a state. The selection follows the `stateSelect` attribute of the
+//! variables: `never` and `avoid` become dummies first, `prefer` and
+//! `always` last, and a state with `always` that cannot be kept is an error.
+use crate::dae::ast::Dae;
+use crate::ir::ast::StateSelect;
+use anyhow::{Result, anyhow};
+use indexmap::{IndexMap, IndexSet};
+
+#[derive(Debug, Default)]
+struct Pantelides {
+    /// unknowns of each equation
+    equations: Vec<IndexSet<usize>>,
+    /// number of times each equation has been differentiated
+    order: Vec<usize>,
+    /// derivative of each equation, once it has been differentiated
+    equation_derivative: Vec<Option<usize>>,
+    /// derivative of each variable, variables without one are unknowns
+    derivative: Vec<Option<usize>>,
+    /// equation matched to each variable
+    assignment: Vec<Option<usize>>,
+    /// state selection of each variable, derivatives inherit it
+    state_select: Vec<StateSelect>,
+}
+
+impl Dae {
+    pub fn structural_index(&self) -> Result<usize> {
+        let (pantelides, _) = self.pantelides()?;
+        Ok(match pantelides.order.iter().max() {
+            Some(order) => order + 1,
+            None => 0,
+        })
+    }
+
+    /// Returns the names of the states of `x` that remain states after index
+    /// reduction, the other states become dummy states.
+    pub fn select_states(&self) -> Result<Vec<String>> {
+        let (pantelides, states) = self.pantelides()?;
+        let dummies = pantelides.dummy_derivatives();
+        let mut selected = Vec::new();
+        for (name, var) in states {
+            let der = pantelides.derivative[var].unwrap();
+            if !dummies.contains(&der) {
+                selected.push(name);
+            } else if pantelides.state_select[var] == StateSelect::Always {
+                return Err(anyhow!(
+                    "'{}' has stateSelect = StateSelect.always, but cannot be selected as a state",
+                    name
+                ));
+            }
+        }
+        Ok(selected)
+    }
+
+    /// Runs the algorithm of Pantelides, returning it along with the variable
+    /// of each state.
+    fn pantelides(&self) -> Result<(Pantelides, IndexMap<String, usize>)> {
+        let mut pantelides = Pantelides::default();
+        let mut variables: IndexMap<String, usize> = IndexMap::new();
+        let mut states = IndexMap::new();
+        for state in &self.x {
+            let der = pantelides.add_variable(state.state_select.clone());
+            let var = pantelides.add_variable(state.state_select.clone());
+            pantelides.derivative[var] = Some(der);
+            variables.insert(format!("der_{}", state.name), der);
+            variables.insert(state.name.clone(), var);
+            states.insert(state.name.clone(), var);
+        }
+        for comp in &self.y {
+            variables.insert(
+                comp.name.clone(),
+                pantelides.add_variable(comp.state_select.clone()),
+            );
+        }
+
+        for residual in self.to_residual_form()? {
+            let unknowns = residual
+                .free_variables()
+                .iter()
+                .filter_map(|cref| variables.get(&cref.to_string()).copied())
+                .collect();
+            pantelides.equations.push(unknowns);
+            pantelides.order.push(0);
+            pantelides.equation_derivative.push(None);
+        }
+
+        let count = pantelides.equations.len();
+        for k in 0..count {
+            let mut i = k;
+            loop {
+                let mut colored_variables = vec![false; pantelides.derivative.len()];
+                let mut colored_equations = vec![false; pantelides.equations.len()];
+                if pantelides.augment(i, &mut colored_variables, &mut colored_equations) {
+                    break;
+                }
+                if pantelides.order[i] >= count {
+                    return Err(anyhow!(
+                        "the continuous equations are structurally singular"
+                    ));
+                }
+                i = pantelides.differentiate(i, &colored_variables, &colored_equations);
+            }
+        }
+        Ok((pantelides, states))
+    }
+}
+
+impl Pantelides {
+    fn add_variable(&mut self, state_select: StateSelect) -> usize {
+        self.derivative.push(None);
+        self.assignment.push(None);
+        self.state_select.push(state_select);
+        self.derivative.len() - 1
+    }
+
+    /// Searches an augmenting path from equation `i`, coloring the visited
+    /// equations and unknowns.
+    fn augment(
+        &mut self,
+        i: usize,
+        colored_variables: &mut [bool],
+        colored_equations: &mut [bool],
+    ) -> bool {
+        colored_equations[i] = true;
+        let unknowns: Vec<usize> = self.equations[i]
+            .iter()
+            .copied()
+            .filter(|&j| self.derivative[j].is_none())
+            .collect();
+        if let Some(&j) = unknowns.iter().find(|&&j| self.assignment[j].is_none()) {
+            self.assignment[j] = Some(i);
+            return true;
+        }
+        for j in unknowns {
+            if colored_variables[j] {
+                continue;
+            }
+            colored_variables[j] = true;
+            let k = self.assignment[j].unwrap();
+            if self.augment(k, colored_variables, colored_equations) {
+                self.assignment[j] = Some(i);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Differentiates the colored equations and unknowns, moving the
+    /// assignments to the derivatives, and returns the derivative of
+    /// equation `i`.
+    fn differentiate(
+        &mut self,
+        i: usize,
+        colored_variables: &[bool],
+        colored_equations: &[bool],
+    ) -> usize {
+        for (j, _) in colored_variables.iter().enumerate().filter(|(_, c)| **c) {
+            let der = self.add_variable(self.state_select[j].clone());
+            self.derivative[j] = Some(der);
+        }
+        for (l, _) in colored_equations.iter().enumerate().filter(|(_, c)| **c) {
+            let mut unknowns = IndexSet::new();
+            for &j in &self.equations[l] {
+                unknowns.insert(j);
+                // every unknown of a colored equation is colored, so all
+                // variables of the equation have a derivative
+                unknowns.insert(self.derivative[j].unwrap());
+            }
+            self.equations.push(unknowns);
+            self.order.push(self.order[l] + 1);
+            self.equation_derivative.push(None);
+            self.equation_derivative[l] = Some(self.equations.len() - 1);
+        }
+        for (j, _) in colored_variables.iter().enumerate().filter(|(_, c)| **c) {
+            let eq = self.assignment[j].unwrap();
+            self.assignment[self.derivative[j].unwrap()] = self.equation_derivative[eq];
+        }
+        self.equation_derivative[i].unwrap()
+    }
+
+    /// Selects the dummy derivatives, level by level from the most
+    /// differentiated equations down to the equations differentiated once.
+    fn dummy_derivatives(&self) -> IndexSet<usize> {
+        let is_derivative: IndexSet<usize> = self.derivative.iter().flatten().copied().collect();
+        let mut equations: Vec<usize> = (0..self.equations.len())
+            .filter(|&l| self.order[l] > 0 && self.equation_derivative[l].is_none())
+            .collect();
+        let mut candidates: IndexSet<usize> = is_derivative
+            .iter()
+            .copied()
+            .filter(|&j| self.derivative[j].is_none())
+            .collect();
+        let mut dummies = IndexSet::new();
+        while !equations.is_empty() {
+            let selected = self.select_columns(&equations, &candidates);
+            // one differentiation less
+            equations = (0..self.equations.len())
+                .filter(|&l| {
+                    self.order[l] > 0
+                        && self.equation_derivative[l].is_some_and(|d| equations.contains(&d))
+                })
+                .collect();
+            candidates = (0..self.derivative.len())
+                .filter(|&j| self.derivative[j].is_some_and(|d| selected.contains(&d)))
+                .collect();
+            dummies.extend(selected);
+        }
+        dummies
+    }
+
+    /// Matches `equations` to distinct `candidates`, preferring the variables
+    /// that are least wanted as states, and returns the matched variables.
+    fn select_columns(&self, equations: &[usize], candidates: &IndexSet<usize>) -> IndexSet<usize> {
+        let mut matched: IndexMap<usize, usize> = IndexMap::new();
+        for tier in 0..=rank(&StateSelect::Always) {
+            let allowed: IndexSet<usize> = candidates
+                .iter()
+                .copied()
+                .filter(|&j| rank(&self.state_select[j]) <= tier)
+                .collect();
+            for &l in equations {
+                if !matched.values().any(|&m| m == l) {
+                    self.match_column(l, &allowed, &mut matched, &mut IndexSet::new());
+                }
+            }
+        }
+        matched.keys().copied().collect()
+    }
+
+    /// Searches an augmenting path from equation `l` among the `allowed`
+    /// variables.
+    fn match_column(
+        &self,
+        l: usize,
+        allowed: &IndexSet<usize>,
+        matched: &mut IndexMap<usize, usize>,
+        visited: &mut IndexSet<usize>,
+    ) -> bool {
+        for &j in &self.equations[l] {
+            if !allowed.contains(&j) || !visited.insert(j) {
+                continue;
+            }
+            let free = match matched.get(&j) {
+                Some(&k) => self.match_column(k, allowed, matched, visited),
+                None => true,
+            };
+            if free {
+                matched.insert(j, l);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Orders the state selections from the least to the most wanted as state.
+fn rank(state_select: &StateSelect) -> usize {
+    match state_select {
+        StateSelect::Never => 0,
+        StateSelect::Avoid => 1,
+        StateSelect::Default => 2,
+        StateSelect::Prefer => 3,
+        StateSelect::Always => 4,
+    }
+}
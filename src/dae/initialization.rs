@@ -0,0 +1,99 @@
+//! This module assembles the initialization problem of a `Dae`.
+//!
+//! Before the integration starts, the solver finds consistent values of all
+//! unknowns at the initial time. `Dae::initialization_system` returns the
+//! unknowns and the equations of that problem:
+//! - The continuous equations `fx`, which also hold initially.
+//! - One start equation `x = start` per state or algebraic variable whose
+//!   start value is fixed, declared `fixed = true`. A state with a `start`
+//!   modification is fixed unless declared `fixed = false`.
+//! - The initial equations `fi`.
+//! - The updates of the `when` equations whose condition holds initially,
+//!   such as `when initial() then`, which are active at the initial time.
+//!
+//! The start values that are not fixed are only guesses, which seed the
+//! solver of the nonlinear initialization problem.
+use crate::dae::ast::Dae;
+use crate::ir::ast::{Component, ComponentReference, Equation, Expression, OpBinary, TerminalType};
+use indexmap::IndexMap;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InitSystem {
+    /// states, derivatives, algebraic and discrete variables
+    pub unknowns: Vec<Component>,
+    pub equations: Vec<Equation>,
+    /// start values that are not fixed, by variable name
+    pub guesses: IndexMap<String, Expression>,
+}
+
+impl Dae {
+    pub fn initialization_system(&self) -> InitSystem {
+        let unknowns = [&self.x, &self.x_dot, &self.y, &self.z, &self.m]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        let mut equations = self.fx.clone();
+        let mut guesses = IndexMap::new();
+        for (comp, state) in self
+            .x
+            .iter()
+            .map(|comp| (comp, true))
+            .chain(self.y.iter().map(|comp| (comp, false)))
+        {
+            let has_start = comp.modifications.contains_key("start");
+            let fixed = match comp.modifications.get("fixed") {
+                Some(Expression::Terminal {
+                    terminal_type: TerminalType::Bool,
+                    token,
+                }) => token.text == "true",
+                _ => state && has_start,
+            };
+            if fixed {
+                equations.push(Equation::Simple {
+                    lhs: Expression::ComponentReference(ComponentReference::new(&comp.name)),
+                    rhs: comp.start.clone(),
+                    origin: None,
+                });
+            } else if has_start {
+                guesses.insert(comp.name.clone(), comp.start.clone());
+            }
+        }
+        equations.extend(self.fi.iter().cloned());
+        for update in self.fz.iter().chain(&self.fm) {
+            // the active branch of an update is its last block
+            if let Equation::When(blocks) = update
+                && let Some(block) = blocks.last()
+                && self.holds_initially(&block.cond)
+            {
+                equations.extend(block.eqs.iter().cloned());
+            }
+        }
+        InitSystem {
+            unknowns,
+            equations,
+            guesses,
+        }
+    }
+
+    /// Tells whether the condition `cond` holds at the initial time, that is
+    /// if it is `initial()`, possibly in a disjunction.
+    fn holds_initially(&self, cond: &Expression) -> bool {
+        match cond {
+            Expression::FunctionCall { comp, args } => {
+                comp.to_string() == "initial" && args.is_empty()
+            }
+            Expression::Binary {
+                op: OpBinary::Or(..),
+                lhs,
+                rhs,
+            } => self.holds_initially(lhs) || self.holds_initially(rhs),
+            // conditions are replaced by condition variables
+            Expression::ComponentReference(cref) => self
+                .fc
+                .get(&cref.to_string())
+                .is_some_and(|expr| self.holds_initially(expr)),
+            _ => false,
+        }
+    }
+}
@@ -2,9 +2,18 @@
 //! which is part of the Abstract Syntax Tree (AST) representation in the
 //! Differential-Algebraic Equation (DAE) system. The `Dae` structure is used
 //! to model and manipulate DAE-related data within the application.
+//!
+//! Besides `panic` and `warn`, templates can call `type_of(expr)`, which returns
+//! the inferred type of an expression (`"Real"`, `"Integer"`, `"Boolean"`,
+//! `"String"` or `"Unknown"`), to choose an operator rendering, such as string
+//! concatenation, and `error(msg)`, which stops the rendering with an error, to
+//! reject expressions a backend does not support.
 use crate::dae::ast::Dae;
+use crate::ir::ast::Expression;
+use crate::ir::types::type_of;
 use anyhow::{Context, Result};
-use minijinja::{Environment, context};
+use minijinja::value::ViaDeserialize;
+use minijinja::{Environment, ErrorKind, context};
 use std::fs;
 
 pub fn panic(msg: &str) {
@@ -15,16 +24,31 @@ pub fn warn(msg: &str) {
     eprintln!("{:?}", msg);
 }
 
+pub fn error(msg: &str) -> std::result::Result<(), minijinja::Error> {
+    Err(minijinja::Error::new(
+        ErrorKind::InvalidOperation,
+        msg.to_string(),
+    ))
+}
+
 pub fn render_template(dae: Dae, template_file: &str) -> Result<()> {
     let template_txt = fs::read_to_string(template_file)
         .with_context(|| format!("Can't read file {}", template_file))?;
+    println!("{}", render(&dae, &template_txt)?);
+    Ok(())
+}
 
+/// Renders the template source `template_txt` for the DAE.
+pub fn render(dae: &Dae, template_txt: &str) -> Result<String> {
     let mut env = Environment::new();
     env.add_function("panic", panic);
     env.add_function("warn", warn);
-    env.add_template("template", &template_txt)?;
+    env.add_function("error", error);
+    let components = dae.components();
+    env.add_function("type_of", move |expr: ViaDeserialize<Expression>| {
+        format!("{:?}", type_of(&expr, &components))
+    });
+    env.add_template("template", template_txt)?;
     let tmpl = env.get_template("template")?;
-    let txt = tmpl.render(context!(dae => dae)).unwrap();
-    println!("{}", txt);
-    Ok(())
+    Ok(tmpl.render(context!(dae => dae))?)
 }
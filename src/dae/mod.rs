@@ -1,2 +1,17 @@
+pub mod aliases;
 pub mod ast;
+pub mod codegen;
+pub mod consistency;
+pub mod events;
+pub mod graph;
+pub mod homotopy;
+pub mod index;
+pub mod initialization;
 pub mod jinja;
+pub mod names;
+pub mod outputs;
+pub mod residual;
+pub mod simplify;
+pub mod sparsity;
+pub mod specialize;
+pub mod variables;
@@ -0,0 +1,51 @@
+//! This module gives the variables of a `Dae` the names users know them by.
+//!
+//! Flattening joins the names of nested components with underscores, the
+//! variable `x` of the subcomponent `b` of `a` is `a_b_x`. Plots and other
+//! outputs rather show the dotted name `a.b.x`. `Dae::display_names` maps
+//! each variable to its display name, as recorded by `flatten` in the
+//! `qualified_name` of the component, without changing the identifiers:
+//! - derivatives are displayed as `der(a.b.x)`,
+//! - the values before an event as `pre(a.b.x)`,
+//! - variables of the main class keep their name.
+use crate::dae::ast::Dae;
+use crate::ir::ast::{Component, ComponentReference};
+use std::collections::HashMap;
+
+impl Dae {
+    pub fn display_names(&self) -> HashMap<ComponentReference, String> {
+        let mut names = HashMap::new();
+        let mut insert = |comp: &Component, display: String| {
+            names.insert(ComponentReference::new(&comp.name), display);
+        };
+        for comp in std::iter::once(&self.t)
+            .chain(&self.p)
+            .chain(&self.cp)
+            .chain(&self.x)
+            .chain(&self.y)
+            .chain(&self.u)
+            .chain(&self.z)
+            .chain(&self.m)
+            .chain(&self.c)
+        {
+            insert(comp, display_name(comp, ""));
+        }
+        // derivatives and pre values are copies of their variable
+        for comp in &self.x_dot {
+            insert(comp, format!("der({})", display_name(comp, "der_")));
+        }
+        for comp in self.pre_x.iter().chain(&self.pre_z).chain(&self.pre_m) {
+            insert(comp, format!("pre({})", display_name(comp, "pre_")));
+        }
+        names
+    }
+}
+
+/// Returns the display name of a variable, or of the variable `comp` is a
+/// copy of, named with `prefix`.
+fn display_name(comp: &Component, prefix: &str) -> String {
+    comp.qualified_name.clone().unwrap_or_else(|| {
+        let name = comp.name.strip_prefix(prefix).unwrap_or(&comp.name);
+        name.to_string()
+    })
+}
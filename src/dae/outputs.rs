@@ -0,0 +1,30 @@
+//! This module identifies the output equations of a `Dae`.
+//!
+//! Control and FMI consumers evaluate the outputs in a separate function
+//! from the state dynamics. `Dae::index_outputs` fills `fo` with the index in
+//! `fx` of the equation defining each algebraic variable declared `output` by
+//! the model itself, as the outputs of its components are internal
+//! variables. The defining equation is the first simple equation with the
+//! variable alone on one side, `y = 2*x` or `2*x = y`. The equations stay in
+//! `fx`, outputs without such an equation are left out.
+use crate::dae::ast::Dae;
+use crate::ir::ast::{Causality, Equation, Expression};
+
+impl Dae {
+    pub fn index_outputs(&mut self) {
+        self.fo.clear();
+        for comp in &self.y {
+            if !matches!(comp.causality, Causality::Output(..)) || comp.qualified_name.is_some() {
+                continue;
+            }
+            let defines = |expr: &Expression| matches!(expr, Expression::ComponentReference(cref) if cref.to_string() == comp.name);
+            let index = self.fx.iter().position(|eq| match eq {
+                Equation::Simple { lhs, rhs, .. } => defines(lhs) || defines(rhs),
+                _ => false,
+            });
+            if let Some(index) = index {
+                self.fo.insert(comp.name.clone(), index);
+            }
+        }
+    }
+}
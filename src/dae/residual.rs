@@ -0,0 +1,132 @@
+//! This module rewrites the continuous equations of a `Dae` into residual
+//! form, as expected by implicit DAE solvers such as IDA.
+//!
+//! `Dae::to_residual_form` returns one expression `r` per scalar equation of
+//! `fx`, such that the equation holds when `r = 0`:
+//! - `lhs = rhs` becomes `lhs - rhs`, or just `lhs` (`rhs`) when the other side
+//!   is the literal zero.
+//! - An `if` equation yields one `if` expression per equation of its branches,
+//!   selecting the residual of the active branch. Every branch, including the
+//!   `else` branch, must have the same number of equations.
+//! - A `for` equation yields the residuals of its body for each value of the
+//!   index, the index being substituted by the value. The ranges are
+//!   evaluated with the parameters of the DAE.
+//!
+//! Other equations, such as `when` equations, remaining `connect` equations
+//! or function calls, have no residual form and are reported as errors.
+use crate::dae::ast::Dae;
+use crate::ir::ast::{Equation, Expression, OpBinary, TerminalType, Token};
+use crate::ir::visitor::Visitable;
+use crate::ir::visitors::for_expander::ForExpander;
+use crate::ir::visitors::index_substituter::IndexSubstituter;
+use anyhow::{Result, anyhow};
+
+impl Dae {
+    pub fn to_residual_form(&self) -> Result<Vec<Expression>> {
+        let mut for_expander = ForExpander::default();
+        for_expander.evaluator.add_components(
+            &self
+                .cp
+                .iter()
+                .chain(&self.p)
+                .map(|comp| (comp.name.clone(), comp.clone()))
+                .collect(),
+        )?;
+        let mut residuals = Vec::new();
+        for eq in &self.fx {
+            residuals.append(&mut equation_residuals(eq, &for_expander)?);
+        }
+        Ok(residuals)
+    }
+}
+
+fn equation_residuals(eq: &Equation, for_expander: &ForExpander) -> Result<Vec<Expression>> {
+    match eq {
+        Equation::Simple { lhs, rhs, .. } => Ok(vec![residual(lhs, rhs)]),
+        Equation::For { indices, equations } => {
+            let Some((index, rest)) = indices.split_first() else {
+                return block_residuals(equations, for_expander);
+            };
+            let mut residuals = Vec::new();
+            for value in for_expander.range_values(&index.range)? {
+                let mut body = Equation::For {
+                    indices: rest.to_vec(),
+                    equations: equations.clone(),
+                };
+                body.accept(&mut IndexSubstituter {
+                    index: index.ident.text.clone(),
+                    value,
+                });
+                residuals.append(&mut equation_residuals(&body, for_expander)?);
+            }
+            Ok(residuals)
+        }
+        Equation::If {
+            cond_blocks,
+            else_block,
+        } => {
+            let mut branches = Vec::new();
+            for block in cond_blocks {
+                branches.push((&block.cond, block_residuals(&block.eqs, for_expander)?));
+            }
+            let else_residuals =
+                block_residuals(else_block.as_deref().unwrap_or(&[]), for_expander)?;
+            let count = else_residuals.len();
+            if let Some((_, residuals)) = branches.iter().find(|(_, r)| r.len() != count) {
+                return Err(anyhow!(
+                    "if equation has branches of {} and {} equations, every branch must have the same number of equations",
+                    residuals.len(),
+                    count
+                ));
+            }
+            Ok((0..count)
+                .map(|i| Expression::If {
+                    branches: branches
+                        .iter()
+                        .map(|(cond, residuals)| ((*cond).clone(), residuals[i].clone()))
+                        .collect(),
+                    else_branch: Box::new(else_residuals[i].clone()),
+                })
+                .collect())
+        }
+        _ => Err(anyhow!(
+            "equation {:?} cannot be written in residual form",
+            eq
+        )),
+    }
+}
+
+fn block_residuals(equations: &[Equation], for_expander: &ForExpander) -> Result<Vec<Expression>> {
+    let mut residuals = Vec::new();
+    for eq in equations {
+        residuals.append(&mut equation_residuals(eq, for_expander)?);
+    }
+    Ok(residuals)
+}
+
+fn residual(lhs: &Expression, rhs: &Expression) -> Expression {
+    if is_zero(rhs) {
+        lhs.clone()
+    } else if is_zero(lhs) {
+        rhs.clone()
+    } else {
+        Expression::Binary {
+            op: OpBinary::Sub(Token {
+                text: "-".to_string(),
+                ..Default::default()
+            }),
+            lhs: Box::new(lhs.clone()),
+            rhs: Box::new(rhs.clone()),
+        }
+    }
+}
+
+fn is_zero(expr: &Expression) -> bool {
+    match expr {
+        Expression::Terminal {
+            terminal_type: TerminalType::UnsignedInteger | TerminalType::UnsignedReal,
+            token,
+        } => token.text.parse::<f64>().is_ok_and(|v| v == 0.0),
+        _ => false,
+    }
+}
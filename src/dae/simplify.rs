@@ -0,0 +1,23 @@
+//! This module simplifies the equations of a `Dae`.
+//!
+//! `Dae::remove_tautologies` drops the equations of `fx`, `fz` and `fm`
+//! whose sides are structurally equal, such as `x = x`, using the
+//! `TautologyRemover`, and returns how many were removed. The output
+//! equations are indexed again, as the equations of `fx` may have moved.
+use crate::dae::ast::Dae;
+use crate::ir::visitor::Visitable;
+use crate::ir::visitors::tautology_remover::TautologyRemover;
+
+impl Dae {
+    pub fn remove_tautologies(&mut self) -> usize {
+        let mut remover = TautologyRemover::default();
+        for equations in [&mut self.fx, &mut self.fz, &mut self.fm] {
+            for eq in equations.iter_mut() {
+                eq.accept(&mut remover);
+            }
+            remover.remove(equations);
+        }
+        self.index_outputs();
+        remover.removed
+    }
+}
@@ -0,0 +1,82 @@
+//! This module computes the sparsity pattern of the Jacobian of the residual
+//! of a `Dae`, as needed by implicit solvers such as IDA and KINSOL.
+//!
+//! `Dae::jacobian_sparsity` returns, for each residual of
+//! `Dae::to_residual_form`, the columns of the unknowns `[x_dot, x, y]` it
+//! depends on. The pattern is structural: a variable appearing in a residual
+//! is a nonzero, even when its partial derivative happens to vanish.
+//! Derivative and state columns are kept apart, so a solver can scale the
+//! derivative columns by the step size, as in `dF/dx + cj*dF/dx_dot`.
+use crate::dae::ast::Dae;
+use anyhow::Result;
+use indexmap::IndexMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnKind {
+    Derivative,
+    State,
+    Algebraic,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SparsePattern {
+    /// names of the columns, `x_dot`, then `x`, then `y`
+    pub columns: Vec<String>,
+    /// kind of each column
+    pub kinds: Vec<ColumnKind>,
+    /// nonzero columns of each residual, in increasing order
+    pub rows: Vec<Vec<usize>>,
+}
+
+impl SparsePattern {
+    pub fn is_nonzero(&self, row: usize, column: usize) -> bool {
+        self.rows
+            .get(row)
+            .is_some_and(|columns| columns.binary_search(&column).is_ok())
+    }
+
+    /// Returns the index of the column of the variable `name`.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column == name)
+    }
+
+    /// Number of nonzero entries.
+    pub fn nnz(&self) -> usize {
+        self.rows.iter().map(Vec::len).sum()
+    }
+}
+
+impl Dae {
+    pub fn jacobian_sparsity(&self) -> Result<SparsePattern> {
+        let mut columns: IndexMap<String, ColumnKind> = IndexMap::new();
+        for (list, kind) in [
+            (&self.x_dot, ColumnKind::Derivative),
+            (&self.x, ColumnKind::State),
+            (&self.y, ColumnKind::Algebraic),
+        ] {
+            for comp in list {
+                columns.entry(comp.name.clone()).or_insert(kind.clone());
+            }
+        }
+        let rows = self
+            .to_residual_form()?
+            .iter()
+            .map(|residual| {
+                let mut row: Vec<usize> = residual
+                    .free_variables()
+                    .iter()
+                    .filter_map(|cref| columns.get_index_of(&cref.to_string()))
+                    .collect();
+                row.sort_unstable();
+                row.dedup();
+                row
+            })
+            .collect();
+        let (columns, kinds) = columns.into_iter().unzip();
+        Ok(SparsePattern {
+            columns,
+            kinds,
+            rows,
+        })
+    }
+}
@@ -0,0 +1,66 @@
+//! This module specializes a `Dae` for given parameter values, as needed for
+//! parameter sweeps.
+//!
+//! `Dae::specialize` substitutes the values into the equations, the reset
+//! statements, the condition updates and the start values of the variables,
+//! folding the constant subexpressions that result, and removes the
+//! parameters from `p`. Parameters without a value stay symbolic, and values
+//! of names that are not parameters of the `Dae` are ignored. The values of
+//! `Integer` and enumeration parameters, which are coded by their ordinals,
+//! are substituted as integers when they are integral.
+use crate::dae::ast::Dae;
+use crate::ir::const_eval::Value;
+use crate::ir::types::Type;
+use crate::ir::visitor::Visitable;
+use crate::ir::visitors::constant_folder::ConstantFolder;
+use std::collections::HashMap;
+
+impl Dae {
+    pub fn specialize(&self, params: &HashMap<String, f64>) -> Dae {
+        let mut folder = ConstantFolder::default();
+        for comp in &self.p {
+            if let Some(&value) = params.get(&comp.name) {
+                let type_name = comp.type_name.to_string();
+                let integer = Type::from_name(&type_name) == Type::Integer
+                    || self.enumerations.contains_key(&type_name);
+                let value = if integer && value.fract() == 0.0 {
+                    Value::Integer(value as i64)
+                } else {
+                    Value::Real(value)
+                };
+                folder.evaluator.values.insert(comp.name.clone(), value);
+            }
+        }
+
+        let mut dae = self.clone();
+        dae.p
+            .retain(|comp| !folder.evaluator.values.contains_key(&comp.name));
+        for eq in dae.fx.iter_mut().chain(&mut dae.fz).chain(&mut dae.fm) {
+            eq.accept(&mut folder);
+        }
+        for stmt in dae.fr.values_mut() {
+            stmt.accept(&mut folder);
+        }
+        for expr in dae.fc.values_mut() {
+            expr.accept(&mut folder);
+        }
+        for comp in dae
+            .p
+            .iter_mut()
+            .chain(&mut dae.cp)
+            .chain(&mut dae.x)
+            .chain(&mut dae.x_dot)
+            .chain(&mut dae.y)
+            .chain(&mut dae.u)
+            .chain(&mut dae.pre_z)
+            .chain(&mut dae.pre_x)
+            .chain(&mut dae.pre_m)
+            .chain(&mut dae.z)
+            .chain(&mut dae.m)
+            .chain(&mut dae.c)
+        {
+            comp.start.accept(&mut folder);
+        }
+        dae
+    }
+}
@@ -0,0 +1,42 @@
+//! This module exports the variables of a `Dae` as JSON, for FMI model
+//! descriptions, GUIs and other tools that need their metadata.
+//!
+//! `Dae::variables_json` returns an object with one entry per variable, by
+//! name, giving the field of the `Dae` the variable belongs to (`"x"`,
+//! `"y"`, `"p"`, ...) and its type. The `unit`, `quantity` and
+//! `displayUnit` attributes are included under their FMI names when the
+//! model sets them.
+use crate::dae::ast::Dae;
+use serde_json::{Map, Value};
+
+impl Dae {
+    pub fn variables_json(&self) -> String {
+        let mut variables = Map::new();
+        for (field, components) in [
+            ("p", &self.p),
+            ("cp", &self.cp),
+            ("x", &self.x),
+            ("y", &self.y),
+            ("u", &self.u),
+            ("z", &self.z),
+            ("m", &self.m),
+        ] {
+            for comp in components {
+                let mut variable = Map::new();
+                variable.insert("field".to_string(), field.into());
+                variable.insert("type".to_string(), comp.type_name.to_string().into());
+                for (name, value) in [
+                    ("unit", &comp.unit),
+                    ("quantity", &comp.quantity),
+                    ("displayUnit", &comp.display_unit),
+                ] {
+                    if let Some(value) = value {
+                        variable.insert(name.to_string(), value.clone().into());
+                    }
+                }
+                variables.insert(comp.name.clone(), Value::Object(variable));
+            }
+        }
+        serde_json::to_string(&variables).expect("variables serialize to JSON")
+    }
+}
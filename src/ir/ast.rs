@@ -39,9 +39,10 @@
 //!
 //! This module is designed to be extensible and serves as the foundation for parsing,
 //! analyzing, and generating code for the custom language or model representation.
+use anyhow::anyhow;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, fmt::Display};
+use std::{fmt::Debug, fmt::Display, hash::Hash, str::FromStr};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Location {
@@ -54,6 +55,36 @@ pub struct Location {
     pub file_name: String,
 }
 
+impl Location {
+    /// Returns the location spanning both locations, from the earlier start
+    /// to the later end. The file name is taken from `self`.
+    pub fn merge(&self, other: &Location) -> Location {
+        let (start_line, start_column) =
+            (self.start_line, self.start_column).min((other.start_line, other.start_column));
+        let (end_line, end_column) =
+            (self.end_line, self.end_column).max((other.end_line, other.end_column));
+        Location {
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+            file_name: self.file_name.clone(),
+        }
+    }
+
+    /// Returns the location spanning all tokens, or the default location if
+    /// there are none.
+    pub fn spanning(tokens: &[&Token]) -> Location {
+        tokens
+            .iter()
+            .map(|token| token.location.clone())
+            .reduce(|span, location| span.merge(&location))
+            .unwrap_or_default()
+    }
+}
+
 #[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
 #[allow(unused)]
 pub struct Token {
@@ -100,6 +131,32 @@ impl Debug for Name {
 pub struct StoredDefinition {
     pub class_list: IndexMap<String, ClassDefinition>,
     pub within: Option<Name>,
+    /// comments of the source, only kept when requested from the parser
+    pub comments: Vec<Comment>,
+}
+
+impl StoredDefinition {
+    /// Returns the comments directly preceding the token at `location`, such
+    /// as the comments above a declaration starting at that token.
+    pub fn comments_before(&self, location: &Location) -> Vec<&Comment> {
+        self.comments
+            .iter()
+            .filter(|comment| {
+                comment
+                    .next
+                    .as_ref()
+                    .is_some_and(|next| next.start == location.start)
+            })
+            .collect()
+    }
+}
+
+/// A line or block comment, with its delimiters, and the position of the
+/// nearest following token, if any.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comment {
+    pub token: Token,
+    pub next: Option<Location>,
 }
 
 #[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
@@ -110,9 +167,53 @@ pub struct Component {
     pub variability: Variability,
     pub causality: Causality,
     pub connection: Connection,
+    pub visibility: Visibility,
     pub description: Vec<Token>,
+    /// declared array dimensions, `Real x[3]` has the shape `[3]`
+    pub shape: Vec<Subscript>,
     pub start: Expression,
-    //pub annotation: Option<Token>,
+    pub nominal: Expression,
+    pub state_select: StateSelect,
+    /// `unit`, `quantity` and `displayUnit` attributes, for GUIs and unit
+    /// conversion
+    pub unit: Option<String>,
+    pub quantity: Option<String>,
+    pub display_unit: Option<String>,
+    pub modifications: IndexMap<String, Expression>,
+    /// declared `replaceable`, so that modifications may redeclare it
+    pub replaceable: bool,
+    /// the `constrainedby` type of a replaceable component, redeclarations
+    /// are constrained by the declared type when not given
+    pub constraint: Option<Name>,
+    /// components of the component's class redeclared by its modification
+    pub redeclarations: IndexMap<String, Component>,
+    /// nested classes of the component's class redeclared by its
+    /// modification, such as `redeclare package Medium = Water`
+    pub redeclared_classes: IndexMap<String, ClassDefinition>,
+    /// annotation modifications, nested modifications are joined with '.'
+    pub annotation: IndexMap<String, Expression>,
+    /// codegen hints of the `__rumoca` vendor annotation, by name
+    pub hints: IndexMap<String, Expression>,
+    /// condition of a conditional component, `Real x if cond`, the component
+    /// is removed when the condition is false
+    pub condition: Option<Expression>,
+    /// dotted name of a component flattened from a subcomponent, `a.b.x`
+    /// for `a_b_x`
+    pub qualified_name: Option<String>,
+    /// path of the components a flattened subcomponent is nested in,
+    /// `["a", "b"]` for `a.b.x`, when flattening preserves the hierarchy
+    pub hierarchy: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(unused)]
+pub enum StateSelect {
+    Never,
+    Avoid,
+    #[default]
+    Default,
+    Prefer,
+    Always,
 }
 
 impl Debug for Component {
@@ -130,9 +231,60 @@ impl Debug for Component {
         if self.connection != Connection::Empty {
             builder.field("connection", &self.connection);
         }
+        if self.visibility != Visibility::Public {
+            builder.field("visibility", &self.visibility);
+        }
         if self.description.len() > 0 {
             builder.field("description", &self.description);
         }
+        if !self.shape.is_empty() {
+            builder.field("shape", &self.shape);
+        }
+        if self.nominal != Expression::Empty {
+            builder.field("nominal", &self.nominal);
+        }
+        if self.state_select != StateSelect::Default {
+            builder.field("state_select", &self.state_select);
+        }
+        if let Some(unit) = &self.unit {
+            builder.field("unit", unit);
+        }
+        if let Some(quantity) = &self.quantity {
+            builder.field("quantity", quantity);
+        }
+        if let Some(display_unit) = &self.display_unit {
+            builder.field("display_unit", display_unit);
+        }
+        if !self.modifications.is_empty() {
+            builder.field("modifications", &self.modifications);
+        }
+        if self.replaceable {
+            builder.field("replaceable", &self.replaceable);
+        }
+        if let Some(constraint) = &self.constraint {
+            builder.field("constraint", constraint);
+        }
+        if !self.redeclarations.is_empty() {
+            builder.field("redeclarations", &self.redeclarations);
+        }
+        if !self.redeclared_classes.is_empty() {
+            builder.field("redeclared_classes", &self.redeclared_classes);
+        }
+        if !self.annotation.is_empty() {
+            builder.field("annotation", &self.annotation);
+        }
+        if !self.hints.is_empty() {
+            builder.field("hints", &self.hints);
+        }
+        if let Some(condition) = &self.condition {
+            builder.field("condition", condition);
+        }
+        if let Some(qualified_name) = &self.qualified_name {
+            builder.field("qualified_name", qualified_name);
+        }
+        if !self.hierarchy.is_empty() {
+            builder.field("hierarchy", &self.hierarchy);
+        }
         builder.finish()
     }
 }
@@ -141,21 +293,139 @@ impl Debug for Component {
 #[allow(unused)]
 pub struct ClassDefinition {
     pub name: Token,
+    pub class_type: ClassType,
+    /// description string following the class name
+    pub description: Vec<Token>,
+    /// base prefix of a short class definition, `connector RealInput = input Real`
+    pub causality: Causality,
+    /// dimensions of a short class definition, `type Vector3 = Real[3]`
+    pub shape: Vec<Subscript>,
     pub encapsulated: bool,
+    pub visibility: Visibility,
+    /// declared `partial`, the class may be extended but not simulated
+    pub partial: bool,
+    /// declared `operator record` or `operator function`, overloading the
+    /// operators of a record
+    pub operator: bool,
+    /// declared `replaceable`, so that modifications may redeclare it
+    pub replaceable: bool,
+    /// the `constrainedby` class of a replaceable class, redeclarations are
+    /// constrained by the base class of a short class definition when not
+    /// given
+    pub constraint: Option<Name>,
     pub extends: Vec<Extend>,
-    //pub imports: Vec<Import>,
-    //pub classes: IndexMap<String, ClassDefinition>,
+    pub imports: Vec<Import>,
+    pub classes: IndexMap<String, ClassDefinition>,
     pub components: IndexMap<String, Component>,
     pub equations: Vec<Equation>,
     pub initial_equations: Vec<Equation>,
     pub algorithms: Vec<Vec<Statement>>,
     pub initial_algorithms: Vec<Vec<Statement>>,
+    pub enumeration_literals: Vec<EnumerationLiteral>,
+    /// declared `enumeration(:)`, the literals are left to a redeclaration
+    pub unspecified_enumeration: bool,
+    /// derivative of a function, `type dF = der(F, x, y)`
+    pub der_class: Option<DerClass>,
+    /// declared by a short class specifier, `connector RealInput = input
+    /// Real`, the base class is the only element of `extends`
+    pub short_class: bool,
+    /// annotation modifications, nested modifications are joined with '.'
+    pub annotation: IndexMap<String, Expression>,
+    /// codegen hints of the `__rumoca` vendor annotation, by name, such as
+    /// `solver` for `annotation(__rumoca(solver = "rk4"))`
+    pub hints: IndexMap<String, Expression>,
+}
+
+impl ClassDefinition {
+    /// Tells whether the class is an enumeration type, including an
+    /// unspecified one.
+    pub fn is_enumeration(&self) -> bool {
+        !self.enumeration_literals.is_empty() || self.unspecified_enumeration
+    }
+
+    /// Returns the components of the class and of its nested classes, each
+    /// with its path from this class, such as `Inner.x` for the component
+    /// `x` of the nested class `Inner`.
+    pub fn components_recursive(&self) -> impl Iterator<Item = (String, &Component)> {
+        let mut components = Vec::new();
+        collect_components(self, "", &mut components);
+        components.into_iter()
+    }
+
+    /// Returns the functions overloading an operator of an operator record, by
+    /// the overloaded symbol without its quotes, such as `+` for
+    /// `operator function '+'`. An `operator` class overloads its symbol with
+    /// each of its functions, in order.
+    pub fn operator_overloads(&self) -> IndexMap<String, Vec<&ClassDefinition>> {
+        let mut overloads: IndexMap<String, Vec<&ClassDefinition>> = IndexMap::new();
+        for class in self.classes.values() {
+            let symbol = class.name.text.trim_matches('\'').to_string();
+            match class.class_type {
+                ClassType::Operator(..) => overloads
+                    .entry(symbol)
+                    .or_default()
+                    .extend(class.classes.values()),
+                ClassType::Function(..) if class.operator => {
+                    overloads.entry(symbol).or_default().push(class)
+                }
+                _ => {}
+            }
+        }
+        overloads
+    }
+}
+
+fn collect_components<'a>(
+    class: &'a ClassDefinition,
+    prefix: &str,
+    components: &mut Vec<(String, &'a Component)>,
+) {
+    for (name, comp) in &class.components {
+        components.push((format!("{}{}", prefix, name), comp));
+    }
+    for (name, nested) in &class.classes {
+        collect_components(nested, &format!("{}{}.", prefix, name), components);
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(unused)]
+pub struct DerClass {
+    /// the differentiated class
+    pub class: Name,
+    /// the variables it is differentiated with respect to, in order
+    pub variables: Vec<Token>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(unused)]
+pub struct EnumerationLiteral {
+    pub ident: Token,
+    pub description: Vec<Token>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[allow(unused)]
 pub struct Extend {
     pub comp: Name,
+    pub modifications: IndexMap<String, Expression>,
+    /// components of the base class redeclared by the modification
+    pub redeclarations: IndexMap<String, Component>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(unused)]
+pub struct Import {
+    /// the short name of a renaming import, `SI` for
+    /// `import SI = Modelica.SIunits`
+    pub alias: Option<Token>,
+    /// the imported class, or the package of an unqualified or multiple
+    /// import
+    pub path: Name,
+    /// the names imported from the package by `import A.{b, c}`
+    pub names: Vec<Token>,
+    /// `import A.*`, importing all names of the package
+    pub unqualified: bool,
 }
 
 #[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
@@ -182,23 +452,157 @@ impl Debug for ComponentRefPart {
     }
 }
 
-#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 #[allow(unused)]
 pub struct ComponentReference {
     pub local: bool,
     pub parts: Vec<ComponentRefPart>,
 }
 
+/// References are compared and hashed by name, so that the same reference at
+/// different locations is equal.
+impl PartialEq for ComponentReference {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for ComponentReference {}
+
+impl Hash for ComponentReference {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.to_string().hash(state);
+    }
+}
+
+impl Display for ComponentRefPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.ident.text)?;
+        if let Some(subs) = &self.subs {
+            let subs: Vec<String> = subs.iter().map(|sub| sub.to_string()).collect();
+            write!(f, "[{}]", subs.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
 impl Display for ComponentReference {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut s = Vec::new();
         for part in &self.parts {
-            s.push(format!("{:?}", part));
+            s.push(part.to_string());
         }
         write!(f, "{}", s.join("."))
     }
 }
 
+impl ComponentReference {
+    /// Creates a reference with a single part, such as `x`.
+    pub fn new(ident: &str) -> Self {
+        ComponentReference::default().with_part(ident)
+    }
+
+    /// Appends a part, so `ComponentReference::new("a").with_part("b")`
+    /// refers to `a.b`.
+    pub fn with_part(mut self, ident: &str) -> Self {
+        self.parts.push(ComponentRefPart {
+            ident: Token {
+                text: ident.to_string(),
+                ..Default::default()
+            },
+            subs: None,
+        });
+        self
+    }
+
+    /// Appends a subscript to the last part.
+    pub fn with_subscript(mut self, sub: Subscript) -> Self {
+        if let Some(part) = self.parts.last_mut() {
+            part.subs.get_or_insert_with(Vec::new).push(sub);
+        }
+        self
+    }
+}
+
+/// Parses a component reference such as `a.b[2].c`. Subscripts may be
+/// unsigned integers, component references or `:`.
+impl FromStr for ComponentReference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cref = ComponentReference::default();
+        for part in split_top_level(s, '.') {
+            let (ident, subs) = match part.find('[') {
+                Some(open) => {
+                    let subs = part[open + 1..].strip_suffix(']').ok_or_else(|| {
+                        anyhow!("unterminated subscript in component reference '{}'", s)
+                    })?;
+                    (&part[..open], Some(subs))
+                }
+                None => (part, None),
+            };
+            let ident = ident.trim();
+            if !is_identifier(ident) {
+                return Err(anyhow!("invalid component reference '{}'", s));
+            }
+            cref = cref.with_part(ident);
+            for sub in subs
+                .map(|subs| split_top_level(subs, ','))
+                .unwrap_or_default()
+            {
+                let sub = sub.trim();
+                cref = cref.with_subscript(if sub == ":" {
+                    Subscript::Range {
+                        token: Token {
+                            text: ":".to_string(),
+                            ..Default::default()
+                        },
+                    }
+                } else if !sub.is_empty() && sub.chars().all(|c| c.is_ascii_digit()) {
+                    Subscript::Expression(Expression::Terminal {
+                        terminal_type: TerminalType::UnsignedInteger,
+                        token: Token {
+                            text: sub.to_string(),
+                            ..Default::default()
+                        },
+                    })
+                } else {
+                    Subscript::Expression(Expression::ComponentReference(sub.parse()?))
+                });
+            }
+        }
+        Ok(cref)
+    }
+}
+
+/// Splits `s` at `sep`, ignoring separators nested inside brackets.
+fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            c if c == sep && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 impl Debug for ComponentReference {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut s = Vec::new();
@@ -238,6 +642,9 @@ pub enum Equation {
     Simple {
         lhs: Expression,
         rhs: Expression,
+        /// location of the connect equation that generated the equation
+        #[serde(default)]
+        origin: Option<Location>,
     },
     Connect {
         lhs: ComponentReference,
@@ -334,6 +741,16 @@ pub enum Expression {
     Array {
         elements: Vec<Expression>,
     },
+    /// `{expr for i in range, ...}`, `expr` is evaluated for every value of
+    /// the indices
+    ArrayComprehension {
+        expr: Box<Expression>,
+        indices: Vec<ForIndex>,
+    },
+    If {
+        branches: Vec<(Expression, Expression)>,
+        else_branch: Box<Expression>,
+    },
 }
 
 impl Debug for Expression {
@@ -366,6 +783,19 @@ impl Debug for Expression {
                 token,
             } => write!(f, "{:?}({:?})", terminal_type, token),
             Expression::Array { elements } => f.debug_list().entries(elements.iter()).finish(),
+            Expression::ArrayComprehension { expr, indices } => f
+                .debug_struct("ArrayComprehension")
+                .field("expr", expr)
+                .field("indices", indices)
+                .finish(),
+            Expression::If {
+                branches,
+                else_branch,
+            } => f
+                .debug_struct("If")
+                .field("branches", branches)
+                .field("else_branch", else_branch)
+                .finish(),
         }
     }
 }
@@ -387,12 +817,18 @@ pub enum Statement {
     },
     For {
         indices: Vec<ForIndex>,
-        equations: Vec<Statement>,
+        body: Vec<Statement>,
+    },
+    If {
+        cond_blocks: Vec<StatementBlock>,
+        else_block: Option<Vec<Statement>>,
     },
     While(StatementBlock),
+    When(Vec<StatementBlock>),
     FunctionCall {
         comp: ComponentReference,
         args: Vec<Expression>,
+        outputs: Vec<Expression>,
     },
 }
 
@@ -407,6 +843,36 @@ pub enum Subscript {
     },
 }
 
+impl Display for Subscript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Subscript::Empty => Ok(()),
+            Subscript::Expression(Expression::Terminal { token, .. }) => {
+                write!(f, "{}", token.text)
+            }
+            Subscript::Expression(Expression::ComponentReference(cref)) => write!(f, "{}", cref),
+            Subscript::Expression(expr) => write!(f, "{:?}", expr),
+            Subscript::Range { token } => write!(f, "{}", token.text),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(unused)]
+pub enum ClassType {
+    #[default]
+    Empty,
+    Class(Token),
+    Model(Token),
+    Record(Token),
+    Block(Token),
+    Connector(Token),
+    Type(Token),
+    Package(Token),
+    Function(Token),
+    Operator(Token),
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 #[allow(unused)]
 pub enum Variability {
@@ -426,6 +892,16 @@ pub enum Connection {
     Stream(Token),
 }
 
+/// Section an element is declared in, elements before the first `public` or
+/// `protected` keyword are public.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(unused)]
+pub enum Visibility {
+    #[default]
+    Public,
+    Protected,
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 #[allow(unused)]
 pub enum Causality {
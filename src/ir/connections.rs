@@ -0,0 +1,257 @@
+//! This module expands `connect` equations of a flat class.
+//!
+//! Connections are transitive, `connect(a, b)` and `connect(b, c)` join the
+//! connectors `a`, `b` and `c` into a single connection set. All connect
+//! equations of the model are therefore first grouped into connection sets with
+//! a union-find, and the equations are generated once per set:
+//! - potential variables of the first connector equal those of every other
+//!   connector of the set, giving `n - 1` equations,
+//! - flow variables, declared with the `flow` prefix, sum to zero in a single
+//!   equation with one term per connector. The flow of an inside connector,
+//!   a connector of a component such as `R1.p`, is added, while the flow of
+//!   an outside connector, a connector of the flattened class itself such as
+//!   `p`, is subtracted, as it flows into the class from the outside.
+//!
+//! When one of the connected variables is an `output`, as for the signal
+//! connectors `RealOutput` and `RealInput`, the other variables are equated to
+//! it instead, so that the inputs are the variables solved for.
+//!
+//! Each generated equation records in `origin` the location of a connect
+//! equation that joined its connectors: the first connect of the connector
+//! equated to another one, and the first connect of the set for the sum of
+//! the flow variables.
+//!
+//! Connectors are named as the flat components, with the parts of the
+//! component reference joined by underscores, so that the variable `v` of the
+//! connector `R1.p` is the flat component `R1_p_v`.
+use crate::ir::ast::{
+    Causality, ClassDefinition, Component, ComponentReference, Connection, Equation, Expression,
+    Location, OpBinary, OpUnary, TerminalType, Token,
+};
+use anyhow::{Result, anyhow};
+use indexmap::IndexMap;
+
+/// Groups the connectors of the connect equations into connection sets, in
+/// order of first appearance.
+pub fn connection_sets(connections: &[Equation]) -> Vec<Vec<ComponentReference>> {
+    let mut connectors: IndexMap<String, ComponentReference> = IndexMap::new();
+    let mut parent: Vec<usize> = Vec::new();
+    for eq in connections {
+        let Equation::Connect { lhs, rhs } = eq else {
+            continue;
+        };
+        let mut index = |cref: &ComponentReference| {
+            let (i, inserted) = connectors.insert_full(flat_name(cref), cref.clone());
+            if inserted.is_none() {
+                parent.push(i);
+            }
+            i
+        };
+        let (a, b) = (index(lhs), index(rhs));
+        let (a, b) = (find(&mut parent, a), find(&mut parent, b));
+        if a != b {
+            // keep the connector seen first as the root of the set
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut sets: IndexMap<usize, Vec<ComponentReference>> = IndexMap::new();
+    for (i, cref) in connectors.values().enumerate() {
+        let root = find(&mut parent, i);
+        sets.entry(root).or_default().push(cref.clone());
+    }
+    sets.into_values().collect()
+}
+
+fn find(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    // path compression
+    let mut i = i;
+    while parent[i] != root {
+        let next = parent[i];
+        parent[i] = root;
+        i = next;
+    }
+    root
+}
+
+/// Replaces the connect equations by the equations of their connection
+/// sets. The connector classes are looked up from `class`, the unflattened
+/// main class, while the generated equations refer to the components of
+/// `fclass`.
+pub fn expand_connections(
+    fclass: &mut ClassDefinition,
+    class: &ClassDefinition,
+    class_dict: &IndexMap<String, ClassDefinition>,
+    connections: &[Equation],
+) -> Result<()> {
+    // location of the first connect equation of each connector
+    let mut origins: IndexMap<String, Location> = IndexMap::new();
+    for eq in connections {
+        if let Equation::Connect { lhs, rhs } = eq {
+            let location = lhs.parts[0].ident.location.clone();
+            for cref in [lhs, rhs] {
+                origins
+                    .entry(flat_name(cref))
+                    .or_insert_with(|| location.clone());
+            }
+        }
+    }
+    for set in connection_sets(connections) {
+        let origin = |i: usize| origins.get(&flat_name(&set[i])).cloned();
+        let first = &set[0];
+        let connector = connector_class(class, class_dict, first)
+            .ok_or_else(|| anyhow!("Connector class of '{}' not found", first))?;
+        // a short connector, `connector RealInput = input Real`, is a
+        // variable itself
+        let variables: Vec<(String, &Connection)> = if connector.short_class {
+            vec![(String::new(), &Connection::Empty)]
+        } else {
+            let mut components = IndexMap::new();
+            connector_components(connector, class_dict, 0, &mut components);
+            components
+                .into_iter()
+                .map(|(name, var)| (format!("_{}", name), &var.connection))
+                .collect()
+        };
+        for (suffix, connection) in variables {
+            let names: Vec<String> = set
+                .iter()
+                .map(|cref| format!("{}{}", flat_name(cref), suffix))
+                .collect();
+            if let Some(name) = names
+                .iter()
+                .find(|name| !fclass.components.contains_key(*name))
+            {
+                return Err(anyhow!("Connector variable '{}' not found", name));
+            }
+            match connection {
+                Connection::Flow(..) => {
+                    let token = |text: &str| Token {
+                        text: text.to_string(),
+                        ..Default::default()
+                    };
+                    let mut sum: Option<Expression> = None;
+                    for (cref, name) in set.iter().zip(&names) {
+                        let outside = cref.parts.len() == 1;
+                        sum = Some(match sum {
+                            None if outside => Expression::Unary {
+                                op: OpUnary::Minus(token("-")),
+                                rhs: Box::new(reference(name)),
+                            },
+                            None => reference(name),
+                            Some(lhs) => Expression::Binary {
+                                op: if outside {
+                                    OpBinary::Sub(token("-"))
+                                } else {
+                                    OpBinary::Add(token("+"))
+                                },
+                                lhs: Box::new(lhs),
+                                rhs: Box::new(reference(name)),
+                            },
+                        });
+                    }
+                    let sum = sum.unwrap();
+                    fclass.equations.push(Equation::Simple {
+                        lhs: sum,
+                        rhs: Expression::Terminal {
+                            terminal_type: TerminalType::UnsignedInteger,
+                            token: Token {
+                                text: "0".to_string(),
+                                ..Default::default()
+                            },
+                        },
+                        origin: origin(0),
+                    });
+                }
+                _ => {
+                    // an output determines the connected inputs, which are
+                    // solved for, otherwise the first connector is equated to
+                    // the others
+                    let source = names.iter().position(|name| {
+                        matches!(fclass.components[name].causality, Causality::Output(..))
+                    });
+                    for (i, name) in names.iter().enumerate() {
+                        let (lhs, rhs) = match source {
+                            Some(source) if i != source => (name, &names[source]),
+                            None if i != 0 => (&names[0], name),
+                            _ => continue,
+                        };
+                        fclass.equations.push(Equation::Simple {
+                            lhs: reference(lhs),
+                            rhs: reference(rhs),
+                            origin: origin(i.max(source.unwrap_or(0))),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Name of a connector in the flat class.
+fn flat_name(cref: &ComponentReference) -> String {
+    cref.parts
+        .iter()
+        .map(|part| part.ident.text.clone())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn reference(name: &str) -> Expression {
+    Expression::ComponentReference(ComponentReference::new(name))
+}
+
+/// Follows the parts of a connector reference through the component
+/// declarations, including inherited ones, to the class of the connector.
+fn connector_class<'a>(
+    class: &'a ClassDefinition,
+    class_dict: &'a IndexMap<String, ClassDefinition>,
+    cref: &ComponentReference,
+) -> Option<&'a ClassDefinition> {
+    let mut class = class;
+    for part in &cref.parts {
+        let comp = find_component(class, class_dict, &part.ident.text, 0)?;
+        class = class_dict.get(&comp.type_name.to_string())?;
+    }
+    Some(class)
+}
+
+/// Collects the components of a connector class, including inherited ones.
+fn connector_components<'a>(
+    class: &'a ClassDefinition,
+    class_dict: &'a IndexMap<String, ClassDefinition>,
+    depth: usize,
+    components: &mut IndexMap<&'a String, &'a Component>,
+) {
+    if depth > class_dict.len() {
+        return;
+    }
+    for extend in &class.extends {
+        if let Some(base) = class_dict.get(&extend.comp.to_string()) {
+            connector_components(base, class_dict, depth + 1, components);
+        }
+    }
+    components.extend(&class.components);
+}
+
+fn find_component<'a>(
+    class: &'a ClassDefinition,
+    class_dict: &'a IndexMap<String, ClassDefinition>,
+    name: &str,
+    depth: usize,
+) -> Option<&'a Component> {
+    if depth > class_dict.len() {
+        return None;
+    }
+    class.components.get(name).or_else(|| {
+        class.extends.iter().find_map(|extend| {
+            let base = class_dict.get(&extend.comp.to_string())?;
+            find_component(base, class_dict, name, depth + 1)
+        })
+    })
+}
@@ -0,0 +1,483 @@
+//! This module provides constant evaluation of IR expressions.
+//!
+//! The `ConstEvaluator` folds an `Expression` into a `Value` when all of the
+//! terminals and component references it contains are known at compile time.
+//! Component references are looked up by name in the `values` table, which
+//! callers populate with the parameters and constants they have already
+//! resolved (for example, the loop index when unrolling a `for` equation).
+//!
+//! # Semantics
+//! - `Integer` arithmetic stays integral for `+`, `-` and `*`, and for `^`
+//!   with a non-negative exponent.
+//! - `/` always produces a `Real`, as required by the Modelica specification.
+//! - `div`, `mod` and `rem` keep `Integer` operands integral. `div`
+//!   truncates towards zero, `mod(x, y) = x - floor(x/y)*y` takes the sign of
+//!   `y`, and `rem(x, y) = x - div(x, y)*y` takes the sign of `x`.
+//! - Relational and logical operators produce a `Bool`.
+//! - `and`/`or` short-circuit, and only the taken branch of an `if`
+//!   expression is evaluated, so a guarded `1/a` is never computed when the
+//!   guard is false.
+//!
+//! Well-known library constants, such as `Modelica.Constants.pi`, are
+//! resolved from the `constants` table, which defaults to
+//! `well_known_constants`. An unknown `Modelica.Constants` reference is an
+//! error rather than an unknown variable.
+//!
+//! `StoredDefinition::constant_env` evaluates the `constant` components of
+//! all classes of a definition, by qualified name, so that passes such as
+//! `flatten` can resolve references like `P.g` to a constant of another
+//! class. A constant may refer to the constants of its own class and of its
+//! enclosing classes by their short name.
+//!
+//! Expressions that cannot be evaluated (unknown references, unsupported
+//! operators or function calls) produce an error, so callers can fall back to
+//! keeping the symbolic expression.
+use crate::ir::ast::{
+    ClassDefinition, Component, Expression, OpBinary, OpUnary, StoredDefinition, TerminalType,
+    Token, Variability,
+};
+use anyhow::{Result, anyhow};
+use indexmap::{IndexMap, IndexSet};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Real(f64),
+    Bool(bool),
+}
+
+impl Value {
+    pub fn as_f64(&self) -> Result<f64> {
+        match self {
+            Value::Integer(v) => Ok(*v as f64),
+            Value::Real(v) => Ok(*v),
+            Value::Bool(_) => Err(anyhow!("expected a numeric value, found {:?}", self)),
+        }
+    }
+
+    pub fn as_i64(&self) -> Result<i64> {
+        match self {
+            Value::Integer(v) => Ok(*v),
+            _ => Err(anyhow!("expected an Integer value, found {:?}", self)),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool> {
+        match self {
+            Value::Bool(v) => Ok(*v),
+            _ => Err(anyhow!("expected a Boolean value, found {:?}", self)),
+        }
+    }
+
+    /// Converts the value back into a literal expression, using a unary minus
+    /// for negative numbers since terminals are unsigned.
+    pub fn to_expression(&self) -> Expression {
+        let (terminal_type, text, negative) = match self {
+            Value::Integer(v) => (
+                TerminalType::UnsignedInteger,
+                v.unsigned_abs().to_string(),
+                *v < 0,
+            ),
+            Value::Real(v) => (
+                TerminalType::UnsignedReal,
+                format!("{:?}", v.abs()),
+                *v < 0.0,
+            ),
+            Value::Bool(v) => (TerminalType::Bool, v.to_string(), false),
+        };
+        let terminal = Expression::Terminal {
+            terminal_type,
+            token: Token {
+                text,
+                ..Default::default()
+            },
+        };
+        if negative {
+            Expression::Unary {
+                op: OpUnary::Minus(Token {
+                    text: "-".to_string(),
+                    ..Default::default()
+                }),
+                rhs: Box::new(terminal),
+            }
+        } else {
+            terminal
+        }
+    }
+}
+
+/// Values of the constants of the Modelica Standard Library that may be
+/// referenced by their qualified name.
+pub fn well_known_constants() -> IndexMap<String, Value> {
+    IndexMap::from([
+        (
+            "Modelica.Constants.pi".to_string(),
+            Value::Real(std::f64::consts::PI),
+        ),
+        (
+            "Modelica.Constants.e".to_string(),
+            Value::Real(std::f64::consts::E),
+        ),
+        ("Modelica.Constants.inf".to_string(), Value::Real(1e60)),
+        ("Modelica.Constants.small".to_string(), Value::Real(1e-60)),
+        ("Modelica.Constants.eps".to_string(), Value::Real(1e-15)),
+    ])
+}
+
+/// Sorts the parameters and constants so that every one of them comes after
+/// the parameters and constants its start expression refers to, keeping the
+/// declaration order otherwise.
+fn parameter_order(components: &IndexMap<String, Component>) -> Result<Vec<String>> {
+    fn visit(
+        name: &str,
+        dependencies: &IndexMap<String, Vec<String>>,
+        visiting: &mut IndexSet<String>,
+        order: &mut IndexSet<String>,
+    ) -> Result<()> {
+        if order.contains(name) {
+            return Ok(());
+        }
+        if !visiting.insert(name.to_string()) {
+            let cycle: Vec<&str> = visiting.iter().map(|s| s.as_str()).collect();
+            return Err(anyhow!(
+                "cyclic parameter dependency {} -> {}",
+                cycle.join(" -> "),
+                name
+            ));
+        }
+        for dependency in &dependencies[name] {
+            visit(dependency, dependencies, visiting, order)?;
+        }
+        visiting.pop();
+        order.insert(name.to_string());
+        Ok(())
+    }
+
+    let parameters: IndexMap<&String, &Component> = components
+        .iter()
+        .filter(|(_, comp)| {
+            matches!(
+                comp.variability,
+                Variability::Parameter(..) | Variability::Constant(..)
+            )
+        })
+        .collect();
+    let dependencies: IndexMap<String, Vec<String>> = parameters
+        .iter()
+        .map(|(name, comp)| {
+            let names = comp
+                .start
+                .free_variables()
+                .iter()
+                .map(|cref| cref.to_string())
+                .filter(|dependency| parameters.contains_key(dependency))
+                .collect();
+            ((*name).clone(), names)
+        })
+        .collect();
+    let mut order = IndexSet::new();
+    for name in dependencies.keys() {
+        visit(name, &dependencies, &mut IndexSet::new(), &mut order)?;
+    }
+    Ok(order.into_iter().collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstEvaluator {
+    pub values: IndexMap<String, Value>,
+    pub constants: IndexMap<String, Value>,
+}
+
+impl Default for ConstEvaluator {
+    fn default() -> Self {
+        ConstEvaluator {
+            values: IndexMap::new(),
+            constants: well_known_constants(),
+        }
+    }
+}
+
+impl ConstEvaluator {
+    /// Adds the values of all parameters and constants whose start expression
+    /// can be evaluated. Parameters may be declared after the parameters
+    /// they depend on, so they are evaluated in dependency order, and a
+    /// cyclic dependency is an error.
+    pub fn add_components(&mut self, components: &IndexMap<String, Component>) -> Result<()> {
+        for name in parameter_order(components)? {
+            if let Ok(value) = self.eval(&components[&name].start) {
+                self.values.insert(name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates the elements of the range `start:step:end`. Integer ranges
+    /// stay integral, otherwise the elements are the `Real` values
+    /// `start + k*step` for `k` up to `floor((end - start)/step)`.
+    pub fn eval_range(
+        &self,
+        start: &Expression,
+        step: Option<&Expression>,
+        end: &Expression,
+    ) -> Result<Vec<Value>> {
+        let start = self.eval(start)?;
+        let step = match step {
+            Some(step) => self.eval(step)?,
+            None => Value::Integer(1),
+        };
+        let end = self.eval(end)?;
+        match (&start, &step, &end) {
+            (Value::Integer(start), Value::Integer(step), Value::Integer(end)) => {
+                if *step == 0 {
+                    return Err(anyhow!("range has a zero step"));
+                }
+                let mut values = Vec::new();
+                let mut i = *start;
+                while (*step > 0 && i <= *end) || (*step < 0 && i >= *end) {
+                    values.push(Value::Integer(i));
+                    i += step;
+                }
+                Ok(values)
+            }
+            _ => {
+                let (start, step, end) = (start.as_f64()?, step.as_f64()?, end.as_f64()?);
+                if step == 0.0 {
+                    return Err(anyhow!("range has a zero step"));
+                }
+                // tolerate rounding, so that 0:0.1:0.3 includes 0.3
+                let n = ((end - start) / step + 1e-9).floor();
+                if n < 0.0 {
+                    return Ok(vec![]);
+                }
+                Ok((0..=n as i64)
+                    .map(|k| Value::Real(start + k as f64 * step))
+                    .collect())
+            }
+        }
+    }
+
+    pub fn eval(&self, expr: &Expression) -> Result<Value> {
+        match expr {
+            Expression::Terminal {
+                terminal_type,
+                token,
+            } => match terminal_type {
+                TerminalType::UnsignedInteger => Ok(Value::Integer(token.text.parse()?)),
+                TerminalType::UnsignedReal => Ok(Value::Real(token.text.parse()?)),
+                TerminalType::Bool => Ok(Value::Bool(token.text == "true")),
+                _ => Err(anyhow!("cannot evaluate terminal {:?}", token)),
+            },
+            Expression::ComponentReference(cref) => {
+                let name = cref.to_string();
+                match self.values.get(&name).or_else(|| self.constants.get(&name)) {
+                    Some(value) => Ok(value.clone()),
+                    None if name.starts_with("Modelica.Constants.") => {
+                        Err(anyhow!("unknown library constant '{}'", name))
+                    }
+                    None => Err(anyhow!("'{}' is not a known constant", cref)),
+                }
+            }
+            Expression::Unary { op, rhs } => {
+                let rhs = self.eval(rhs)?;
+                match (op, rhs) {
+                    (OpUnary::Minus(..) | OpUnary::DotMinus(..), Value::Integer(v)) => {
+                        Ok(Value::Integer(-v))
+                    }
+                    (OpUnary::Minus(..) | OpUnary::DotMinus(..), Value::Real(v)) => {
+                        Ok(Value::Real(-v))
+                    }
+                    (OpUnary::Plus(..) | OpUnary::DotPlus(..), v) => Ok(v),
+                    (OpUnary::Not(..), Value::Bool(v)) => Ok(Value::Bool(!v)),
+                    (op, v) => Err(anyhow!("cannot apply {:?} to {:?}", op, v)),
+                }
+            }
+            Expression::Binary { op, lhs, rhs } => {
+                let lhs = self.eval(lhs)?;
+                // short-circuit logical operators, the rhs is only evaluated when needed
+                match (op, &lhs) {
+                    (OpBinary::And(..), Value::Bool(false)) => return Ok(Value::Bool(false)),
+                    (OpBinary::Or(..), Value::Bool(true)) => return Ok(Value::Bool(true)),
+                    _ => {}
+                }
+                let rhs = self.eval(rhs)?;
+                eval_binary(op, &lhs, &rhs)
+            }
+            Expression::If {
+                branches,
+                else_branch,
+            } => {
+                // only the taken branch is evaluated
+                for (cond, then) in branches {
+                    if self.eval(cond)?.as_bool()? {
+                        return self.eval(then);
+                    }
+                }
+                self.eval(else_branch)
+            }
+            Expression::FunctionCall { comp, args } => {
+                let name = comp.to_string();
+                match (name.as_str(), args.as_slice()) {
+                    ("div" | "mod" | "rem", [lhs, rhs]) => {
+                        eval_division(&name, &self.eval(lhs)?, &self.eval(rhs)?)
+                    }
+                    _ => Err(anyhow!("cannot evaluate function call '{}'", name)),
+                }
+            }
+            _ => Err(anyhow!("cannot evaluate {:?}", expr)),
+        }
+    }
+}
+
+impl StoredDefinition {
+    /// Returns the values of all constants that can be evaluated, by their
+    /// qualified name. Booleans are left out.
+    pub fn constant_env(&self) -> HashMap<String, f64> {
+        self.constant_values()
+            .into_iter()
+            .filter_map(|(name, value)| value.as_f64().ok().map(|v| (name, v)))
+            .collect()
+    }
+
+    /// Returns the values of all constants that can be evaluated, by their
+    /// qualified name, such as `P.g` for the constant `g` of the package
+    /// `P`. Constants are evaluated until no more of them can be resolved,
+    /// so they may refer to constants declared later or in other classes.
+    pub fn constant_values(&self) -> IndexMap<String, Value> {
+        fn collect<'a>(
+            name: String,
+            class: &'a ClassDefinition,
+            classes: &mut Vec<(String, &'a ClassDefinition)>,
+        ) {
+            for (nested_name, nested) in &class.classes {
+                collect(format!("{}.{}", name, nested_name), nested, classes);
+            }
+            classes.push((name, class));
+        }
+        let mut classes = Vec::new();
+        for (name, class) in &self.class_list {
+            collect(name.clone(), class, &mut classes);
+        }
+
+        let mut values: IndexMap<String, Value> = IndexMap::new();
+        loop {
+            let mut resolved = false;
+            for (class_name, class) in &classes {
+                let mut evaluator = ConstEvaluator::default();
+                evaluator.constants.extend(values.clone());
+                // short names of the constants of the class and of its
+                // enclosing classes, the innermost declaration wins
+                let mut scope = Some(class_name.as_str());
+                while let Some(prefix) = scope {
+                    for (name, value) in &values {
+                        if let Some(short) =
+                            name.strip_prefix(prefix).and_then(|n| n.strip_prefix('.'))
+                            && !short.contains('.')
+                        {
+                            evaluator
+                                .values
+                                .entry(short.to_string())
+                                .or_insert_with(|| value.clone());
+                        }
+                    }
+                    scope = prefix.rsplit_once('.').map(|(parent, _)| parent);
+                }
+                for (name, comp) in &class.components {
+                    let qualified = format!("{}.{}", class_name, name);
+                    if !matches!(comp.variability, Variability::Constant(..))
+                        || values.contains_key(&qualified)
+                    {
+                        continue;
+                    }
+                    if let Ok(value) = evaluator.eval(&comp.start) {
+                        evaluator.values.insert(name.clone(), value.clone());
+                        values.insert(qualified, value);
+                        resolved = true;
+                    }
+                }
+            }
+            if !resolved {
+                return values;
+            }
+        }
+    }
+}
+
+/// Evaluates the built-in `div`, `mod` and `rem` functions.
+fn eval_division(name: &str, lhs: &Value, rhs: &Value) -> Result<Value> {
+    match (lhs, rhs) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            if *b == 0 {
+                return Err(anyhow!("Integer division by zero evaluating {}", name));
+            }
+            let v = match name {
+                "div" => a.checked_div(*b),
+                "rem" => a.checked_rem(*b),
+                _ => a.checked_rem(*b).map(|r| {
+                    if r != 0 && (r < 0) != (*b < 0) {
+                        r + b
+                    } else {
+                        r
+                    }
+                }),
+            };
+            v.map(Value::Integer)
+                .ok_or_else(|| anyhow!("Integer overflow evaluating {}", name))
+        }
+        _ => {
+            let (a, b) = (lhs.as_f64()?, rhs.as_f64()?);
+            Ok(Value::Real(match name {
+                "div" => (a / b).trunc(),
+                "rem" => a % b,
+                _ => a - (a / b).floor() * b,
+            }))
+        }
+    }
+}
+
+fn eval_binary(op: &OpBinary, lhs: &Value, rhs: &Value) -> Result<Value> {
+    match op {
+        OpBinary::And(..) => Ok(Value::Bool(lhs.as_bool()? && rhs.as_bool()?)),
+        OpBinary::Or(..) => Ok(Value::Bool(lhs.as_bool()? || rhs.as_bool()?)),
+        OpBinary::Eq(..) => Ok(Value::Bool(lhs.as_f64()? == rhs.as_f64()?)),
+        OpBinary::Neq(..) => Ok(Value::Bool(lhs.as_f64()? != rhs.as_f64()?)),
+        OpBinary::Lt(..) => Ok(Value::Bool(lhs.as_f64()? < rhs.as_f64()?)),
+        OpBinary::Le(..) => Ok(Value::Bool(lhs.as_f64()? <= rhs.as_f64()?)),
+        OpBinary::Gt(..) => Ok(Value::Bool(lhs.as_f64()? > rhs.as_f64()?)),
+        OpBinary::Ge(..) => Ok(Value::Bool(lhs.as_f64()? >= rhs.as_f64()?)),
+        OpBinary::Div(..) | OpBinary::DivElem(..) => Ok(Value::Real(lhs.as_f64()? / rhs.as_f64()?)),
+        OpBinary::Add(..)
+        | OpBinary::AddElem(..)
+        | OpBinary::Sub(..)
+        | OpBinary::SubElem(..)
+        | OpBinary::Mul(..)
+        | OpBinary::MulElem(..) => match (lhs, rhs) {
+            (Value::Integer(a), Value::Integer(b)) => {
+                let v = match op {
+                    OpBinary::Add(..) | OpBinary::AddElem(..) => a.checked_add(*b),
+                    OpBinary::Sub(..) | OpBinary::SubElem(..) => a.checked_sub(*b),
+                    _ => a.checked_mul(*b),
+                };
+                v.map(Value::Integer)
+                    .ok_or_else(|| anyhow!("Integer overflow evaluating {:?}", op))
+            }
+            _ => {
+                let (a, b) = (lhs.as_f64()?, rhs.as_f64()?);
+                Ok(Value::Real(match op {
+                    OpBinary::Add(..) | OpBinary::AddElem(..) => a + b,
+                    OpBinary::Sub(..) | OpBinary::SubElem(..) => a - b,
+                    _ => a * b,
+                }))
+            }
+        },
+        OpBinary::Exp(..) => match (lhs, rhs) {
+            (Value::Integer(a), Value::Integer(b)) if *b >= 0 => u32::try_from(*b)
+                .ok()
+                .and_then(|b| a.checked_pow(b))
+                .map(Value::Integer)
+                .ok_or_else(|| anyhow!("Integer overflow evaluating {:?}", op)),
+            _ => Ok(Value::Real(lhs.as_f64()?.powf(rhs.as_f64()?))),
+        },
+        OpBinary::Empty => Err(anyhow!("cannot evaluate empty binary operator")),
+    }
+}
@@ -4,33 +4,77 @@
 //! manipulate DAE-related constructs within the application.
 use crate::dae::ast::Dae;
 use crate::ir::ast::{
-    Causality, ClassDefinition, Component, Equation, Expression, Name, Statement, Token,
+    Causality, ClassDefinition, Component, ComponentRefPart, ComponentReference, Equation,
+    EquationBlock, Expression, Name, StateSelect, Statement, Subscript, TerminalType, Token,
     Variability,
 };
+use crate::ir::const_eval::{ConstEvaluator, Value};
+use crate::ir::flatten::FlattenOptions;
+use crate::ir::types::Type;
 use crate::ir::visitor::Visitable;
 use crate::ir::visitors::condition_finder::ConditionFinder;
+use crate::ir::visitors::enumeration_coder::EnumerationCoder;
+use crate::ir::visitors::for_expander::ForExpander;
+use crate::ir::visitors::index_substituter::IndexSubstituter;
 use crate::ir::visitors::state_finder::StateFinder;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use indexmap::{IndexMap, IndexSet};
 
 use super::visitors::pre_finder::PreFinder;
 
 pub fn create_dae(fclass: &mut ClassDefinition) -> Result<Dae> {
-    // create default Dae struct
+    create_dae_with_options(fclass, &FlattenOptions::default())
+}
+
+/// Creates the DAE of the flat class `fclass`. Unless the arrays were kept
+/// whole by `options.preserve_arrays`, each array variable becomes one scalar
+/// variable per element, named like the reference to the element, `x[1]`.
+pub fn create_dae_with_options(
+    fclass: &mut ClassDefinition,
+    options: &FlattenOptions,
+) -> Result<Dae> {
+    // create default Dae struct, with time as the independent variable
+    if fclass.components.contains_key("time") {
+        return Err(anyhow!(
+            "'time' is reserved for the independent variable and cannot be declared"
+        ));
+    }
     let mut dae = Dae {
         t: Component {
-            name: "t".to_string(),
+            name: "time".to_string(),
             type_name: Name {
                 name: vec![Token {
                     text: "Real".to_string(),
                     ..Default::default()
                 }],
             },
+            description: vec![Token {
+                text: "independent variable".to_string(),
+                ..Default::default()
+            }],
+            start: Expression::Terminal {
+                terminal_type: TerminalType::UnsignedReal,
+                token: Token {
+                    text: "0.0".to_string(),
+                    ..Default::default()
+                },
+            },
             ..Default::default()
         },
         ..Default::default()
     };
 
+    // algorithm sections become equations, so that they are classified and
+    // rewritten along with the equations of the class
+    let algorithm_equations =
+        algorithm_equations(&fclass.algorithms, &fclass.components, &fclass.classes)?;
+    fclass.equations.extend(algorithm_equations);
+    fclass.algorithms.clear();
+    if !options.preserve_arrays {
+        split_arrays(fclass)?;
+    }
+
     // run statefinder to find states and replace
     // derivative references
     let mut state_finder = StateFinder::default();
@@ -40,6 +84,64 @@ pub fn create_dae(fclass: &mut ClassDefinition) -> Result<Dae> {
     let mut condition_finder = ConditionFinder::default();
     fclass.accept(&mut condition_finder);
 
+    // code enumeration literals as integers, starting at 1
+    for (name, class) in &fclass.classes {
+        if !class.enumeration_literals.is_empty() {
+            let literals = class
+                .enumeration_literals
+                .iter()
+                .enumerate()
+                .map(|(i, literal)| (literal.ident.text.clone(), i as i64 + 1))
+                .collect();
+            dae.enumerations.insert(name.clone(), literals);
+        }
+    }
+    let mut enumeration_coder = EnumerationCoder {
+        enumerations: dae.enumerations.clone(),
+    };
+    fclass.accept(&mut enumeration_coder);
+    for comp in fclass.components.values_mut() {
+        if dae.enumerations.contains_key(&comp.type_name.to_string())
+            && comp.start == Expression::Empty
+        {
+            comp.start = Value::Integer(1).to_expression();
+        }
+        comp.start.accept(&mut enumeration_coder);
+    }
+
+    // solver and unit attributes
+    for comp in fclass.components.values_mut() {
+        if let Some(nominal) = comp.modifications.get("nominal") {
+            comp.nominal = nominal.clone();
+        }
+        comp.unit = string_attribute(comp, "unit")?;
+        comp.quantity = string_attribute(comp, "quantity")?;
+        comp.display_unit = string_attribute(comp, "displayUnit")?;
+        if let Some(state_select) = comp.modifications.get("stateSelect") {
+            let name = match state_select {
+                Expression::ComponentReference(cref) => cref.to_string(),
+                _ => String::new(),
+            };
+            comp.state_select = match name.as_str() {
+                "StateSelect.never" => StateSelect::Never,
+                "StateSelect.avoid" => StateSelect::Avoid,
+                "StateSelect.default" => StateSelect::Default,
+                "StateSelect.prefer" => StateSelect::Prefer,
+                "StateSelect.always" => StateSelect::Always,
+                _ => {
+                    return Err(anyhow!(
+                        "invalid stateSelect {:?} of '{}'",
+                        state_select,
+                        comp.name
+                    ));
+                }
+            };
+        }
+    }
+
+    // variables assigned in when equations are discrete, as are enumerations
+    let when_assigned = when_assigned_variables(&fclass.equations);
+
     // handle components
     for (_, comp) in &fclass.components {
         match comp.variability {
@@ -50,19 +152,28 @@ pub fn create_dae(fclass: &mut ClassDefinition) -> Result<Dae> {
                 dae.cp.push(comp.clone());
             }
             Variability::Discrete(..) => {
-                dae.m.push(comp.clone());
+                add_discrete_component(comp, &mut dae);
             }
             Variability::Empty => {
-                if state_finder.states.contains(&comp.name) {
+                if when_assigned.contains(&comp.name)
+                    || dae.enumerations.contains_key(&comp.type_name.to_string())
+                {
+                    add_discrete_component(comp, &mut dae);
+                } else if is_state(&comp.name, &state_finder.states) {
                     dae.x.push(comp.clone());
                     let mut der_comp = comp.clone();
                     der_comp.name = format!("der_{}", comp.name);
                     dae.x_dot.push(der_comp);
                 } else {
+                    // only the inputs of the model itself are inputs of the
+                    // DAE, the inputs of its components are connected
                     match comp.causality {
-                        Causality::Input(..) => {
+                        Causality::Input(..) if comp.qualified_name.is_none() => {
                             dae.u.push(comp.clone());
                         }
+                        Causality::Input(..) => {
+                            dae.y.push(comp.clone());
+                        }
                         Causality::Output(..) => {
                             dae.y.push(comp.clone());
                         }
@@ -75,12 +186,49 @@ pub fn create_dae(fclass: &mut ClassDefinition) -> Result<Dae> {
         }
     }
 
+    // a higher derivative, der(der(x)), makes the derivative der_x a state
+    // itself, the states found in order of increasing derivative
+    for name in &state_finder.states {
+        if fclass.components.contains_key(name) {
+            continue;
+        }
+        let Some(state) = name
+            .strip_prefix("der_")
+            .and_then(|base| dae.x.iter().find(|comp| comp.name == base))
+        else {
+            continue;
+        };
+        let mut comp = state.clone();
+        comp.name = name.clone();
+        comp.start = Type::from_name(&comp.type_name.to_string()).default_start();
+        comp.modifications.clear();
+        comp.qualified_name = None;
+        let mut der_comp = comp.clone();
+        der_comp.name = format!("der_{}", name);
+        dae.x.push(comp);
+        dae.x_dot.push(der_comp);
+    }
+
     // handle pre
     let mut pre_finder = PreFinder::default();
     fclass.accept(&mut pre_finder);
     add_pre_components(&dae.x, &mut dae.pre_x);
     add_pre_components(&dae.m, &mut dae.pre_m);
     add_pre_components(&dae.z, &mut dae.pre_z);
+    for name in &pre_finder.outside_when {
+        if !dae
+            .z
+            .iter()
+            .chain(dae.m.iter())
+            .any(|comp| &comp.name == name)
+        {
+            return Err(anyhow!(
+                "pre({}) outside of a when equation requires '{}' to be discrete",
+                name,
+                name
+            ));
+        }
+    }
 
     // handle conditions and relations
     dae.c = condition_finder.conditions.clone();
@@ -96,36 +244,104 @@ pub fn create_dae(fclass: &mut ClassDefinition) -> Result<Dae> {
                 dae.fx.push(eq.clone());
             }
             Equation::Connect { .. } => {
-                panic!("connection equations should already by expanded in flatten")
+                // only left when flattening without expanding connections
+                dae.fx.push(eq.clone());
             }
             Equation::When(blocks) => {
-                for block in blocks {
+                for (i, block) in blocks.iter().enumerate() {
                     for eq in &block.eqs {
+                        // keep the condition with each event update equation,
+                        // preceded by the conditions of the earlier branches, as
+                        // only the first true branch of a when/elsewhen is active
+                        let mut update_blocks: Vec<EquationBlock> = blocks[..i]
+                            .iter()
+                            .map(|prior| EquationBlock {
+                                cond: prior.cond.clone(),
+                                eqs: vec![],
+                            })
+                            .collect();
+                        update_blocks.push(EquationBlock {
+                            cond: block.cond.clone(),
+                            eqs: vec![eq.clone()],
+                        });
+                        let update = Equation::When(update_blocks);
                         match eq {
                             Equation::FunctionCall { comp, args } => {
                                 let name = comp.to_string();
                                 if name == "reinit" {
-                                    let cond_name = match &block.cond {
-                                        Expression::ComponentReference(cref) => cref.to_string(),
-                                        _ => todo!("handle other condition types"),
+                                    let location = &comp.parts[0].ident.location;
+                                    let Expression::ComponentReference(cond) = &block.cond else {
+                                        return Err(anyhow!(
+                                            "condition of the reinit at {}:{}:{} was not replaced by a condition variable",
+                                            location.file_name,
+                                            location.start_line,
+                                            location.start_column
+                                        ));
                                     };
+                                    let cond_name = cond.to_string();
                                     if args.len() != 2 {
-                                        panic!("reinit function call must have two arguments");
+                                        return Err(anyhow!(
+                                            "reinit at {}:{}:{} must have two arguments",
+                                            location.file_name,
+                                            location.start_line,
+                                            location.start_column
+                                        ));
                                     }
-                                    match &args[0] {
-                                        Expression::ComponentReference(cref) => {
-                                            dae.fr.insert(cond_name, Statement::Assignment {
-                                                comp: cref.clone(),
-                                                value: args[1].clone(),
-                                            });
-                                        }
-                                        _ => panic!(
-                                            "first argument of reinit must be a component reference"
-                                        ),
+                                    let Expression::ComponentReference(cref) = &args[0] else {
+                                        return Err(anyhow!(
+                                            "first argument of reinit at {}:{}:{} must be a component reference",
+                                            location.file_name,
+                                            location.start_line,
+                                            location.start_column
+                                        ));
+                                    };
+                                    // the reset is an event update of the state, which
+                                    // stays continuous, resetting a parameter or an
+                                    // algebraic variable is a modeling error
+                                    if !dae.x.iter().any(|comp| comp.name == cref.to_string()) {
+                                        let location = &cref.parts[0].ident.location;
+                                        return Err(anyhow!(
+                                            "reinit of '{}' at {}:{}:{} requires a continuous state",
+                                            cref,
+                                            location.file_name,
+                                            location.start_line,
+                                            location.start_column
+                                        ));
                                     }
+                                    dae.fr.insert(cond_name, Statement::Assignment {
+                                        comp: cref.clone(),
+                                        value: args[1].clone(),
+                                    });
+                                    dae.fz.push(update);
+                                }
+                            }
+                            Equation::Simple { lhs, .. } => {
+                                let Expression::ComponentReference(cref) = lhs else {
+                                    return Err(anyhow!(
+                                        "when equation must assign a variable, found {:?}",
+                                        lhs
+                                    ));
+                                };
+                                let name = cref.to_string();
+                                // Real discrete updates go to fz, Boolean, Integer
+                                // and enumeration updates to fm
+                                if dae.z.iter().any(|comp| comp.name == name) {
+                                    dae.fz.push(update);
+                                } else if dae.m.iter().any(|comp| comp.name == name) {
+                                    dae.fm.push(update);
+                                } else {
+                                    return Err(anyhow!(
+                                        "when equation assigns '{}', which is not a discrete variable",
+                                        name
+                                    ));
                                 }
                             }
-                            _ => todo!("handle other equation types"),
+                            _ => {
+                                return Err(anyhow!(
+                                    "unsupported equation in when equation {:?}",
+                                    eq
+                                ));
+                            }
                         }
                     }
                 }
@@ -133,9 +349,516 @@ pub fn create_dae(fclass: &mut ClassDefinition) -> Result<Dae> {
             _ => {}
         }
     }
+
+    // initial equations, with derivatives named as in fx, and if equations
+    // whose conditions are parameter expressions reduced to their branches
+    let mut initial_state_finder = StateFinder::default();
+    for eq in &mut fclass.initial_equations {
+        eq.accept(&mut initial_state_finder);
+        eq.accept(&mut enumeration_coder);
+    }
+    let mut evaluator = ConstEvaluator::default();
+    evaluator.add_components(&fclass.components)?;
+    dae.fi = initial_equations(&fclass.initial_equations, &evaluator);
+
+    dae.index_outputs();
     Ok(dae)
 }
 
+/// Returns whether the variable `name` is a state, because it is derived
+/// itself or, for an array kept whole, one of its elements is.
+fn is_state(name: &str, states: &IndexSet<String>) -> bool {
+    let element = format!("{}[", name);
+    states
+        .iter()
+        .any(|state| state == name || state.starts_with(&element))
+}
+
+/// Replaces each array component of constant dimensions by one component
+/// per element, in row-major order. An element takes its start value from
+/// an array start value, and a scalar start value applies to all elements.
+fn split_arrays(fclass: &mut ClassDefinition) -> Result<()> {
+    let mut evaluator = ConstEvaluator::default();
+    evaluator.add_components(&fclass.components)?;
+    let mut components = IndexMap::new();
+    for (name, comp) in fclass.components.drain(..) {
+        let shape: Option<Vec<i64>> = comp
+            .shape
+            .iter()
+            .map(|sub| match sub {
+                Subscript::Expression(expr) => evaluator.eval(expr).and_then(|v| v.as_i64()).ok(),
+                _ => None,
+            })
+            .collect();
+        let Some(shape) = shape.filter(|shape| !shape.is_empty()) else {
+            components.insert(name, comp);
+            continue;
+        };
+        let mut position = vec![1; shape.len()];
+        'elements: loop {
+            let cref = position
+                .iter()
+                .fold(ComponentReference::new(&name), |cref, &i| {
+                    cref.with_subscript(Subscript::Expression(Value::Integer(i).to_expression()))
+                });
+            let mut element = comp.clone();
+            element.name = cref.to_string();
+            element.shape.clear();
+            element.start = array_element(&comp.start, &position);
+            if let Some(start) = element.modifications.get_mut("start") {
+                *start = array_element(start, &position);
+            }
+            element.nominal = array_element(&comp.nominal, &position);
+            element.qualified_name = comp
+                .qualified_name
+                .as_ref()
+                .map(|qualified_name| format!("{}{}", qualified_name, &element.name[name.len()..]));
+            components.insert(element.name.clone(), element);
+            for dim in (0..position.len()).rev() {
+                position[dim] += 1;
+                if position[dim] <= shape[dim] {
+                    continue 'elements;
+                }
+                position[dim] = 1;
+            }
+            break;
+        }
+    }
+    fclass.components = components;
+    Ok(())
+}
+
+/// Returns the element of an array literal at the one-based `position`, any
+/// other value is the value of every element.
+fn array_element(value: &Expression, position: &[i64]) -> Expression {
+    match (value, position.split_first()) {
+        (Expression::Array { elements }, Some((&i, rest))) => {
+            match usize::try_from(i - 1).ok().and_then(|i| elements.get(i)) {
+                Some(element) => array_element(element, rest),
+                None => value.clone(),
+            }
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Returns the initial equations, replacing each if equation whose active
+/// branch is known from its parameter conditions by the equations of that
+/// branch. Other if equations stay conditional, without the branches whose
+/// condition is known to be false.
+fn initial_equations(equations: &[Equation], evaluator: &ConstEvaluator) -> Vec<Equation> {
+    let mut initial = Vec::new();
+    for eq in equations {
+        let Equation::If {
+            cond_blocks,
+            else_block,
+        } = eq
+        else {
+            initial.push(eq.clone());
+            continue;
+        };
+        let mut blocks = Vec::new();
+        let mut else_eqs = else_block.clone();
+        for block in cond_blocks {
+            match evaluator
+                .eval(&block.cond)
+                .and_then(|value| value.as_bool())
+            {
+                Ok(false) => {}
+                // the branch is taken unless an earlier condition holds
+                Ok(true) => {
+                    else_eqs = Some(block.eqs.clone());
+                    break;
+                }
+                Err(_) => blocks.push(EquationBlock {
+                    cond: block.cond.clone(),
+                    eqs: initial_equations(&block.eqs, evaluator),
+                }),
+            }
+        }
+        let else_eqs = else_eqs.map(|eqs| initial_equations(&eqs, evaluator));
+        if blocks.is_empty() {
+            initial.extend(else_eqs.unwrap_or_default());
+        } else {
+            initial.push(Equation::If {
+                cond_blocks: blocks,
+                else_block: else_eqs,
+            });
+        }
+    }
+    initial
+}
+
+/// Executes the algorithm sections in order. Every variable assigned outside
+/// of a when statement gets one equation with its final value, with earlier
+/// assignments substituted into later ones, so `x := 1; y := x + 1; x := 2`
+/// gives `y = 1 + 1` and `x = 2`. When statements become when equations, and
+/// `for` statements are unrolled over their constant ranges beforehand, and
+/// assignments to array slices are split into one assignment per element.
+///
+/// A variable read before the algorithm assigns it has its value from before
+/// the algorithm, so `x := x + 1` does not give the unsolvable `x = x + 1`:
+/// `pre(x)` for a `discrete` variable, and its start value, or 0, otherwise.
+fn algorithm_equations(
+    sections: &[Vec<Statement>],
+    components: &IndexMap<String, Component>,
+    classes: &IndexMap<String, ClassDefinition>,
+) -> Result<Vec<Equation>> {
+    let mut for_expander = ForExpander::default();
+    for_expander.evaluator.add_components(components)?;
+    for_expander.add_enumerations(classes);
+    let stmts = unroll_for_statements(&sections.concat(), &for_expander)?;
+    let stmts = stmts
+        .iter()
+        .map(|stmt| slice_assignments(stmt, components, &for_expander.evaluator))
+        .collect::<Result<Vec<_>>>()?
+        .concat();
+
+    let assigned: IndexMap<String, ComponentReference> = stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::Assignment { comp, .. } => Some((comp.to_string(), comp.clone())),
+            _ => None,
+        })
+        .collect();
+    let mut values: IndexMap<String, (ComponentReference, Expression)> = IndexMap::new();
+    let mut when_equations = Vec::new();
+    for stmt in &stmts {
+        match stmt {
+            Statement::Empty => {}
+            Statement::Assignment { comp, value } => {
+                let mut value = value.clone();
+                for (name, cref) in &assigned {
+                    let assigned = match values.get(name) {
+                        Some((_, assigned)) => assigned.clone(),
+                        None => value_before_algorithm(cref, components, &for_expander.evaluator),
+                    };
+                    value.accept(&mut IndexSubstituter {
+                        index: name.clone(),
+                        value: assigned,
+                    });
+                }
+                values.insert(comp.to_string(), (comp.clone(), value));
+            }
+            Statement::When(blocks) => {
+                let blocks = blocks
+                    .iter()
+                    .map(|block| {
+                        Ok(EquationBlock {
+                            cond: block.cond.clone(),
+                            eqs: block
+                                .stmts
+                                .iter()
+                                .map(when_equation)
+                                .collect::<Result<Vec<_>>>()?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                when_equations.push(Equation::When(blocks));
+            }
+            _ => return Err(anyhow!("unsupported algorithm statement {:?}", stmt)),
+        }
+    }
+    let mut equations: Vec<Equation> = values
+        .into_values()
+        .map(|(comp, value)| Equation::Simple {
+            lhs: Expression::ComponentReference(comp),
+            rhs: value,
+            origin: None,
+        })
+        .collect();
+    equations.append(&mut when_equations);
+    Ok(equations)
+}
+
+/// Returns the value of `cref` before an algorithm assigns it, `pre(cref)` for
+/// a `discrete` variable, and its start value, or 0, otherwise. The start
+/// value of an array element is the element of an array literal start value.
+fn value_before_algorithm(
+    cref: &ComponentReference,
+    components: &IndexMap<String, Component>,
+    evaluator: &ConstEvaluator,
+) -> Expression {
+    let mut whole = cref.clone();
+    let subs = whole.parts.last_mut().and_then(|part| part.subs.take());
+    let Some(comp) = components
+        .get(&cref.to_string())
+        .or_else(|| components.get(&whole.to_string()))
+    else {
+        return real_zero();
+    };
+    if let Variability::Discrete(..) = comp.variability {
+        return Expression::FunctionCall {
+            comp: ComponentReference {
+                local: false,
+                parts: vec![ComponentRefPart {
+                    ident: Token {
+                        text: "pre".to_string(),
+                        ..Default::default()
+                    },
+                    subs: None,
+                }],
+            },
+            args: vec![Expression::ComponentReference(cref.clone())],
+        };
+    }
+    let mut start = &comp.start;
+    if comp.name != cref.to_string() {
+        for sub in subs.iter().flatten() {
+            let index = match sub {
+                Subscript::Expression(expr) => evaluator.eval(expr).and_then(|v| v.as_i64()),
+                _ => return real_zero(),
+            };
+            match (start, index) {
+                (Expression::Array { elements }, Ok(index)) if index >= 1 => {
+                    match elements.get(index as usize - 1) {
+                        Some(element) => start = element,
+                        None => return real_zero(),
+                    }
+                }
+                _ => return real_zero(),
+            }
+        }
+    }
+    match start {
+        Expression::Empty => real_zero(),
+        start => start.clone(),
+    }
+}
+
+fn real_zero() -> Expression {
+    Expression::Terminal {
+        terminal_type: TerminalType::UnsignedReal,
+        token: Token {
+            text: "0.0".to_string(),
+            ..Default::default()
+        },
+    }
+}
+
+/// Unrolls `for` statements over constant ranges into one copy of the loop
+/// body per index value, in iteration order.
+fn unroll_for_statements(
+    stmts: &[Statement],
+    for_expander: &ForExpander,
+) -> Result<Vec<Statement>> {
+    let mut unrolled = Vec::new();
+    for stmt in stmts {
+        let Statement::For { indices, body } = stmt else {
+            unrolled.push(stmt.clone());
+            continue;
+        };
+        let Some((index, rest)) = indices.split_first() else {
+            unrolled.append(&mut unroll_for_statements(body, for_expander)?);
+            continue;
+        };
+        for value in for_expander.range_values(&index.range)? {
+            let mut iteration = Statement::For {
+                indices: rest.to_vec(),
+                body: body.clone(),
+            };
+            iteration.accept(&mut IndexSubstituter {
+                index: index.ident.text.clone(),
+                value,
+            });
+            unrolled.append(&mut unroll_for_statements(&[iteration], for_expander)?);
+        }
+    }
+    Ok(unrolled)
+}
+
+/// Splits an assignment to an array slice, `y[2:3] := {1, 2}`, into one
+/// assignment per element, `y[2] := 1` and `y[3] := 2`. A subscript selects a
+/// slice when it is `:`, a range or an array of indices, the bounds of `:`
+/// are the constant dimensions of the component. The value is an array
+/// literal of the shape of the slice, or a reference to a whole array
+/// component, whose elements are assigned in order.
+fn slice_assignments(
+    stmt: &Statement,
+    components: &IndexMap<String, Component>,
+    evaluator: &ConstEvaluator,
+) -> Result<Vec<Statement>> {
+    let Statement::Assignment { comp, value } = stmt else {
+        return Ok(vec![stmt.clone()]);
+    };
+    let Some((part, parents)) = comp.parts.split_last() else {
+        return Ok(vec![stmt.clone()]);
+    };
+    let Some(subs) = &part.subs else {
+        return Ok(vec![stmt.clone()]);
+    };
+    let is_slice = |sub: &Subscript| {
+        matches!(
+            sub,
+            Subscript::Range { .. }
+                | Subscript::Expression(Expression::Range { .. } | Expression::Array { .. })
+        )
+    };
+    if !subs.iter().any(is_slice) {
+        return Ok(vec![stmt.clone()]);
+    }
+
+    // the indices selected by each subscript, a scalar subscript selects one
+    let name = comp
+        .parts
+        .iter()
+        .map(|part| part.ident.text.as_str())
+        .collect::<Vec<_>>()
+        .join(".");
+    let shape = components
+        .get(&name)
+        .map(|comp| comp.shape.clone())
+        .unwrap_or_default();
+    let mut selected: Vec<Vec<Expression>> = Vec::new();
+    for (dim, sub) in subs.iter().enumerate() {
+        let indices = match sub {
+            Subscript::Range { .. } => {
+                let size = match shape.get(dim) {
+                    Some(Subscript::Expression(expr)) => evaluator.eval(expr)?.as_i64()?,
+                    _ => {
+                        return Err(anyhow!(
+                            "dimension {} of '{}' is not known for the slice assignment",
+                            dim + 1,
+                            comp
+                        ));
+                    }
+                };
+                (1..=size)
+                    .map(|i| Value::Integer(i).to_expression())
+                    .collect()
+            }
+            Subscript::Expression(Expression::Range { start, step, end }) => evaluator
+                .eval_range(start, step.as_deref(), end)?
+                .iter()
+                .map(Value::to_expression)
+                .collect(),
+            Subscript::Expression(Expression::Array { elements }) => elements
+                .iter()
+                .map(|element| evaluator.eval(element).map(|v| v.to_expression()))
+                .collect::<Result<Vec<_>>>()?,
+            Subscript::Expression(expr) => vec![expr.clone()],
+            Subscript::Empty => return Ok(vec![stmt.clone()]),
+        };
+        selected.push(indices);
+    }
+
+    // one assignment per element of the slice, in row-major order
+    let mut assignments = Vec::new();
+    let mut position = vec![0; subs.len()];
+    'elements: loop {
+        let mut target = part.clone();
+        target.subs = Some(
+            position
+                .iter()
+                .zip(&selected)
+                .map(|(&i, indices)| Subscript::Expression(indices[i].clone()))
+                .collect(),
+        );
+        let mut target_comp = comp.clone();
+        target_comp.parts = parents.to_vec();
+        target_comp.parts.push(target);
+        // the value is indexed by the position within the slice dimensions
+        let slice_position: Vec<usize> = position
+            .iter()
+            .zip(subs)
+            .filter(|(_, sub)| is_slice(sub))
+            .map(|(&i, _)| i)
+            .collect();
+        assignments.push(Statement::Assignment {
+            comp: target_comp,
+            value: slice_element(value, &slice_position, components).ok_or_else(|| {
+                anyhow!(
+                    "the value assigned to the slice '{}' does not match its shape",
+                    comp
+                )
+            })?,
+        });
+        for dim in (0..position.len()).rev() {
+            position[dim] += 1;
+            if position[dim] < selected[dim].len() {
+                continue 'elements;
+            }
+            position[dim] = 0;
+        }
+        break;
+    }
+    Ok(assignments)
+}
+
+/// Returns the element of `value` at the zero-based `position`.
+fn slice_element(
+    value: &Expression,
+    position: &[usize],
+    components: &IndexMap<String, Component>,
+) -> Option<Expression> {
+    match value {
+        Expression::Array { elements } => match position.split_first() {
+            Some((&i, rest)) => slice_element(elements.get(i)?, rest, components),
+            None => None,
+        },
+        Expression::ComponentReference(cref)
+            if cref.parts.iter().all(|part| part.subs.is_none())
+                && components
+                    .get(&cref.to_string())
+                    .is_some_and(|comp| comp.shape.len() == position.len()) =>
+        {
+            let mut element = cref.clone();
+            if let Some(part) = element.parts.last_mut() {
+                part.subs = Some(
+                    position
+                        .iter()
+                        .map(|&i| {
+                            Subscript::Expression(Value::Integer(i as i64 + 1).to_expression())
+                        })
+                        .collect(),
+                );
+            }
+            Some(Expression::ComponentReference(element))
+        }
+        _ if position.is_empty() => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn when_equation(stmt: &Statement) -> Result<Equation> {
+    match stmt {
+        Statement::Assignment { comp, value } => Ok(Equation::Simple {
+            lhs: Expression::ComponentReference(comp.clone()),
+            rhs: value.clone(),
+            origin: None,
+        }),
+        Statement::FunctionCall {
+            comp,
+            args,
+            outputs,
+        } if outputs.is_empty() => Ok(Equation::FunctionCall {
+            comp: comp.clone(),
+            args: args.clone(),
+        }),
+        _ => Err(anyhow!(
+            "unsupported statement in when statement {:?}",
+            stmt
+        )),
+    }
+}
+
+/// Returns the value of the string attribute `name` of `comp`, if modified.
+fn string_attribute(comp: &Component, name: &str) -> Result<Option<String>> {
+    match comp.modifications.get(name) {
+        Some(Expression::Terminal {
+            terminal_type: TerminalType::String,
+            token,
+        }) => Ok(Some(token.text.clone())),
+        Some(expr) => Err(anyhow!(
+            "{} of '{}' must be a string, found {:?}",
+            name,
+            comp.name,
+            expr
+        )),
+        None => Ok(None),
+    }
+}
+
 fn add_pre_components(source: &Vec<Component>, target: &mut Vec<Component>) {
     for comp in source {
         let mut pre_comp = comp.clone();
@@ -143,3 +866,33 @@ fn add_pre_components(source: &Vec<Component>, target: &mut Vec<Component>) {
         target.push(pre_comp);
     }
 }
+
+/// Real discrete variables belong to z, other discrete-valued variables,
+/// including enumerations, to m.
+fn add_discrete_component(comp: &Component, dae: &mut Dae) {
+    if comp.type_name.to_string() == "Real" {
+        dae.z.push(comp.clone());
+    } else {
+        dae.m.push(comp.clone());
+    }
+}
+
+fn when_assigned_variables(equations: &[Equation]) -> IndexSet<String> {
+    let mut names = IndexSet::new();
+    for eq in equations {
+        if let Equation::When(blocks) = eq {
+            for block in blocks {
+                for eq in &block.eqs {
+                    if let Equation::Simple {
+                        lhs: Expression::ComponentReference(cref),
+                        ..
+                    } = eq
+                    {
+                        names.insert(cref.to_string());
+                    }
+                }
+            }
+        }
+    }
+    names
+}
@@ -0,0 +1,138 @@
+//! This module compares two stored definitions by their structure, for
+//! regression tests of the parser.
+//!
+//! `StoredDefinition::diff` reports the classes and components that the
+//! other definition adds, removes or changes, by their path, such as
+//! `Pkg.Model` for a nested class and `Pkg.Model.x` for one of its
+//! components. A class is changed when anything but its nested classes and
+//! components differs, these being compared on their own.
+//!
+//! Source locations are ignored, so the same model parsed from a reformatted
+//! source has no differences. The definitions are compared through their
+//! serialized form, with each token reduced to its text.
+//!
+//! `Component::structurally_eq` compares two declarations the same way, such
+//! as a component that a class inherits more than once.
+use crate::ir::ast::{ClassDefinition, Component, StoredDefinition};
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StructuralDiff {
+    /// path of a class or component only found in the other definition
+    Added(String),
+    /// path of a class or component only found in this definition
+    Removed(String),
+    /// path of a class or component that differs
+    Changed(String),
+}
+
+impl Display for StructuralDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StructuralDiff::Added(path) => write!(f, "'{}' is added", path),
+            StructuralDiff::Removed(path) => write!(f, "'{}' is removed", path),
+            StructuralDiff::Changed(path) => write!(f, "'{}' is changed", path),
+        }
+    }
+}
+
+impl StoredDefinition {
+    pub fn diff(&self, other: &StoredDefinition) -> Vec<StructuralDiff> {
+        let mut diffs = Vec::new();
+        diff_classes("", &self.class_list, &other.class_list, &mut diffs);
+        diffs
+    }
+}
+
+impl Component {
+    pub fn structurally_eq(&self, other: &Component) -> bool {
+        structure(self) == structure(other)
+    }
+}
+
+fn diff_classes(
+    prefix: &str,
+    classes: &IndexMap<String, ClassDefinition>,
+    others: &IndexMap<String, ClassDefinition>,
+    diffs: &mut Vec<StructuralDiff>,
+) {
+    for (name, class) in classes {
+        let path = format!("{}{}", prefix, name);
+        let Some(other) = others.get(name) else {
+            diffs.push(StructuralDiff::Removed(path));
+            continue;
+        };
+        if structure(&without_members(class)) != structure(&without_members(other)) {
+            diffs.push(StructuralDiff::Changed(path.clone()));
+        }
+        diff_components(&path, &class.components, &other.components, diffs);
+        diff_classes(&format!("{}.", path), &class.classes, &other.classes, diffs);
+    }
+    for name in others.keys().filter(|name| !classes.contains_key(*name)) {
+        diffs.push(StructuralDiff::Added(format!("{}{}", prefix, name)));
+    }
+}
+
+fn diff_components(
+    class_path: &str,
+    components: &IndexMap<String, Component>,
+    others: &IndexMap<String, Component>,
+    diffs: &mut Vec<StructuralDiff>,
+) {
+    for (name, comp) in components {
+        let path = format!("{}.{}", class_path, name);
+        match others.get(name) {
+            Some(other) if structure(comp) != structure(other) => {
+                diffs.push(StructuralDiff::Changed(path));
+            }
+            Some(_) => {}
+            None => diffs.push(StructuralDiff::Removed(path)),
+        }
+    }
+    for name in others.keys().filter(|name| !components.contains_key(*name)) {
+        diffs.push(StructuralDiff::Added(format!("{}.{}", class_path, name)));
+    }
+}
+
+/// Returns the class without its nested classes and components.
+fn without_members(class: &ClassDefinition) -> ClassDefinition {
+    ClassDefinition {
+        classes: IndexMap::new(),
+        components: IndexMap::new(),
+        ..class.clone()
+    }
+}
+
+/// Returns the serialized form of `value` without source locations, `None`
+/// if it cannot be serialized.
+fn structure<T: Serialize>(value: &T) -> Option<Value> {
+    serde_json::to_value(value).ok().map(strip_locations)
+}
+
+fn strip_locations(value: Value) -> Value {
+    match value {
+        Value::Object(mut fields) => {
+            // a token is reduced to its text, its location and its number
+            // change as the source moves
+            if fields.contains_key("location") && fields.contains_key("token_number") {
+                return fields.remove("text").unwrap_or_default();
+            }
+            // locations kept outside of tokens, such as the origin of
+            // generated equations
+            if fields.contains_key("start_line") && fields.contains_key("file_name") {
+                return Value::Null;
+            }
+            Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key, strip_locations(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(strip_locations).collect()),
+        value => value,
+    }
+}
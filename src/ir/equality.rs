@@ -0,0 +1,157 @@
+//! This module compares expressions by their structure.
+//!
+//! The derived `PartialEq` of `Expression` also compares the source locations
+//! of the tokens, so the same expression written twice is not equal to
+//! itself. `Expression::structurally_eq` only compares what the expressions
+//! mean: the kinds of operators, the text of terminals and the names of
+//! component references, including their subscripts.
+//!
+//! `Expression::equivalent` also accepts the operands of commutative
+//! operators in either order, `a + b` is equivalent to `b + a` and `x > 0` to
+//! `0 < x`, which is what de-duplicating conditions needs.
+use crate::ir::ast::{Expression, ForIndex, OpBinary};
+use std::mem::discriminant;
+
+impl Expression {
+    pub fn structurally_eq(&self, other: &Expression) -> bool {
+        equal(self, other, false)
+    }
+
+    pub fn equivalent(&self, other: &Expression) -> bool {
+        equal(self, other, true)
+    }
+}
+
+fn equal(a: &Expression, b: &Expression, commutative: bool) -> bool {
+    let eq = |a: &Expression, b: &Expression| equal(a, b, commutative);
+    match (a, b) {
+        (Expression::Empty, Expression::Empty) => true,
+        (
+            Expression::Terminal {
+                terminal_type: a_type,
+                token: a,
+            },
+            Expression::Terminal {
+                terminal_type: b_type,
+                token: b,
+            },
+        ) => a_type == b_type && a.text == b.text,
+        (Expression::ComponentReference(a), Expression::ComponentReference(b)) => {
+            a.local == b.local && a.to_string() == b.to_string()
+        }
+        (Expression::Unary { op: a_op, rhs: a }, Expression::Unary { op: b_op, rhs: b }) => {
+            discriminant(a_op) == discriminant(b_op) && eq(a, b)
+        }
+        (
+            Expression::Binary {
+                op: a_op,
+                lhs: a_lhs,
+                rhs: a_rhs,
+            },
+            Expression::Binary {
+                op: b_op,
+                lhs: b_lhs,
+                rhs: b_rhs,
+            },
+        ) => {
+            (discriminant(a_op) == discriminant(b_op) && eq(a_lhs, b_lhs) && eq(a_rhs, b_rhs))
+                || (commutative
+                    && swapped(a_op).is_some_and(|op| discriminant(&op) == discriminant(b_op))
+                    && eq(a_lhs, b_rhs)
+                    && eq(a_rhs, b_lhs))
+        }
+        (
+            Expression::FunctionCall {
+                comp: a_comp,
+                args: a,
+            },
+            Expression::FunctionCall {
+                comp: b_comp,
+                args: b,
+            },
+        ) => a_comp.to_string() == b_comp.to_string() && all_eq(a, b, commutative),
+        (Expression::Array { elements: a }, Expression::Array { elements: b }) => {
+            all_eq(a, b, commutative)
+        }
+        (
+            Expression::ArrayComprehension {
+                expr: a,
+                indices: a_indices,
+            },
+            Expression::ArrayComprehension {
+                expr: b,
+                indices: b_indices,
+            },
+        ) => eq(a, b) && indices_eq(a_indices, b_indices, commutative),
+        (
+            Expression::If {
+                branches: a,
+                else_branch: a_else,
+            },
+            Expression::If {
+                branches: b,
+                else_branch: b_else,
+            },
+        ) => {
+            a.len() == b.len()
+                && a.iter().zip(b).all(|((a_cond, a_then), (b_cond, b_then))| {
+                    eq(a_cond, b_cond) && eq(a_then, b_then)
+                })
+                && eq(a_else, b_else)
+        }
+        (
+            Expression::Range {
+                start: a_start,
+                step: a_step,
+                end: a_end,
+            },
+            Expression::Range {
+                start: b_start,
+                step: b_step,
+                end: b_end,
+            },
+        ) => {
+            eq(a_start, b_start)
+                && match (a_step, b_step) {
+                    (Some(a), Some(b)) => eq(a, b),
+                    (None, None) => true,
+                    _ => false,
+                }
+                && eq(a_end, b_end)
+        }
+        _ => false,
+    }
+}
+
+/// Returns the operator that gives the same result with the operands
+/// swapped, if any.
+fn swapped(op: &OpBinary) -> Option<OpBinary> {
+    match op {
+        OpBinary::Add(..)
+        | OpBinary::AddElem(..)
+        // the equations are scalarized before conditions are compared, so
+        // there are no matrix products left
+        | OpBinary::Mul(..)
+        | OpBinary::MulElem(..)
+        | OpBinary::Eq(..)
+        | OpBinary::Neq(..)
+        | OpBinary::And(..)
+        | OpBinary::Or(..) => Some(op.clone()),
+        OpBinary::Lt(token) => Some(OpBinary::Gt(token.clone())),
+        OpBinary::Le(token) => Some(OpBinary::Ge(token.clone())),
+        OpBinary::Gt(token) => Some(OpBinary::Lt(token.clone())),
+        OpBinary::Ge(token) => Some(OpBinary::Le(token.clone())),
+        _ => None,
+    }
+}
+
+fn all_eq(a: &[Expression], b: &[Expression], commutative: bool) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b, commutative))
+}
+
+fn indices_eq(a: &[ForIndex], b: &[ForIndex], commutative: bool) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| a.ident.text == b.ident.text && equal(&a.range, &b.range, commutative))
+}
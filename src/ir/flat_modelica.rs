@@ -0,0 +1,653 @@
+//! This module exports a flattened class as flat Modelica source.
+//!
+//! `ClassDefinition::to_flat_modelica` prints the result of `flatten` as a
+//! single `model`, the classic debugging artifact of Modelica tools. The
+//! components keep their flattened, underscore separated names, and the
+//! enumeration types used by the model are declared inside of it, so the
+//! export can be parsed again.
+//!
+//! Operands are only parenthesized where they are themselves operations, the
+//! parser drops parentheses, so the export parses into the same expressions.
+//! A component prints its binding only when the start value differs from the
+//! `start` modification, or without it, from the default start value of its
+//! type.
+//!
+//! `StoredDefinition::to_modelica` prints a parsed definition back as
+//! Modelica source, with its classes, nested classes, imports, extends
+//! clauses, declarations and sections. What the parser does not keep, such
+//! as comments, `each` and `final`, or the external clause of a function, is
+//! not printed.
+use crate::ir::ast::{
+    Causality, ClassDefinition, ClassType, Component, ComponentReference, Connection, Equation,
+    Expression, ForIndex, Import, OpBinary, OpUnary, Statement, StoredDefinition, Subscript,
+    TerminalType, Token, Variability, Visibility,
+};
+use crate::ir::types::Type;
+use indexmap::IndexMap;
+
+const INDENT: &str = "  ";
+
+impl ClassDefinition {
+    pub fn to_flat_modelica(&self) -> String {
+        let mut s = format!("model {}\n", self.name.text);
+        for (name, class) in &self.classes {
+            if !class.enumeration_literals.is_empty() {
+                let literals: Vec<String> = class
+                    .enumeration_literals
+                    .iter()
+                    .map(|literal| {
+                        format!(
+                            "{}{}",
+                            literal.ident.text,
+                            description(&literal.description)
+                        )
+                    })
+                    .collect();
+                s += &format!(
+                    "{}type {} = enumeration({});\n",
+                    INDENT,
+                    name,
+                    literals.join(", ")
+                );
+            }
+        }
+        for comp in self.components.values() {
+            s += &format!("{}{};\n", INDENT, component(comp));
+        }
+        for (section, equations) in [
+            ("initial equation", &self.initial_equations),
+            ("equation", &self.equations),
+        ] {
+            if !equations.is_empty() {
+                s += &format!("{}\n", section);
+                for eq in equations {
+                    s += &equation(eq, 1);
+                }
+            }
+        }
+        for (section, algorithms) in [
+            ("initial algorithm", &self.initial_algorithms),
+            ("algorithm", &self.algorithms),
+        ] {
+            for stmts in algorithms {
+                s += &format!("{}\n", section);
+                for stmt in stmts {
+                    s += &statement(stmt, 1);
+                }
+            }
+        }
+        s += &format!("end {};\n", self.name.text);
+        s
+    }
+}
+
+impl StoredDefinition {
+    pub fn to_modelica(&self) -> String {
+        let mut s = String::new();
+        if let Some(within) = &self.within {
+            s += &format!("within {};\n", within);
+        }
+        for class in self.class_list.values() {
+            s += &class_definition(class, 0);
+        }
+        s
+    }
+}
+
+fn class_definition(class: &ClassDefinition, depth: usize) -> String {
+    let indent = INDENT.repeat(depth);
+    let mut prefixes = Vec::new();
+    if class.replaceable {
+        prefixes.push("replaceable ");
+    }
+    if class.encapsulated {
+        prefixes.push("encapsulated ");
+    }
+    if class.partial {
+        prefixes.push("partial ");
+    }
+    if class.operator {
+        prefixes.push("operator ");
+    }
+    prefixes.push(match class.class_type {
+        ClassType::Empty | ClassType::Class(..) => "class ",
+        ClassType::Model(..) => "model ",
+        ClassType::Record(..) => "record ",
+        ClassType::Block(..) => "block ",
+        ClassType::Connector(..) => "connector ",
+        ClassType::Type(..) => "type ",
+        ClassType::Package(..) => "package ",
+        ClassType::Function(..) => "function ",
+        ClassType::Operator(..) => "operator ",
+    });
+    let header = format!("{}{}{}", indent, prefixes.concat(), class.name.text);
+    let constraint = match &class.constraint {
+        Some(constraint) => format!(" constrainedby {}", constraint),
+        None => String::new(),
+    };
+
+    // short class specifiers
+    let specifier = if let Some(der) = &class.der_class {
+        let variables: Vec<&str> = der.variables.iter().map(|v| v.text.as_str()).collect();
+        Some(format!("der({}, {})", der.class, variables.join(", ")))
+    } else if class.unspecified_enumeration {
+        Some("enumeration(:)".to_string())
+    } else if !class.enumeration_literals.is_empty() {
+        let literals: Vec<String> = class
+            .enumeration_literals
+            .iter()
+            .map(|literal| {
+                format!(
+                    "{}{}",
+                    literal.ident.text,
+                    description(&literal.description)
+                )
+            })
+            .collect();
+        Some(format!("enumeration({})", literals.join(", ")))
+    } else if class.short_class {
+        class.extends.first().map(|base| {
+            let mut s = format!("{}{}", causality(&class.causality), base.comp);
+            if !class.shape.is_empty() {
+                s += &format!("[{}]", subscripts(&class.shape));
+            }
+            s + &class_modification(&base.modifications, &base.redeclarations, &IndexMap::new())
+        })
+    } else {
+        None
+    };
+    if let Some(specifier) = specifier {
+        return format!(
+            "{} = {}{}{};\n",
+            header,
+            specifier,
+            description(&class.description),
+            constraint
+        );
+    }
+
+    // `model extends M(...)` extends the inherited class of the same name
+    let mut s = header;
+    let mut extends = class.extends.as_slice();
+    if let Some((first, rest)) = extends.split_first()
+        && first.comp.to_string() == class.name.text
+    {
+        s = format!(
+            "{}{}extends {}{}",
+            indent,
+            prefixes.concat(),
+            class.name.text,
+            class_modification(
+                &first.modifications,
+                &first.redeclarations,
+                &IndexMap::new()
+            )
+        );
+        extends = rest;
+    }
+    s += &format!("{}\n", description(&class.description));
+    let inner = INDENT.repeat(depth + 1);
+    for import in &class.imports {
+        s += &format!("{}{};\n", inner, import_clause(import));
+    }
+    for extend in extends {
+        s += &format!(
+            "{}extends {}{};\n",
+            inner,
+            extend.comp,
+            class_modification(
+                &extend.modifications,
+                &extend.redeclarations,
+                &IndexMap::new()
+            )
+        );
+    }
+    for visibility in [Visibility::Public, Visibility::Protected] {
+        let classes: Vec<&ClassDefinition> = class
+            .classes
+            .values()
+            .filter(|nested| nested.visibility == visibility)
+            .collect();
+        let components: Vec<&Component> = class
+            .components
+            .values()
+            .filter(|comp| comp.visibility == visibility)
+            .collect();
+        if visibility == Visibility::Protected && (!classes.is_empty() || !components.is_empty()) {
+            s += &format!("{}protected\n", indent);
+        }
+        for nested in classes {
+            s += &class_definition(nested, depth + 1);
+        }
+        for comp in components {
+            s += &format!("{}{};\n", inner, declaration(comp));
+        }
+    }
+    for (section, equations) in [
+        ("initial equation", &class.initial_equations),
+        ("equation", &class.equations),
+    ] {
+        if !equations.is_empty() {
+            s += &format!("{}{}\n", indent, section);
+            for eq in equations {
+                s += &equation(eq, depth + 1);
+            }
+        }
+    }
+    for (section, algorithms) in [
+        ("initial algorithm", &class.initial_algorithms),
+        ("algorithm", &class.algorithms),
+    ] {
+        for stmts in algorithms {
+            s += &format!("{}{}\n", indent, section);
+            for stmt in stmts {
+                s += &statement(stmt, depth + 1);
+            }
+        }
+    }
+    if !class.annotation.is_empty() {
+        s += &format!(
+            "{}annotation({});\n",
+            inner,
+            modifications(&class.annotation).join(", ")
+        );
+    }
+    s + &format!("{}end {}{};\n", indent, class.name.text, constraint)
+}
+
+fn import_clause(import: &Import) -> String {
+    match &import.alias {
+        Some(alias) => format!("import {} = {}", alias.text, import.path),
+        None if import.unqualified => format!("import {}.*", import.path),
+        None if !import.names.is_empty() => {
+            let names: Vec<&str> = import.names.iter().map(|n| n.text.as_str()).collect();
+            format!("import {}.{{{}}}", import.path, names.join(", "))
+        }
+        None => format!("import {}", import.path),
+    }
+}
+
+/// Returns the modification of a class or component, empty without
+/// arguments.
+fn class_modification(
+    modifications: &IndexMap<String, Expression>,
+    redeclarations: &IndexMap<String, Component>,
+    redeclared_classes: &IndexMap<String, ClassDefinition>,
+) -> String {
+    let mut arguments = self::modifications(modifications);
+    for comp in redeclarations.values() {
+        arguments.push(format!("redeclare {}", declaration(comp)));
+    }
+    for class in redeclared_classes.values() {
+        let definition = class_definition(class, 0);
+        arguments.push(format!("redeclare {}", definition.trim_end_matches(";\n")));
+    }
+    if arguments.is_empty() {
+        String::new()
+    } else {
+        format!("({})", arguments.join(", "))
+    }
+}
+
+/// Returns the declaration of a component as written in a class.
+fn declaration(comp: &Component) -> String {
+    let mut s = String::new();
+    if comp.replaceable {
+        s += "replaceable ";
+    }
+    s += &format!("{}{} {}", type_prefixes(comp), comp.type_name, comp.name);
+    if !comp.shape.is_empty() {
+        s += &format!("[{}]", subscripts(&comp.shape));
+    }
+    s += &class_modification(
+        &comp.modifications,
+        &comp.redeclarations,
+        &comp.redeclared_classes,
+    );
+    s += &binding(comp);
+    if let Some(condition) = &comp.condition {
+        s += &format!(" if {}", expression(condition));
+    }
+    s += &description(&comp.description);
+    if !comp.annotation.is_empty() {
+        s += &format!(
+            " annotation({})",
+            modifications(&comp.annotation).join(", ")
+        );
+    }
+    if let Some(constraint) = &comp.constraint {
+        s += &format!(" constrainedby {}", constraint);
+    }
+    s
+}
+
+fn component(comp: &Component) -> String {
+    let mut s = format!("{}{} {}", type_prefixes(comp), comp.type_name, comp.name);
+    if !comp.shape.is_empty() {
+        s += &format!("[{}]", subscripts(&comp.shape));
+    }
+
+    if !comp.modifications.is_empty() {
+        s += &format!("({})", modifications(&comp.modifications).join(", "));
+    }
+    s += &binding(comp);
+    s += &description(&comp.description);
+    s
+}
+
+fn type_prefixes(comp: &Component) -> String {
+    let mut prefixes = Vec::new();
+    match comp.connection {
+        Connection::Flow(..) => prefixes.push("flow "),
+        Connection::Stream(..) => prefixes.push("stream "),
+        Connection::Empty => {}
+    }
+    match comp.variability {
+        Variability::Constant(..) => prefixes.push("constant "),
+        Variability::Discrete(..) => prefixes.push("discrete "),
+        Variability::Parameter(..) => prefixes.push("parameter "),
+        Variability::Empty => {}
+    }
+    prefixes.push(causality(&comp.causality));
+    prefixes.concat()
+}
+
+fn causality(causality: &Causality) -> &'static str {
+    match causality {
+        Causality::Input(..) => "input ",
+        Causality::Output(..) => "output ",
+        Causality::Empty => "",
+    }
+}
+
+/// Returns the binding of a component, empty when the start value is the
+/// one given by the `start` modification, or without it, the default start
+/// value of its type.
+fn binding(comp: &Component) -> String {
+    let start = expression(&comp.start);
+    let implied_start = match comp.modifications.get("start") {
+        Some(modified_start) => expression(modified_start),
+        None => expression(&Type::from_name(&comp.type_name.to_string()).default_start()),
+    };
+    if !start.is_empty() && start != implied_start {
+        format!(" = {}", start)
+    } else {
+        String::new()
+    }
+}
+
+/// Returns the arguments of a modification, nested modifications keep their
+/// dotted name.
+fn modifications(modifications: &IndexMap<String, Expression>) -> Vec<String> {
+    modifications
+        .iter()
+        .map(|(name, expr)| match expr {
+            Expression::Empty => name.clone(),
+            _ => format!("{} = {}", name, expression(expr)),
+        })
+        .collect()
+}
+
+/// Returns a description string, its parts joined with `+`.
+fn description(tokens: &[Token]) -> String {
+    if tokens.is_empty() {
+        return String::new();
+    }
+    let parts: Vec<String> = tokens
+        .iter()
+        .map(|token| format!("\"{}\"", token.text))
+        .collect();
+    format!(" {}", parts.join(" + "))
+}
+
+fn equation(eq: &Equation, depth: usize) -> String {
+    let indent = INDENT.repeat(depth);
+    match eq {
+        Equation::Empty => String::new(),
+        Equation::Simple { lhs, rhs, .. } => {
+            format!("{}{} = {};\n", indent, expression(lhs), expression(rhs))
+        }
+        Equation::Connect { lhs, rhs } => format!(
+            "{}connect({}, {});\n",
+            indent,
+            component_reference(lhs),
+            component_reference(rhs)
+        ),
+        Equation::For { indices, equations } => {
+            let mut s = format!("{}for {} loop\n", indent, for_indices(indices));
+            for eq in equations {
+                s += &equation(eq, depth + 1);
+            }
+            s + &format!("{}end for;\n", indent)
+        }
+        Equation::When(blocks) => {
+            let mut s = String::new();
+            for (i, block) in blocks.iter().enumerate() {
+                let keyword = if i == 0 { "when" } else { "elsewhen" };
+                s += &format!("{}{} {} then\n", indent, keyword, expression(&block.cond));
+                for eq in &block.eqs {
+                    s += &equation(eq, depth + 1);
+                }
+            }
+            s + &format!("{}end when;\n", indent)
+        }
+        Equation::If {
+            cond_blocks,
+            else_block,
+        } => {
+            let mut s = String::new();
+            for (i, block) in cond_blocks.iter().enumerate() {
+                let keyword = if i == 0 { "if" } else { "elseif" };
+                s += &format!("{}{} {} then\n", indent, keyword, expression(&block.cond));
+                for eq in &block.eqs {
+                    s += &equation(eq, depth + 1);
+                }
+            }
+            if let Some(else_block) = else_block {
+                s += &format!("{}else\n", indent);
+                for eq in else_block {
+                    s += &equation(eq, depth + 1);
+                }
+            }
+            s + &format!("{}end if;\n", indent)
+        }
+        Equation::FunctionCall { comp, args } => format!(
+            "{}{}({});\n",
+            indent,
+            component_reference(comp),
+            expressions(args)
+        ),
+    }
+}
+
+fn statement(stmt: &Statement, depth: usize) -> String {
+    let indent = INDENT.repeat(depth);
+    match stmt {
+        Statement::Empty => String::new(),
+        Statement::Assignment { comp, value } => format!(
+            "{}{} := {};\n",
+            indent,
+            component_reference(comp),
+            expression(value)
+        ),
+        Statement::Return { .. } => format!("{}return;\n", indent),
+        Statement::Break { .. } => format!("{}break;\n", indent),
+        Statement::For { indices, body } => {
+            let mut s = format!("{}for {} loop\n", indent, for_indices(indices));
+            for stmt in body {
+                s += &statement(stmt, depth + 1);
+            }
+            s + &format!("{}end for;\n", indent)
+        }
+        Statement::If {
+            cond_blocks,
+            else_block,
+        } => {
+            let mut s = String::new();
+            for (i, block) in cond_blocks.iter().enumerate() {
+                let keyword = if i == 0 { "if" } else { "elseif" };
+                s += &format!("{}{} {} then\n", indent, keyword, expression(&block.cond));
+                for stmt in &block.stmts {
+                    s += &statement(stmt, depth + 1);
+                }
+            }
+            if let Some(else_block) = else_block {
+                s += &format!("{}else\n", indent);
+                for stmt in else_block {
+                    s += &statement(stmt, depth + 1);
+                }
+            }
+            s + &format!("{}end if;\n", indent)
+        }
+        Statement::While(block) => {
+            let mut s = format!("{}while {} loop\n", indent, expression(&block.cond));
+            for stmt in &block.stmts {
+                s += &statement(stmt, depth + 1);
+            }
+            s + &format!("{}end while;\n", indent)
+        }
+        Statement::When(blocks) => {
+            let mut s = String::new();
+            for (i, block) in blocks.iter().enumerate() {
+                let keyword = if i == 0 { "when" } else { "elsewhen" };
+                s += &format!("{}{} {} then\n", indent, keyword, expression(&block.cond));
+                for stmt in &block.stmts {
+                    s += &statement(stmt, depth + 1);
+                }
+            }
+            s + &format!("{}end when;\n", indent)
+        }
+        Statement::FunctionCall {
+            comp,
+            args,
+            outputs,
+        } => {
+            let call = format!("{}({})", component_reference(comp), expressions(args));
+            if outputs.is_empty() {
+                format!("{}{};\n", indent, call)
+            } else {
+                format!("{}({}) := {};\n", indent, expressions(outputs), call)
+            }
+        }
+    }
+}
+
+fn for_indices(indices: &[ForIndex]) -> String {
+    indices
+        .iter()
+        .map(|index| match index.range {
+            Expression::Empty => index.ident.text.clone(),
+            _ => format!("{} in {}", index.ident.text, expression(&index.range)),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn expressions(exprs: &[Expression]) -> String {
+    exprs.iter().map(expression).collect::<Vec<_>>().join(", ")
+}
+
+fn expression(expr: &Expression) -> String {
+    match expr {
+        Expression::Empty => String::new(),
+        Expression::Terminal {
+            terminal_type,
+            token,
+        } => match terminal_type {
+            TerminalType::String => format!("\"{}\"", token.text),
+            _ => token.text.clone(),
+        },
+        Expression::ComponentReference(cref) => component_reference(cref),
+        Expression::Unary { op, rhs } => {
+            let op = match op {
+                OpUnary::Minus(..) => "-",
+                OpUnary::Plus(..) => "+",
+                OpUnary::DotMinus(..) => ".-",
+                OpUnary::DotPlus(..) => ".+",
+                OpUnary::Not(..) => "not ",
+                OpUnary::Empty => "",
+            };
+            format!("{}{}", op, operand(rhs))
+        }
+        Expression::Binary { op, lhs, rhs } => {
+            let op = match op {
+                OpBinary::Add(..) => "+",
+                OpBinary::Sub(..) => "-",
+                OpBinary::Mul(..) => "*",
+                OpBinary::Div(..) => "/",
+                OpBinary::Exp(..) => "^",
+                OpBinary::AddElem(..) => ".+",
+                OpBinary::SubElem(..) => ".-",
+                OpBinary::MulElem(..) => ".*",
+                OpBinary::DivElem(..) => "./",
+                OpBinary::Eq(..) => "==",
+                OpBinary::Neq(..) => "<>",
+                OpBinary::Lt(..) => "<",
+                OpBinary::Le(..) => "<=",
+                OpBinary::Gt(..) => ">",
+                OpBinary::Ge(..) => ">=",
+                OpBinary::And(..) => "and",
+                OpBinary::Or(..) => "or",
+                OpBinary::Empty => "",
+            };
+            format!("{} {} {}", operand(lhs), op, operand(rhs))
+        }
+        Expression::FunctionCall { comp, args } => {
+            format!("{}({})", component_reference(comp), expressions(args))
+        }
+        Expression::Array { elements } => format!("{{{}}}", expressions(elements)),
+        Expression::ArrayComprehension { expr, indices } => {
+            format!("{{{} for {}}}", expression(expr), for_indices(indices))
+        }
+        Expression::If {
+            branches,
+            else_branch,
+        } => {
+            let mut s = String::new();
+            for (i, (cond, then)) in branches.iter().enumerate() {
+                let keyword = if i == 0 { "if" } else { " elseif" };
+                s += &format!("{} {} then {}", keyword, expression(cond), expression(then));
+            }
+            s + &format!(" else {}", expression(else_branch))
+        }
+        Expression::Range { start, step, end } => match step {
+            Some(step) => format!("{}:{}:{}", operand(start), operand(step), operand(end)),
+            None => format!("{}:{}", operand(start), operand(end)),
+        },
+    }
+}
+
+/// Parenthesizes operands that are operations themselves.
+fn operand(expr: &Expression) -> String {
+    match expr {
+        Expression::Unary { .. }
+        | Expression::Binary { .. }
+        | Expression::If { .. }
+        | Expression::Range { .. } => format!("({})", expression(expr)),
+        _ => expression(expr),
+    }
+}
+
+fn component_reference(cref: &ComponentReference) -> String {
+    let parts: Vec<String> = cref
+        .parts
+        .iter()
+        .map(|part| match &part.subs {
+            Some(subs) => format!("{}[{}]", part.ident.text, subscripts(subs)),
+            None => part.ident.text.clone(),
+        })
+        .collect();
+    let prefix = if cref.local { "." } else { "" };
+    format!("{}{}", prefix, parts.join("."))
+}
+
+fn subscripts(subs: &[Subscript]) -> String {
+    subs.iter()
+        .map(|sub| match sub {
+            Subscript::Empty => String::new(),
+            Subscript::Expression(expr) => expression(expr),
+            Subscript::Range { token } => token.text.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
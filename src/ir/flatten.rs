@@ -6,10 +6,25 @@
 //! The main function in this module is `flatten`, which takes a stored definition of the IR
 //! and produces a flattened class definition. The process involves:
 //!
-//! - Identifying the main class and other class definitions from the provided IR.
-//! - Iteratively expanding components in the main class that reference other class definitions.
+//! - Identifying the main class and other class definitions from the provided IR. Nested
+//!   classes are known by their qualified name, such as `Pkg.Sub.Model`, and a class may refer
+//!   to a class of an enclosing package by its short name.
+//! - Rejecting main classes that cannot be simulated, such as a `package`, a `function` or a
+//!   `connector`. Only a `model`, a `block` or a general `class` is accepted, unless it is
+//!   `partial`.
+//! - Rejecting component and inheritance hierarchies deeper than `FlattenOptions::max_depth`.
+//! - Iteratively expanding components in the main class that reference other class definitions,
+//!   after replacing their replaceable components by the redeclarations of the component.
+//! - Resolving references to the constants of nested packages, such as `Medium.X` for
+//!   `replaceable package Medium = PartialMedium`, to the package redeclared by the
+//!   modification of the component, if any.
+//! - Declaring components of a derivative type, `type V = der(Real, x)`, with the base type
+//!   of the derivative, linked to the derived variable by an equation `v = der(x)`.
 //! - Propagating equations and subcomponents from referenced classes into the main class.
 //! - Removing expanded components from the main class to ensure a flat structure.
+//! - Removing conditional components whose condition is false, together with
+//!   their connect equations.
+//! - Inlining the values of parameters annotated with `Evaluate = true`.
 //!
 //! This module relies on visitors such as `ScopePusher` and `SubCompNamer` to handle
 //! scoping and naming during the flattening process.
@@ -20,33 +35,116 @@
 //!
 
 use crate::ir;
+use crate::ir::connections::expand_connections;
+use crate::ir::const_eval::{ConstEvaluator, Value, well_known_constants};
+use crate::ir::types::Type;
 use crate::ir::visitor::Visitable;
+use crate::ir::visitors::builtin_resolver::BuiltinResolver;
+use crate::ir::visitors::component_ref_collector::ComponentRefCollector;
+use crate::ir::visitors::constant_substituter::ConstantSubstituter;
+use crate::ir::visitors::for_expander::ForExpander;
+use crate::ir::visitors::range_expander::RangeExpander;
+use crate::ir::visitors::scalarizer::Scalarizer;
 use crate::ir::visitors::scope_pusher::ScopePusher;
 use crate::ir::visitors::sub_comp_namer::SubCompNamer;
-use anyhow::Result;
+use crate::ir::visitors::tautology_remover::TautologyRemover;
+use anyhow::{Result, anyhow};
 use indexmap::{IndexMap, IndexSet};
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlattenOptions {
+    /// Resolve the full inheritance chain of each class into a single class
+    /// before expanding its components. Declarations of a derived class then
+    /// override inherited ones, as in other Modelica tools.
+    pub extends_first: bool,
+    /// Maximum depth of the component and inheritance hierarchy of the main
+    /// class. Deeper, or recursive, models are rejected before expansion.
+    pub max_depth: usize,
+    /// Name of the class to flatten, the first class of the stored
+    /// definition when not given.
+    pub main_class: Option<String>,
+    /// Replace the connect equations by the equations of their connection
+    /// sets. When disabled, the connect equations are kept for tools that do
+    /// their own connection handling.
+    pub expand_connects: bool,
+    /// Record on each flattened subcomponent the path of the components it
+    /// is nested in, see `Component::hierarchy`.
+    pub hierarchy: bool,
+    /// Keep array variables and equations whole, for backends that handle
+    /// arrays natively: `der(x) = A*x` stays one equation of the variable
+    /// `x` of shape `[3]`. Otherwise equations between arrays are split into
+    /// one equation per element.
+    pub preserve_arrays: bool,
+}
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        FlattenOptions {
+            extends_first: false,
+            max_depth: 100,
+            main_class: None,
+            expand_connects: true,
+            hierarchy: false,
+            preserve_arrays: false,
+        }
+    }
+}
+
 pub fn flatten(def: &ir::ast::StoredDefinition) -> Result<ir::ast::ClassDefinition> {
-    // flatten the syntax tree
-    let mut count = 0;
-    let mut main_class_name = String::new();
-    let mut class_dict = IndexMap::new();
+    flatten_with_options(def, &FlattenOptions::default())
+}
 
-    // find all class definitions
+pub fn flatten_with_options(
+    def: &ir::ast::StoredDefinition,
+    options: &FlattenOptions,
+) -> Result<ir::ast::ClassDefinition> {
+    // find all class definitions, nested classes by their qualified name
+    let mut class_dict = IndexMap::new();
     for (class_name, class) in &def.class_list {
-        if count == 0 {
-            main_class_name = class.name.text.clone();
-        } else {
-            class_dict.insert(class_name.clone(), class.clone());
-        }
-        count += 1;
+        collect_classes(class_name, class, &mut class_dict);
     }
+    qualify_class_names(&mut class_dict);
 
     // get main class
-    let main_class = def
-        .class_list
-        .get(&main_class_name)
-        .expect("Main class not found");
+    let main_class_name = match &options.main_class {
+        Some(name) => name.clone(),
+        None => def.class_list.keys().next().cloned().unwrap_or_default(),
+    };
+    let mut main_class = class_dict
+        .shift_remove(&main_class_name)
+        .ok_or_else(|| match &options.main_class {
+            Some(name) => anyhow!("Class '{}' not found", name),
+            None => anyhow!("No class to flatten"),
+        })?;
+    check_restriction(&main_class_name, &main_class)?;
+    hierarchy_height(
+        &main_class,
+        &class_dict,
+        0,
+        options.max_depth,
+        &mut IndexMap::new(),
+    )?;
+
+    // replace library constants and the constants of other classes by their
+    // values, and qualified calls of built-in functions by the built-in
+    let mut constants = well_known_constants();
+    constants.extend(def.constant_values());
+    substitute_constants(&mut main_class, &constants)?;
+    let packages = nested_packages(&main_class_name, &IndexMap::new(), &class_dict)?;
+    substitute_constants(
+        &mut main_class,
+        &package_constants(&packages, &class_dict, &constants),
+    )?;
+    resolve_builtins(&mut main_class);
+    for class in class_dict.values_mut() {
+        substitute_constants(class, &constants)?;
+        resolve_builtins(class);
+    }
+    if options.extends_first {
+        main_class = inline_extends(&main_class, &class_dict, &mut IndexSet::new())?;
+    }
+    resolve_der_types(&mut main_class, &class_dict)?;
+    resolve_type_aliases(&mut main_class, &class_dict);
 
     // create flat class
     let mut fclass = main_class.clone();
@@ -54,14 +152,18 @@ pub fn flatten(def: &ir::ast::StoredDefinition) -> Result<ir::ast::ClassDefiniti
     //  handle extend clauses
     for extend in &main_class.extends {
         let class_name = extend.comp.to_string();
-        let class = class_dict
+        if is_type(&class_name, &class_dict) {
+            continue;
+        }
+        let mut class = class_dict
             .get(&class_name)
-            .expect(&format!("Class for extend '{}' not found", class_name));
+            .expect(&format!("Class for extend '{}' not found", class_name))
+            .clone();
+        apply_modifications(&mut class, &extend.modifications)?;
+        redeclare(&mut class, &extend.redeclarations, &class_dict)?;
 
         // add components
-        for comp in &class.components {
-            fclass.components.insert(comp.0.clone(), comp.1.clone());
-        }
+        inherit_components(&mut fclass, &class.components, &class_name)?;
 
         // add equations
         for eq in &class.equations {
@@ -69,10 +171,20 @@ pub fn flatten(def: &ir::ast::StoredDefinition) -> Result<ir::ast::ClassDefiniti
         }
     }
 
-    // expaand connection equations
-    for eq in &main_class.equations {
-        if let ir::ast::Equation::Connect { .. } = eq {}
-    }
+    // replace type aliases by their built-in base type
+    resolve_der_types(&mut fclass, &class_dict)?;
+    resolve_type_aliases(&mut fclass, &class_dict);
+
+    // remove disabled conditional components, the paths of removed
+    // subcomponents are added as the components are expanded
+    let mut removed = remove_disabled_components(&mut fclass)?;
+
+    // set aside connection equations, they are expanded once the class is flat
+    let (mut connections, equations): (Vec<_>, Vec<_>) = fclass
+        .equations
+        .drain(..)
+        .partition(|eq| matches!(eq, ir::ast::Equation::Connect { .. }));
+    fclass.equations = equations;
 
     // flatten the class by expanding components
     let mut scope_pusher = ScopePusher {
@@ -90,16 +202,59 @@ pub fn flatten(def: &ir::ast::StoredDefinition) -> Result<ir::ast::ClassDefiniti
 
     // for each component in the main class
     for (comp_name, comp) in &main_class.components {
-        // if the the component type is a class
-        if class_dict.contains_key(&comp.type_name.to_string()) {
-            let comp_class = class_dict.get(&comp.type_name.to_string()).unwrap();
+        if removed.contains(comp_name) {
+            continue;
+        }
+        // if the the component type is a class, other than an enumeration
+        // or a type, possibly a nested class of the main class
+        let type_name = comp.type_name.to_string();
+        let Some(class_name) = component_class(&type_name, &main_class_name, &class_dict) else {
+            if Type::from_name(&type_name) == Type::Unknown {
+                return Err(anyhow!(
+                    "Class '{}' of component '{}' not found",
+                    type_name,
+                    comp_name
+                ));
+            }
+            continue;
+        };
+        if !class_dict[&class_name].is_enumeration() && !is_type(&class_name, &class_dict) {
+            let mut comp_class = class_dict[&class_name].clone();
+            // redeclared components may be inherited by the component class
+            if options.extends_first || !comp.redeclarations.is_empty() {
+                comp_class = inline_extends(&comp_class, &class_dict, &mut IndexSet::new())?;
+            }
+            redeclare(&mut comp_class, &comp.redeclarations, &class_dict)?;
+            let packages = nested_packages(&class_name, &comp.redeclared_classes, &class_dict)?;
+            substitute_constants(
+                &mut comp_class,
+                &package_constants(&packages, &class_dict, &constants),
+            )?;
+            // the modifications of the instance may enable or disable its
+            // conditional components
+            apply_modifications(&mut comp_class, &comp.modifications)?;
+            let paths: IndexSet<String> = remove_disabled_components(&mut comp_class)?
+                .iter()
+                .map(|name| format!("{}.{}", comp_name, name))
+                .collect();
+            check_removed_usage(&fclass.equations, &paths, &main_class_name)?;
+            removed.extend(paths);
+
+            // unroll for equations before the loop indices are scoped
+            expand_for_equations(&mut comp_class, &class_dict)?;
+            resolve_der_types(&mut comp_class, &class_dict)?;
+            resolve_type_aliases(&mut comp_class, &class_dict);
 
             // add equation from component to flat class
             for eq in &comp_class.equations {
                 let mut feq = eq.clone();
                 scope_pusher.comp = comp_name.clone();
                 feq.accept(&mut scope_pusher);
-                fclass.equations.push(feq);
+                if let ir::ast::Equation::Connect { .. } = feq {
+                    connections.push(feq);
+                } else {
+                    fclass.equations.push(feq);
+                }
             }
 
             // expand comp.sub_comp names to use underscores
@@ -112,6 +267,20 @@ pub fn flatten(def: &ir::ast::StoredDefinition) -> Result<ir::ast::ClassDefiniti
                 let mut scomp = subcomp.clone();
                 let name = format!("{}_{}", comp_name, subcomp_name);
                 scomp.name = name.clone();
+                // the subcomponents of a protected component are protected
+                if comp.visibility == ir::ast::Visibility::Protected {
+                    scomp.visibility = ir::ast::Visibility::Protected;
+                }
+                let qualified_name = format!(
+                    "{}.{}",
+                    comp_name,
+                    subcomp.qualified_name.as_deref().unwrap_or(subcomp_name)
+                );
+                if options.hierarchy {
+                    scomp.hierarchy = qualified_name.split('.').map(String::from).collect();
+                    scomp.hierarchy.pop();
+                }
+                scomp.qualified_name = Some(qualified_name);
                 fclass.components.insert(name, scomp);
             }
 
@@ -119,5 +288,902 @@ pub fn flatten(def: &ir::ast::StoredDefinition) -> Result<ir::ast::ClassDefiniti
             fclass.components.swap_remove(comp_name);
         }
     }
+
+    // drop the connections of removed components
+    connections.retain(|eq| match eq {
+        ir::ast::Equation::Connect { lhs, rhs } => {
+            !is_removed(&lhs.to_string(), &removed) && !is_removed(&rhs.to_string(), &removed)
+        }
+        _ => true,
+    });
+
+    // expand connection sets
+    if options.expand_connects {
+        expand_connections(&mut fclass, &main_class, &class_dict, &connections)?;
+    } else {
+        fclass.equations.append(&mut connections);
+    }
+
+    // keep the enumeration types used by the flat class
+    for comp in fclass.components.values() {
+        if let Some(class) = class_dict.get(&comp.type_name.to_string())
+            && class.is_enumeration()
+        {
+            fclass
+                .classes
+                .insert(class.name.text.clone(), class.clone());
+        }
+    }
+
+    // unroll for equations of the main class
+    expand_for_equations(&mut fclass, &class_dict)?;
+
+    // expand constant ranges and array concatenation
+    scalarize(&mut fclass, options.preserve_arrays)?;
+
+    // inline parameters whose value is fixed at compile time
+    evaluate_parameters(&mut fclass)?;
+
+    // drop the equations that substitutions turned into tautologies
+    fclass.accept(&mut TautologyRemover::default());
     Ok(fclass)
 }
+
+/// Fails unless the main class is a `model`, a `block` or a general `class`,
+/// naming the restriction of the class. A `partial` class is only a base for
+/// other classes, so it is rejected as well.
+fn check_restriction(name: &str, class: &ir::ast::ClassDefinition) -> Result<()> {
+    if class.partial {
+        return Err(anyhow!(
+            "Class '{}' is partial and cannot be flattened for simulation, select a class extending it",
+            name
+        ));
+    }
+    let restriction = match &class.class_type {
+        ir::ast::ClassType::Empty
+        | ir::ast::ClassType::Class(..)
+        | ir::ast::ClassType::Model(..)
+        | ir::ast::ClassType::Block(..) => return Ok(()),
+        ir::ast::ClassType::Record(..) => "record",
+        ir::ast::ClassType::Connector(..) => "connector",
+        ir::ast::ClassType::Type(..) => "type",
+        ir::ast::ClassType::Package(..) => "package",
+        ir::ast::ClassType::Function(..) => "function",
+        ir::ast::ClassType::Operator(..) => "operator",
+    };
+    Err(anyhow!(
+        "Class '{}' is a {} and cannot be flattened for simulation, select a model or block",
+        name,
+        restriction
+    ))
+}
+
+/// Returns the name of the class of a component of the main class, found at
+/// the top level or, by its short name, among the nested classes of the main
+/// class.
+fn component_class(
+    type_name: &str,
+    main_class_name: &str,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+) -> Option<String> {
+    let nested = format!("{}.{}", main_class_name, type_name);
+    [type_name.to_string(), nested]
+        .into_iter()
+        .find(|name| class_dict.contains_key(name))
+}
+
+/// Adds `class` and its nested classes to `classes`, by qualified name.
+fn collect_classes(
+    name: &str,
+    class: &ir::ast::ClassDefinition,
+    classes: &mut IndexMap<String, ir::ast::ClassDefinition>,
+) {
+    classes.insert(name.to_string(), class.clone());
+    for (nested_name, nested) in &class.classes {
+        collect_classes(&format!("{}.{}", name, nested_name), nested, classes);
+    }
+}
+
+/// Qualifies the type names used by each class that refer to a class of an
+/// enclosing package, so that a class may refer to its siblings by their
+/// short name. Names of nested classes of the class itself and of top level
+/// classes are kept.
+fn qualify_class_names(classes: &mut IndexMap<String, ir::ast::ClassDefinition>) {
+    let names: IndexSet<String> = classes.keys().cloned().collect();
+    for (class_name, class) in classes.iter_mut() {
+        for extend in &mut class.extends {
+            qualify_name(&mut extend.comp, class_name, &names);
+        }
+        for comp in class.components.values_mut() {
+            qualify_name(&mut comp.type_name, class_name, &names);
+            if let Some(constraint) = &mut comp.constraint {
+                qualify_name(constraint, class_name, &names);
+            }
+            for redeclared in comp.redeclarations.values_mut() {
+                qualify_name(&mut redeclared.type_name, class_name, &names);
+            }
+            for redeclared in comp.redeclared_classes.values_mut() {
+                for extend in &mut redeclared.extends {
+                    qualify_name(&mut extend.comp, class_name, &names);
+                }
+            }
+        }
+        if let Some(constraint) = &mut class.constraint {
+            qualify_name(constraint, class_name, &names);
+        }
+        if let Some(der_class) = &mut class.der_class {
+            qualify_name(&mut der_class.class, class_name, &names);
+        }
+    }
+}
+
+/// Looks `name` up from the scope of the class `class_name` outwards, and
+/// replaces it by its qualified name when found in an enclosing package.
+fn qualify_name(name: &mut ir::ast::Name, class_name: &str, names: &IndexSet<String>) {
+    let Some(first) = name.name.first().cloned() else {
+        return;
+    };
+    let short_name = name.to_string();
+    let mut scope = Some(class_name);
+    while let Some(package) = scope {
+        let qualified = format!("{}.{}", package, short_name);
+        if names.contains(&qualified) {
+            if package != class_name {
+                name.name = qualified
+                    .split('.')
+                    .map(|part| ir::ast::Token {
+                        text: part.to_string(),
+                        ..first.clone()
+                    })
+                    .collect();
+            }
+            return;
+        }
+        scope = package.rsplit_once('.').map(|(parent, _)| parent);
+    }
+}
+
+/// Returns the height of the component and inheritance hierarchy below
+/// `class`, found at `depth`, failing once the hierarchy gets deeper than
+/// `max_depth`. Heights are memoized by class name.
+fn hierarchy_height(
+    class: &ir::ast::ClassDefinition,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+    depth: usize,
+    max_depth: usize,
+    heights: &mut IndexMap<String, usize>,
+) -> Result<usize> {
+    let height = match heights.get(&class.name.text) {
+        Some(height) => *height,
+        None if depth > max_depth => 0,
+        None => {
+            let children = class
+                .extends
+                .iter()
+                .map(|extend| extend.comp.to_string())
+                .chain(
+                    class
+                        .components
+                        .values()
+                        .map(|comp| comp.type_name.to_string()),
+                );
+            let mut height = 0;
+            for name in children {
+                if let Some(child) = class_dict.get(&name) {
+                    let child_height =
+                        hierarchy_height(child, class_dict, depth + 1, max_depth, heights)?;
+                    height = height.max(child_height + 1);
+                }
+            }
+            heights.insert(class.name.text.clone(), height);
+            height
+        }
+    };
+    if depth + height > max_depth {
+        return Err(anyhow!(
+            "Hierarchy exceeds the maximum depth of {} at class '{}'",
+            max_depth,
+            class.name.text
+        ));
+    }
+    Ok(height)
+}
+
+/// Merges the inheritance chain of a class into a single class without
+/// extends clauses. Inherited elements come first, with the modifications of
+/// the extends clause applied, so that the outermost modification of a
+/// component wins. A component declared more than once, by the class and a
+/// base class or by several base classes, must be declared the same way each
+/// time.
+fn inline_extends(
+    class: &ir::ast::ClassDefinition,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+    visited: &mut IndexSet<String>,
+) -> Result<ir::ast::ClassDefinition> {
+    if !visited.insert(class.name.text.clone()) {
+        return Err(anyhow!("Class '{}' extends itself", class.name.text));
+    }
+    let mut inlined = ir::ast::ClassDefinition {
+        name: class.name.clone(),
+        encapsulated: class.encapsulated,
+        classes: class.classes.clone(),
+        enumeration_literals: class.enumeration_literals.clone(),
+        annotation: class.annotation.clone(),
+        hints: class.hints.clone(),
+        ..Default::default()
+    };
+    for extend in &class.extends {
+        let class_name = extend.comp.to_string();
+        if is_type(&class_name, class_dict) {
+            continue;
+        }
+        let base = class_dict
+            .get(&class_name)
+            .ok_or_else(|| anyhow!("Class for extend '{}' not found", class_name))?;
+        let mut base = inline_extends(base, class_dict, visited)?;
+        apply_modifications(&mut base, &extend.modifications)?;
+        redeclare(&mut base, &extend.redeclarations, class_dict)?;
+        inherit_components(&mut inlined, &base.components, &class_name)?;
+        inlined.equations.extend(base.equations);
+        inlined.initial_equations.extend(base.initial_equations);
+        inlined.algorithms.extend(base.algorithms);
+        inlined.initial_algorithms.extend(base.initial_algorithms);
+    }
+    inherit_components(&mut inlined, &class.components, &class.name.text)?;
+    inlined.equations.extend(class.equations.clone());
+    inlined
+        .initial_equations
+        .extend(class.initial_equations.clone());
+    inlined.algorithms.extend(class.algorithms.clone());
+    inlined
+        .initial_algorithms
+        .extend(class.initial_algorithms.clone());
+    visited.swap_remove(&class.name.text);
+    Ok(inlined)
+}
+
+/// Adds the `components` declared by the class `origin` to `class`. A name
+/// already declared by the class or another of its base classes is only
+/// accepted when both declarations are identical.
+fn inherit_components(
+    class: &mut ir::ast::ClassDefinition,
+    components: &IndexMap<String, ir::ast::Component>,
+    origin: &str,
+) -> Result<()> {
+    for (name, comp) in components {
+        if let Some(declared) = class.components.get(name)
+            && !declared.structurally_eq(comp)
+        {
+            return Err(anyhow!(
+                "Duplicate declaration of '{}' in class '{}', the declaration from '{}' differs from an earlier one",
+                name,
+                class.name.text,
+                origin
+            ));
+        }
+        class.components.insert(name.clone(), comp.clone());
+    }
+    Ok(())
+}
+
+/// Applies the `modifications` of an extends clause or a component to the
+/// components of `class`. A modified component takes the modification as its
+/// binding, and a nested modification such as `k(start = 2)` or `sub.k = 2`
+/// is passed on to the modifications of the component `k` or `sub`.
+fn apply_modifications(
+    class: &mut ir::ast::ClassDefinition,
+    modifications: &IndexMap<String, ir::ast::Expression>,
+) -> Result<()> {
+    for (name, expr) in modifications {
+        let (comp_name, sub_name) = match name.split_once('.') {
+            Some((comp_name, sub_name)) => (comp_name, Some(sub_name)),
+            None => (name.as_str(), None),
+        };
+        let comp = class.components.get_mut(comp_name).ok_or_else(|| {
+            anyhow!(
+                "Modified component '{}' not found in class '{}'",
+                comp_name,
+                class.name.text
+            )
+        })?;
+        match sub_name {
+            None if *expr == ir::ast::Expression::Empty => {}
+            None => comp.start = expr.clone(),
+            Some(sub_name) => {
+                if sub_name == "start" {
+                    comp.start = expr.clone();
+                }
+                comp.modifications
+                    .insert(sub_name.to_string(), expr.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces replaceable components of `class` by their redeclarations. A
+/// redeclared type must satisfy the constraint of the replaced component,
+/// components that are not redeclared keep their default type. The
+/// redeclaration of a subcomponent, such as `sub.x`, is checked against the
+/// class of `sub` and kept by `sub` for when it is expanded.
+fn redeclare(
+    class: &mut ir::ast::ClassDefinition,
+    redeclarations: &IndexMap<String, ir::ast::Component>,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+) -> Result<()> {
+    for (path, redeclared) in redeclarations {
+        let (name, sub_path) = match path.split_once('.') {
+            Some((name, sub_path)) => (name, Some(sub_path)),
+            None => (path.as_str(), None),
+        };
+        let comp = class.components.get_mut(name).ok_or_else(|| {
+            anyhow!(
+                "Redeclared component '{}' not found in class '{}'",
+                name,
+                class.name.text
+            )
+        })?;
+        if let Some(sub_path) = sub_path {
+            let sub_class = class_dict.get(&comp.type_name.to_string()).ok_or_else(|| {
+                anyhow!(
+                    "Class '{}' of component '{}' not found",
+                    comp.type_name,
+                    name
+                )
+            })?;
+            let sub_redeclarations = IndexMap::from([(sub_path.to_string(), redeclared.clone())]);
+            let mut sub_class = inline_extends(sub_class, class_dict, &mut IndexSet::new())?;
+            redeclare(&mut sub_class, &sub_redeclarations, class_dict)?;
+            comp.redeclarations.extend(sub_redeclarations);
+            continue;
+        }
+        if !comp.replaceable {
+            return Err(anyhow!(
+                "Component '{}' of class '{}' is not replaceable",
+                name,
+                class.name.text
+            ));
+        }
+        let constraint = comp.constraint.as_ref().unwrap_or(&comp.type_name);
+        if !satisfies(
+            &redeclared.type_name.to_string(),
+            &constraint.to_string(),
+            class_dict,
+            0,
+        ) {
+            return Err(anyhow!(
+                "Redeclared type '{}' of '{}' does not satisfy the constraint '{}'",
+                redeclared.type_name,
+                name,
+                constraint
+            ));
+        }
+
+        // prefixes that are not given are kept from the replaced declaration
+        let mut value = redeclared.clone();
+        value.name = comp.name.clone();
+        if value.variability == ir::ast::Variability::Empty {
+            value.variability = comp.variability.clone();
+        }
+        if value.causality == ir::ast::Causality::Empty {
+            value.causality = comp.causality.clone();
+        }
+        if value.connection == ir::ast::Connection::Empty {
+            value.connection = comp.connection.clone();
+        }
+        *comp = value;
+    }
+    Ok(())
+}
+
+/// Returns the packages that the nested classes of the class `class_name`
+/// stand for, by their short name, such as `PartialMedium` for `Medium` when
+/// declared as `replaceable package Medium = PartialMedium`. A class
+/// redeclared by `redeclarations` stands for its new base class instead, which
+/// must satisfy the constraint of the replaced class.
+fn nested_packages(
+    class_name: &str,
+    redeclarations: &IndexMap<String, ir::ast::ClassDefinition>,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+) -> Result<IndexMap<String, String>> {
+    let prefix = format!("{}.", class_name);
+    let mut packages = IndexMap::new();
+    for name in class_dict.keys() {
+        if let Some(short_name) = name.strip_prefix(&prefix)
+            && !short_name.contains('.')
+        {
+            packages.insert(short_name.to_string(), package_alias(name, class_dict));
+        }
+    }
+    for (name, redeclared) in redeclarations {
+        let qualified = format!("{}{}", prefix, name);
+        let class = class_dict.get(&qualified).ok_or_else(|| {
+            anyhow!(
+                "Redeclared class '{}' not found in class '{}'",
+                name,
+                class_name
+            )
+        })?;
+        if !class.replaceable {
+            return Err(anyhow!(
+                "Class '{}' of class '{}' is not replaceable",
+                name,
+                class_name
+            ));
+        }
+        let constraint = match (&class.constraint, class.extends.as_slice()) {
+            (Some(constraint), _) => constraint.to_string(),
+            (None, [base]) => base.comp.to_string(),
+            (None, _) => qualified.clone(),
+        };
+        // a redeclaration is a short class definition, extending a single class
+        let base = redeclared
+            .extends
+            .first()
+            .map(|base| base.comp.to_string())
+            .unwrap_or_default();
+        if !satisfies(&base, &constraint, class_dict, 0) {
+            return Err(anyhow!(
+                "Redeclared class '{}' of '{}' does not satisfy the constraint '{}'",
+                base,
+                name,
+                constraint
+            ));
+        }
+        packages.insert(name.clone(), package_alias(&base, class_dict));
+    }
+    Ok(packages)
+}
+
+/// Follows a chain of short class definitions, such as
+/// `package Medium = PartialMedium`, to the class they stand for.
+fn package_alias(name: &str, class_dict: &IndexMap<String, ir::ast::ClassDefinition>) -> String {
+    let mut visited = IndexSet::new();
+    let mut name = name.to_string();
+    while let Some(class) = class_dict.get(&name)
+        && let [base] = class.extends.as_slice()
+        && class.components.is_empty()
+        && class.classes.is_empty()
+        && class.equations.is_empty()
+        && class_dict.contains_key(&base.comp.to_string())
+        && visited.insert(name.clone())
+    {
+        name = base.comp.to_string();
+    }
+    name
+}
+
+/// Returns the values of the constants of `packages`, and of the classes they
+/// extend, by the short name of the package, such as `Medium.X`. Constants of
+/// a package take precedence over inherited ones.
+fn package_constants(
+    packages: &IndexMap<String, String>,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+    constants: &IndexMap<String, Value>,
+) -> IndexMap<String, Value> {
+    let mut values = IndexMap::new();
+    for (short_name, package) in packages {
+        let mut visited = IndexSet::new();
+        let mut bases = vec![package.clone()];
+        while let Some(base) = bases.pop() {
+            if !visited.insert(base.clone()) {
+                continue;
+            }
+            let prefix = format!("{}.", base);
+            for (name, value) in constants {
+                if let Some(member) = name.strip_prefix(&prefix) {
+                    values
+                        .entry(format!("{}.{}", short_name, member))
+                        .or_insert_with(|| value.clone());
+                }
+            }
+            if let Some(class) = class_dict.get(&base) {
+                bases.extend(class.extends.iter().rev().map(|e| e.comp.to_string()));
+            }
+        }
+    }
+    values
+}
+
+/// Tells whether the type `name` satisfies the `constraint` of a replaceable
+/// component, that is if it is the constraining type, extends it, or declares
+/// every component of the constraining class.
+fn satisfies(
+    name: &str,
+    constraint: &str,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+    depth: usize,
+) -> bool {
+    if name == constraint {
+        return true;
+    }
+    let Some(class) = class_dict.get(name) else {
+        return false;
+    };
+    if depth < class_dict.len()
+        && class
+            .extends
+            .iter()
+            .any(|extend| satisfies(&extend.comp.to_string(), constraint, class_dict, depth + 1))
+    {
+        return true;
+    }
+    class_dict.get(constraint).is_some_and(|constraint| {
+        !constraint.is_enumeration()
+            && constraint.components.iter().all(|(name, comp)| {
+                class
+                    .components
+                    .get(name)
+                    .is_some_and(|c| c.type_name.to_string() == comp.type_name.to_string())
+            })
+    })
+}
+
+/// Removes the conditional components of `class` whose condition is false
+/// and returns their names. Only connect equations may use a conditional
+/// component, they are dropped along with it.
+fn remove_disabled_components(class: &mut ir::ast::ClassDefinition) -> Result<IndexSet<String>> {
+    let mut evaluator = ConstEvaluator::default();
+    evaluator.add_components(&class.components)?;
+    let mut removed = IndexSet::new();
+    for (name, comp) in &class.components {
+        if let Some(condition) = &comp.condition {
+            let enabled = evaluator
+                .eval(condition)
+                .and_then(|value| value.as_bool())
+                .map_err(|err| {
+                    anyhow!(
+                        "Condition of component '{}' in class '{}' must be a Boolean parameter expression: {}",
+                        name,
+                        class.name.text,
+                        err
+                    )
+                })?;
+            if !enabled {
+                removed.insert(name.clone());
+            }
+        }
+    }
+    class.components.retain(|name, _| !removed.contains(name));
+    check_removed_usage(&class.equations, &removed, &class.name.text)?;
+    check_removed_usage(&class.initial_equations, &removed, &class.name.text)?;
+    Ok(removed)
+}
+
+/// Fails when an equation, other than a connect equation, uses a removed
+/// component.
+fn check_removed_usage(
+    equations: &[ir::ast::Equation],
+    removed: &IndexSet<String>,
+    class_name: &str,
+) -> Result<()> {
+    if removed.is_empty() {
+        return Ok(());
+    }
+    for eq in equations {
+        if let ir::ast::Equation::Connect { .. } = eq {
+            continue;
+        }
+        let mut collector = ComponentRefCollector::default();
+        eq.clone().accept(&mut collector);
+        if let Some(name) = collector
+            .names
+            .iter()
+            .find(|name| is_removed(name, removed))
+        {
+            return Err(anyhow!(
+                "Component '{}' of class '{}' is removed by its condition, but used outside of a connect equation",
+                name,
+                class_name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns true when `name` refers to a removed component or one of its
+/// elements.
+fn is_removed(name: &str, removed: &IndexSet<String>) -> bool {
+    removed.iter().any(|path| {
+        name.strip_prefix(path.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+    })
+}
+
+fn expand_for_equations(
+    class: &mut ir::ast::ClassDefinition,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+) -> Result<()> {
+    let mut for_expander = ForExpander::default();
+    for_expander.evaluator.add_components(&class.components)?;
+    for_expander.add_enumerations(class_dict);
+    for_expander.add_enumerations(&class.classes);
+    class.accept(&mut for_expander);
+    match for_expander.errors.first() {
+        Some(err) => Err(anyhow!("failed to expand for equation: {}", err)),
+        None => Ok(()),
+    }
+}
+
+fn substitute_constants(
+    class: &mut ir::ast::ClassDefinition,
+    constants: &IndexMap<String, Value>,
+) -> Result<()> {
+    let mut substituter = ConstantSubstituter {
+        constants: constants.clone(),
+        errors: Vec::new(),
+    };
+    class.accept(&mut substituter);
+    for comp in class.components.values_mut() {
+        comp.start.accept(&mut substituter);
+        for expr in comp.modifications.values_mut() {
+            expr.accept(&mut substituter);
+        }
+    }
+    match substituter.errors.first() {
+        Some(err) => Err(anyhow!("failed to substitute constants: {}", err)),
+        None => Ok(()),
+    }
+}
+
+/// Replaces the parameters annotated with `Evaluate = true` by their values
+/// and removes them from the class. Parameters whose binding cannot be
+/// evaluated are kept.
+fn evaluate_parameters(class: &mut ir::ast::ClassDefinition) -> Result<()> {
+    let mut evaluator = ConstEvaluator::default();
+    evaluator.add_components(&class.components)?;
+    let mut substituter = ConstantSubstituter {
+        constants: IndexMap::new(),
+        errors: Vec::new(),
+    };
+    for (name, comp) in &class.components {
+        let evaluate = matches!(
+            comp.annotation.get("Evaluate"),
+            Some(ir::ast::Expression::Terminal {
+                terminal_type: ir::ast::TerminalType::Bool,
+                token,
+            }) if token.text == "true"
+        );
+        if evaluate
+            && matches!(comp.variability, ir::ast::Variability::Parameter(..))
+            && let Some(value) = evaluator.values.get(name)
+        {
+            substituter.constants.insert(name.clone(), value.clone());
+        }
+    }
+    if substituter.constants.is_empty() {
+        return Ok(());
+    }
+
+    class
+        .components
+        .retain(|name, _| !substituter.constants.contains_key(name));
+    class.accept(&mut substituter);
+    for eq in &mut class.initial_equations {
+        eq.accept(&mut substituter);
+    }
+    for stmt in class
+        .algorithms
+        .iter_mut()
+        .chain(class.initial_algorithms.iter_mut())
+        .flatten()
+    {
+        stmt.accept(&mut substituter);
+    }
+    for comp in class.components.values_mut() {
+        comp.start.accept(&mut substituter);
+        for expr in comp.modifications.values_mut() {
+            expr.accept(&mut substituter);
+        }
+    }
+    Ok(())
+}
+
+fn resolve_builtins(class: &mut ir::ast::ClassDefinition) {
+    let mut resolver = BuiltinResolver;
+    class.accept(&mut resolver);
+    for comp in class.components.values_mut() {
+        comp.start.accept(&mut resolver);
+        for expr in comp.modifications.values_mut() {
+            expr.accept(&mut resolver);
+        }
+    }
+}
+
+/// Expands constant ranges, array constructors and concatenations. Unless
+/// `preserve_arrays`, arithmetic on array variables is expanded element by
+/// element and equations between arrays are split per element.
+fn scalarize(class: &mut ir::ast::ClassDefinition, preserve_arrays: bool) -> Result<()> {
+    // constant ranges become arrays first, so that they can be concatenated
+    let mut range_expander = RangeExpander::default();
+    range_expander.evaluator.add_components(&class.components)?;
+    class.accept(&mut range_expander);
+    let mut scalarizer = Scalarizer {
+        evaluator: range_expander.evaluator.clone(),
+        ..Default::default()
+    };
+    for (name, comp) in class.components.iter_mut() {
+        comp.start.accept(&mut range_expander);
+        // array variables are kept whole, with constant dimensions
+        for sub in &mut comp.shape {
+            if let ir::ast::Subscript::Expression(expr) = sub
+                && let Ok(value) = range_expander.evaluator.eval(expr)
+            {
+                *expr = value.to_expression();
+            }
+        }
+        let shape: Option<Vec<usize>> = comp
+            .shape
+            .iter()
+            .map(|sub| match sub {
+                ir::ast::Subscript::Expression(expr) => range_expander
+                    .evaluator
+                    .eval(expr)
+                    .and_then(|v| v.as_i64())
+                    .ok()
+                    .and_then(|n| usize::try_from(n).ok()),
+                _ => None,
+            })
+            .collect();
+        if let Some(shape) = shape
+            && !shape.is_empty()
+            && !preserve_arrays
+        {
+            scalarizer.shapes.insert(name.clone(), shape);
+        }
+    }
+    class.accept(&mut scalarizer);
+    for comp in class.components.values_mut() {
+        comp.start.accept(&mut scalarizer);
+    }
+    match scalarizer.errors.first() {
+        Some(err) => Err(anyhow!("failed to scalarize: {}", err)),
+        None => Ok(()),
+    }
+}
+
+/// Replaces the type of components declared with a derivative type, such as
+/// `v` in `type Velocity = der(Real, x); Velocity v;`, by the base type of the
+/// derivative, and links the component to the variable it derives with the
+/// equation `v = der(x)`.
+fn resolve_der_types(
+    class: &mut ir::ast::ClassDefinition,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+) -> Result<()> {
+    for (comp_name, comp) in class.components.iter_mut() {
+        let type_name = comp.type_name.to_string();
+        // nested classes of the class keep their short name
+        let Some(der_class) = class
+            .classes
+            .get(&type_name)
+            .or_else(|| class_dict.get(&type_name))
+            .and_then(|class| class.der_class.as_ref())
+        else {
+            continue;
+        };
+        let [variable] = der_class.variables.as_slice() else {
+            return Err(anyhow!(
+                "Component '{}' of type '{}' derives {} variables, a component may only be the derivative of one",
+                comp_name,
+                type_name,
+                der_class.variables.len()
+            ));
+        };
+        comp.type_name = der_class.class.clone();
+        class.equations.push(ir::ast::Equation::Simple {
+            lhs: ir::ast::Expression::ComponentReference(ir::ast::ComponentReference::new(
+                comp_name,
+            )),
+            rhs: ir::ast::Expression::FunctionCall {
+                comp: ir::ast::ComponentReference::new("der"),
+                args: vec![ir::ast::Expression::ComponentReference(
+                    ir::ast::ComponentReference {
+                        local: false,
+                        parts: vec![ir::ast::ComponentRefPart {
+                            ident: variable.clone(),
+                            subs: None,
+                        }],
+                    },
+                )],
+            },
+            origin: None,
+        });
+    }
+    Ok(())
+}
+
+/// Replaces the type of components declared with a type alias, such as
+/// `type T = Real(start=2)`, by the aliased built-in type. Modifications of the
+/// alias apply unless the component overrides them, so the start value is taken
+/// from the component binding or modification, then the alias, then the
+/// built-in default.
+fn resolve_type_aliases(
+    class: &mut ir::ast::ClassDefinition,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+) {
+    for comp in class.components.values_mut() {
+        let Some(alias) = type_alias(&comp.type_name.to_string(), &class.classes, class_dict)
+        else {
+            continue;
+        };
+        // the dimensions of `V x[2]` with `type V = Real[3]` are [2, 3]
+        comp.shape.extend(alias.shape);
+        if comp.causality == ir::ast::Causality::Empty {
+            comp.causality = alias.causality;
+        }
+        for (name, expr) in alias.modifications {
+            comp.modifications.entry(name).or_insert(expr);
+        }
+        if comp.start == ir::ast::Expression::Empty {
+            comp.start = match comp.modifications.get("start") {
+                Some(start) => start.clone(),
+                None => Type::from_name(&alias.base.to_string()).default_start(),
+            };
+        }
+        comp.type_name = alias.base;
+    }
+}
+
+/// Returns true when `name` is a built-in type or a type alias. Extending a
+/// type inherits its attributes, which `type_alias` collects, rather than
+/// components and equations.
+fn is_type(name: &str, class_dict: &IndexMap<String, ir::ast::ClassDefinition>) -> bool {
+    Type::from_name(name) != Type::Unknown
+        || type_alias(name, &IndexMap::new(), class_dict).is_some()
+}
+
+/// A chain of type aliases resolved down to its built-in type.
+struct TypeAlias {
+    base: ir::ast::Name,
+    /// modifications collected along the chain, the outermost alias taking
+    /// precedence
+    modifications: IndexMap<String, ir::ast::Expression>,
+    causality: ir::ast::Causality,
+    /// dimensions of the aliases, outermost first
+    shape: Vec<ir::ast::Subscript>,
+}
+
+/// Follows a chain of type aliases down to a built-in type, collecting the
+/// modifications, base prefix and dimensions along the way. Names are looked
+/// up among the `nested` classes of the class using the alias first, as they
+/// keep their short name. A `type` extending a single type, such as
+/// `type V extends Voltage(min = 0); end V;`, is an alias too.
+fn type_alias(
+    name: &str,
+    nested: &IndexMap<String, ir::ast::ClassDefinition>,
+    class_dict: &IndexMap<String, ir::ast::ClassDefinition>,
+) -> Option<TypeAlias> {
+    let mut modifications = IndexMap::new();
+    let mut causality = ir::ast::Causality::Empty;
+    let mut shape = Vec::new();
+    let mut visited = IndexSet::new();
+    let mut name = name.to_string();
+    loop {
+        let class = nested.get(&name).or_else(|| class_dict.get(&name))?;
+        let extends_type = matches!(class.class_type, ir::ast::ClassType::Type(..))
+            && class.extends.len() == 1
+            && class.components.is_empty();
+        if !(class.short_class || extends_type) || !visited.insert(name.clone()) {
+            return None;
+        }
+        if causality == ir::ast::Causality::Empty {
+            causality = class.causality.clone();
+        }
+        shape.extend(class.shape.iter().cloned());
+        let base = &class.extends[0];
+        for (mod_name, expr) in &base.modifications {
+            modifications
+                .entry(mod_name.clone())
+                .or_insert_with(|| expr.clone());
+        }
+        name = base.comp.to_string();
+        if Type::from_name(&name) != Type::Unknown {
+            return Some(TypeAlias {
+                base: base.comp.clone(),
+                modifications,
+                causality,
+                shape,
+            });
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! This module finds the free variables of an expression.
+//!
+//! `Expression::free_variables` returns the component references that an
+//! expression depends on, which is what substitution and inlining must look
+//! at. Unlike collecting every `ComponentReference`, names bound inside the
+//! expression are excluded:
+//! - the indices of an array comprehension `{i + k for i in 1:3}` within its
+//!   body, so only `k` is free; the ranges are evaluated outside the
+//!   comprehension and may still refer to free variables,
+//! - the names of called functions, `sin(x)` only depends on `x`.
+//!
+//! `ClassDefinition::free_variables` does the same for the body of a function,
+//! its bindings and algorithms, where the inputs, outputs and protected
+//! variables of the function are bound, as well as the indices of `for`
+//! statements within their body.
+//!
+//! References are returned once per name, in order of first appearance.
+//! Subscripts are part of the reference and are not searched.
+use crate::ir::ast::{ClassDefinition, ComponentReference, Expression, Statement};
+use indexmap::IndexSet;
+
+impl Expression {
+    pub fn free_variables(&self) -> IndexSet<ComponentReference> {
+        let mut free = IndexSet::new();
+        collect(self, &mut Vec::new(), &mut free);
+        free
+    }
+}
+
+impl ClassDefinition {
+    pub fn free_variables(&self) -> IndexSet<ComponentReference> {
+        let mut free = IndexSet::new();
+        let mut bound: Vec<String> = self.components.keys().cloned().collect();
+        for comp in self.components.values() {
+            collect(&comp.start, &mut bound, &mut free);
+        }
+        for stmt in self.algorithms.iter().flatten() {
+            collect_statement(stmt, &mut bound, &mut free);
+        }
+        free
+    }
+}
+
+fn collect_reference(
+    cref: &ComponentReference,
+    bound: &[String],
+    free: &mut IndexSet<ComponentReference>,
+) {
+    // `x[i]` and `r.a` are bound with `x` and `r`
+    let is_bound = cref
+        .parts
+        .first()
+        .is_some_and(|part| bound.contains(&part.ident.text));
+    if !is_bound {
+        free.insert(cref.clone());
+    }
+}
+
+fn collect_statement(
+    stmt: &Statement,
+    bound: &mut Vec<String>,
+    free: &mut IndexSet<ComponentReference>,
+) {
+    match stmt {
+        Statement::Assignment { comp, value } => {
+            collect_reference(comp, bound, free);
+            collect(value, bound, free);
+        }
+        Statement::For { indices, body } => {
+            let depth = bound.len();
+            for index in indices {
+                collect(&index.range, bound, free);
+                bound.push(index.ident.text.clone());
+            }
+            for stmt in body {
+                collect_statement(stmt, bound, free);
+            }
+            bound.truncate(depth);
+        }
+        Statement::If {
+            cond_blocks,
+            else_block,
+        } => {
+            for block in cond_blocks {
+                collect(&block.cond, bound, free);
+                for stmt in &block.stmts {
+                    collect_statement(stmt, bound, free);
+                }
+            }
+            for stmt in else_block.iter().flatten() {
+                collect_statement(stmt, bound, free);
+            }
+        }
+        Statement::While(block) => {
+            collect(&block.cond, bound, free);
+            for stmt in &block.stmts {
+                collect_statement(stmt, bound, free);
+            }
+        }
+        Statement::When(blocks) => {
+            for block in blocks {
+                collect(&block.cond, bound, free);
+                for stmt in &block.stmts {
+                    collect_statement(stmt, bound, free);
+                }
+            }
+        }
+        Statement::FunctionCall { args, outputs, .. } => {
+            for expr in args.iter().chain(outputs) {
+                collect(expr, bound, free);
+            }
+        }
+        Statement::Return { .. } | Statement::Break { .. } | Statement::Empty => {}
+    }
+}
+
+fn collect(expr: &Expression, bound: &mut Vec<String>, free: &mut IndexSet<ComponentReference>) {
+    match expr {
+        Expression::ComponentReference(cref) => collect_reference(cref, bound, free),
+        Expression::Unary { rhs, .. } => collect(rhs, bound, free),
+        Expression::Binary { lhs, rhs, .. } => {
+            collect(lhs, bound, free);
+            collect(rhs, bound, free);
+        }
+        Expression::FunctionCall { args: exprs, .. } | Expression::Array { elements: exprs } => {
+            for expr in exprs {
+                collect(expr, bound, free);
+            }
+        }
+        Expression::ArrayComprehension { expr, indices } => {
+            // a range may refer to the indices before it
+            let depth = bound.len();
+            for index in indices {
+                collect(&index.range, bound, free);
+                bound.push(index.ident.text.clone());
+            }
+            collect(expr, bound, free);
+            bound.truncate(depth);
+        }
+        Expression::Range { start, step, end } => {
+            collect(start, bound, free);
+            if let Some(step) = step {
+                collect(step, bound, free);
+            }
+            collect(end, bound, free);
+        }
+        Expression::If {
+            branches,
+            else_branch,
+        } => {
+            for (cond, then) in branches {
+                collect(cond, bound, free);
+                collect(then, bound, free);
+            }
+            collect(else_branch, bound, free);
+        }
+        Expression::Terminal { .. } | Expression::Empty => {}
+    }
+}
@@ -0,0 +1,57 @@
+//! This module measures the size of expressions, for heuristics of code
+//! generators such as common subexpression elimination thresholds or not
+//! inlining large function bodies.
+//!
+//! - `Expression::node_count` counts the expressions of the tree, including
+//!   the expression itself, so a literal counts 1 and `a + b` counts 3.
+//! - `Expression::depth` is the number of expressions on the longest path
+//!   from the expression down to a leaf, so `a + b` has depth 2.
+//!
+//! `Expression::Empty` has neither nodes nor depth. Subscripts of component
+//! references are not counted.
+use crate::ir::ast::Expression;
+
+impl Expression {
+    pub fn node_count(&self) -> usize {
+        match self {
+            Expression::Empty => 0,
+            _ => 1 + children(self).iter().map(|e| e.node_count()).sum::<usize>(),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        match self {
+            Expression::Empty => 0,
+            _ => 1 + children(self).iter().map(|e| e.depth()).max().unwrap_or(0),
+        }
+    }
+}
+
+fn children(expr: &Expression) -> Vec<&Expression> {
+    match expr {
+        Expression::Empty | Expression::Terminal { .. } | Expression::ComponentReference(..) => {
+            vec![]
+        }
+        Expression::Unary { rhs, .. } => vec![rhs],
+        Expression::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+        Expression::FunctionCall { args, .. } => args.iter().collect(),
+        Expression::Array { elements } => elements.iter().collect(),
+        Expression::ArrayComprehension { expr, indices } => indices
+            .iter()
+            .map(|index| &index.range)
+            .chain(std::iter::once(expr.as_ref()))
+            .collect(),
+        Expression::If {
+            branches,
+            else_branch,
+        } => branches
+            .iter()
+            .flat_map(|(cond, then)| [cond, then])
+            .chain(std::iter::once(else_branch.as_ref()))
+            .collect(),
+        Expression::Range { start, step, end } => std::iter::once(start.as_ref())
+            .chain(step.as_deref())
+            .chain(std::iter::once(end.as_ref()))
+            .collect(),
+    }
+}
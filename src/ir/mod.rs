@@ -1,5 +1,16 @@
 pub mod ast;
+pub mod connections;
+pub mod const_eval;
 pub mod create_dae;
+pub mod diff;
+pub mod equality;
+pub mod flat_modelica;
 pub mod flatten;
+pub mod free_variables;
+pub mod metrics;
+pub mod rename;
+pub mod rpn;
+pub mod types;
+pub mod validate;
 pub mod visitor;
 pub mod visitors;
@@ -0,0 +1,53 @@
+//! This module provides renaming of classes within a `StoredDefinition`.
+//!
+//! `StoredDefinition::rename_class` renames a class and updates every
+//! reference to it, so that refactoring tools do not leave dangling
+//! component types or `extends` clauses behind. References are matched on the
+//! full (dotted) name, and the location of the original reference is kept.
+use crate::ir::ast::{Name, StoredDefinition, Token};
+use anyhow::{Result, anyhow};
+
+impl StoredDefinition {
+    pub fn rename_class(&mut self, old: &str, new: &str) -> Result<()> {
+        if !self.class_list.contains_key(old) {
+            return Err(anyhow!("Class '{}' not found", old));
+        }
+        if self.class_list.contains_key(new) {
+            return Err(anyhow!("Class '{}' already exists", new));
+        }
+
+        // rename the class, keeping its position in the class list
+        self.class_list = self
+            .class_list
+            .drain(..)
+            .map(|(name, mut class)| {
+                if name == old {
+                    class.name.text = new.to_string();
+                    (new.to_string(), class)
+                } else {
+                    (name, class)
+                }
+            })
+            .collect();
+
+        // update references to the class
+        for class in self.class_list.values_mut() {
+            for comp in class.components.values_mut() {
+                rename_reference(&mut comp.type_name, old, new);
+            }
+            for extend in &mut class.extends {
+                rename_reference(&mut extend.comp, old, new);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn rename_reference(name: &mut Name, old: &str, new: &str) {
+    if name.to_string() == old {
+        name.name = vec![Token {
+            text: new.to_string(),
+            ..name.name.first().cloned().unwrap_or_default()
+        }];
+    }
+}
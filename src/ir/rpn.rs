@@ -0,0 +1,106 @@
+//! This module lowers expressions to reverse Polish notation, for the stack
+//! based interpreters of embedded targets.
+//!
+//! `Expression::to_rpn` returns the operations evaluating an expression on a
+//! stack, without recursion. The operands of an operation are pushed before
+//! it, so `a + b * c` becomes `a b c * +`:
+//! - A function call pops its arguments, pushed in order, and pushes its
+//!   result.
+//! - An `if` expression pushes its condition and both of its values before
+//!   a `Select`, which keeps one of the values. `elseif` branches are nested
+//!   in the value of the `else` branch.
+//!
+//! Arrays, ranges and string literals have no scalar value and are rejected,
+//! so expressions are expected to be scalarized first.
+use crate::ir::ast::{Expression, OpBinary, OpUnary, TerminalType};
+use crate::ir::const_eval::Value;
+use anyhow::{Result, anyhow};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpnOp {
+    /// pushes a literal value
+    Literal(Value),
+    /// pushes the value of a variable, by name
+    Variable(String),
+    /// pops the operand and pushes the result
+    Unary(OpUnary),
+    /// pops the right, then the left operand and pushes the result
+    Binary(OpBinary),
+    /// pops `args` arguments and pushes the result of the function `name`
+    Call { name: String, args: usize },
+    /// pops the else value, the then value and the condition, and pushes the
+    /// then value if the condition holds, otherwise the else value
+    Select,
+}
+
+impl Expression {
+    pub fn to_rpn(&self) -> Result<Vec<RpnOp>> {
+        let mut ops = Vec::new();
+        lower(self, &mut ops)?;
+        Ok(ops)
+    }
+}
+
+fn lower(expr: &Expression, ops: &mut Vec<RpnOp>) -> Result<()> {
+    match expr {
+        Expression::Terminal {
+            terminal_type,
+            token,
+        } => {
+            let value = match terminal_type {
+                TerminalType::UnsignedInteger => Value::Integer(token.text.parse()?),
+                TerminalType::UnsignedReal => Value::Real(token.text.parse()?),
+                TerminalType::Bool => Value::Bool(token.text == "true"),
+                _ => return Err(anyhow!("cannot lower the literal '{}'", token.text)),
+            };
+            ops.push(RpnOp::Literal(value));
+        }
+        Expression::ComponentReference(cref) => ops.push(RpnOp::Variable(cref.to_string())),
+        Expression::Unary { op, rhs } => {
+            lower(rhs, ops)?;
+            ops.push(RpnOp::Unary(op.clone()));
+        }
+        Expression::Binary { op, lhs, rhs } => {
+            lower(lhs, ops)?;
+            lower(rhs, ops)?;
+            ops.push(RpnOp::Binary(op.clone()));
+        }
+        Expression::FunctionCall { comp, args } => {
+            for arg in args {
+                lower(arg, ops)?;
+            }
+            ops.push(RpnOp::Call {
+                name: comp.to_string(),
+                args: args.len(),
+            });
+        }
+        Expression::If {
+            branches,
+            else_branch,
+        } => lower_if(branches, else_branch, ops)?,
+        Expression::Empty => return Err(anyhow!("cannot lower an empty expression")),
+        Expression::Array { .. }
+        | Expression::ArrayComprehension { .. }
+        | Expression::Range { .. } => {
+            return Err(anyhow!("cannot lower the array expression {:?}", expr));
+        }
+    }
+    Ok(())
+}
+
+fn lower_if(
+    branches: &[(Expression, Expression)],
+    else_branch: &Expression,
+    ops: &mut Vec<RpnOp>,
+) -> Result<()> {
+    match branches.split_first() {
+        Some(((cond, then), rest)) => {
+            lower(cond, ops)?;
+            lower(then, ops)?;
+            lower_if(rest, else_branch, ops)?;
+            ops.push(RpnOp::Select);
+            Ok(())
+        }
+        None => lower(else_branch, ops),
+    }
+}
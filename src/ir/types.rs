@@ -0,0 +1,198 @@
+//! This module provides a minimal type inference for IR expressions.
+//!
+//! `type_of` computes the built-in type of an expression given the components
+//! that are in scope. Component references are resolved by their first part,
+//! so references into sub-components and references to unknown names yield
+//! `Type::Unknown`, which callers treat as compatible with anything.
+//!
+//! # Rules
+//! - Literals have their obvious type, `end` is an `Integer`.
+//! - `+`, `-` and `*` on two `Integer`s are `Integer`, otherwise `Real`.
+//! - `/` always produces a `Real`. `^` produces an `Integer` for an `Integer`
+//!   base and a literal, thus non-negative, `Integer` exponent, otherwise a
+//!   `Real`.
+//! - `+` on two `String`s is a `String` (concatenation).
+//! - Relational and logical operators produce a `Boolean`.
+//! - Calls of the built-in mathematical functions produce a `Real`, also when
+//!   qualified by a math package such as `Modelica.Math.sin`.
+use crate::ir::ast::{Component, Expression, OpBinary, OpUnary, TerminalType, Token};
+use indexmap::IndexMap;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    #[default]
+    Unknown,
+    Real,
+    Integer,
+    Boolean,
+    String,
+}
+
+impl Type {
+    /// Returns the built-in type named by a component's `type_name`.
+    pub fn from_name(name: &str) -> Type {
+        match name {
+            "Real" => Type::Real,
+            "Integer" => Type::Integer,
+            "Boolean" => Type::Boolean,
+            "String" => Type::String,
+            _ => Type::Unknown,
+        }
+    }
+
+    /// Returns the built-in default `start` value of the type, or an empty
+    /// expression if the type has none.
+    pub fn default_start(&self) -> Expression {
+        let (terminal_type, text) = match self {
+            Type::Real => (TerminalType::UnsignedReal, "0.0"),
+            Type::Integer => (TerminalType::UnsignedInteger, "0"),
+            Type::Boolean => (TerminalType::Bool, "false"),
+            _ => return Expression::Empty,
+        };
+        Expression::Terminal {
+            terminal_type,
+            token: Token {
+                text: text.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Real | Type::Integer)
+    }
+
+    /// Two types are compatible if they are equal, both numeric, or either is
+    /// unknown.
+    pub fn is_compatible(&self, other: &Type) -> bool {
+        *self == Type::Unknown
+            || *other == Type::Unknown
+            || self == other
+            || (self.is_numeric() && other.is_numeric())
+    }
+}
+
+/// Built-in mathematical functions, which library code also calls through
+/// one of the `MATH_PACKAGES`.
+pub const MATH_FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "asin", "acos", "atan", "atan2", "sinh", "cosh", "tanh", "exp", "log",
+    "log10",
+];
+
+/// Packages whose functions of the same name are the built-in mathematical
+/// functions.
+pub const MATH_PACKAGES: &[&str] = &["Modelica.Math"];
+
+/// Returns the name of the built-in function called by `name`, such that
+/// `Modelica.Math.cos` is the built-in `cos`. Other names are returned as is.
+pub fn builtin_function(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((package, leaf))
+            if MATH_PACKAGES.contains(&package) && MATH_FUNCTIONS.contains(&leaf) =>
+        {
+            leaf
+        }
+        _ => name,
+    }
+}
+
+pub fn type_of(expr: &Expression, components: &IndexMap<String, Component>) -> Type {
+    match expr {
+        Expression::Empty => Type::Unknown,
+        Expression::Terminal { terminal_type, .. } => match terminal_type {
+            TerminalType::UnsignedReal => Type::Real,
+            TerminalType::UnsignedInteger | TerminalType::End => Type::Integer,
+            TerminalType::Bool => Type::Boolean,
+            TerminalType::String => Type::String,
+            TerminalType::Empty => Type::Unknown,
+        },
+        Expression::ComponentReference(cref) => match components.get(&cref.parts[0].ident.text) {
+            Some(comp) if cref.parts.len() == 1 => Type::from_name(&comp.type_name.to_string()),
+            _ => Type::Unknown,
+        },
+        Expression::Unary { op, rhs } => match op {
+            OpUnary::Not(..) => Type::Boolean,
+            _ => type_of(rhs, components),
+        },
+        Expression::Binary { op, lhs, rhs } => match op {
+            OpBinary::Eq(..)
+            | OpBinary::Neq(..)
+            | OpBinary::Lt(..)
+            | OpBinary::Le(..)
+            | OpBinary::Gt(..)
+            | OpBinary::Ge(..)
+            | OpBinary::And(..)
+            | OpBinary::Or(..) => Type::Boolean,
+            OpBinary::Div(..) | OpBinary::DivElem(..) => Type::Real,
+            OpBinary::Exp(..) => match (type_of(lhs, components), rhs.as_ref()) {
+                (
+                    Type::Integer,
+                    Expression::Terminal {
+                        terminal_type: TerminalType::UnsignedInteger,
+                        ..
+                    },
+                ) => Type::Integer,
+                (Type::Unknown, _) => Type::Unknown,
+                _ => Type::Real,
+            },
+            _ => {
+                let lhs = type_of(lhs, components);
+                let rhs = type_of(rhs, components);
+                match (lhs, rhs) {
+                    (Type::Integer, Type::Integer) => Type::Integer,
+                    (Type::String, Type::String) => Type::String,
+                    (Type::Unknown, _) | (_, Type::Unknown) => Type::Unknown,
+                    _ => Type::Real,
+                }
+            }
+        },
+        Expression::FunctionCall { comp, args } => match builtin_function(&comp.to_string()) {
+            "der" | "sqrt" => Type::Real,
+            name if MATH_FUNCTIONS.contains(&name) => Type::Real,
+            "initial" | "terminal" | "edge" | "change" | "sample" => Type::Boolean,
+            "homotopy" => match args.first() {
+                Some(actual) => type_of(actual, components),
+                None => Type::Unknown,
+            },
+            "div" | "mod" | "rem" => {
+                if args
+                    .iter()
+                    .all(|arg| type_of(arg, components) == Type::Integer)
+                {
+                    Type::Integer
+                } else {
+                    Type::Real
+                }
+            }
+            _ => Type::Unknown,
+        },
+        Expression::Array { elements } => match elements.first() {
+            Some(first) => type_of(first, components),
+            None => Type::Unknown,
+        },
+        Expression::ArrayComprehension { expr, .. } => type_of(expr, components),
+        Expression::Range { start, step, end } => {
+            let mut ty = type_of(start, components);
+            for e in step.iter().map(|s| s.as_ref()).chain([end.as_ref()]) {
+                if type_of(e, components) == Type::Real {
+                    ty = Type::Real;
+                }
+            }
+            ty
+        }
+        Expression::If {
+            branches,
+            else_branch,
+        } => {
+            let mut ty = type_of(else_branch, components);
+            for (_, then) in branches {
+                match (ty, type_of(then, components)) {
+                    (Type::Unknown, t) => ty = t,
+                    (Type::Integer, Type::Real) => ty = Type::Real,
+                    _ => {}
+                }
+            }
+            ty
+        }
+    }
+}
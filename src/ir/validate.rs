@@ -0,0 +1,709 @@
+//! This module provides validation of a parsed `StoredDefinition`.
+//!
+//! `StoredDefinition::validate` is the single "lint the model" entry point. It
+//! runs every check and collects all findings as `Diagnostic`s instead of
+//! stopping at the first problem, so tools can report everything at once.
+//!
+//! # Checks
+//! - **Undefined types**: component types and `extends` targets must be a
+//!   built-in type or a class of the stored definition, looked up from the
+//!   enclosing classes outwards as in flatten, such as a nested enumeration
+//!   or `P.Sub.Pin`.
+//! - **Inheritance cycles**: a class must not (indirectly) extend itself.
+//! - **Type checking**: both sides of an equation must have compatible types,
+//!   see `ir::types::type_of`.
+//! - **Conditions**: the conditions of `if`/`when` equation blocks and of
+//!   statement blocks must be `Boolean`.
+//! - **Logical operators**: the operands of `and`, `or` and `not` must be
+//!   `Boolean`.
+//! - **Function calls**: a function with several outputs can only be called
+//!   in a tuple assignment `(a, b) := f(x)`, not inside an expression, and a
+//!   tuple assignment cannot assign more outputs than the function has.
+//! - **Balance**: the flattened main class must have as many equations as
+//!   unknowns. An array counts one unknown per element, and a binding
+//!   `Real y = 2*x` counts as one equation per element. This check is skipped
+//!   when the inheritance structure is invalid, since the class cannot be
+//!   flattened, and when a dimension is not constant.
+//!
+//! `diagnostics_to_json` serializes the diagnostics, with their severity,
+//! code, message and location, for tooling integration.
+use crate::ir::ast::{
+    Causality, ClassDefinition, ClassType, Component, Equation, Expression, Location, OpBinary,
+    OpUnary, Statement, StoredDefinition, Subscript, Variability,
+};
+use crate::ir::const_eval::ConstEvaluator;
+use crate::ir::flatten::flatten;
+use crate::ir::types::{Type, type_of};
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: String,
+    pub message: String,
+    pub location: Location,
+}
+
+impl Diagnostic {
+    pub fn error(code: &str, message: String, location: Location) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: code.to_string(),
+            message,
+            location,
+        }
+    }
+}
+
+/// Serializes diagnostics as a JSON array, for tools that consume the
+/// findings of `validate` rather than formatted messages.
+pub fn diagnostics_to_json(diagnostics: &[Diagnostic]) -> String {
+    serde_json::to_string(diagnostics).expect("diagnostics serialize to JSON")
+}
+
+impl StoredDefinition {
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let extends_valid = check_undefined_types(self, &mut diagnostics)
+            & check_inheritance_cycles(self, &mut diagnostics);
+        check_types(self, &mut diagnostics);
+        check_conditions(self, &mut diagnostics);
+        check_logical_operators(self, &mut diagnostics);
+        check_function_calls(self, &mut diagnostics);
+        if extends_valid {
+            check_balance(self, &mut diagnostics);
+        }
+        diagnostics
+    }
+}
+
+/// Returns the location of an equation, the connect equation it was
+/// generated from when known.
+pub fn equation_location(eq: &Equation) -> Location {
+    match eq {
+        Equation::Simple {
+            origin: Some(origin),
+            ..
+        } => origin.clone(),
+        Equation::Simple { lhs, .. } => expression_location(lhs),
+        Equation::Connect { lhs, .. } => lhs.parts[0].ident.location.clone(),
+        Equation::FunctionCall { comp, .. } => comp.parts[0].ident.location.clone(),
+        Equation::For { indices, .. } => match indices.first() {
+            Some(index) => index.ident.location.clone(),
+            None => Location::default(),
+        },
+        Equation::When(blocks)
+        | Equation::If {
+            cond_blocks: blocks,
+            ..
+        } => match blocks.first() {
+            Some(block) => expression_location(&block.cond),
+            None => Location::default(),
+        },
+        Equation::Empty => Location::default(),
+    }
+}
+
+/// Returns the location of the first token found in the expression.
+pub fn expression_location(expr: &Expression) -> Location {
+    match expr {
+        Expression::Terminal { token, .. } => token.location.clone(),
+        Expression::ComponentReference(cref) => cref.parts[0].ident.location.clone(),
+        Expression::FunctionCall { comp, .. } => comp.parts[0].ident.location.clone(),
+        Expression::Unary { rhs, .. } => expression_location(rhs),
+        Expression::Binary { lhs, .. } => expression_location(lhs),
+        Expression::Range { start, .. } => expression_location(start),
+        Expression::ArrayComprehension { expr, .. } => expression_location(expr),
+        Expression::Array { elements } => match elements.first() {
+            Some(first) => expression_location(first),
+            None => Location::default(),
+        },
+        Expression::If { branches, .. } => match branches.first() {
+            Some((cond, _)) => expression_location(cond),
+            None => Location::default(),
+        },
+        Expression::Empty => Location::default(),
+    }
+}
+
+fn is_builtin_type(name: &str) -> bool {
+    Type::from_name(name) != Type::Unknown
+}
+
+/// Returns the classes of the stored definition and their nested classes, by
+/// qualified name.
+fn all_classes(def: &StoredDefinition) -> Vec<(String, &ClassDefinition)> {
+    let mut classes = Vec::new();
+    let mut stack: Vec<(String, &ClassDefinition)> = def
+        .class_list
+        .iter()
+        .rev()
+        .map(|(name, class)| (name.clone(), class))
+        .collect();
+    while let Some((path, class)) = stack.pop() {
+        for (name, nested) in class.classes.iter().rev() {
+            stack.push((format!("{}.{}", path, name), nested));
+        }
+        classes.push((path, class));
+    }
+    classes
+}
+
+/// Returns the class of the qualified name `path`, following the nested
+/// classes of a top level class.
+fn find_class<'a>(def: &'a StoredDefinition, path: &str) -> Option<&'a ClassDefinition> {
+    let mut parts = path.split('.');
+    let mut class = def.class_list.get(parts.next()?)?;
+    for part in parts {
+        class = class.classes.get(part)?;
+    }
+    Some(class)
+}
+
+/// Looks the class `name` up as flatten does, among the nested classes of
+/// the class `scope` and of its enclosing classes, then at the top level.
+fn resolve_class<'a>(
+    def: &'a StoredDefinition,
+    scope: &str,
+    name: &str,
+) -> Option<&'a ClassDefinition> {
+    let mut scope = Some(scope);
+    while let Some(package) = scope {
+        if let Some(class) = find_class(def, &format!("{}.{}", package, name)) {
+            return Some(class);
+        }
+        scope = package.rsplit_once('.').map(|(parent, _)| parent);
+    }
+    find_class(def, name)
+}
+
+/// Reports unknown component types and extends targets, returns false if an
+/// extends target is unknown.
+fn check_undefined_types(def: &StoredDefinition, diagnostics: &mut Vec<Diagnostic>) -> bool {
+    let mut extends_valid = true;
+    for (path, class) in all_classes(def) {
+        for comp in class.components.values() {
+            let type_name = comp.type_name.to_string();
+            if !is_builtin_type(&type_name) && resolve_class(def, &path, &type_name).is_none() {
+                diagnostics.push(Diagnostic::error(
+                    "undefined-type",
+                    format!(
+                        "Type '{}' of component '{}' in class '{}' is not defined",
+                        type_name, comp.name, class.name.text
+                    ),
+                    comp.type_name
+                        .name
+                        .first()
+                        .map(|t| t.location.clone())
+                        .unwrap_or_default(),
+                ));
+            }
+        }
+        for extend in &class.extends {
+            let base = extend.comp.to_string();
+            if !is_builtin_type(&base) && resolve_class(def, &path, &base).is_none() {
+                extends_valid = false;
+                diagnostics.push(Diagnostic::error(
+                    "undefined-type",
+                    format!(
+                        "Class '{}' extends undefined class '{}'",
+                        class.name.text, base
+                    ),
+                    extend
+                        .comp
+                        .name
+                        .first()
+                        .map(|t| t.location.clone())
+                        .unwrap_or_default(),
+                ));
+            }
+        }
+    }
+    extends_valid
+}
+
+/// Reports classes that extend themselves, returns false if a cycle exists.
+fn check_inheritance_cycles(def: &StoredDefinition, diagnostics: &mut Vec<Diagnostic>) -> bool {
+    let mut valid = true;
+    for (name, class) in &def.class_list {
+        // depth first search of the bases of the class
+        let mut visited = IndexSet::new();
+        let mut stack: Vec<String> = class.extends.iter().map(|e| e.comp.to_string()).collect();
+        while let Some(base) = stack.pop() {
+            if base == *name {
+                valid = false;
+                diagnostics.push(Diagnostic::error(
+                    "inheritance-cycle",
+                    format!("Class '{}' extends itself", name),
+                    class.name.location.clone(),
+                ));
+                break;
+            }
+            if !visited.insert(base.clone()) {
+                continue;
+            }
+            if let Some(base_class) = def.class_list.get(&base) {
+                stack.extend(base_class.extends.iter().map(|e| e.comp.to_string()));
+            }
+        }
+    }
+    valid
+}
+
+/// Returns the components of a class including the inherited ones.
+fn scope_components(
+    def: &StoredDefinition,
+    class: &ClassDefinition,
+) -> IndexMap<String, Component> {
+    let mut components = IndexMap::new();
+    let mut visited = IndexSet::new();
+    let mut stack = vec![class];
+    while let Some(class) = stack.pop() {
+        if !visited.insert(class.name.text.clone()) {
+            continue;
+        }
+        for (name, comp) in &class.components {
+            components
+                .entry(name.clone())
+                .or_insert_with(|| comp.clone());
+        }
+        for extend in &class.extends {
+            if let Some(base) = def.class_list.get(&extend.comp.to_string()) {
+                stack.push(base);
+            }
+        }
+    }
+    components
+}
+
+fn for_each_equation<'a>(eqs: &'a [Equation], f: &mut impl FnMut(&'a Equation)) {
+    for eq in eqs {
+        f(eq);
+        match eq {
+            Equation::For { equations, .. } => for_each_equation(equations, f),
+            Equation::When(blocks) => {
+                for block in blocks {
+                    for_each_equation(&block.eqs, f);
+                }
+            }
+            Equation::If {
+                cond_blocks,
+                else_block,
+            } => {
+                for block in cond_blocks {
+                    for_each_equation(&block.eqs, f);
+                }
+                if let Some(else_block) = else_block {
+                    for_each_equation(else_block, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn check_types(def: &StoredDefinition, diagnostics: &mut Vec<Diagnostic>) {
+    for class in def.class_list.values() {
+        let components = scope_components(def, class);
+        for eqs in [&class.equations, &class.initial_equations] {
+            for_each_equation(eqs, &mut |eq| {
+                if let Equation::Simple { lhs, rhs, .. } = eq {
+                    let lhs_type = type_of(lhs, &components);
+                    let rhs_type = type_of(rhs, &components);
+                    if !lhs_type.is_compatible(&rhs_type) {
+                        diagnostics.push(Diagnostic::error(
+                            "type-mismatch",
+                            format!(
+                                "Equation in class '{}' equates {:?} and {:?} expressions",
+                                class.name.text, lhs_type, rhs_type
+                            ),
+                            expression_location(lhs),
+                        ));
+                    }
+                }
+            });
+        }
+    }
+}
+
+fn check_condition(
+    class: &ClassDefinition,
+    cond: &Expression,
+    components: &IndexMap<String, Component>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let cond_type = type_of(cond, components);
+    if !cond_type.is_compatible(&Type::Boolean) {
+        diagnostics.push(Diagnostic::error(
+            "non-boolean-condition",
+            format!(
+                "Condition in class '{}' must be Boolean, found {:?}",
+                class.name.text, cond_type
+            ),
+            expression_location(cond),
+        ));
+    }
+}
+
+fn for_each_statement<'a>(stmts: &'a [Statement], f: &mut impl FnMut(&'a Statement)) {
+    for stmt in stmts {
+        f(stmt);
+        match stmt {
+            Statement::For { body, .. } => for_each_statement(body, f),
+            Statement::If {
+                cond_blocks,
+                else_block,
+            } => {
+                for block in cond_blocks {
+                    for_each_statement(&block.stmts, f);
+                }
+                if let Some(else_block) = else_block {
+                    for_each_statement(else_block, f);
+                }
+            }
+            Statement::While(block) => for_each_statement(&block.stmts, f),
+            Statement::When(blocks) => {
+                for block in blocks {
+                    for_each_statement(&block.stmts, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn check_conditions(def: &StoredDefinition, diagnostics: &mut Vec<Diagnostic>) {
+    for class in def.class_list.values() {
+        let components = scope_components(def, class);
+        for eqs in [&class.equations, &class.initial_equations] {
+            for_each_equation(eqs, &mut |eq| match eq {
+                Equation::When(blocks) => {
+                    for block in blocks {
+                        check_condition(class, &block.cond, &components, diagnostics);
+                    }
+                }
+                Equation::If { cond_blocks, .. } => {
+                    for block in cond_blocks {
+                        check_condition(class, &block.cond, &components, diagnostics);
+                    }
+                }
+                _ => {}
+            });
+        }
+        for stmts in class
+            .algorithms
+            .iter()
+            .chain(class.initial_algorithms.iter())
+        {
+            for_each_statement(stmts, &mut |stmt| match stmt {
+                Statement::If { cond_blocks, .. } => {
+                    for block in cond_blocks {
+                        check_condition(class, &block.cond, &components, diagnostics);
+                    }
+                }
+                Statement::While(block) => {
+                    check_condition(class, &block.cond, &components, diagnostics);
+                }
+                Statement::When(blocks) => {
+                    for block in blocks {
+                        check_condition(class, &block.cond, &components, diagnostics);
+                    }
+                }
+                _ => {}
+            });
+        }
+    }
+}
+
+fn for_each_subexpression<'a>(expr: &'a Expression, f: &mut impl FnMut(&'a Expression)) {
+    f(expr);
+    match expr {
+        Expression::Range { start, step, end } => {
+            for_each_subexpression(start, f);
+            if let Some(step) = step {
+                for_each_subexpression(step, f);
+            }
+            for_each_subexpression(end, f);
+        }
+        Expression::ArrayComprehension { expr, indices } => {
+            for index in indices {
+                for_each_subexpression(&index.range, f);
+            }
+            for_each_subexpression(expr, f);
+        }
+        Expression::Unary { rhs, .. } => for_each_subexpression(rhs, f),
+        Expression::Binary { lhs, rhs, .. } => {
+            for_each_subexpression(lhs, f);
+            for_each_subexpression(rhs, f);
+        }
+        Expression::FunctionCall { args: exprs, .. } | Expression::Array { elements: exprs } => {
+            for expr in exprs {
+                for_each_subexpression(expr, f);
+            }
+        }
+        Expression::If {
+            branches,
+            else_branch,
+        } => {
+            for (cond, then) in branches {
+                for_each_subexpression(cond, f);
+                for_each_subexpression(then, f);
+            }
+            for_each_subexpression(else_branch, f);
+        }
+        _ => {}
+    }
+}
+
+/// Calls `f` on the bindings of the components of a class and on the
+/// expressions of its equations and statements.
+fn for_each_expression<'a>(class: &'a ClassDefinition, f: &mut impl FnMut(&'a Expression)) {
+    for comp in class.components.values() {
+        f(&comp.start);
+    }
+    for eqs in [&class.equations, &class.initial_equations] {
+        for_each_equation(eqs, &mut |eq| match eq {
+            Equation::Simple { lhs, rhs, .. } => {
+                f(lhs);
+                f(rhs);
+            }
+            Equation::FunctionCall { args, .. } => args.iter().for_each(&mut *f),
+            Equation::When(blocks) => blocks.iter().for_each(|block| f(&block.cond)),
+            Equation::If { cond_blocks, .. } => cond_blocks.iter().for_each(|block| f(&block.cond)),
+            _ => {}
+        });
+    }
+    for stmts in class.algorithms.iter().chain(&class.initial_algorithms) {
+        for_each_statement(stmts, &mut |stmt| match stmt {
+            Statement::Assignment { value, .. } => f(value),
+            Statement::FunctionCall { args, .. } => args.iter().for_each(&mut *f),
+            Statement::If { cond_blocks, .. } => {
+                cond_blocks.iter().for_each(|block| f(&block.cond))
+            }
+            Statement::While(block) => f(&block.cond),
+            Statement::When(blocks) => blocks.iter().for_each(|block| f(&block.cond)),
+            _ => {}
+        });
+    }
+}
+
+fn check_logical_operators(def: &StoredDefinition, diagnostics: &mut Vec<Diagnostic>) {
+    for class in def.class_list.values() {
+        let components = scope_components(def, class);
+        for_each_expression(class, &mut |expr| {
+            for_each_subexpression(expr, &mut |expr| {
+                let (op, operands) = match expr {
+                    Expression::Binary {
+                        op: OpBinary::And(..),
+                        lhs,
+                        rhs,
+                    } => ("and", vec![lhs, rhs]),
+                    Expression::Binary {
+                        op: OpBinary::Or(..),
+                        lhs,
+                        rhs,
+                    } => ("or", vec![lhs, rhs]),
+                    Expression::Unary {
+                        op: OpUnary::Not(..),
+                        rhs,
+                    } => ("not", vec![rhs]),
+                    _ => return,
+                };
+                for operand in operands {
+                    let operand_type = type_of(operand, &components);
+                    if !operand_type.is_compatible(&Type::Boolean) {
+                        diagnostics.push(Diagnostic::error(
+                            "non-boolean-operand",
+                            format!(
+                                "Operand of '{}' in class '{}' must be Boolean, found {:?}",
+                                op, class.name.text, operand_type
+                            ),
+                            expression_location(operand),
+                        ));
+                    }
+                }
+            });
+        });
+    }
+}
+
+/// Number of outputs of `name`, if it is a function of the stored definition.
+fn function_outputs(def: &StoredDefinition, name: &str) -> Option<usize> {
+    let class = def.class_list.get(name)?;
+    if !matches!(class.class_type, ClassType::Function(..)) {
+        return None;
+    }
+    Some(
+        class
+            .components
+            .values()
+            .filter(|comp| matches!(comp.causality, Causality::Output(..)))
+            .count(),
+    )
+}
+
+fn check_function_calls(def: &StoredDefinition, diagnostics: &mut Vec<Diagnostic>) {
+    for class in def.class_list.values() {
+        let mut check = |expr: &Expression| {
+            for_each_subexpression(expr, &mut |expr| {
+                if let Expression::FunctionCall { comp, .. } = expr
+                    && let Some(outputs) = function_outputs(def, &comp.to_string())
+                    && outputs > 1
+                {
+                    diagnostics.push(Diagnostic::error(
+                        "multi-output-call",
+                        format!(
+                            "Function '{}' has {} outputs and can only be called in a tuple assignment",
+                            comp, outputs
+                        ),
+                        expression_location(expr),
+                    ));
+                }
+            });
+        };
+        for eqs in [&class.equations, &class.initial_equations] {
+            for_each_equation(eqs, &mut |eq| match eq {
+                Equation::Simple { lhs, rhs, .. } => {
+                    check(lhs);
+                    check(rhs);
+                }
+                Equation::FunctionCall { args, .. } => args.iter().for_each(&mut check),
+                Equation::When(blocks) => blocks.iter().for_each(|block| check(&block.cond)),
+                Equation::If { cond_blocks, .. } => {
+                    cond_blocks.iter().for_each(|block| check(&block.cond))
+                }
+                _ => {}
+            });
+        }
+        let mut output_errors = Vec::new();
+        for stmts in class.algorithms.iter().chain(&class.initial_algorithms) {
+            for_each_statement(stmts, &mut |stmt| match stmt {
+                Statement::Assignment { value, .. } => check(value),
+                Statement::FunctionCall {
+                    comp,
+                    args,
+                    outputs,
+                } => {
+                    args.iter().for_each(&mut check);
+                    if let Some(count) = function_outputs(def, &comp.to_string())
+                        && outputs.len() > count
+                    {
+                        output_errors.push(Diagnostic::error(
+                            "output-count",
+                            format!(
+                                "Function '{}' has {} output(s), but {} are assigned",
+                                comp,
+                                count,
+                                outputs.len()
+                            ),
+                            comp.parts[0].ident.location.clone(),
+                        ));
+                    }
+                }
+                Statement::If { cond_blocks, .. } => {
+                    cond_blocks.iter().for_each(|block| check(&block.cond))
+                }
+                Statement::While(block) => check(&block.cond),
+                Statement::When(blocks) => blocks.iter().for_each(|block| check(&block.cond)),
+                _ => {}
+            });
+        }
+        diagnostics.append(&mut output_errors);
+    }
+}
+
+/// Number of scalar equations contributed by an equation.
+fn equation_count(eq: &Equation) -> usize {
+    match eq {
+        Equation::Simple { .. } => 1,
+        Equation::For { equations, .. } => equations.iter().map(equation_count).sum(),
+        Equation::When(blocks) => blocks
+            .first()
+            .map(|b| b.eqs.iter().map(equation_count).sum())
+            .unwrap_or(0),
+        Equation::If { cond_blocks, .. } => cond_blocks
+            .first()
+            .map(|b| b.eqs.iter().map(equation_count).sum())
+            .unwrap_or(0),
+        Equation::Connect { .. } | Equation::FunctionCall { .. } | Equation::Empty => 0,
+    }
+}
+
+fn check_balance(def: &StoredDefinition, diagnostics: &mut Vec<Diagnostic>) {
+    let Ok(fclass) = flatten(def) else {
+        return;
+    };
+    let mut evaluator = ConstEvaluator::default();
+    if evaluator.add_components(&fclass.components).is_err() {
+        return;
+    }
+    let mut unknowns = 0;
+    let mut equations: usize = fclass.equations.iter().map(equation_count).sum();
+    for comp in fclass.components.values() {
+        if !matches!(
+            comp.variability,
+            Variability::Empty | Variability::Discrete(..)
+        ) {
+            continue;
+        }
+        // the balance is unknown unless every dimension is constant
+        let Some(size) = scalar_count(comp, &evaluator) else {
+            return;
+        };
+        let type_name = comp.type_name.to_string();
+        if (is_builtin_type(&type_name) || fclass.classes.contains_key(&type_name))
+            && !matches!(comp.causality, Causality::Input(..))
+        {
+            unknowns += size;
+        }
+        if has_binding(comp) {
+            equations += size;
+        }
+    }
+    if unknowns != equations {
+        diagnostics.push(Diagnostic::error(
+            "unbalanced",
+            format!(
+                "Class '{}' has {} equation(s) and {} unknown(s)",
+                fclass.name.text, equations, unknowns
+            ),
+            fclass.name.location.clone(),
+        ));
+    }
+}
+
+/// Number of scalars of a component, the product of its dimensions, or
+/// `None` when a dimension is not constant.
+fn scalar_count(comp: &Component, evaluator: &ConstEvaluator) -> Option<usize> {
+    comp.shape
+        .iter()
+        .map(|sub| match sub {
+            Subscript::Expression(expr) => evaluator
+                .eval(expr)
+                .and_then(|value| value.as_i64())
+                .ok()
+                .and_then(|n| usize::try_from(n).ok()),
+            _ => None,
+        })
+        .product()
+}
+
+/// Tells whether a component has a binding equation, `Real y = 2*x`, that
+/// is a start value other than the one given by its `start` modification or,
+/// without it, the default start value of its type.
+fn has_binding(comp: &Component) -> bool {
+    let implied_start = match comp.modifications.get("start") {
+        Some(start) => start.clone(),
+        None => Type::from_name(&comp.type_name.to_string()).default_start(),
+    };
+    comp.start != Expression::Empty && !comp.start.structurally_eq(&implied_start)
+}
@@ -48,6 +48,9 @@ pub trait Visitor {
     fn enter_equation(&mut self, _node: &mut ir::ast::Equation) {}
     fn exit_equation(&mut self, _node: &mut ir::ast::Equation) {}
 
+    fn enter_statement(&mut self, _node: &mut ir::ast::Statement) {}
+    fn exit_statement(&mut self, _node: &mut ir::ast::Statement) {}
+
     fn enter_expression(&mut self, _node: &mut ir::ast::Expression) {}
     fn exit_expression(&mut self, _node: &mut ir::ast::Expression) {}
 
@@ -89,7 +92,7 @@ impl Visitable for ir::ast::Equation {
     fn accept<V: Visitor>(&mut self, visitor: &mut V) {
         visitor.enter_equation(self);
         match self {
-            ir::ast::Equation::Simple { lhs, rhs } => {
+            ir::ast::Equation::Simple { lhs, rhs, .. } => {
                 lhs.accept(visitor);
                 rhs.accept(visitor);
             }
@@ -141,6 +144,74 @@ impl Visitable for ir::ast::Equation {
     }
 }
 
+#[allow(unused)]
+impl Visitable for ir::ast::Statement {
+    fn accept<V: Visitor>(&mut self, visitor: &mut V) {
+        visitor.enter_statement(self);
+        match self {
+            ir::ast::Statement::Assignment { comp, value } => {
+                comp.accept(visitor);
+                value.accept(visitor);
+            }
+            ir::ast::Statement::For { indices, body } => {
+                for index in indices {
+                    index.range.accept(visitor);
+                }
+                for stmt in body {
+                    stmt.accept(visitor);
+                }
+            }
+            ir::ast::Statement::If {
+                cond_blocks,
+                else_block,
+            } => {
+                for block in cond_blocks {
+                    block.cond.accept(visitor);
+                    for stmt in &mut block.stmts {
+                        stmt.accept(visitor);
+                    }
+                }
+                if let Some(else_block) = else_block {
+                    for stmt in else_block {
+                        stmt.accept(visitor);
+                    }
+                }
+            }
+            ir::ast::Statement::While(block) => {
+                block.cond.accept(visitor);
+                for stmt in &mut block.stmts {
+                    stmt.accept(visitor);
+                }
+            }
+            ir::ast::Statement::When(blocks) => {
+                for block in blocks {
+                    block.cond.accept(visitor);
+                    for stmt in &mut block.stmts {
+                        stmt.accept(visitor);
+                    }
+                }
+            }
+            ir::ast::Statement::FunctionCall {
+                comp,
+                args,
+                outputs,
+            } => {
+                comp.accept(visitor);
+                for arg in args {
+                    arg.accept(visitor);
+                }
+                for output in outputs {
+                    output.accept(visitor);
+                }
+            }
+            ir::ast::Statement::Return { .. }
+            | ir::ast::Statement::Break { .. }
+            | ir::ast::Statement::Empty => {}
+        }
+        visitor.exit_statement(self);
+    }
+}
+
 #[allow(unused)]
 impl Visitable for ir::ast::Expression {
     fn accept<V: Visitor>(&mut self, visitor: &mut V) {
@@ -167,6 +238,12 @@ impl Visitable for ir::ast::Expression {
                     element.accept(visitor);
                 }
             }
+            ir::ast::Expression::ArrayComprehension { expr, indices } => {
+                for index in indices {
+                    index.range.accept(visitor);
+                }
+                expr.accept(visitor);
+            }
             ir::ast::Expression::Range { start, step, end } => {
                 start.accept(visitor);
                 if step.is_some() {
@@ -174,6 +251,16 @@ impl Visitable for ir::ast::Expression {
                 }
                 end.accept(visitor);
             }
+            ir::ast::Expression::If {
+                branches,
+                else_branch,
+            } => {
+                for (cond, then) in branches {
+                    cond.accept(visitor);
+                    then.accept(visitor);
+                }
+                else_branch.accept(visitor);
+            }
             ir::ast::Expression::Terminal { .. } => {}
             ir::ast::Expression::Empty => {}
         }
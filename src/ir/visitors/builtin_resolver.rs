@@ -0,0 +1,38 @@
+//! A visitor that resolves qualified calls of built-in functions.
+//!
+//! Library code calls the mathematical functions through their package, as in
+//! `Modelica.Math.sin(x)`. The `BuiltinResolver` replaces the qualified name
+//! by the built-in function of the same name, see `types::builtin_function`,
+//! before the model is flattened. Otherwise the call would be scoped like a
+//! component reference and not be recognized by the code generators.
+use crate::ir;
+use crate::ir::ast::{ComponentRefPart, Expression};
+use crate::ir::types::builtin_function;
+use crate::ir::visitor::Visitor;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BuiltinResolver;
+
+impl Visitor for BuiltinResolver {
+    fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
+        let Expression::FunctionCall { comp, .. } = node else {
+            return;
+        };
+        if comp.parts.len() < 2 {
+            return;
+        }
+        let name = comp.to_string();
+        let builtin = builtin_function(&name);
+        if builtin != name
+            && let Some(last) = comp.parts.pop()
+        {
+            comp.parts = vec![ComponentRefPart {
+                ident: ir::ast::Token {
+                    text: builtin.to_string(),
+                    ..last.ident
+                },
+                subs: None,
+            }];
+        }
+    }
+}
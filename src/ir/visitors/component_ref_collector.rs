@@ -0,0 +1,25 @@
+//! A visitor that collects the names of all component references.
+//!
+//! The `ComponentRefCollector` records every `Expression::ComponentReference`
+//! it visits, in order of first appearance. Function names are not collected,
+//! so `sin(x)` only yields `x`.
+//!
+//! # Fields
+//! - `names`: The collected component reference names.
+use indexmap::IndexSet;
+
+use crate::ir;
+use crate::ir::visitor::Visitor;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ComponentRefCollector {
+    pub names: IndexSet<String>,
+}
+
+impl Visitor for ComponentRefCollector {
+    fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
+        if let ir::ast::Expression::ComponentReference(cref) = node {
+            self.names.insert(cref.to_string());
+        }
+    }
+}
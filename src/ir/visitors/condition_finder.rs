@@ -1,4 +1,8 @@
 //! Finds conditions, and replaces them with variables
+//!
+//! Each distinct condition gets one variable, conditions that are equivalent
+//! up to the order of the operands of commutative operators, such as `x > 0`
+//! and `0 < x`, share it.
 use indexmap::IndexMap;
 
 use crate::ir;
@@ -16,6 +20,15 @@ pub struct ConditionFinder {
 
 impl ConditionFinder {
     fn process_condition_block(&mut self, block: &mut EquationBlock) {
+        if let Some(name) = self
+            .expressions
+            .iter()
+            .find(|(_, expr)| expr.equivalent(&block.cond))
+            .map(|(name, _)| name.clone())
+        {
+            block.cond = Expression::ComponentReference(ComponentReference::new(&name));
+            return;
+        }
         let i = self.conditions.len();
         let name = format!("c{}", i);
         let comp = Component {
@@ -0,0 +1,29 @@
+//! A visitor that folds constant subexpressions into literals.
+//!
+//! The `ConstantFolder` replaces every expression that its `evaluator` can
+//! evaluate by the literal of its value. Expressions are visited bottom up,
+//! so the largest constant subexpressions end up as single literals, and
+//! `-k*x` becomes `-2.0*x` once the evaluator knows `k = 2.0`.
+//!
+//! # Fields
+//! - `evaluator`: Constant evaluator holding the known values.
+use crate::ir;
+use crate::ir::ast::Expression;
+use crate::ir::const_eval::ConstEvaluator;
+use crate::ir::visitor::Visitor;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConstantFolder {
+    pub evaluator: ConstEvaluator,
+}
+
+impl Visitor for ConstantFolder {
+    fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
+        if let Expression::Terminal { .. } = node {
+            return;
+        }
+        if let Ok(value) = self.evaluator.eval(node) {
+            *node = value.to_expression();
+        }
+    }
+}
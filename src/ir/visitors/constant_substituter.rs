@@ -0,0 +1,47 @@
+//! A visitor that replaces references to well-known library constants by
+//! their values.
+//!
+//! Library constants, such as `Modelica.Constants.pi`, are not declared in the
+//! model, so the `ConstantSubstituter` replaces them with literals before the
+//! model is flattened. Otherwise they would be scoped and treated as unknown
+//! variables.
+//!
+//! # Fields
+//! - `constants`: The constants to substitute, by qualified name. Defaults to
+//!   `const_eval::well_known_constants`.
+//! - `errors`: References to `Modelica.Constants` that are not in the table.
+use crate::ir;
+use crate::ir::ast::Expression;
+use crate::ir::const_eval::{Value, well_known_constants};
+use crate::ir::visitor::Visitor;
+use indexmap::IndexMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstantSubstituter {
+    pub constants: IndexMap<String, Value>,
+    pub errors: Vec<String>,
+}
+
+impl Default for ConstantSubstituter {
+    fn default() -> Self {
+        ConstantSubstituter {
+            constants: well_known_constants(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl Visitor for ConstantSubstituter {
+    fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
+        let Expression::ComponentReference(cref) = node else {
+            return;
+        };
+        let name = cref.to_string();
+        if let Some(value) = self.constants.get(&name) {
+            *node = value.to_expression();
+        } else if name.starts_with("Modelica.Constants.") {
+            self.errors
+                .push(format!("unknown library constant '{}'", name));
+        }
+    }
+}
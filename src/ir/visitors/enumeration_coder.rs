@@ -0,0 +1,39 @@
+//! A visitor that codes enumeration literals as integers.
+//!
+//! The `EnumerationCoder` replaces every reference to an enumeration literal,
+//! such as `Color.green`, with its ordinal as an `Integer` literal, so that
+//! code generators only ever see integer-coded enumeration values. Ordinals
+//! start at 1, as for `Integer(e)` in Modelica.
+//!
+//! # Fields
+//! - `enumerations`: The ordinal of each literal, by enumeration type name.
+use indexmap::IndexMap;
+
+use crate::ir;
+use crate::ir::ast::{Expression, TerminalType, Token};
+use crate::ir::visitor::Visitor;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EnumerationCoder {
+    pub enumerations: IndexMap<String, IndexMap<String, i64>>,
+}
+
+impl Visitor for EnumerationCoder {
+    fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
+        if let Expression::ComponentReference(cref) = node
+            && let Some((type_name, literal)) = cref.to_string().rsplit_once('.')
+            && let Some(ordinal) = self
+                .enumerations
+                .get(type_name)
+                .and_then(|literals| literals.get(literal))
+        {
+            *node = Expression::Terminal {
+                terminal_type: TerminalType::UnsignedInteger,
+                token: Token {
+                    text: ordinal.to_string(),
+                    ..cref.parts[0].ident.clone()
+                },
+            };
+        }
+    }
+}
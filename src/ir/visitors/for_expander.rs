@@ -0,0 +1,164 @@
+//! A visitor that unrolls `for` equations over constant ranges.
+//!
+//! The `ForExpander` replaces every `Equation::For` in a class with one copy of
+//! the loop body per index value. The range of each index is evaluated with
+//! the `evaluator`, so ranges may refer to parameters and constants that the
+//! caller has added to it. Each copy of the body is rewritten using the
+//! `IndexSubstituter`, which also folds subscript arithmetic, so that
+//! `x[i] = x[i-1]` at `i = 2` becomes `x[2] = x[1]`.
+//!
+//! Nested loops and loops with several indices are expanded recursively.
+//!
+//! Besides `Integer` ranges, an index may iterate over:
+//! - an array literal of any element type, `for b in {false, true} loop`,
+//!   whose elements are evaluated when possible,
+//! - the type `Boolean`, as `{false, true}`,
+//! - an enumeration type, over its literals in declaration order.
+//!
+//! # Fields
+//! - `evaluator`: Constant evaluator used for the loop ranges.
+//! - `enumerations`: Literals of the enumeration types, by type name.
+//! - `errors`: Ranges that could not be expanded. The offending loops are
+//!   left in place.
+use crate::ir;
+use crate::ir::ast::{ClassDefinition, Equation, Expression, ForIndex};
+use crate::ir::const_eval::{ConstEvaluator, Value};
+use crate::ir::visitor::{Visitable, Visitor};
+use crate::ir::visitors::index_substituter::IndexSubstituter;
+use anyhow::{Result, anyhow};
+use indexmap::IndexMap;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ForExpander {
+    pub evaluator: ConstEvaluator,
+    pub enumerations: IndexMap<String, Vec<String>>,
+    pub errors: Vec<String>,
+}
+
+impl ForExpander {
+    /// Adds the literals of the enumeration types among `classes`.
+    pub fn add_enumerations(&mut self, classes: &IndexMap<String, ClassDefinition>) {
+        for (name, class) in classes {
+            if !class.enumeration_literals.is_empty() {
+                let literals = class
+                    .enumeration_literals
+                    .iter()
+                    .map(|literal| literal.ident.text.clone())
+                    .collect();
+                self.enumerations.insert(name.clone(), literals);
+            }
+        }
+    }
+
+    /// Returns the values of a loop index iterating over `range`.
+    pub fn range_values(&self, range: &Expression) -> Result<Vec<Expression>> {
+        match range {
+            Expression::Range { start, step, end } => {
+                let values = self.evaluator.eval_range(start, step.as_deref(), end)?;
+                if let Some(value) = values.iter().find(|v| v.as_i64().is_err()) {
+                    return Err(anyhow!(
+                        "for loop index must be an Integer, found {:?}",
+                        value
+                    ));
+                }
+                Ok(values.iter().map(Value::to_expression).collect())
+            }
+            Expression::Array { elements } => elements
+                .iter()
+                .map(|element| match self.evaluator.eval(element) {
+                    Ok(value) => Ok(value.to_expression()),
+                    Err(_) if self.is_enumeration_literal(element) => Ok(element.clone()),
+                    Err(e) => Err(anyhow!("unsupported for loop range element: {}", e)),
+                })
+                .collect(),
+            Expression::ComponentReference(cref) => {
+                let name = cref.to_string();
+                if name == "Boolean" {
+                    return Ok(vec![
+                        Value::Bool(false).to_expression(),
+                        Value::Bool(true).to_expression(),
+                    ]);
+                }
+                match self.enumerations.get(&name) {
+                    Some(literals) => Ok(literals
+                        .iter()
+                        .map(|literal| {
+                            Expression::ComponentReference(cref.clone().with_part(literal))
+                        })
+                        .collect()),
+                    None => Err(anyhow!("unsupported for loop range '{}'", name)),
+                }
+            }
+            _ => Err(anyhow!("unsupported for loop range {:?}", range)),
+        }
+    }
+
+    /// Returns true for a reference to a literal of a known enumeration,
+    /// such as `E.a`.
+    fn is_enumeration_literal(&self, expr: &Expression) -> bool {
+        let Expression::ComponentReference(cref) = expr else {
+            return false;
+        };
+        let Some((literal, type_name)) = cref.parts.split_last() else {
+            return false;
+        };
+        let type_name = type_name
+            .iter()
+            .map(|part| part.ident.text.as_str())
+            .collect::<Vec<_>>()
+            .join(".");
+        self.enumerations
+            .get(&type_name)
+            .is_some_and(|literals| literals.contains(&literal.ident.text))
+    }
+
+    fn expand(&mut self, equations: &[Equation]) -> Vec<Equation> {
+        let mut expanded = Vec::new();
+        for eq in equations {
+            match eq {
+                Equation::For { indices, equations } => match self.expand_for(indices, equations) {
+                    Ok(mut eqs) => expanded.append(&mut eqs),
+                    Err(e) => {
+                        self.errors.push(e.to_string());
+                        expanded.push(eq.clone());
+                    }
+                },
+                _ => expanded.push(eq.clone()),
+            }
+        }
+        expanded
+    }
+
+    fn expand_for(
+        &mut self,
+        indices: &[ForIndex],
+        equations: &[Equation],
+    ) -> Result<Vec<Equation>> {
+        let Some((index, rest)) = indices.split_first() else {
+            return Ok(self.expand(equations));
+        };
+        let mut expanded = Vec::new();
+        for value in self.range_values(&index.range)? {
+            let mut substituter = IndexSubstituter {
+                index: index.ident.text.clone(),
+                value,
+            };
+            let mut body = Equation::For {
+                indices: rest.to_vec(),
+                equations: equations.to_vec(),
+            };
+            body.accept(&mut substituter);
+            if let Equation::For { indices, equations } = body {
+                expanded.append(&mut self.expand_for(&indices, &equations)?);
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+impl Visitor for ForExpander {
+    fn exit_class_definition(&mut self, node: &mut ir::ast::ClassDefinition) {
+        node.equations = self.expand(&node.equations);
+        node.initial_equations = self.expand(&node.initial_equations);
+    }
+}
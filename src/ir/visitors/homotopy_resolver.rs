@@ -0,0 +1,33 @@
+//! A visitor that resolves calls of the `homotopy` operator.
+//!
+//! `homotopy(actual, simplified)` lets an initialization solver start from
+//! the `simplified` expression and continuously move to the `actual` one. The
+//! `HomotopyResolver` replaces each call by one of its arguments: the
+//! `simplified` expression, at the homotopy parameter `lambda = 0`, or the
+//! `actual` expression, at `lambda = 1`, as used outside of initialization.
+//!
+//! # Fields
+//! - `simplified`: Select the simplified expression instead of the actual one.
+//! - `found`: Whether a call of `homotopy` was resolved.
+use crate::ir;
+use crate::ir::ast::Expression;
+use crate::ir::visitor::Visitor;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HomotopyResolver {
+    pub simplified: bool,
+    pub found: bool,
+}
+
+impl Visitor for HomotopyResolver {
+    fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
+        if let Expression::FunctionCall { comp, args } = node
+            && comp.to_string() == "homotopy"
+            && args.len() == 2
+        {
+            self.found = true;
+            let arg = if self.simplified { 1 } else { 0 };
+            *node = args.swap_remove(arg);
+        }
+    }
+}
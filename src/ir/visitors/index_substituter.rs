@@ -0,0 +1,47 @@
+//! A visitor that substitutes a `for`-loop index with a concrete value.
+//!
+//! The `IndexSubstituter` replaces every reference to the loop index `index`
+//! with the literal expression `value`. Any other reference, such as `a.x[2]`,
+//! can be substituted the same way, with `index` being its full name. References appearing inside array
+//! subscripts are also substituted, and the resulting subscript expression is
+//! constant evaluated when possible, so that `x[i-1]` with `i = 2` becomes
+//! `x[1]` rather than `x[2-1]`.
+//!
+//! # Fields
+//! - `index`: The name of the loop index, or of the reference, to replace.
+//! - `value`: The literal expression substituted for the index.
+use crate::ir;
+use crate::ir::const_eval::ConstEvaluator;
+use crate::ir::visitor::{Visitable, Visitor};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IndexSubstituter {
+    pub index: String,
+    pub value: ir::ast::Expression,
+}
+
+impl Visitor for IndexSubstituter {
+    fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
+        if let ir::ast::Expression::ComponentReference(cref) = node
+            && cref.to_string() == self.index
+        {
+            *node = self.value.clone();
+        }
+    }
+
+    fn exit_component_reference(&mut self, node: &mut ir::ast::ComponentReference) {
+        let evaluator = ConstEvaluator::default();
+        for part in &mut node.parts {
+            if let Some(subs) = &mut part.subs {
+                for sub in subs.iter_mut() {
+                    if let ir::ast::Subscript::Expression(expr) = sub {
+                        expr.accept(self);
+                        if let Ok(value) = evaluator.eval(expr) {
+                            *expr = value.to_expression();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
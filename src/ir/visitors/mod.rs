@@ -1,5 +1,16 @@
+pub mod builtin_resolver;
+pub mod component_ref_collector;
 pub mod condition_finder;
+pub mod constant_folder;
+pub mod constant_substituter;
+pub mod enumeration_coder;
+pub mod for_expander;
+pub mod homotopy_resolver;
+pub mod index_substituter;
 pub mod pre_finder;
+pub mod range_expander;
+pub mod scalarizer;
 pub mod scope_pusher;
 pub mod state_finder;
 pub mod sub_comp_namer;
+pub mod tautology_remover;
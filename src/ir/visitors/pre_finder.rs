@@ -1,33 +1,46 @@
-//! A visitor implementation for finding and transforming state variables in an
-//! abstract syntax tree (AST). The `StateFinder` struct is designed to traverse
-//! the AST and identify state variables that are referenced within derivative
-//! function calls (`der`). It collects these state variable names into a
-//! `HashSet` and modifies the AST to replace the original state variable
-//! references with their derivative counterparts.
+//! A visitor implementation for finding and transforming `pre` operator calls
+//! in an abstract syntax tree (AST). The `PreFinder` struct traverses the AST,
+//! collects the variables referenced within `pre` function calls, and replaces
+//! each call with a component reference prefixed with `pre_`.
 //!
 //! # Fields
-//! - `states`: A `HashSet` containing the names of the state variables found
-//!   during the traversal.
+//! - `variables`: The names of all variables used inside `pre(...)`.
+//! - `outside_when`: The names of variables used inside `pre(...)` outside of
+//!   a `when` equation. Only discrete variables may appear here, which the
+//!   caller validates once variables have been classified.
 //!
 //! # Visitor Implementation
-//! - The `exit_expression` method is invoked when exiting an expression node
-//!   during the AST traversal. It performs the following actions:
-//!   - Checks if the expression is a function call with the identifier `der`.
-//!   - If the first argument of the `der` function is a component reference,
-//!     the state variable name is extracted and added to the `states` set.
-//!   - Modifies the AST by replacing the original state variable reference with
-//!     a new component reference prefixed with `der_`.
-//!
-//! This visitor is useful for analyzing and transforming ASTs in scenarios
-//! where state variables and their derivatives need to be explicitly tracked
-//! and processed.
+//! - `enter_equation`/`exit_equation` track whether the traversal is inside a
+//!   `when` equation.
+//! - The `exit_expression` method checks if the expression is a function call
+//!   with the identifier `pre` whose argument is a component reference. The
+//!   variable name is recorded, and the call is replaced with a reference to
+//!   the `pre_` variable.
+use indexmap::IndexSet;
+
 use crate::ir;
 use crate::ir::visitor::Visitor;
 
 #[derive(Debug, Default, Clone, PartialEq)]
-pub struct PreFinder {}
+pub struct PreFinder {
+    pub variables: IndexSet<String>,
+    pub outside_when: IndexSet<String>,
+    when_depth: usize,
+}
 
 impl Visitor for PreFinder {
+    fn enter_equation(&mut self, node: &mut ir::ast::Equation) {
+        if let ir::ast::Equation::When(..) = node {
+            self.when_depth += 1;
+        }
+    }
+
+    fn exit_equation(&mut self, node: &mut ir::ast::Equation) {
+        if let ir::ast::Equation::When(..) = node {
+            self.when_depth -= 1;
+        }
+    }
+
     fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
         match &node {
             ir::ast::Expression::FunctionCall { comp, args } => {
@@ -35,6 +48,11 @@ impl Visitor for PreFinder {
                     let arg = args.get(0).unwrap();
                     match &arg {
                         ir::ast::Expression::ComponentReference(comp) => {
+                            let name = comp.parts[0].ident.text.clone();
+                            if self.when_depth == 0 {
+                                self.outside_when.insert(name.clone());
+                            }
+                            self.variables.insert(name);
                             let mut pre_comp = comp.clone();
                             pre_comp.parts[0].ident.text =
                                 format!("pre_{}", comp.parts[0].ident.text);
@@ -0,0 +1,32 @@
+//! A visitor that replaces constant ranges by explicit arrays.
+//!
+//! The `RangeExpander` evaluates the start, step and end of every
+//! `Expression::Range` with the `evaluator`, and replaces the range with the
+//! `Expression::Array` of its elements, so that `1:3` becomes `{1, 2, 3}` and
+//! `0:0.5:1` becomes `{0.0, 0.5, 1.0}`. Integer ranges produce Integer
+//! elements, and a Real bound or step produces Real elements. Ranges that
+//! cannot be evaluated, such as ranges over variables, are left intact.
+//!
+//! # Fields
+//! - `evaluator`: Constant evaluator used for the range bounds and step.
+use crate::ir;
+use crate::ir::ast::Expression;
+use crate::ir::const_eval::ConstEvaluator;
+use crate::ir::visitor::Visitor;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RangeExpander {
+    pub evaluator: ConstEvaluator,
+}
+
+impl Visitor for RangeExpander {
+    fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
+        if let Expression::Range { start, step, end } = node
+            && let Ok(values) = self.evaluator.eval_range(start, step.as_deref(), end)
+        {
+            *node = Expression::Array {
+                elements: values.iter().map(|v| v.to_expression()).collect(),
+            };
+        }
+    }
+}
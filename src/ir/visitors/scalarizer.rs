@@ -0,0 +1,664 @@
+//! A visitor that expands array concatenation into array literals.
+//!
+//! The `Scalarizer` replaces calls of the concatenation built-ins with the
+//! concatenated `Expression::Array`, when all of the concatenated arguments
+//! are arrays:
+//! - `cat(k, A, B, ...)` concatenates along dimension `k`, all other
+//!   dimensions must agree.
+//! - `vertcat(A, B, ...)` and `horzcat(A, B, ...)` concatenate along the first
+//!   and second dimension. As for the bracket notation `[A, B; C, D]`, which
+//!   the parser lowers to these functions, scalar arguments are promoted to
+//!   1x1 matrices and vectors to column matrices first.
+//!
+//! The array constructors are expanded to array literals as well, their
+//! dimensions must be constant Integers:
+//! - `zeros(n1, n2, ...)`, `ones(n1, n2, ...)` and `fill(s, n1, n2, ...)`
+//!   give an array of the given dimensions filled with `0`, `1` or `s`.
+//! - `identity(n)` gives the `n`x`n` identity matrix, and `diagonal(v)` the
+//!   square matrix with the elements of the vector `v` on its diagonal.
+//! - `transpose(A)` swaps the first two dimensions of `A`.
+//!
+//! Arrays are represented as nested `Expression::Array`s, and their shape is
+//! taken from the first element of each level. The concatenated arguments
+//! may also be array components of known shape, which are expanded to the
+//! array of their elements. Any other argument is treated as a scalar by
+//! `vertcat`/`horzcat`, while `cat` is left unchanged.
+//!
+//! Arithmetic on arrays is expanded element by element, references to the
+//! array components in `shapes` are expanded to the array of their elements,
+//! `A` of shape `[2]` to `{A[1], A[2]}`. The derivative `der(x)` of an array
+//! is the array of the derivatives of its elements, `{der(x[1]), der(x[2])}`:
+//! - `+`, `-` and the element-wise operators `.+`, `.-`, `.*`, `./` combine
+//!   arrays of equal shape element-wise, the element-wise operators and `*`,
+//!   `/` also combine an array with a scalar.
+//! - `*` of two arrays is the matrix product: vector times vector is the
+//!   scalar product, matrix times vector, vector times matrix and matrix
+//!   times matrix contract the last dimension of the left operand with the
+//!   first dimension of the right operand.
+//!
+//! Equations between arrays are then split into one equation per element.
+//!
+//! # Fields
+//! - `shapes`: Constant shapes of the array components.
+//! - `evaluator`: Evaluates the dimensions of the array constructors.
+//! - `errors`: Concatenations and operations that could not be expanded, such
+//!   as mismatched dimensions. The offending expressions are left in place.
+use crate::ir;
+use crate::ir::ast::{
+    ComponentReference, Equation, Expression, Location, OpBinary, OpUnary, Subscript, Token,
+};
+use crate::ir::const_eval::{ConstEvaluator, Value};
+use crate::ir::visitor::Visitor;
+use anyhow::{Result, anyhow};
+use indexmap::IndexMap;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Scalarizer {
+    pub shapes: IndexMap<String, Vec<usize>>,
+    pub evaluator: ConstEvaluator,
+    pub errors: Vec<String>,
+}
+
+impl Visitor for Scalarizer {
+    fn exit_expression(&mut self, node: &mut ir::ast::Expression) {
+        let result = match node {
+            Expression::FunctionCall { comp, args } => match comp.to_string().as_str() {
+                "cat" => cat(&self.expand(args)),
+                "vertcat" => concatenate(1, &promote(&self.expand(args))).map(Some),
+                "horzcat" => concatenate(2, &promote(&self.expand(args))).map(Some),
+                "zeros" => self.fill(&Value::Integer(0).to_expression(), args, "zeros"),
+                "ones" => self.fill(&Value::Integer(1).to_expression(), args, "ones"),
+                "fill" => match args.split_first() {
+                    Some((value, dims)) => self.fill(value, dims, "fill"),
+                    None => Err(anyhow!("fill requires a value argument")),
+                },
+                "identity" => self.identity(args),
+                "diagonal" => self.diagonal(args),
+                "transpose" => self.transpose(args),
+                "der" => Ok(self.der(comp, args)),
+                _ => return,
+            },
+            Expression::Binary { op, lhs, rhs } => self.binary(op, lhs, rhs),
+            Expression::Unary { op, rhs } => Ok(self.unary(op, rhs)),
+            _ => return,
+        };
+        match result {
+            Ok(Some(expr)) => *node = expr,
+            Ok(None) => {}
+            Err(e) => self.errors.push(e.to_string()),
+        }
+    }
+
+    fn exit_class_definition(&mut self, node: &mut ir::ast::ClassDefinition) {
+        let equations = std::mem::take(&mut node.equations);
+        for eq in equations {
+            match eq {
+                Equation::Simple { lhs, rhs, origin } => match self.split(&lhs, &rhs, &origin) {
+                    Ok(mut eqs) => node.equations.append(&mut eqs),
+                    Err(e) => {
+                        self.errors.push(e.to_string());
+                        node.equations.push(Equation::Simple { lhs, rhs, origin });
+                    }
+                },
+                _ => node.equations.push(eq),
+            }
+        }
+    }
+}
+
+impl Scalarizer {
+    /// Returns the shape of an expression, scalars have an empty shape.
+    fn shape(&self, expr: &Expression) -> Vec<usize> {
+        match expr {
+            Expression::ComponentReference(cref) => self
+                .shapes
+                .get(&cref.to_string())
+                .cloned()
+                .unwrap_or_default(),
+            _ => shape(expr),
+        }
+    }
+
+    /// Returns the array literal of an array expression, expanding the
+    /// references to array components.
+    fn array(&self, expr: &Expression) -> Option<Expression> {
+        match expr {
+            Expression::Array { .. } => Some(expr.clone()),
+            Expression::ComponentReference(cref) => self
+                .shapes
+                .get(&cref.to_string())
+                .map(|shape| elements(cref, shape, &mut Vec::new())),
+            _ => None,
+        }
+    }
+
+    /// Returns the arguments of a concatenation, with the references to array
+    /// components expanded to the array of their elements.
+    fn expand(&self, args: &[Expression]) -> Vec<Expression> {
+        args.iter()
+            .map(|arg| self.array(arg).unwrap_or_else(|| arg.clone()))
+            .collect()
+    }
+
+    /// Expands `zeros`, `ones` and `fill` of the function `name`, an array of
+    /// the dimensions `dims` filled with `value`.
+    fn fill(
+        &self,
+        value: &Expression,
+        dims: &[Expression],
+        name: &str,
+    ) -> Result<Option<Expression>> {
+        if dims.is_empty() {
+            return Err(anyhow!("{} requires at least one dimension", name));
+        }
+        let dims = dims
+            .iter()
+            .map(|dim| self.dimension(dim, name))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(dims.iter().rev().fold(
+            value.clone(),
+            |element, &n| Expression::Array {
+                elements: vec![element; n],
+            },
+        )))
+    }
+
+    /// Expands `identity(n)`.
+    fn identity(&self, args: &[Expression]) -> Result<Option<Expression>> {
+        let [n] = args else {
+            return Err(anyhow!("identity requires one argument"));
+        };
+        let n = self.dimension(n, "identity")?;
+        Ok(Some(square(n, &|_| Value::Integer(1).to_expression())))
+    }
+
+    /// Evaluates a dimension argument of the function `name`.
+    fn dimension(&self, dim: &Expression, name: &str) -> Result<usize> {
+        let n = self
+            .evaluator
+            .eval(dim)
+            .and_then(|v| v.as_i64())
+            .map_err(|_| anyhow!("{} dimensions must be constant Integers", name))?;
+        usize::try_from(n)
+            .map_err(|_| anyhow!("{} dimensions must not be negative, found {}", name, n))
+    }
+
+    /// Expands `diagonal(v)`, returns `None` if `v` is not an array literal
+    /// or an array component.
+    fn diagonal(&self, args: &[Expression]) -> Result<Option<Expression>> {
+        let [v] = args else {
+            return Err(anyhow!("diagonal requires one argument"));
+        };
+        let shape = self.shape(v);
+        if shape.len() != 1 {
+            return Err(anyhow!(
+                "diagonal requires a vector, found an array of shape {:?}",
+                shape
+            ));
+        }
+        let Some(Expression::Array { elements }) = self.array(v) else {
+            return Ok(None);
+        };
+        Ok(Some(square(elements.len(), &|i| elements[i].clone())))
+    }
+
+    /// Expands `transpose(A)`, returns `None` if `A` is not an array literal
+    /// or an array component.
+    fn transpose(&self, args: &[Expression]) -> Result<Option<Expression>> {
+        let [a] = args else {
+            return Err(anyhow!("transpose requires one argument"));
+        };
+        let shape = self.shape(a);
+        if shape.len() < 2 {
+            return Err(anyhow!(
+                "transpose requires a matrix, found an array of shape {:?}",
+                shape
+            ));
+        }
+        let Some(array) = self.array(a) else {
+            return Ok(None);
+        };
+        rectangular(&array)?;
+        let Expression::Array { elements: rows } = array else {
+            return Ok(None);
+        };
+        // every row is an array of `shape[1]` elements once the literal is
+        // known to be rectangular
+        let element = |i: usize, j: usize| match &rows[i] {
+            Expression::Array { elements } => elements[j].clone(),
+            _ => unreachable!(),
+        };
+        Ok(Some(Expression::Array {
+            elements: (0..shape[1])
+                .map(|j| Expression::Array {
+                    elements: (0..shape[0]).map(|i| element(i, j)).collect(),
+                })
+                .collect(),
+        }))
+    }
+
+    /// Expands `der(x)` of an array `x` to the array of the derivatives of
+    /// its elements, returns `None` for scalars.
+    fn der(&self, comp: &ComponentReference, args: &[Expression]) -> Option<Expression> {
+        let [arg] = args else {
+            return None;
+        };
+        if self.shape(arg).is_empty() {
+            return None;
+        }
+        let arg = self.array(arg)?;
+        Some(map(&arg, &|e| Expression::FunctionCall {
+            comp: comp.clone(),
+            args: vec![e.clone()],
+        }))
+    }
+
+    fn unary(&self, op: &OpUnary, rhs: &Expression) -> Option<Expression> {
+        if self.shape(rhs).is_empty() {
+            return None;
+        }
+        let rhs = self.array(rhs)?;
+        Some(map(&rhs, &|e| Expression::Unary {
+            op: op.clone(),
+            rhs: Box::new(e.clone()),
+        }))
+    }
+
+    fn binary(
+        &self,
+        op: &OpBinary,
+        lhs: &Expression,
+        rhs: &Expression,
+    ) -> Result<Option<Expression>> {
+        let (lhs_shape, rhs_shape) = (self.shape(lhs), self.shape(rhs));
+        if lhs_shape.is_empty() && rhs_shape.is_empty() {
+            return Ok(None);
+        }
+        let scalar_op = match op {
+            OpBinary::Add(t) | OpBinary::AddElem(t) => OpBinary::Add(t.clone()),
+            OpBinary::Sub(t) | OpBinary::SubElem(t) => OpBinary::Sub(t.clone()),
+            OpBinary::Mul(t) | OpBinary::MulElem(t) => OpBinary::Mul(t.clone()),
+            OpBinary::Div(t) | OpBinary::DivElem(t) => OpBinary::Div(t.clone()),
+            _ => return Ok(None),
+        };
+        let element_wise = |lhs: &Expression, rhs: &Expression| binary(&scalar_op, lhs, rhs);
+        let mismatch = || {
+            anyhow!(
+                "cannot apply '{}' to arrays of shape {:?} and {:?}",
+                operator(op),
+                lhs_shape,
+                rhs_shape
+            )
+        };
+        let (Some(l), Some(r)) = (
+            self.array(lhs)
+                .or_else(|| lhs_shape.is_empty().then(|| lhs.clone())),
+            self.array(rhs)
+                .or_else(|| rhs_shape.is_empty().then(|| rhs.clone())),
+        ) else {
+            return Ok(None);
+        };
+        for array in [&l, &r] {
+            rectangular(array)?;
+        }
+
+        if matches!(op, OpBinary::Mul(..)) && !lhs_shape.is_empty() && !rhs_shape.is_empty() {
+            return match (lhs_shape.len(), rhs_shape.len()) {
+                (1 | 2, 1 | 2) if lhs_shape.last() == rhs_shape.first() => {
+                    Ok(Some(product(&l, &r, &scalar_op)))
+                }
+                _ => Err(mismatch()),
+            };
+        }
+        if lhs_shape.is_empty() || rhs_shape.is_empty() {
+            // only the element-wise operators, `*` and `/` by a scalar
+            // combine an array with a scalar
+            let broadcast = match op {
+                OpBinary::Mul(..) => true,
+                OpBinary::Div(..) => rhs_shape.is_empty(),
+                OpBinary::AddElem(..)
+                | OpBinary::SubElem(..)
+                | OpBinary::MulElem(..)
+                | OpBinary::DivElem(..) => true,
+                _ => false,
+            };
+            if !broadcast {
+                return Err(mismatch());
+            }
+            return Ok(Some(if lhs_shape.is_empty() {
+                map(&r, &|e| element_wise(&l, e))
+            } else {
+                map(&l, &|e| element_wise(e, &r))
+            }));
+        }
+        if lhs_shape != rhs_shape || matches!(op, OpBinary::Div(..)) {
+            return Err(mismatch());
+        }
+        Ok(Some(zip(&l, &r, &element_wise)))
+    }
+
+    /// Splits an equation between arrays into one equation per element.
+    fn split(
+        &self,
+        lhs: &Expression,
+        rhs: &Expression,
+        origin: &Option<Location>,
+    ) -> Result<Vec<Equation>> {
+        let (lhs_shape, rhs_shape) = (self.shape(lhs), self.shape(rhs));
+        let equation = || Equation::Simple {
+            lhs: lhs.clone(),
+            rhs: rhs.clone(),
+            origin: origin.clone(),
+        };
+        if lhs_shape.is_empty() && rhs_shape.is_empty() {
+            return Ok(vec![equation()]);
+        }
+        // keep equations with a side of unknown shape, such as a call of an
+        // array valued function, unless the other side is an array literal
+        if (lhs_shape.is_empty() || rhs_shape.is_empty())
+            && !matches!(lhs, Expression::Array { .. })
+            && !matches!(rhs, Expression::Array { .. })
+        {
+            return Ok(vec![equation()]);
+        }
+        if lhs_shape != rhs_shape {
+            return Err(anyhow!(
+                "cannot equate arrays of shape {:?} and {:?}",
+                lhs_shape,
+                rhs_shape
+            ));
+        }
+        let (Some(l), Some(r)) = (self.array(lhs), self.array(rhs)) else {
+            return Ok(vec![equation()]);
+        };
+        let equations = leaves(&l)
+            .into_iter()
+            .zip(leaves(&r))
+            .map(|(lhs, rhs)| Equation::Simple {
+                lhs,
+                rhs,
+                origin: origin.clone(),
+            })
+            .collect();
+        Ok(equations)
+    }
+}
+
+/// Expands `cat(k, A, B, ...)`, returns `None` if an argument is not an
+/// array literal, after the expansion of array components.
+fn cat(args: &[Expression]) -> Result<Option<Expression>> {
+    let Some((dim, arrays)) = args.split_first() else {
+        return Err(anyhow!("cat requires a dimension argument"));
+    };
+    let dim = ConstEvaluator::default()
+        .eval(dim)
+        .and_then(|v| v.as_i64())
+        .map_err(|_| anyhow!("cat dimension must be a constant Integer"))?;
+    if dim < 1 {
+        return Err(anyhow!("cat dimension must be positive, found {}", dim));
+    }
+    if !arrays.iter().all(|a| matches!(a, Expression::Array { .. })) {
+        return Ok(None);
+    }
+    concatenate(dim as usize, arrays).map(Some)
+}
+
+/// Returns the `n`x`n` matrix with `diagonal(i)` on its diagonal and zeros
+/// elsewhere.
+fn square(n: usize, diagonal: &dyn Fn(usize) -> Expression) -> Expression {
+    Expression::Array {
+        elements: (0..n)
+            .map(|i| Expression::Array {
+                elements: (0..n)
+                    .map(|j| {
+                        if i == j {
+                            diagonal(i)
+                        } else {
+                            Value::Integer(0).to_expression()
+                        }
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Returns the shape of an array literal, scalars have an empty shape.
+fn shape(expr: &Expression) -> Vec<usize> {
+    match expr {
+        Expression::Array { elements } => {
+            let mut shape = vec![elements.len()];
+            if let Some(first) = elements.first() {
+                shape.extend(self::shape(first));
+            }
+            shape
+        }
+        _ => vec![],
+    }
+}
+
+/// Checks that the elements of an array literal all have the same shape,
+/// `shape` only looks at the first element of each dimension.
+fn rectangular(expr: &Expression) -> Result<()> {
+    if let Expression::Array { elements } = expr {
+        let first = elements.first().map(shape).unwrap_or_default();
+        for element in elements {
+            if shape(element) != first {
+                return Err(anyhow!(
+                    "array is not rectangular, found elements of shape {:?} and {:?}",
+                    first,
+                    shape(element)
+                ));
+            }
+            rectangular(element)?;
+        }
+    }
+    Ok(())
+}
+
+/// Promotes scalars and vectors to matrices for `vertcat`/`horzcat`.
+fn promote(args: &[Expression]) -> Vec<Expression> {
+    args.iter()
+        .map(|arg| match shape(arg).len() {
+            0 => Expression::Array {
+                elements: vec![Expression::Array {
+                    elements: vec![arg.clone()],
+                }],
+            },
+            1 => match arg {
+                Expression::Array { elements } => Expression::Array {
+                    elements: elements
+                        .iter()
+                        .map(|e| Expression::Array {
+                            elements: vec![e.clone()],
+                        })
+                        .collect(),
+                },
+                _ => unreachable!(),
+            },
+            _ => arg.clone(),
+        })
+        .collect()
+}
+
+/// Concatenates array literals along dimension `dim`, starting at 1.
+fn concatenate(dim: usize, arrays: &[Expression]) -> Result<Expression> {
+    let shapes: Vec<Vec<usize>> = arrays.iter().map(shape).collect();
+    for (i, s) in shapes.iter().enumerate() {
+        if s.len() < dim {
+            return Err(anyhow!(
+                "cannot concatenate along dimension {} an argument with {} dimension(s)",
+                dim,
+                s.len()
+            ));
+        }
+        if s.len() != shapes[0].len()
+            || s.iter()
+                .zip(&shapes[0])
+                .enumerate()
+                .any(|(d, (a, b))| d != dim - 1 && a != b)
+        {
+            return Err(anyhow!(
+                "cannot concatenate along dimension {} arrays of shape {:?} and {:?}",
+                dim,
+                shapes[0],
+                shapes[i]
+            ));
+        }
+    }
+    let elements: Vec<&Vec<Expression>> = arrays
+        .iter()
+        .map(|a| match a {
+            Expression::Array { elements } => elements,
+            _ => unreachable!(),
+        })
+        .collect();
+    if dim == 1 {
+        return Ok(Expression::Array {
+            elements: elements.into_iter().flatten().cloned().collect(),
+        });
+    }
+    let rows = shapes.first().map(|s| s[0]).unwrap_or(0);
+    let mut result = Vec::new();
+    for i in 0..rows {
+        let row: Vec<Expression> = elements.iter().map(|e| e[i].clone()).collect();
+        result.push(concatenate(dim - 1, &row)?);
+    }
+    Ok(Expression::Array { elements: result })
+}
+
+/// Returns the array of the elements of the array component `cref`.
+fn elements(cref: &ComponentReference, shape: &[usize], index: &mut Vec<usize>) -> Expression {
+    let Some((&n, rest)) = shape.split_first() else {
+        let mut element = cref.clone();
+        if let Some(part) = element.parts.last_mut() {
+            part.subs = Some(
+                index
+                    .iter()
+                    .map(|&i| Subscript::Expression(Value::Integer(i as i64).to_expression()))
+                    .collect(),
+            );
+        }
+        return Expression::ComponentReference(element);
+    };
+    Expression::Array {
+        elements: (1..=n)
+            .map(|i| {
+                index.push(i);
+                let element = elements(cref, rest, index);
+                index.pop();
+                element
+            })
+            .collect(),
+    }
+}
+
+/// Applies `f` to each element of an array.
+fn map(expr: &Expression, f: &dyn Fn(&Expression) -> Expression) -> Expression {
+    match expr {
+        Expression::Array { elements } => Expression::Array {
+            elements: elements.iter().map(|e| map(e, f)).collect(),
+        },
+        _ => f(expr),
+    }
+}
+
+/// Returns the elements of an array in row-major order.
+fn leaves(expr: &Expression) -> Vec<Expression> {
+    match expr {
+        Expression::Array { elements } => elements.iter().flat_map(leaves).collect(),
+        _ => vec![expr.clone()],
+    }
+}
+
+/// Applies `f` to the pairs of elements of two arrays of equal shape.
+fn zip(
+    lhs: &Expression,
+    rhs: &Expression,
+    f: &dyn Fn(&Expression, &Expression) -> Expression,
+) -> Expression {
+    match (lhs, rhs) {
+        (Expression::Array { elements: l }, Expression::Array { elements: r }) => {
+            Expression::Array {
+                elements: l.iter().zip(r).map(|(l, r)| zip(l, r, f)).collect(),
+            }
+        }
+        _ => f(lhs, rhs),
+    }
+}
+
+/// Multiplies two vectors or matrices.
+fn product(lhs: &Expression, rhs: &Expression, mul: &OpBinary) -> Expression {
+    let rows = |expr: &Expression| -> Vec<Expression> {
+        match expr {
+            Expression::Array { elements } => elements.clone(),
+            _ => vec![expr.clone()],
+        }
+    };
+    let (l, r) = (rows(lhs), rows(rhs));
+    let column =
+        |j: usize| -> Vec<Expression> { r.iter().map(|row| rows(row)[j].clone()).collect() };
+    let dot = |u: &[Expression], v: &[Expression]| -> Expression {
+        u.iter()
+            .zip(v)
+            .map(|(a, b)| binary(mul, a, b))
+            .reduce(|sum, term| {
+                binary(
+                    &OpBinary::Add(Token {
+                        text: "+".to_string(),
+                        ..Default::default()
+                    }),
+                    &sum,
+                    &term,
+                )
+            })
+            .unwrap_or_default()
+    };
+    let is_matrix = |rows: &[Expression]| matches!(rows.first(), Some(Expression::Array { .. }));
+    let columns = match r.first() {
+        Some(Expression::Array { elements }) => elements.len(),
+        _ => 0,
+    };
+    match (is_matrix(&l), is_matrix(&r)) {
+        // scalar product
+        (false, false) => dot(&l, &r),
+        // matrix times vector
+        (true, false) => Expression::Array {
+            elements: l.iter().map(|row| dot(&rows(row), &r)).collect(),
+        },
+        // vector times matrix
+        (false, true) => Expression::Array {
+            elements: (0..columns).map(|j| dot(&l, &column(j))).collect(),
+        },
+        (true, true) => Expression::Array {
+            elements: l
+                .iter()
+                .map(|row| Expression::Array {
+                    elements: (0..columns).map(|j| dot(&rows(row), &column(j))).collect(),
+                })
+                .collect(),
+        },
+    }
+}
+
+fn binary(op: &OpBinary, lhs: &Expression, rhs: &Expression) -> Expression {
+    Expression::Binary {
+        op: op.clone(),
+        lhs: Box::new(lhs.clone()),
+        rhs: Box::new(rhs.clone()),
+    }
+}
+
+fn operator(op: &OpBinary) -> &str {
+    match op {
+        OpBinary::Add(t)
+        | OpBinary::Sub(t)
+        | OpBinary::Mul(t)
+        | OpBinary::Div(t)
+        | OpBinary::AddElem(t)
+        | OpBinary::SubElem(t)
+        | OpBinary::MulElem(t)
+        | OpBinary::DivElem(t) => &t.text,
+        _ => "",
+    }
+}
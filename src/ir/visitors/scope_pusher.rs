@@ -7,7 +7,8 @@
 //! This visitor implements the `Visitor` trait and overrides the `exit_component_reference` method.
 //! When visiting a `ComponentReference` node, it checks if the first part of the reference's identifier
 //! is not `"der"`. If this condition is met, it prepends a new `ComponentRefPart` to the reference's parts,
-//! using the `comp` field as the identifier text.
+//! using the `comp` field as the identifier text. The new part takes the source location of the
+//! reference, so that the flattened equation still points at its original declaration.
 //!
 //! This is useful for ensuring that component references are properly scoped by adding a prefix
 //! to their identifiers when necessary.
@@ -33,7 +34,7 @@ impl Visitor for ScopePusher {
             node.parts.insert(0, ir::ast::ComponentRefPart {
                 ident: ir::ast::Token {
                     text: self.comp.clone(),
-                    ..Default::default()
+                    ..node.parts[0].ident.clone()
                 },
                 subs: None,
             });
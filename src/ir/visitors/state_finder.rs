@@ -14,9 +14,13 @@
 //!   during the AST traversal. It performs the following actions:
 //!   - Checks if the expression is a function call with the identifier `der`.
 //!   - If the first argument of the `der` function is a component reference,
-//!     the state variable name is extracted and added to the `states` set.
+//!     the referenced name, such as `x` or `x[1]` for an array element, is
+//!     added to the `states` set.
+//!     References to `time`, the independent variable, are left unchanged.
 //!   - Modifies the AST by replacing the original state variable reference with
 //!     a new component reference prefixed with `der_`.
+//!   - Expressions are exited inner first, so `der(der(x))` becomes
+//!     `der_der_x`, with both `x` and `der_x` added to `states`.
 //!
 //! This visitor is useful for analyzing and transforming ASTs in scenarios
 //! where state variables and their derivatives need to be explicitly tracked
@@ -39,8 +43,11 @@ impl Visitor for StateFinder {
                 if comp.to_string() == "der" {
                     let arg = args.get(0).unwrap();
                     match &arg {
-                        ir::ast::Expression::ComponentReference(comp) => {
-                            self.states.insert(comp.parts[0].ident.text.clone());
+                        // time is the independent variable, never a state
+                        ir::ast::Expression::ComponentReference(comp)
+                            if comp.to_string() != "time" =>
+                        {
+                            self.states.insert(comp.to_string());
                             let mut der_comp = comp.clone();
                             der_comp.parts[0].ident.text =
                                 format!("der_{}", comp.parts[0].ident.text);
@@ -5,7 +5,8 @@
 //! It operates by checking if the first part of the component reference matches
 //! the specified component name (`comp`). If a match is found, the first part
 //! of the reference is removed, and the next part is renamed by prefixing it
-//! with the component name.
+//! with the component name. The renamed part keeps its token, and with it the
+//! source location of the reference.
 //!
 //! # Fields
 //! - `comp`: The name of the component to match and use as a prefix for renaming.
@@ -0,0 +1,67 @@
+//! A visitor that removes tautological equations.
+//!
+//! Substituting constants or eliminating aliases can leave equations whose
+//! sides are the same expression, such as `x = x`. These hold for any value
+//! and only disturb the count of equations, so the `TautologyRemover` drops
+//! them from the equations of a class, including the bodies of `for`, `if`
+//! and `when` equations. Sides are compared with
+//! `Expression::structurally_eq`, so `x = -x`, which implies `x = 0`, is
+//! kept.
+//!
+//! # Fields
+//! - `removed`: Number of equations removed.
+use crate::ir;
+use crate::ir::ast::Equation;
+use crate::ir::visitor::Visitor;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TautologyRemover {
+    pub removed: usize,
+}
+
+impl TautologyRemover {
+    /// Removes the tautologies among `equations`.
+    pub fn remove(&mut self, equations: &mut Vec<Equation>) {
+        let count = equations.len();
+        equations.retain(|eq| !is_tautology(eq));
+        self.removed += count - equations.len();
+    }
+}
+
+/// Returns true for a simple equation whose sides are structurally equal.
+pub fn is_tautology(eq: &Equation) -> bool {
+    match eq {
+        Equation::Simple { lhs, rhs, .. } => lhs.structurally_eq(rhs),
+        _ => false,
+    }
+}
+
+impl Visitor for TautologyRemover {
+    fn exit_equation(&mut self, node: &mut ir::ast::Equation) {
+        match node {
+            Equation::For { equations, .. } => self.remove(equations),
+            Equation::When(blocks) => {
+                for block in blocks {
+                    self.remove(&mut block.eqs);
+                }
+            }
+            Equation::If {
+                cond_blocks,
+                else_block,
+            } => {
+                for block in cond_blocks {
+                    self.remove(&mut block.eqs);
+                }
+                if let Some(else_block) = else_block {
+                    self.remove(else_block);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn exit_class_definition(&mut self, node: &mut ir::ast::ClassDefinition) {
+        self.remove(&mut node.equations);
+        self.remove(&mut node.initial_equations);
+    }
+}
@@ -3,3 +3,48 @@ pub mod ir;
 pub mod modelica_grammar;
 pub mod modelica_grammar_trait;
 pub mod modelica_parser;
+pub mod parser;
+
+use anyhow::{Context, Result, anyhow};
+use dae::ast::Dae;
+use ir::create_dae::create_dae_with_options;
+use ir::flatten::{FlattenOptions, flatten_with_options};
+
+/// Compiles the Modelica source `src` to the DAE of the class `model_class`,
+/// running the parser, `flatten` and `create_dae` with their default options.
+/// Errors name the stage that failed.
+pub fn compile(src: &str, model_class: &str) -> Result<Dae> {
+    let def = parser::Parser::new()
+        .parse(src, "<source>")
+        .context("failed to parse the source")?;
+    let options = FlattenOptions {
+        main_class: Some(model_class.to_string()),
+        ..Default::default()
+    };
+    let mut fclass = flatten_with_options(&def, &options)
+        .with_context(|| format!("failed to flatten '{}'", model_class))?;
+    create_dae_with_options(&mut fclass, &options)
+        .with_context(|| format!("failed to create the DAE of '{}'", model_class))
+}
+
+/// Checks that the Modelica source `src` survives printing: the parsed
+/// definition is printed as Modelica source, which is parsed again. Returns
+/// the printed source when both definitions are structurally equal, ignoring
+/// source locations, and otherwise an error describing the first divergence,
+/// such as a construct the printer does not support.
+pub fn round_trip(src: &str) -> Result<String> {
+    let def = parser::Parser::new()
+        .parse(src, "<source>")
+        .context("failed to parse the source")?;
+    let printed = def.to_modelica();
+    let reparsed = parser::Parser::new()
+        .parse(&printed, "<printed>")
+        .context("failed to parse the printed Modelica")?;
+    match def.diff(&reparsed).first() {
+        Some(divergence) => Err(anyhow!(
+            "the printed Modelica differs from the source: {}",
+            divergence
+        )),
+        None => Ok(printed),
+    }
+}
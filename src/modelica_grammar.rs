@@ -15,7 +15,8 @@
 //! - **ClassDefinition Conversion**: Handles the conversion of Modelica class
 //!   definitions, including long and short class specifiers.
 //! - **Composition and ElementList**: Converts Modelica compositions and element
-//!   lists into their internal representations.
+//!   lists into their internal representations, nested class definitions
+//!   become the `classes` of the enclosing class.
 //! - **Equation and Algorithm Sections**: Converts Modelica equation and algorithm
 //!   sections, including initial and non-initial variants.
 //! - **Expressions and Statements**: Provides detailed conversions for Modelica
@@ -37,6 +38,7 @@
 //! implements the `modelica_grammar_trait::ModelicaGrammarTrait` trait. The `stored_definition`
 //! method is used to parse and store the converted Modelica AST.
 use crate::ir;
+use crate::ir::types::Type;
 use crate::modelica_grammar_trait;
 use indexmap::IndexMap;
 use parol_runtime::{Result, Token};
@@ -106,6 +108,7 @@ impl TryFrom<&modelica_grammar_trait::ClassDefinition> for ir::ast::ClassDefinit
     fn try_from(
         ast: &modelica_grammar_trait::ClassDefinition,
     ) -> std::result::Result<Self, Self::Error> {
+        let class_type = class_type(&ast.class_prefixes);
         match &ast.class_specifier {
             modelica_grammar_trait::ClassSpecifier::LongClassSpecifier(long) => {
                 match &long.long_class_specifier {
@@ -115,31 +118,223 @@ impl TryFrom<&modelica_grammar_trait::ClassDefinition> for ir::ast::ClassDefinit
                         let spec = &class_specifier.standard_class_specifier;
                         Ok(ir::ast::ClassDefinition {
                             name: spec.name.clone(),
+                            class_type,
                             extends: spec.composition.extends.clone(),
                             equations: spec.composition.equations.clone(),
                             algorithms: spec.composition.algorithms.clone(),
                             initial_equations: spec.composition.initial_equations.clone(),
                             initial_algorithms: spec.composition.initial_algorithms.clone(),
                             components: spec.composition.components.clone(),
+                            classes: spec.composition.classes.clone(),
+                            imports: spec.composition.imports.clone(),
+                            description: spec.description_string.tokens.clone(),
+                            annotation: spec.composition.annotation.clone(),
+                            hints: rumoca_hints(&spec.composition.annotation),
                             encapsulated: ast.class_definition_opt.is_some(),
+                            partial: ast.class_prefixes.class_prefixes_opt.is_some(),
+                            operator: is_operator(&ast.class_prefixes),
+                            ..Default::default()
                         })
                     }
-                    modelica_grammar_trait::LongClassSpecifier::ExtendsClassSpecifier(..) => {
-                        todo!("extends")
+                    modelica_grammar_trait::LongClassSpecifier::ExtendsClassSpecifier(
+                        class_specifier,
+                    ) => {
+                        // `class extends B(...)` extends the inherited class B
+                        // of the same name, with the given modifications
+                        let spec = &class_specifier.extends_class_specifier;
+                        let mut extends = vec![ir::ast::Extend {
+                            comp: ir::ast::Name {
+                                name: vec![spec.ident.clone()],
+                            },
+                            modifications: match &spec.extends_class_specifier_opt {
+                                Some(opt) => opt.class_modification.modifications.clone(),
+                                None => IndexMap::new(),
+                            },
+                            redeclarations: match &spec.extends_class_specifier_opt {
+                                Some(opt) => opt.class_modification.redeclarations.clone(),
+                                None => IndexMap::new(),
+                            },
+                        }];
+                        extends.extend(spec.composition.extends.clone());
+                        Ok(ir::ast::ClassDefinition {
+                            name: spec.ident.clone(),
+                            class_type,
+                            extends,
+                            equations: spec.composition.equations.clone(),
+                            algorithms: spec.composition.algorithms.clone(),
+                            initial_equations: spec.composition.initial_equations.clone(),
+                            initial_algorithms: spec.composition.initial_algorithms.clone(),
+                            components: spec.composition.components.clone(),
+                            classes: spec.composition.classes.clone(),
+                            imports: spec.composition.imports.clone(),
+                            description: spec.description_string.tokens.clone(),
+                            annotation: spec.composition.annotation.clone(),
+                            hints: rumoca_hints(&spec.composition.annotation),
+                            encapsulated: ast.class_definition_opt.is_some(),
+                            partial: ast.class_prefixes.class_prefixes_opt.is_some(),
+                            operator: is_operator(&ast.class_prefixes),
+                            ..Default::default()
+                        })
                     }
                 }
             }
-            modelica_grammar_trait::ClassSpecifier::DerClassSpecifier(_spec) => todo!("der"),
-            modelica_grammar_trait::ClassSpecifier::ShortClassSpecifier(short) => {
-                match &short.short_class_specifier {
-                    modelica_grammar_trait::ShortClassSpecifier::EnumClassSpecifier(_spec) => {
-                        todo!("enum class specifier")
-                    }
-                    modelica_grammar_trait::ShortClassSpecifier::TypeClassSpecifier(_spec) => {
-                        //spec.type_class_specifier.base_prefix.
-                        todo!("type class specifier");
+            modelica_grammar_trait::ClassSpecifier::DerClassSpecifier(der) => {
+                let spec = &der.der_class_specifier;
+                let mut variables = vec![spec.ident0.clone()];
+                variables.extend(
+                    spec.der_class_specifier_list
+                        .iter()
+                        .map(|item| item.ident.clone()),
+                );
+                Ok(ir::ast::ClassDefinition {
+                    name: spec.ident.clone(),
+                    class_type,
+                    description: spec.description.description_string.tokens.clone(),
+                    encapsulated: ast.class_definition_opt.is_some(),
+                    partial: ast.class_prefixes.class_prefixes_opt.is_some(),
+                    operator: is_operator(&ast.class_prefixes),
+                    der_class: Some(ir::ast::DerClass {
+                        class: spec.type_specifier.name.clone(),
+                        variables,
+                    }),
+                    ..Default::default()
+                })
+            }
+            modelica_grammar_trait::ClassSpecifier::ShortClassSpecifier(short) => Ok(short_class(
+                &ast.class_prefixes,
+                &short.short_class_specifier,
+                ast.class_definition_opt.is_some(),
+            )),
+        }
+    }
+}
+
+/// Returns the class type given by the class prefixes.
+fn class_type(prefixes: &modelica_grammar_trait::ClassPrefixes) -> ir::ast::ClassType {
+    match &prefixes.class_type {
+        modelica_grammar_trait::ClassType::Class(c) => {
+            ir::ast::ClassType::Class(c.class.class.clone())
+        }
+        modelica_grammar_trait::ClassType::Model(c) => {
+            ir::ast::ClassType::Model(c.model.model.clone())
+        }
+        modelica_grammar_trait::ClassType::ClassTypeOptRecord(c) => {
+            ir::ast::ClassType::Record(c.record.record.clone())
+        }
+        modelica_grammar_trait::ClassType::Block(c) => {
+            ir::ast::ClassType::Block(c.block.block.clone())
+        }
+        modelica_grammar_trait::ClassType::ClassTypeOpt0Connector(c) => {
+            ir::ast::ClassType::Connector(c.connector.connector.clone())
+        }
+        modelica_grammar_trait::ClassType::Type(c) => {
+            ir::ast::ClassType::Type(c.r#type.r#type.clone())
+        }
+        modelica_grammar_trait::ClassType::Package(c) => {
+            ir::ast::ClassType::Package(c.package.package.clone())
+        }
+        modelica_grammar_trait::ClassType::ClassTypeOpt1ClassTypeOpt2Function(c) => {
+            ir::ast::ClassType::Function(c.function.function.clone())
+        }
+        modelica_grammar_trait::ClassType::Operator(c) => {
+            ir::ast::ClassType::Operator(c.operator.operator.clone())
+        }
+    }
+}
+
+/// Tells whether the class prefixes declare an `operator record` or an
+/// `operator function`.
+fn is_operator(prefixes: &modelica_grammar_trait::ClassPrefixes) -> bool {
+    match &prefixes.class_type {
+        modelica_grammar_trait::ClassType::ClassTypeOptRecord(c) => c.class_type_opt.is_some(),
+        modelica_grammar_trait::ClassType::ClassTypeOpt1ClassTypeOpt2Function(c) => {
+            c.class_type_opt2.is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Returns the class of a short class specifier, an enumeration or a class
+/// extending a single base class, such as a type alias.
+fn short_class(
+    prefixes: &modelica_grammar_trait::ClassPrefixes,
+    spec: &modelica_grammar_trait::ShortClassSpecifier,
+    encapsulated: bool,
+) -> ir::ast::ClassDefinition {
+    let class_type = class_type(prefixes);
+    let partial = prefixes.class_prefixes_opt.is_some();
+    let operator = is_operator(prefixes);
+    match spec {
+        modelica_grammar_trait::ShortClassSpecifier::EnumClassSpecifier(spec) => {
+            let spec = &spec.enum_class_specifier;
+            let mut literals = Vec::new();
+            let mut unspecified = false;
+            match &spec.enum_class_specifier_group {
+                modelica_grammar_trait::EnumClassSpecifierGroup::EnumClassSpecifierOpt(opt) => {
+                    if let Some(opt) = &opt.enum_class_specifier_opt {
+                        let list = &opt.enum_list;
+                        for literal in std::iter::once(&list.enumeration_literal)
+                            .chain(list.enum_list_list.iter().map(|l| &l.enumeration_literal))
+                        {
+                            literals.push(ir::ast::EnumerationLiteral {
+                                ident: literal.ident.clone(),
+                                description: literal.description.description_string.tokens.clone(),
+                            });
+                        }
                     }
                 }
+                modelica_grammar_trait::EnumClassSpecifierGroup::Colon(..) => {
+                    unspecified = true;
+                }
+            }
+            ir::ast::ClassDefinition {
+                name: spec.ident.clone(),
+                class_type,
+                description: spec.description.description_string.tokens.clone(),
+                encapsulated,
+                partial,
+                operator,
+                enumeration_literals: literals,
+                unspecified_enumeration: unspecified,
+                ..Default::default()
+            }
+        }
+        modelica_grammar_trait::ShortClassSpecifier::TypeClassSpecifier(spec) => {
+            let spec = &spec.type_class_specifier;
+            let causality = match &spec.base_prefix.base_prefix_opt {
+                Some(opt) => match &opt.base_prefix_opt_group {
+                    modelica_grammar_trait::BasePrefixOptGroup::Input(c) => {
+                        ir::ast::Causality::Input(c.input.input.clone())
+                    }
+                    modelica_grammar_trait::BasePrefixOptGroup::Output(c) => {
+                        ir::ast::Causality::Output(c.output.output.clone())
+                    }
+                },
+                None => ir::ast::Causality::Empty,
+            };
+            // a type alias is represented as a class extending its base type
+            ir::ast::ClassDefinition {
+                name: spec.ident.clone(),
+                class_type,
+                causality,
+                extends: vec![ir::ast::Extend {
+                    comp: spec.type_specifier.name.clone(),
+                    modifications: match &spec.type_class_specifier_opt0 {
+                        Some(opt) => opt.class_modification.modifications.clone(),
+                        None => IndexMap::new(),
+                    },
+                    ..Default::default()
+                }],
+                shape: match &spec.type_class_specifier_opt {
+                    Some(opt) => opt.array_subscripts.subscripts.clone(),
+                    None => Vec::new(),
+                },
+                description: spec.description.description_string.tokens.clone(),
+                encapsulated,
+                partial,
+                operator,
+                short_class: true,
+                ..Default::default()
             }
         }
     }
@@ -150,11 +345,14 @@ impl TryFrom<&modelica_grammar_trait::ClassDefinition> for ir::ast::ClassDefinit
 #[allow(unused)]
 pub struct Composition {
     pub extends: Vec<ir::ast::Extend>,
+    pub imports: Vec<ir::ast::Import>,
     pub components: IndexMap<String, ir::ast::Component>,
+    pub classes: IndexMap<String, ir::ast::ClassDefinition>,
     pub equations: Vec<ir::ast::Equation>,
     pub initial_equations: Vec<ir::ast::Equation>,
     pub algorithms: Vec<Vec<ir::ast::Statement>>,
     pub initial_algorithms: Vec<Vec<ir::ast::Statement>>,
+    pub annotation: IndexMap<String, ir::ast::Expression>,
 }
 
 impl TryFrom<&modelica_grammar_trait::Composition> for Composition {
@@ -167,16 +365,37 @@ impl TryFrom<&modelica_grammar_trait::Composition> for Composition {
             ..Default::default()
         };
 
-        comp.components = ast.element_list.components.clone();
-        comp.extends = ast.element_list.extends.clone();
+        // location of each declared name, used to report duplicates across
+        // sections
+        let mut declared = IndexMap::new();
+        comp.add_elements(
+            &ast.element_list,
+            ir::ast::Visibility::Public,
+            &mut declared,
+        )?;
+        if let Some(opt) = &ast.composition_opt0 {
+            comp.annotation = opt
+                .annotation_clause
+                .class_modification
+                .modifications
+                .clone();
+        }
 
         for comp_list in &ast.composition_list {
             match &comp_list.composition_list_group {
-                modelica_grammar_trait::CompositionListGroup::PublicElementList(_elem_list) => {
-                    todo!("public element list")
+                modelica_grammar_trait::CompositionListGroup::PublicElementList(elem_list) => {
+                    comp.add_elements(
+                        &elem_list.element_list,
+                        ir::ast::Visibility::Public,
+                        &mut declared,
+                    )?;
                 }
-                modelica_grammar_trait::CompositionListGroup::ProtectedElementList(_elem_list) => {
-                    todo!("protected element list")
+                modelica_grammar_trait::CompositionListGroup::ProtectedElementList(elem_list) => {
+                    comp.add_elements(
+                        &elem_list.element_list,
+                        ir::ast::Visibility::Protected,
+                        &mut declared,
+                    )?;
                 }
                 modelica_grammar_trait::CompositionListGroup::EquationSection(eq_sec) => {
                     let sec = &eq_sec.equation_section;
@@ -206,13 +425,46 @@ impl TryFrom<&modelica_grammar_trait::Composition> for Composition {
     }
 }
 
+impl Composition {
+    /// Adds the elements of a section with the visibility of the section,
+    /// the leading element list being a public section as well, rejecting
+    /// names declared by an earlier section.
+    fn add_elements(
+        &mut self,
+        elements: &ElementList,
+        visibility: ir::ast::Visibility,
+        declared: &mut IndexMap<String, ir::ast::Location>,
+    ) -> anyhow::Result<()> {
+        for (name, location) in &elements.declared {
+            check_duplicate(declared, name, location)?;
+            declared.insert(name.clone(), location.clone());
+        }
+        for (name, comp) in &elements.components {
+            let mut comp = comp.clone();
+            comp.visibility = visibility.clone();
+            self.components.insert(name.clone(), comp);
+        }
+        for (name, class) in &elements.classes {
+            let mut class = class.clone();
+            class.visibility = visibility.clone();
+            self.classes.insert(name.clone(), class);
+        }
+        self.extends.extend(elements.extends.clone());
+        self.imports.extend(elements.imports.clone());
+        Ok(())
+    }
+}
+
 //-----------------------------------------------------------------------------
 #[derive(Debug, Default, Clone)]
 #[allow(unused)]
 pub struct ElementList {
     pub components: IndexMap<String, ir::ast::Component>,
-    pub imports: Vec<ir::ast::Token>,
+    pub classes: IndexMap<String, ir::ast::ClassDefinition>,
+    pub imports: Vec<ir::ast::Import>,
     pub extends: Vec<ir::ast::Extend>,
+    /// location of each declared name, used to report duplicates
+    pub declared: IndexMap<String, ir::ast::Location>,
 }
 
 impl TryFrom<&modelica_grammar_trait::ElementList> for ElementList {
@@ -229,153 +481,88 @@ impl TryFrom<&modelica_grammar_trait::ElementList> for ElementList {
             match &elem_list.element {
                 modelica_grammar_trait::Element::ElementDefinition(edef) => {
                     match &edef.element_definition.element_definition_group {
-                        modelica_grammar_trait::ElementDefinitionGroup::ClassDefinition(_class) => {
-                            todo!("class definition")
+                        modelica_grammar_trait::ElementDefinitionGroup::ClassDefinition(class) => {
+                            let class = &class.class_definition;
+                            check_duplicate(&def.declared, &class.name.text, &class.name.location)?;
+                            def.declared
+                                .insert(class.name.text.clone(), class.name.location.clone());
+                            def.classes.insert(class.name.text.clone(), class.clone());
                         }
                         modelica_grammar_trait::ElementDefinitionGroup::ComponentClause(clause) => {
-                            let connection =
-                                match &clause.component_clause.type_prefix.type_prefix_opt {
-                                    Some(opt) => match &opt.type_prefix_opt_group {
-                                        modelica_grammar_trait::TypePrefixOptGroup::Flow(flow) => {
-                                            ir::ast::Connection::Flow(flow.flow.flow.clone())
-                                        }
-                                        modelica_grammar_trait::TypePrefixOptGroup::Stream(
-                                            stream,
-                                        ) => ir::ast::Connection::Stream(
-                                            stream.stream.stream.clone(),
-                                        ),
-                                    },
-                                    None => ir::ast::Connection::Empty,
-                                };
-
-                            let variability = match &clause
-                                .component_clause
-                                .type_prefix
-                                .type_prefix_opt0
-                            {
-                                Some(opt) => match &opt.type_prefix_opt0_group {
-                                    modelica_grammar_trait::TypePrefixOpt0Group::Constant(c) => {
-                                        ir::ast::Variability::Constant(c.constant.constant.clone())
-                                    }
-                                    modelica_grammar_trait::TypePrefixOpt0Group::Discrete(c) => {
-                                        ir::ast::Variability::Discrete(c.discrete.discrete.clone())
-                                    }
-                                    modelica_grammar_trait::TypePrefixOpt0Group::Parameter(c) => {
-                                        ir::ast::Variability::Parameter(
-                                            c.parameter.parameter.clone(),
-                                        )
-                                    }
-                                },
-                                None => ir::ast::Variability::Empty,
-                            };
-
-                            let causality =
-                                match &clause.component_clause.type_prefix.type_prefix_opt1 {
-                                    Some(opt) => match &opt.type_prefix_opt1_group {
-                                        modelica_grammar_trait::TypePrefixOpt1Group::Input(c) => {
-                                            ir::ast::Causality::Input(c.input.input.clone())
-                                        }
-                                        modelica_grammar_trait::TypePrefixOpt1Group::Output(c) => {
-                                            ir::ast::Causality::Output(c.output.output.clone())
-                                        }
-                                    },
-                                    None => ir::ast::Causality::Empty,
-                                };
-
-                            for c in &clause.component_clause.component_list.components {
-                                let mut value = ir::ast::Component {
-                                    name: c.declaration.ident.text.clone(),
-                                    type_name: clause.component_clause.type_specifier.name.clone(),
-                                    variability: variability.clone(),
-                                    causality: causality.clone(),
-                                    connection: connection.clone(),
-                                    description: c.description.description_string.tokens.clone(),
-                                    start: ir::ast::Expression::Terminal {
-                                        terminal_type: ir::ast::TerminalType::UnsignedReal,
-                                        token: ir::ast::Token {
-                                            text: "0.0".to_string(),
-                                            ..Default::default()
-                                        },
-                                    },
-                                };
-
-                                // set default start value
-                                value.start = match value.type_name.to_string().as_str() {
-                                    "Real" => ir::ast::Expression::Terminal {
-                                        terminal_type: ir::ast::TerminalType::UnsignedReal,
-                                        token: ir::ast::Token {
-                                            text: "0.0".to_string(),
-                                            ..Default::default()
-                                        },
-                                    },
-                                    "Integer" => ir::ast::Expression::Terminal {
-                                        terminal_type: ir::ast::TerminalType::UnsignedInteger,
-                                        token: ir::ast::Token {
-                                            text: "0".to_string(),
-                                            ..Default::default()
-                                        },
-                                    },
-                                    "Bool" => ir::ast::Expression::Terminal {
-                                        terminal_type: ir::ast::TerminalType::Bool,
-                                        token: ir::ast::Token {
-                                            text: "0".to_string(),
-                                            ..Default::default()
-                                        },
-                                    },
-                                    _ => ir::ast::Expression::Empty {},
-                                };
-
-                                // handle for component modification
-                                if let Some(modif) = &c.declaration.declaration_opt0 {
-                                    match &modif.modification {
-                                        modelica_grammar_trait::Modification::ClassModificationModificationOpt(
-                                            class_mod,
-                                        ) => {
-                                            let modif = &*(class_mod.class_modification);
-                                            match &modif.class_modification_opt {
-                                                Some(_opt) => {
-                                                    //opt.argument_list.args
-                                                },
-                                                None => {},
-                                            }
-                                        }
-                                        modelica_grammar_trait::Modification::EquModificationExpression(
-                                            eq_mod,
-                                        ) => {
-                                            match &eq_mod.modification_expression {
-                                                modelica_grammar_trait::ModificationExpression::Expression(expr) => {
-                                                    value.start = expr.expression.clone();
-                                                }
-                                                &modelica_grammar_trait::ModificationExpression::Break(..) => {
-                                                    todo!("break")
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-
-                                def.components
-                                    .insert(c.declaration.ident.text.clone(), value);
-                            }
+                            declare(&mut def, component_clause(&clause.component_clause))?;
                         }
                     }
                 }
-                modelica_grammar_trait::Element::ImportClause(..) => {
-                    todo!("import clause")
+                modelica_grammar_trait::Element::ImportClause(clause) => {
+                    def.imports.push(import_clause(&clause.import_clause));
                 }
                 modelica_grammar_trait::Element::ExtendsClause(clause) => {
-                    if let Some(_opt) = &clause.extends_clause.extends_clause_opt {
-                        todo!("unhandled extends class or inheritance modification")
+                    let mut modifications = IndexMap::new();
+                    let mut redeclarations = IndexMap::new();
+                    if let Some(opt) = &clause.extends_clause.extends_clause_opt
+                        && let Some(opt) = &opt
+                            .class_or_inheritance_modification
+                            .class_or_inheritance_modification_opt
+                    {
+                        let list = &opt.argument_or_inheritance_modification_list;
+                        let first = match &list.argument_or_inheritance_modification_list_group {
+                            modelica_grammar_trait::ArgumentOrInheritanceModificationListGroup::Argument(arg) => &arg.argument,
+                            modelica_grammar_trait::ArgumentOrInheritanceModificationListGroup::InheritanceModification(..) => {
+                                todo!("break inheritance modification")
+                            }
+                        };
+                        modifications.extend(first.modifications.clone());
+                        redeclarations.extend(first.redeclarations.clone());
+                        for item in &list.argument_or_inheritance_modification_list_list {
+                            match &item.argument_or_inheritance_modification_list_list_group {
+                                modelica_grammar_trait::ArgumentOrInheritanceModificationListListGroup::Argument(arg) => {
+                                    modifications.extend(arg.argument.modifications.clone());
+                                    redeclarations.extend(arg.argument.redeclarations.clone());
+                                }
+                                modelica_grammar_trait::ArgumentOrInheritanceModificationListListGroup::InheritanceModification(..) => {
+                                    todo!("break inheritance modification")
+                                }
+                            }
+                        }
                     }
                     if let Some(_opt) = &clause.extends_clause.extends_clause_opt0 {
                         todo!("unhandled annotation")
                     }
                     def.extends.push(ir::ast::Extend {
                         comp: clause.extends_clause.type_specifier.name.clone(),
+                        modifications,
+                        redeclarations,
                     });
                 }
-                modelica_grammar_trait::Element::ElementReplaceableDefinition(..) => {
-                    todo!("element replaceable definition")
+                modelica_grammar_trait::Element::ElementReplaceableDefinition(repl) => {
+                    let repl = &repl.element_replaceable_definition;
+                    match &repl.element_replaceable_definition_group {
+                        modelica_grammar_trait::ElementReplaceableDefinitionGroup::ClassDefinition(class) => {
+                            let mut class = class.class_definition.clone();
+                            class.replaceable = true;
+                            if let Some(opt) = &repl.element_replaceable_definition_opt {
+                                class.constraint =
+                                    Some(opt.constraining_clause.type_specifier.name.clone());
+                            }
+                            check_duplicate(&def.declared, &class.name.text, &class.name.location)?;
+                            def.declared
+                                .insert(class.name.text.clone(), class.name.location.clone());
+                            def.classes.insert(class.name.text.clone(), class);
+                        }
+                        modelica_grammar_trait::ElementReplaceableDefinitionGroup::ComponentClause(clause) => {
+                            let mut components = component_clause(&clause.component_clause);
+                            for (_, comp) in components.iter_mut() {
+                                comp.replaceable = true;
+                                if let Some(opt) = &repl.element_replaceable_definition_opt {
+                                    comp.constraint =
+                                        Some(opt.constraining_clause.type_specifier.name.clone());
+                                    comp.description
+                                        .extend(opt.description.description_string.tokens.clone());
+                                }
+                            }
+                            declare(&mut def, components)?;
+                        }
+                    }
                 }
             }
         }
@@ -383,6 +570,264 @@ impl TryFrom<&modelica_grammar_trait::ElementList> for ElementList {
     }
 }
 
+fn import_clause(clause: &modelica_grammar_trait::ImportClause) -> ir::ast::Import {
+    match &clause.import_clause_group {
+        modelica_grammar_trait::ImportClauseGroup::IdentEquName(renaming) => ir::ast::Import {
+            alias: Some(renaming.ident.clone()),
+            path: renaming.name.clone(),
+            ..Default::default()
+        },
+        modelica_grammar_trait::ImportClauseGroup::NameImportClauseOpt(import) => {
+            let mut value = ir::ast::Import {
+                path: import.name.clone(),
+                ..Default::default()
+            };
+            if let Some(opt) = &import.import_clause_opt {
+                match &opt.import_clause_opt_group {
+                    modelica_grammar_trait::ImportClauseOptGroup::DotStar(..) => {
+                        value.unqualified = true;
+                    }
+                    modelica_grammar_trait::ImportClauseOptGroup::DotImportClauseOptGroupGroup(
+                        group,
+                    ) => match &group.import_clause_opt_group_group {
+                        modelica_grammar_trait::ImportClauseOptGroupGroup::Star(..) => {
+                            value.unqualified = true;
+                        }
+                        modelica_grammar_trait::ImportClauseOptGroupGroup::LBraceImportListRBrace(
+                            list,
+                        ) => {
+                            let list = &list.import_list;
+                            value.names = std::iter::once(&list.ident)
+                                .chain(list.import_list_list.iter().map(|item| &item.ident))
+                                .cloned()
+                                .collect();
+                        }
+                    },
+                }
+            }
+            value
+        }
+    }
+}
+
+/// Adds declared components to the element list, rejecting names that are
+/// already declared.
+fn declare(
+    def: &mut ElementList,
+    components: Vec<(ir::ast::Token, ir::ast::Component)>,
+) -> anyhow::Result<()> {
+    for (ident, value) in components {
+        check_duplicate(&def.declared, &ident.text, &ident.location)?;
+        def.declared
+            .insert(ident.text.clone(), ident.location.clone());
+        def.components.insert(ident.text.clone(), value);
+    }
+    Ok(())
+}
+
+fn check_duplicate(
+    declared: &IndexMap<String, ir::ast::Location>,
+    name: &str,
+    location: &ir::ast::Location,
+) -> anyhow::Result<()> {
+    match declared.get(name) {
+        Some(first) => Err(anyhow::anyhow!(
+            "Duplicate declaration of '{}' at {}:{}:{}, first declared at {}:{}:{}",
+            name,
+            location.file_name,
+            location.start_line,
+            location.start_column,
+            first.file_name,
+            first.start_line,
+            first.start_column,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Returns the components of a component clause, with the identifier
+/// declaring each of them.
+fn component_clause(
+    clause: &modelica_grammar_trait::ComponentClause,
+) -> Vec<(ir::ast::Token, ir::ast::Component)> {
+    let type_subscripts = match &clause.component_clause_opt {
+        Some(opt) => opt.array_subscripts.subscripts.clone(),
+        None => Vec::new(),
+    };
+    clause
+        .component_list
+        .components
+        .iter()
+        .map(|c| {
+            let mut comp = declared_component(
+                &clause.type_prefix,
+                &clause.type_specifier,
+                &type_subscripts,
+                &c.declaration,
+                &c.description,
+            );
+            comp.condition = c
+                .component_declaration_opt
+                .as_ref()
+                .map(|opt| opt.condition_attribute.expression.clone());
+            (c.declaration.ident.clone(), comp)
+        })
+        .collect()
+}
+
+fn declared_component(
+    type_prefix: &modelica_grammar_trait::TypePrefix,
+    type_specifier: &modelica_grammar_trait::TypeSpecifier,
+    type_subscripts: &[ir::ast::Subscript],
+    declaration: &modelica_grammar_trait::Declaration,
+    description: &modelica_grammar_trait::Description,
+) -> ir::ast::Component {
+    let connection = match &type_prefix.type_prefix_opt {
+        Some(opt) => match &opt.type_prefix_opt_group {
+            modelica_grammar_trait::TypePrefixOptGroup::Flow(flow) => {
+                ir::ast::Connection::Flow(flow.flow.flow.clone())
+            }
+            modelica_grammar_trait::TypePrefixOptGroup::Stream(stream) => {
+                ir::ast::Connection::Stream(stream.stream.stream.clone())
+            }
+        },
+        None => ir::ast::Connection::Empty,
+    };
+
+    let variability = match &type_prefix.type_prefix_opt0 {
+        Some(opt) => match &opt.type_prefix_opt0_group {
+            modelica_grammar_trait::TypePrefixOpt0Group::Constant(c) => {
+                ir::ast::Variability::Constant(c.constant.constant.clone())
+            }
+            modelica_grammar_trait::TypePrefixOpt0Group::Discrete(c) => {
+                ir::ast::Variability::Discrete(c.discrete.discrete.clone())
+            }
+            modelica_grammar_trait::TypePrefixOpt0Group::Parameter(c) => {
+                ir::ast::Variability::Parameter(c.parameter.parameter.clone())
+            }
+        },
+        None => ir::ast::Variability::Empty,
+    };
+
+    let causality = match &type_prefix.type_prefix_opt1 {
+        Some(opt) => match &opt.type_prefix_opt1_group {
+            modelica_grammar_trait::TypePrefixOpt1Group::Input(c) => {
+                ir::ast::Causality::Input(c.input.input.clone())
+            }
+            modelica_grammar_trait::TypePrefixOpt1Group::Output(c) => {
+                ir::ast::Causality::Output(c.output.output.clone())
+            }
+        },
+        None => ir::ast::Causality::Empty,
+    };
+
+    let type_name = type_specifier.name.clone();
+    // the dimensions of `Real[2] x[3]` are [3, 2]
+    let mut shape = Vec::new();
+    if let Some(opt) = &declaration.declaration_opt {
+        shape.extend(opt.array_subscripts.subscripts.clone());
+    }
+    shape.extend(type_subscripts.iter().cloned());
+    let mut value = ir::ast::Component {
+        name: declaration.ident.text.clone(),
+        shape,
+        // set default start value, type aliases are resolved during flattening
+        start: Type::from_name(&type_name.to_string()).default_start(),
+        type_name,
+        variability,
+        causality,
+        connection,
+        description: description.description_string.tokens.clone(),
+        ..Default::default()
+    };
+    if let Some(opt) = &description.description_opt {
+        value.annotation = opt
+            .annotation_clause
+            .class_modification
+            .modifications
+            .clone();
+        value.hints = rumoca_hints(&value.annotation);
+    }
+
+    // handle for component modification, a binding takes
+    // precedence over a start modification
+    if let Some(opt) = &declaration.declaration_opt0 {
+        value.modifications = opt.modification.modifications.clone();
+        value.redeclarations = opt.modification.redeclarations.clone();
+        value.redeclared_classes = opt.modification.redeclared_classes.clone();
+        if let Some(start) = value.modifications.get("start") {
+            value.start = start.clone();
+        }
+        if let Some(expr) = &opt.modification.expression {
+            value.start = expr.clone();
+        }
+    }
+    value
+}
+
+/// Returns the hints of the `__rumoca` vendor annotation among the annotation
+/// modifications, by their name within `__rumoca`. Annotations of other
+/// tools are ignored.
+fn rumoca_hints(
+    annotation: &IndexMap<String, ir::ast::Expression>,
+) -> IndexMap<String, ir::ast::Expression> {
+    annotation
+        .iter()
+        .filter_map(|(name, expr)| {
+            name.strip_prefix("__rumoca.")
+                .map(|hint| (hint.to_string(), expr.clone()))
+        })
+        .collect()
+}
+
+/// Returns the redeclaration of an `element_replaceable` modification
+/// argument, a component or a short class definition.
+fn element_replaceable(ast: &modelica_grammar_trait::ElementReplaceable) -> ModificationList {
+    let constraint = ast
+        .element_replaceable_opt
+        .as_ref()
+        .map(|opt| opt.constraining_clause.type_specifier.name.clone());
+    let mut list = ModificationList::default();
+    match &ast.element_replaceable_group {
+        modelica_grammar_trait::ElementReplaceableGroup::ShortClassDefinition(def) => {
+            let mut class = short_class_definition(&def.short_class_definition);
+            class.replaceable = true;
+            class.constraint = constraint;
+            list.redeclared_classes
+                .insert(class.name.text.clone(), class);
+        }
+        modelica_grammar_trait::ElementReplaceableGroup::ComponentClause1(clause) => {
+            let (ident, mut comp) = component_clause1(&clause.component_clause1);
+            comp.replaceable = true;
+            comp.constraint = constraint;
+            list.redeclarations.insert(ident.text, comp);
+        }
+    }
+    list
+}
+
+fn short_class_definition(
+    def: &modelica_grammar_trait::ShortClassDefinition,
+) -> ir::ast::ClassDefinition {
+    short_class(&def.class_prefixes, &def.short_class_specifier, false)
+}
+
+fn component_clause1(
+    clause: &modelica_grammar_trait::ComponentClause1,
+) -> (ir::ast::Token, ir::ast::Component) {
+    let decl = &clause.component_declaration1;
+    (
+        decl.declaration.ident.clone(),
+        declared_component(
+            &clause.type_prefix,
+            &clause.type_specifier,
+            &[],
+            &decl.declaration,
+            &decl.description,
+        ),
+    )
+}
+
 //-----------------------------------------------------------------------------
 impl TryFrom<&modelica_grammar_trait::String> for ir::ast::Token {
     type Error = anyhow::Error;
@@ -542,6 +987,7 @@ impl TryFrom<&modelica_grammar_trait::UnsignedReal> for ir::ast::Token {
             modelica_grammar_trait::UnsignedReal::Decimal(num) => Ok(num.decimal.clone()),
             modelica_grammar_trait::UnsignedReal::Scientific(num) => Ok(num.scientific.clone()),
             modelica_grammar_trait::UnsignedReal::Scientific2(num) => Ok(num.scientific2.clone()),
+            modelica_grammar_trait::UnsignedReal::Scientific3(num) => Ok(num.scientific3.clone()),
         }
     }
 }
@@ -593,6 +1039,7 @@ impl TryFrom<&modelica_grammar_trait::SomeEquation> for ir::ast::Equation {
                     Some(rhs) => Ok(ir::ast::Equation::Simple {
                         lhs: eq.simple_equation.simple_expression.clone(),
                         rhs: rhs.expression.clone(),
+                        origin: None,
                     }),
                     None => {
                         // this is a function call eq (reinit, assert, terminate, etc.)
@@ -618,7 +1065,17 @@ impl TryFrom<&modelica_grammar_trait::SomeEquation> for ir::ast::Equation {
                     rhs: eq.connect_equation.component_reference0.clone(),
                 })
             }
-            modelica_grammar_trait::SomeEquationOption::ForEquation(..) => todo!("for"),
+            modelica_grammar_trait::SomeEquationOption::ForEquation(eq) => {
+                Ok(ir::ast::Equation::For {
+                    indices: for_indices(&eq.for_equation.for_indices),
+                    equations: eq
+                        .for_equation
+                        .for_equation_list
+                        .iter()
+                        .map(|item| item.some_equation.clone())
+                        .collect(),
+                })
+            }
             modelica_grammar_trait::SomeEquationOption::IfEquation(eq) => {
                 let mut blocks = vec![eq.if_equation.if0.clone()];
                 for when in &eq.if_equation.if_equation_list {
@@ -666,6 +1123,7 @@ impl TryFrom<&modelica_grammar_trait::Statement> for ir::ast::Statement {
                         Ok(ir::ast::Statement::FunctionCall {
                             comp: stmt.component_statement.component_reference.clone(),
                             args: args.function_call_args.args.clone(),
+                            outputs: vec![],
                         })
                     }
                 }
@@ -678,17 +1136,57 @@ impl TryFrom<&modelica_grammar_trait::Statement> for ir::ast::Statement {
                     token: tok.r#return.r#return.clone(),
                 })
             }
-            modelica_grammar_trait::StatementOption::ForStatement(..) => {
+            modelica_grammar_trait::StatementOption::ForStatement(stmt) => {
                 Ok(ir::ast::Statement::For {
-                    indices: vec![], // todo
-                    equations: vec![],
+                    indices: for_indices(&stmt.for_statement.for_indices),
+                    body: stmt
+                        .for_statement
+                        .for_statement_list
+                        .iter()
+                        .map(|item| item.statement.clone())
+                        .collect(),
                 })
             }
-            modelica_grammar_trait::StatementOption::IfStatement(..) => todo!("if"),
-            modelica_grammar_trait::StatementOption::WhenStatement(..) => todo!("when"),
-            modelica_grammar_trait::StatementOption::WhileStatement(..) => todo!("while"),
-            modelica_grammar_trait::StatementOption::FunctionCallOutputStatement(..) => {
-                todo!("function call")
+            modelica_grammar_trait::StatementOption::IfStatement(stmt) => {
+                let mut cond_blocks = vec![stmt.if_statement.if0.clone()];
+                for elseif in &stmt.if_statement.if_statement_list {
+                    cond_blocks.push(elseif.elseif0.clone());
+                }
+                Ok(ir::ast::Statement::If {
+                    cond_blocks,
+                    else_block: stmt.if_statement.if_statement_opt.as_ref().map(|opt| {
+                        opt.if_statement_opt_list
+                            .iter()
+                            .map(|x| x.r#else.clone())
+                            .collect()
+                    }),
+                })
+            }
+            modelica_grammar_trait::StatementOption::WhenStatement(stmt) => {
+                let mut blocks = vec![stmt.when_statement.when0.clone()];
+                for when in &stmt.when_statement.when_statement_list {
+                    blocks.push(when.elsewhen0.clone());
+                }
+                Ok(ir::ast::Statement::When(blocks))
+            }
+            modelica_grammar_trait::StatementOption::WhileStatement(stmt) => {
+                Ok(ir::ast::Statement::While(ir::ast::StatementBlock {
+                    cond: stmt.while_statement.expression.clone(),
+                    stmts: stmt
+                        .while_statement
+                        .while_statement_list
+                        .iter()
+                        .map(|item| item.statement.clone())
+                        .collect(),
+                }))
+            }
+            modelica_grammar_trait::StatementOption::FunctionCallOutputStatement(stmt) => {
+                let stmt = &stmt.function_call_output_statement;
+                Ok(ir::ast::Statement::FunctionCall {
+                    comp: stmt.component_reference.clone(),
+                    args: stmt.function_call_args.args.clone(),
+                    outputs: stmt.output_expression_list.args.clone(),
+                })
             }
         }
     }
@@ -735,6 +1233,8 @@ impl TryFrom<&modelica_grammar_trait::Subscript> for ir::ast::Subscript {
 #[allow(unused)]
 pub struct ExpressionList {
     pub args: Vec<ir::ast::Expression>,
+    /// indices of an array comprehension `{expr for indices}`
+    pub indices: Vec<ir::ast::ForIndex>,
 }
 
 impl TryFrom<&modelica_grammar_trait::FunctionArgument> for ir::ast::Expression {
@@ -778,7 +1278,10 @@ impl TryFrom<&modelica_grammar_trait::FunctionArguments> for ExpressionList {
                     }
                     None => {}
                 }
-                Ok(ExpressionList { args })
+                Ok(ExpressionList {
+                    args,
+                    ..Default::default()
+                })
             }
             modelica_grammar_trait::FunctionArguments::FunctionPartialApplicationFunctionArgumentsOpt0(..) => {
                 todo!("partial application")
@@ -805,7 +1308,10 @@ impl TryFrom<&modelica_grammar_trait::FunctionArgumentsNonFirst> for ExpressionL
                     }
                     None => {}
                 }
-                Ok(ExpressionList { args })
+                Ok(ExpressionList {
+                    args,
+                    ..Default::default()
+                })
             }
             modelica_grammar_trait::FunctionArgumentsNonFirst::NamedArguments(..) => {
                 todo!("named arguments")
@@ -815,21 +1321,142 @@ impl TryFrom<&modelica_grammar_trait::FunctionArgumentsNonFirst> for ExpressionL
 }
 
 //-----------------------------------------------------------------------------
-impl TryFrom<&modelica_grammar_trait::ArgumentList> for ExpressionList {
+impl TryFrom<&modelica_grammar_trait::ArrayArguments> for ExpressionList {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        ast: &modelica_grammar_trait::ArrayArguments,
+    ) -> std::result::Result<Self, Self::Error> {
+        let mut args = vec![(*ast.expression).clone()];
+        let mut indices = Vec::new();
+        if let Some(opt) = &ast.array_arguments_opt {
+            match &opt.array_arguments_opt_group {
+                modelica_grammar_trait::ArrayArgumentsOptGroup::CommaArrayArgumentsNonFirst(
+                    non_first,
+                ) => {
+                    args.append(&mut non_first.array_arguments_non_first.args.clone());
+                }
+                modelica_grammar_trait::ArrayArgumentsOptGroup::ForForIndices(comprehension) => {
+                    indices = for_indices(&comprehension.for_indices);
+                }
+            }
+        }
+        Ok(ExpressionList { args, indices })
+    }
+}
+
+impl TryFrom<&modelica_grammar_trait::ArrayArgumentsNonFirst> for ExpressionList {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        ast: &modelica_grammar_trait::ArrayArgumentsNonFirst,
+    ) -> std::result::Result<Self, Self::Error> {
+        let mut args = vec![ast.expression.clone()];
+        if let Some(opt) = &ast.array_arguments_non_first_opt {
+            args.append(&mut opt.array_arguments_non_first.args.clone());
+        }
+        Ok(ExpressionList {
+            args,
+            ..Default::default()
+        })
+    }
+}
+
+impl TryFrom<&modelica_grammar_trait::ExpressionList> for ExpressionList {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        ast: &modelica_grammar_trait::ExpressionList,
+    ) -> std::result::Result<Self, Self::Error> {
+        let mut args = vec![(*ast.expression).clone()];
+        for expr in &ast.expression_list_list {
+            args.push(expr.expression.clone());
+        }
+        Ok(ExpressionList {
+            args,
+            ..Default::default()
+        })
+    }
+}
+
+/// Converts the indices of a `for` loop or comprehension, an index without a
+/// range gets an empty range.
+fn for_indices(ast: &modelica_grammar_trait::ForIndices) -> Vec<ir::ast::ForIndex> {
+    std::iter::once(&ast.for_index)
+        .chain(ast.for_indices_list.iter().map(|item| &item.for_index))
+        .map(|index| ir::ast::ForIndex {
+            ident: index.ident.clone(),
+            range: index
+                .for_index_opt
+                .as_ref()
+                .map(|opt| opt.expression.clone())
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Creates a call of a built-in concatenation function
+fn concatenation(name: &str, args: &[ir::ast::Expression]) -> ir::ast::Expression {
+    ir::ast::Expression::FunctionCall {
+        comp: ir::ast::ComponentReference {
+            local: false,
+            parts: vec![ir::ast::ComponentRefPart {
+                ident: ir::ast::Token {
+                    text: name.to_string(),
+                    ..Default::default()
+                },
+                subs: None,
+            }],
+        },
+        args: args.to_vec(),
+    }
+}
+
+//-----------------------------------------------------------------------------
+#[derive(Debug, Default, Clone)]
+#[allow(unused)]
+pub struct ModificationList {
+    /// modified element names, nested modifications are joined with '.'
+    pub modifications: IndexMap<String, ir::ast::Expression>,
+    /// redeclared elements by name
+    pub redeclarations: IndexMap<String, ir::ast::Component>,
+    /// redeclared classes by name
+    pub redeclared_classes: IndexMap<String, ir::ast::ClassDefinition>,
+}
+
+impl TryFrom<&modelica_grammar_trait::ClassModification> for ModificationList {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        ast: &modelica_grammar_trait::ClassModification,
+    ) -> std::result::Result<Self, Self::Error> {
+        match &ast.class_modification_opt {
+            Some(opt) => Ok(opt.argument_list.clone()),
+            None => Ok(ModificationList::default()),
+        }
+    }
+}
+
+impl TryFrom<&modelica_grammar_trait::ArgumentList> for ModificationList {
     type Error = anyhow::Error;
 
     fn try_from(
         ast: &modelica_grammar_trait::ArgumentList,
     ) -> std::result::Result<Self, Self::Error> {
-        let mut args = vec![(*ast.argument).clone()];
+        let mut list = (*ast.argument).clone();
         for arg in &ast.argument_list_list {
-            args.push(arg.argument.clone())
+            list.modifications
+                .extend(arg.argument.modifications.clone());
+            list.redeclarations
+                .extend(arg.argument.redeclarations.clone());
+            list.redeclared_classes
+                .extend(arg.argument.redeclared_classes.clone());
         }
-        Ok(ExpressionList { args })
+        Ok(list)
     }
 }
 
-impl TryFrom<&modelica_grammar_trait::Argument> for ir::ast::Expression {
+impl TryFrom<&modelica_grammar_trait::Argument> for ModificationList {
     type Error = anyhow::Error;
 
     fn try_from(ast: &modelica_grammar_trait::Argument) -> std::result::Result<Self, Self::Error> {
@@ -837,41 +1464,110 @@ impl TryFrom<&modelica_grammar_trait::Argument> for ir::ast::Expression {
             modelica_grammar_trait::Argument::ElementModificationOrReplaceable(modif) => {
                 match &modif.element_modification_or_replaceable.element_modification_or_replaceable_group {
                     modelica_grammar_trait::ElementModificationOrReplaceableGroup::ElementModification(elem) => {
+                        let name = elem.element_modification.name.to_string();
+                        let mut list = ModificationList::default();
                         match &elem.element_modification.element_modification_opt {
                             Some(opt) => {
-                                match &opt.modification {
-                                    modelica_grammar_trait::Modification::ClassModificationModificationOpt(_modif) => {
-                                        todo!("argument class modification")
-                                    }
-                                    modelica_grammar_trait::Modification::EquModificationExpression(modif) => {
-                                        match &modif.modification_expression {
-                                            modelica_grammar_trait::ModificationExpression::Break(..) => {
-                                                todo!("break expression")
-                                            }
-                                            modelica_grammar_trait::ModificationExpression::Expression(expr) => {
-                                                Ok(expr.expression.clone())
-                                            }
-                                        }
-                                    }
+                                for (sub_name, expr) in &opt.modification.modifications {
+                                    list.modifications
+                                        .insert(format!("{}.{}", name, sub_name), expr.clone());
+                                }
+                                for (sub_name, comp) in &opt.modification.redeclarations {
+                                    list.redeclarations
+                                        .insert(format!("{}.{}", name, sub_name), comp.clone());
+                                }
+                                for (sub_name, class) in &opt.modification.redeclared_classes {
+                                    list.redeclared_classes
+                                        .insert(format!("{}.{}", name, sub_name), class.clone());
+                                }
+                                if let Some(expr) = &opt.modification.expression {
+                                    list.modifications.insert(name, expr.clone());
                                 }
                             }
                             None => {
-                                Ok(ir::ast::Expression::Empty)
+                                list.modifications.insert(name, ir::ast::Expression::Empty);
                             }
                         }
+                        Ok(list)
                     }
-                    modelica_grammar_trait::ElementModificationOrReplaceableGroup::ElementReplaceable(..) => {
-                        todo!("element replaceable")
+                    modelica_grammar_trait::ElementModificationOrReplaceableGroup::ElementReplaceable(repl) => {
+                        Ok(element_replaceable(&repl.element_replaceable))
                     }
                 }
             }
-            modelica_grammar_trait::Argument::ElementRedeclaration(_redcl) => {
-                todo!("element redeclaration")
+            modelica_grammar_trait::Argument::ElementRedeclaration(redcl) => {
+                let mut list = ModificationList::default();
+                match &redcl.element_redeclaration.element_redeclaration_group {
+                    modelica_grammar_trait::ElementRedeclarationGroup::ShortClassDefinition(def) => {
+                        let class = short_class_definition(&def.short_class_definition);
+                        list.redeclared_classes.insert(class.name.text.clone(), class);
+                    }
+                    modelica_grammar_trait::ElementRedeclarationGroup::ComponentClause1(clause) => {
+                        let (ident, comp) = component_clause1(&clause.component_clause1);
+                        list.redeclarations.insert(ident.text, comp);
+                    }
+                    modelica_grammar_trait::ElementRedeclarationGroup::ElementReplaceable(repl) => {
+                        list = element_replaceable(&repl.element_replaceable);
+                    }
+                }
+                Ok(list)
+            }
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+#[derive(Debug, Default, Clone)]
+#[allow(unused)]
+pub struct Modification {
+    /// the expression following '=', if any
+    pub expression: Option<ir::ast::Expression>,
+    pub modifications: IndexMap<String, ir::ast::Expression>,
+    pub redeclarations: IndexMap<String, ir::ast::Component>,
+    pub redeclared_classes: IndexMap<String, ir::ast::ClassDefinition>,
+}
+
+impl TryFrom<&modelica_grammar_trait::Modification> for Modification {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        ast: &modelica_grammar_trait::Modification,
+    ) -> std::result::Result<Self, Self::Error> {
+        match ast {
+            modelica_grammar_trait::Modification::ClassModificationModificationOpt(modif) => {
+                Ok(Modification {
+                    expression: modif
+                        .modification_opt
+                        .as_ref()
+                        .map(|opt| modification_expression(&opt.modification_expression)),
+                    modifications: modif.class_modification.modifications.clone(),
+                    redeclarations: modif.class_modification.redeclarations.clone(),
+                    redeclared_classes: modif.class_modification.redeclared_classes.clone(),
+                })
+            }
+            modelica_grammar_trait::Modification::EquModificationExpression(modif) => {
+                Ok(Modification {
+                    expression: Some(modification_expression(&modif.modification_expression)),
+                    modifications: IndexMap::new(),
+                    redeclarations: IndexMap::new(),
+                    redeclared_classes: IndexMap::new(),
+                })
             }
         }
     }
 }
 
+fn modification_expression(
+    ast: &modelica_grammar_trait::ModificationExpression,
+) -> ir::ast::Expression {
+    match ast {
+        modelica_grammar_trait::ModificationExpression::Expression(expr) => expr.expression.clone(),
+        modelica_grammar_trait::ModificationExpression::Break(..) => {
+            todo!("break expression")
+        }
+    }
+}
+
 //-----------------------------------------------------------------------------
 impl TryFrom<&modelica_grammar_trait::OutputExpressionList> for ExpressionList {
     type Error = anyhow::Error;
@@ -888,7 +1584,10 @@ impl TryFrom<&modelica_grammar_trait::OutputExpressionList> for ExpressionList {
                 v.push(opt.expression.clone());
             }
         }
-        Ok(ExpressionList { args: v })
+        Ok(ExpressionList {
+            args: v,
+            ..Default::default()
+        })
     }
 }
 
@@ -901,9 +1600,10 @@ impl TryFrom<&modelica_grammar_trait::FunctionCallArgs> for ExpressionList {
         if let Some(opt) = &ast.function_call_args_opt {
             Ok(ExpressionList {
                 args: opt.function_arguments.args.clone(),
+                ..Default::default()
             })
         } else {
-            Ok(ExpressionList { args: vec![] })
+            Ok(ExpressionList::default())
         }
     }
 }
@@ -956,21 +1656,44 @@ impl TryFrom<&modelica_grammar_trait::Primary> for ir::ast::Expression {
                 terminal_type: ir::ast::TerminalType::End,
                 token: end.end.end.clone(),
             }),
-            modelica_grammar_trait::Primary::ArrayPrimary(..) => {
-                todo!("array")
+            modelica_grammar_trait::Primary::ArrayPrimary(array) => {
+                let arguments = &array.array_primary.array_arguments;
+                if arguments.indices.is_empty() {
+                    Ok(ir::ast::Expression::Array {
+                        elements: arguments.args.clone(),
+                    })
+                } else {
+                    Ok(ir::ast::Expression::ArrayComprehension {
+                        expr: Box::new(arguments.args[0].clone()),
+                        indices: arguments.indices.clone(),
+                    })
+                }
             }
-            modelica_grammar_trait::Primary::RangePrimary(..) => {
-                todo!("expression list")
+            modelica_grammar_trait::Primary::RangePrimary(range) => {
+                // [a, b; c, d] is vertcat(horzcat(a, b), horzcat(c, d)), the
+                // concatenation is expanded by the Scalarizer
+                let primary = &range.range_primary;
+                let mut rows = vec![concatenation("horzcat", &primary.expression_list.args)];
+                for row in &primary.range_primary_list {
+                    rows.push(concatenation("horzcat", &row.expression_list.args));
+                }
+                Ok(concatenation("vertcat", &rows))
             }
             modelica_grammar_trait::Primary::OutputPrimary(output) => {
                 let primary = &output.output_primary;
                 if primary.output_primary_opt.is_some() {
-                    todo!("output_primary array subs/ ident");
+                    return Err(anyhow::anyhow!(
+                        "subscripts and members of parenthesized expressions are not supported"
+                    ));
                 };
-                if primary.output_expression_list.args.len() > 1 {
-                    todo!("comma in output primary");
+                match primary.output_expression_list.args.as_slice() {
+                    [expr] => Ok(expr.clone()),
+                    // multiple outputs are only assigned by a function call
+                    // statement, (a, b) := f(x)
+                    _ => Err(anyhow::anyhow!(
+                        "a list of outputs is only supported on the left side of a function call statement, such as (a, b) := f(x)"
+                    )),
                 }
-                Ok(primary.output_expression_list.args[0].clone())
             }
             modelica_grammar_trait::Primary::GlobalFunctionCall(expr) => {
                 let tok = match &expr.global_function_call.global_function_call_group {
@@ -1235,8 +1958,19 @@ impl TryFrom<&modelica_grammar_trait::Expression> for ir::ast::Expression {
             modelica_grammar_trait::Expression::SimpleExpression(simple_expression) => {
                 Ok(simple_expression.simple_expression.as_ref().clone())
             }
-            modelica_grammar_trait::Expression::IfExpression(..) => {
-                todo!("if")
+            modelica_grammar_trait::Expression::IfExpression(expr) => {
+                let if_expr = &expr.if_expression;
+                let mut branches = vec![(
+                    if_expr.expression.as_ref().clone(),
+                    if_expr.expression0.clone(),
+                )];
+                for elseif in &if_expr.if_expression_list {
+                    branches.push((elseif.expression.clone(), elseif.expression0.clone()));
+                }
+                Ok(ir::ast::Expression::If {
+                    branches,
+                    else_branch: Box::new(if_expr.expression1.clone()),
+                })
             }
         }
     }
@@ -1252,7 +1986,10 @@ impl TryFrom<&modelica_grammar_trait::ComponentReference> for ir::ast::Component
         let mut parts = Vec::new();
         parts.push(ir::ast::ComponentRefPart {
             ident: ast.ident.clone(),
-            subs: None,
+            subs: ast
+                .component_reference_opt0
+                .as_ref()
+                .map(|opt| opt.array_subscripts.subscripts.clone()),
         });
         for comp_ref in &ast.component_reference_list {
             parts.push(comp_ref.component_ref_part.clone());
@@ -1298,6 +2035,9 @@ impl TryFrom<&modelica_grammar_trait::Name> for ir::ast::Name {
 #[derive(Debug, Default)]
 pub struct ModelicaGrammar<'t> {
     pub modelica: Option<ir::ast::StoredDefinition>,
+    /// collect the skipped comments into `comments`
+    pub keep_comments: bool,
+    pub comments: Vec<ir::ast::Token>,
     _phantom: std::marker::PhantomData<&'t str>,
 }
 
@@ -1327,4 +2067,12 @@ impl<'t> modelica_grammar_trait::ModelicaGrammarTrait for ModelicaGrammar<'t> {
         self.modelica = Some(arg.try_into()?);
         Ok(())
     }
+
+    fn on_comment(&mut self, token: Token<'_>) {
+        if self.keep_comments
+            && let Ok(token) = ir::ast::Token::try_from(&token)
+        {
+            self.comments.push(token);
+        }
+    }
 }
@@ -0,0 +1,210 @@
+//! This module provides a reusable parser for batch processing of Modelica files.
+//!
+//! The generated `modelica_parser::parse` function expects a fresh
+//! `ModelicaGrammar` for every call. The `Parser` keeps a single grammar and
+//! resets it between calls, so tools parsing many small files can call
+//! `parser.parse(src, name)` repeatedly. The lookahead automata, productions
+//! and scanners of the generated parser are static and initialized once on
+//! first use, so they are shared by all parses. Only the token stream and the
+//! parse stacks, which borrow the source, are created for each call.
+//!
+//! Comments are skipped by the scanner. Tools such as formatters or
+//! documentation extraction can have them kept in
+//! `StoredDefinition::comments` with `ParseOptions::keep_comments`, each associated
+//! with the position of the nearest following token.
+//!
+//! Services parsing untrusted models can limit the size of the input with
+//! `ParseOptions::max_bytes`, larger sources are rejected before they are
+//! tokenized. `parse_str` parses a single source with given options.
+//!
+//! `for_each_class` visits the classes of a whole library, such as for batch
+//! linting. The `.mo` files below the root are parsed one at a time, and the
+//! stored definition of a file is dropped once its classes are visited, so
+//! only a single file is held in memory.
+use crate::ir::ast::{ClassDefinition, Comment, Location, StoredDefinition, Token};
+use crate::modelica_grammar::ModelicaGrammar;
+use crate::modelica_parser;
+use anyhow::{Context, anyhow};
+use parol_runtime::ParolError;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOptions {
+    /// largest source accepted, in bytes
+    pub max_bytes: usize,
+    /// keep the comments of the source in the stored definition
+    pub keep_comments: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_bytes: usize::MAX,
+            keep_comments: false,
+        }
+    }
+}
+
+/// Parses `src` into a stored definition with the given `options`.
+pub fn parse_str<T: AsRef<Path>>(
+    src: &str,
+    name: T,
+    options: &ParseOptions,
+) -> Result<StoredDefinition, ParolError> {
+    Parser::new().with_options(options.clone()).parse(src, name)
+}
+
+/// Calls `f` for every class, including nested classes, of the `.mo` files
+/// below `root`, or of the file `root`, with the path of the class, such as
+/// `Library.Package.Model`. Files are parsed lazily in the order of their
+/// paths, and classes in the order of declaration, enclosing classes first.
+pub fn for_each_class<T, F>(root: T, mut f: F) -> anyhow::Result<()>
+where
+    T: AsRef<Path>,
+    F: FnMut(&str, &ClassDefinition),
+{
+    let mut files = Vec::new();
+    collect_files(root.as_ref(), &mut files)?;
+    let mut parser = Parser::new();
+    for file in files {
+        let src = fs::read_to_string(&file)
+            .with_context(|| format!("can't read file {}", file.display()))?;
+        let def = parser
+            .parse(&src, &file)
+            .with_context(|| format!("failed to parse {}", file.display()))?;
+        let prefix = match &def.within {
+            Some(within) if !within.name.is_empty() => format!("{}.", within),
+            _ => String::new(),
+        };
+        for (name, class) in &def.class_list {
+            visit_class(&format!("{}{}", prefix, name), class, &mut f);
+        }
+    }
+    Ok(())
+}
+
+/// Collects the `.mo` files below `path`, sorted by path.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)
+        .with_context(|| format!("can't read directory {}", path.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_files(&entry, files)?;
+        } else if entry.extension().is_some_and(|ext| ext == "mo") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+fn visit_class<F>(path: &str, class: &ClassDefinition, f: &mut F)
+where
+    F: FnMut(&str, &ClassDefinition),
+{
+    f(path, class);
+    for (name, nested) in &class.classes {
+        visit_class(&format!("{}.{}", path, name), nested, f);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Parser<'t> {
+    grammar: ModelicaGrammar<'t>,
+    options: ParseOptions,
+}
+
+impl Parser<'_> {
+    pub fn new() -> Self {
+        Parser::default()
+    }
+
+    /// Sets the options of the following parses.
+    pub fn with_options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Parses `src` into a stored definition, `name` is used for the
+    /// locations of tokens and in error messages.
+    pub fn parse<T: AsRef<Path>>(
+        &mut self,
+        src: &str,
+        name: T,
+    ) -> Result<StoredDefinition, ParolError> {
+        if src.len() > self.options.max_bytes {
+            return Err(ParolError::UserError(anyhow!(
+                "{}: source of {} bytes exceeds the limit of {} bytes",
+                name.as_ref().display(),
+                src.len(),
+                self.options.max_bytes
+            )));
+        }
+        self.grammar.modelica = None;
+        self.grammar.keep_comments = self.options.keep_comments;
+        self.grammar.comments.clear();
+        modelica_parser::parse(src, name, &mut self.grammar)?;
+        let mut def = self
+            .grammar
+            .modelica
+            .take()
+            .ok_or_else(|| ParolError::UserError(anyhow!("no stored definition parsed")))?;
+        def.comments = self
+            .grammar
+            .comments
+            .drain(..)
+            .map(|token| Comment {
+                next: next_token(src, &token),
+                token,
+            })
+            .collect();
+        Ok(def)
+    }
+}
+
+/// Returns the position of the first token after `comment`, skipping
+/// whitespace and further comments.
+fn next_token(src: &str, comment: &Token) -> Option<Location> {
+    // the comment itself is skipped first, the end position of a line
+    // comment is not reliable as it includes the line break
+    let mut offset = comment.location.start as usize;
+    let mut line = comment.location.start_line;
+    let mut column = comment.location.start_column;
+    loop {
+        let rest = src.get(offset..)?;
+        let c = rest.chars().next()?;
+        let skipped = if c.is_whitespace() {
+            c.len_utf8()
+        } else if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if let Some(block) = rest.strip_prefix("/*") {
+            block.find("*/").map_or(rest.len(), |end| end + 4)
+        } else {
+            return Some(Location {
+                start_line: line,
+                start_column: column,
+                end_line: line,
+                end_column: column,
+                start: offset as u32,
+                end: offset as u32,
+                file_name: comment.location.file_name.clone(),
+            });
+        };
+        for c in rest[..skipped].chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        offset += skipped;
+    }
+}
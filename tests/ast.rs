@@ -0,0 +1,192 @@
+use rumoca::ir::ast::{
+    ComponentReference, Expression, Location, Statement, Subscript, TerminalType, Token,
+};
+use rumoca::parser::Parser;
+
+#[test]
+fn component_reference_round_trips_through_strings() {
+    for text in ["a.b[2].c", "x", "m[i, :].v", "a[b[1]]"] {
+        let cref: ComponentReference = text.parse().unwrap();
+        assert_eq!(cref.to_string(), text);
+    }
+    let cref: ComponentReference = "a.b[2].c".parse().unwrap();
+    let idents: Vec<&str> = cref.parts.iter().map(|p| p.ident.text.as_str()).collect();
+    assert_eq!(idents, ["a", "b", "c"]);
+    assert!(matches!(
+        cref.parts[1].subs.as_deref(),
+        Some([Subscript::Expression(Expression::Terminal {
+            terminal_type: TerminalType::UnsignedInteger,
+            ..
+        })])
+    ));
+
+    for text in ["a..b", "1a", "a[2", ""] {
+        assert!(text.parse::<ComponentReference>().is_err(), "{}", text);
+    }
+}
+
+#[test]
+fn component_reference_builder() {
+    let two = Subscript::Expression(Expression::Terminal {
+        terminal_type: TerminalType::UnsignedInteger,
+        token: Token {
+            text: "2".to_string(),
+            ..Default::default()
+        },
+    });
+    let cref = ComponentReference::new("a")
+        .with_part("b")
+        .with_subscript(two)
+        .with_part("c");
+    assert_eq!(cref, "a.b[2].c".parse().unwrap());
+}
+
+/// Returns the binding of `y` in `model M Real y = <expr>; end M;`.
+fn binding(expr: &str) -> Expression {
+    let src = format!("model M Real y = {}; end M;", expr);
+    let def = Parser::new().parse(&src, "test.mo").unwrap();
+    def.class_list["M"].components["y"].start.clone()
+}
+
+fn free_names(expr: &Expression) -> Vec<String> {
+    expr.free_variables()
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+#[test]
+fn comprehension_indices_are_not_free() {
+    assert!(free_names(&binding("{i for i in 1:3}")).is_empty());
+    assert_eq!(free_names(&binding("{i + k for i in 1:3}")), ["k"]);
+    assert_eq!(free_names(&binding("{i for i in 1:n}")), ["n"]);
+    // calls are not references, repeated references are reported once
+    assert_eq!(free_names(&binding("sin(x) + x * a.b[2]")), ["x", "a.b[2]"]);
+}
+
+#[test]
+fn references_are_equal_by_name() {
+    let expr = binding("x + x");
+    let Expression::Binary { lhs, rhs, .. } = expr else {
+        panic!("expected a binary expression");
+    };
+    let (Expression::ComponentReference(a), Expression::ComponentReference(b)) = (*lhs, *rhs)
+    else {
+        panic!("expected references");
+    };
+    assert_ne!(a.parts[0].ident.location, b.parts[0].ident.location);
+    assert_eq!(a, b);
+}
+
+#[test]
+fn function_variables_are_not_free() {
+    let def = Parser::new()
+        .parse(
+            "function f
+               input Real u[2];
+               input Real g = k;
+               output Real y;
+             protected
+               Real s;
+             algorithm
+               s := 0;
+               for i in 1:n loop
+                 s := s + u[i] * w;
+               end for;
+               y := s * g;
+             end f;",
+            "test.mo",
+        )
+        .unwrap();
+    let free: Vec<String> = def.class_list["f"]
+        .free_variables()
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    assert_eq!(free, ["k", "n", "w"]);
+}
+
+fn location(start_line: u32, start_column: u32, end_line: u32, end_column: u32) -> Location {
+    Location {
+        start_line,
+        start_column,
+        end_line,
+        end_column,
+        start: start_line * 100 + start_column,
+        end: end_line * 100 + end_column,
+        file_name: "test.mo".to_string(),
+    }
+}
+
+#[test]
+fn merging_overlapping_locations() {
+    let a = location(1, 5, 2, 10);
+    let b = location(1, 8, 3, 2);
+    assert_eq!(a.merge(&b), location(1, 5, 3, 2));
+    assert_eq!(b.merge(&a), location(1, 5, 3, 2));
+    // a location inside another one
+    assert_eq!(a.merge(&location(1, 6, 1, 9)), a);
+}
+
+#[test]
+fn merging_adjacent_locations() {
+    let a = location(1, 1, 1, 4);
+    let b = location(1, 4, 1, 7);
+    assert_eq!(a.merge(&b), location(1, 1, 1, 7));
+    // a later start column on an earlier line still starts earlier
+    assert_eq!(
+        location(2, 1, 2, 3).merge(&location(1, 9, 1, 12)),
+        location(1, 9, 2, 3)
+    );
+}
+
+#[test]
+fn spanning_tokens() {
+    let token = |location| Token {
+        location,
+        ..Default::default()
+    };
+    let tokens = [
+        token(location(2, 3, 2, 5)),
+        token(location(1, 7, 1, 8)),
+        token(location(2, 9, 2, 12)),
+    ];
+    let refs: Vec<&Token> = tokens.iter().collect();
+    assert_eq!(Location::spanning(&refs), location(1, 7, 2, 12));
+    assert_eq!(Location::spanning(&[]), Location::default());
+}
+
+#[test]
+fn for_statement_keeps_its_indices_and_body() {
+    let def = Parser::new()
+        .parse(
+            "model M
+               Real y[2, 3];
+             algorithm
+               for i in 1:2, j in 1:3 loop
+                 y[i, j] := i;
+                 y[i, j] := y[i, j] + j;
+               end for;
+             end M;",
+            "test.mo",
+        )
+        .unwrap();
+    let Statement::For { indices, body } = &def.class_list["M"].algorithms[0][0] else {
+        panic!("expected a for statement");
+    };
+    let idents: Vec<&str> = indices.iter().map(|i| i.ident.text.as_str()).collect();
+    assert_eq!(idents, ["i", "j"]);
+    assert_eq!(body.len(), 2);
+    assert!(matches!(body[0], Statement::Assignment { .. }));
+}
+
+#[test]
+fn expression_size_metrics() {
+    // -((a + b) * c): the negation, the product, the sum and the three operands
+    let expr = binding("-(a + b) * c");
+    assert_eq!(expr.node_count(), 6);
+    assert_eq!(expr.depth(), 4);
+    assert_eq!(binding("1").node_count(), 1);
+    assert_eq!(binding("1").depth(), 1);
+    assert_eq!(Expression::Empty.node_count(), 0);
+}
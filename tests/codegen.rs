@@ -0,0 +1,306 @@
+use rumoca::compile;
+use rumoca::dae::codegen::{MtkOptions, to_c, to_mtk, to_mtk_with_options};
+use rumoca::dae::jinja::render;
+use rumoca::ir::ast::{ComponentReference, Equation};
+
+const BOUNCING_BALL: &str = "
+model BouncingBall
+  parameter Real e = 0.8 \"Coefficient of restitution\";
+  Real h(start = 1.0);
+  Real v;
+equation
+  der(h) = v;
+  der(v) = -9.81;
+  when h < 0 then
+    reinit(v, -e*pre(v));
+  end when;
+end BouncingBall;
+";
+
+#[test]
+fn mtk_declares_states_and_parameters() {
+    let dae = compile(BOUNCING_BALL, "BouncingBall").unwrap();
+    let julia = to_mtk(&dae).unwrap();
+    assert!(julia.contains(
+        "@parameters begin\n    e = 0.8, [description = \"Coefficient of restitution\"]\n"
+    ));
+    assert!(julia.contains("    h(t) = 1.0\n"));
+    assert!(julia.contains("D(h) ~ v"));
+    assert!(julia.contains("D(v) ~ -(9.81)"));
+    assert!(!julia.contains("der_"));
+}
+
+#[test]
+fn mtk_emits_when_updates_as_events() {
+    let dae = compile(BOUNCING_BALL, "BouncingBall").unwrap();
+    let julia = to_mtk(&dae).unwrap();
+    assert!(julia.contains("events = [\n    ((h < 0)) => [v ~ -((e * v))],\n]"));
+    assert!(julia.contains("ODESystem(eqs, t; discrete_events = events)"));
+}
+
+#[test]
+fn mtk_elsewhen_fires_first_true_branch() {
+    let dae = compile(
+        "model M
+           Integer n(start = 0);
+           Real x(start = 0);
+         equation
+           der(x) = 1;
+           when x > 2 then
+             n = 2;
+           elsewhen x > 1 then
+             n = 1;
+           end when;
+         end M;",
+        "M",
+    )
+    .unwrap();
+    let julia = to_mtk(&dae).unwrap();
+    assert!(julia.contains("((x > 2)) => [n ~ 2]"), "{}", julia);
+    assert!(
+        julia.contains("((x > 1) & !(x > 2)) => [n ~ 1]"),
+        "{}",
+        julia
+    );
+}
+
+#[test]
+fn mtk_rejects_unsupported_equations() {
+    let mut dae = compile(BOUNCING_BALL, "BouncingBall").unwrap();
+    dae.fx.push(Equation::Connect {
+        lhs: ComponentReference::new("a"),
+        rhs: ComponentReference::new("b"),
+    });
+    let err = to_mtk(&dae).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("not supported by the ModelingToolkit export")
+    );
+}
+
+#[test]
+fn c_guards_division_by_if_expression() {
+    let dae = compile(
+        "model M
+           input Real a;
+           Real y;
+         equation
+           y = if a <> 0 and a > -1 then 1/a else 0;
+         end M;",
+        "M",
+    )
+    .unwrap();
+    let c = to_c(&dae).unwrap();
+    assert!(c.contains("    const double a = u[0];\n"), "{}", c);
+    assert!(
+        c.contains("    r[0] = y_ - ((((a != 0.0) && (a > -(1.0))) ? (1.0 / a) : 0.0));\n"),
+        "{}",
+        c
+    );
+}
+
+#[test]
+fn c_emits_residuals_of_states() {
+    let dae = compile(BOUNCING_BALL, "BouncingBall").unwrap();
+    let c = to_c(&dae).unwrap();
+    for line in [
+        "    const double h = x[0];\n",
+        "    const double der_v = x_dot[1];\n",
+        "    const double e = p[0];\n",
+        "    const int c0 = (h < 0.0);\n",
+        "    r[0] = der_h - (v);\n",
+    ] {
+        assert!(c.contains(line), "{}", c);
+    }
+}
+
+#[test]
+fn c_rejects_unsupported_functions() {
+    let dae = compile(
+        "model M
+           Real x;
+         equation
+           x = Modelica.Math.foo(time);
+         end M;",
+        "M",
+    )
+    .unwrap();
+    let err = to_c(&dae).unwrap_err();
+    assert!(err.to_string().contains("not supported by the C export"));
+}
+
+#[test]
+fn integer_division_functions_keep_their_semantics() {
+    let dae = compile(
+        "model M
+           input Real a;
+           Real q;
+           Real s;
+           Real m;
+         equation
+           q = div(a, 2);
+           s = rem(a, 2);
+           m = mod(a, 2);
+         end M;",
+        "M",
+    )
+    .unwrap();
+    let c = to_c(&dae).unwrap();
+    for line in [
+        "    r[0] = q - (trunc(a / 2.0));\n",
+        "    r[1] = s - (fmod(a, 2.0));\n",
+        "    r[2] = m - ((a - floor(a / 2.0) * 2.0));\n",
+    ] {
+        assert!(c.contains(line), "{}", c);
+    }
+    let julia = to_mtk(&dae).unwrap();
+    for eq in ["q ~ div(a, 2)", "s ~ rem(a, 2)", "m ~ mod(a, 2)"] {
+        assert!(julia.contains(eq), "{}", julia);
+    }
+}
+
+#[test]
+fn c_renames_variables_shadowing_arguments() {
+    let dae = compile(
+        "model M
+           parameter Real p = 2;
+           Real r;
+           Real y;
+           Real x(start = 1);
+         equation
+           der(x) = -p * x;
+           r = x;
+           y = r;
+         end M;",
+        "M",
+    )
+    .unwrap();
+    let c = to_c(&dae).unwrap();
+    for line in [
+        "    const double x_ = x[0];\n",
+        "    const double r_ = y[0];\n",
+        "    const double y_ = y[1];\n",
+        "    const double p_ = p[0];\n",
+        "    r[0] = der_x - (-((p_ * x_)));\n",
+        "    r[2] = y_ - (r_);\n",
+    ] {
+        assert!(c.contains(line), "{}", c);
+    }
+}
+
+const STRING_CONCATENATION: &str = "
+model M
+  parameter String s = \"a\";
+  String t;
+  Real x;
+equation
+  der(x) = -x;
+  t = g(x) + s;
+end M;
+";
+
+fn render_file(src: &str, template_file: &str) -> anyhow::Result<String> {
+    let dae = compile(src, "M").unwrap();
+    render(&dae, &std::fs::read_to_string(template_file).unwrap())
+}
+
+#[test]
+fn string_concatenation_follows_the_operand_types() {
+    let dae = compile(STRING_CONCATENATION, "M").unwrap();
+    // the left operand has an unknown type, the right one decides
+    assert!(to_mtk(&dae).unwrap().contains("(g(x) * s)"));
+    let python = render_file(STRING_CONCATENATION, "tests/templates/sympy.jinja").unwrap();
+    assert!(python.contains("(g(x) + s)"));
+    let err = to_c(&dae).unwrap_err().to_string();
+    assert!(err.contains("not supported by the C export"), "{}", err);
+}
+
+#[test]
+fn casadi_rejects_string_expressions() {
+    let err = render_file(STRING_CONCATENATION, "tests/templates/casadi.jinja")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("String expressions are not supported by the CasADi backend"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn qualified_math_functions_lower_to_the_builtin() {
+    assert_eq!(
+        rumoca::ir::types::builtin_function("Modelica.Math.cos"),
+        "cos"
+    );
+    assert_eq!(
+        rumoca::ir::types::builtin_function("Modelica.Math.foo"),
+        "Modelica.Math.foo"
+    );
+    let dae = compile(
+        "model M
+           input Real a;
+           Real q;
+         equation
+           q = Modelica.Math.cos(a);
+         end M;",
+        "M",
+    )
+    .unwrap();
+    let c = to_c(&dae).unwrap();
+    assert!(c.contains("    r[0] = q - (cos(a));\n"), "{}", c);
+}
+
+#[test]
+fn homotopy_emits_both_initialization_stages() {
+    let dae = compile(
+        "model M
+           Real x(start = 1);
+           Real y;
+         equation
+           der(x) = -y;
+           y = homotopy(x^3, x);
+         end M;",
+        "M",
+    )
+    .unwrap();
+    let julia = to_mtk(&dae).unwrap();
+    for stages in [
+        "init_eqs_simplified = [\n    0 ~ (D(x) - -(y)),\n    0 ~ (y - x),\n]",
+        "init_eqs_actual = [\n    0 ~ (D(x) - -(y)),\n    0 ~ (y - (x ^ 3)),\n]",
+        "    y ~ (x ^ 3),\n",
+    ] {
+        assert!(julia.contains(stages), "{}", julia);
+    }
+    assert!(!julia.contains("homotopy"), "{}", julia);
+
+    let plain = compile(BOUNCING_BALL, "BouncingBall").unwrap();
+    assert!(plain.homotopy_residuals().unwrap().is_none());
+    assert!(!to_mtk(&plain).unwrap().contains("init_eqs_simplified"));
+}
+
+#[test]
+fn integer_states_are_rejected_unless_relaxed() {
+    let dae = compile(
+        "model M
+           Integer n(start = 0);
+         equation
+           der(n) = 1;
+         end M;",
+        "M",
+    )
+    .unwrap();
+    let err = to_mtk(&dae).unwrap_err();
+    assert!(
+        err.to_string().contains("Integer variable 'n' is a state"),
+        "{}",
+        err
+    );
+
+    let options = MtkOptions {
+        relax_integer_states: true,
+    };
+    let julia = to_mtk_with_options(&dae, &options).unwrap();
+    assert!(julia.contains("# Integer state n is relaxed to a continuous variable\n"));
+    assert!(julia.contains("D(n) ~ 1"), "{}", julia);
+}
@@ -0,0 +1,254 @@
+use indexmap::IndexMap;
+use rumoca::ir::ast::Expression;
+use rumoca::ir::const_eval::{ConstEvaluator, Value};
+use rumoca::ir::flatten::flatten;
+use rumoca::ir::rpn::RpnOp;
+use rumoca::ir::types::{Type, type_of};
+use rumoca::ir::visitor::Visitable;
+use rumoca::ir::visitors::range_expander::RangeExpander;
+use rumoca::parser::Parser;
+
+/// Parses `expr` as the binding of a parameter.
+fn expression(expr: &str) -> Expression {
+    let def = Parser::new()
+        .parse(
+            &format!("model M parameter Real p = {}; end M;", expr),
+            "test.mo",
+        )
+        .expect("failed to parse");
+    def.class_list["M"].components["p"].start.clone()
+}
+
+fn eval(expr: &str) -> anyhow::Result<Value> {
+    ConstEvaluator::default().eval(&expression(expr))
+}
+
+#[test]
+fn integer_arithmetic_stays_integral() {
+    assert_eq!(eval("2 * 3 - 1").unwrap(), Value::Integer(5));
+    assert_eq!(eval("7 / 2").unwrap(), Value::Real(3.5));
+    assert_eq!(eval("-(4 - 6)").unwrap(), Value::Integer(2));
+}
+
+#[test]
+fn integer_power_stays_integral() {
+    assert_eq!(eval("2 ^ 3").unwrap(), Value::Integer(8));
+    assert_eq!(eval("(-3) ^ 0").unwrap(), Value::Integer(1));
+    assert_eq!(eval("2 ^ (-1)").unwrap(), Value::Real(0.5));
+    assert_eq!(eval("2.0 ^ 2").unwrap(), Value::Real(4.0));
+    assert!(eval("10 ^ 30").is_err());
+    let components = IndexMap::new();
+    assert_eq!(type_of(&expression("2 ^ 3"), &components), Type::Integer);
+    assert_eq!(type_of(&expression("2 ^ 0.5"), &components), Type::Real);
+}
+
+#[test]
+fn untaken_operands_are_not_evaluated() {
+    assert_eq!(eval("false and unknown > 0").unwrap(), Value::Bool(false));
+    assert_eq!(eval("true or unknown > 0").unwrap(), Value::Bool(true));
+    assert_eq!(
+        eval("if 1 > 0 then 1 elseif unknown then 2 else unknown").unwrap(),
+        Value::Integer(1)
+    );
+    assert!(eval("true and unknown > 0").is_err());
+}
+
+#[test]
+fn integer_division_functions() {
+    assert_eq!(eval("div(7, 2)").unwrap(), Value::Integer(3));
+    assert_eq!(eval("div(-7, 2)").unwrap(), Value::Integer(-3));
+    assert_eq!(eval("mod(-1, 3)").unwrap(), Value::Integer(2));
+    assert_eq!(eval("mod(1, -3)").unwrap(), Value::Integer(-2));
+    assert_eq!(eval("rem(-1, 3)").unwrap(), Value::Integer(-1));
+    assert_eq!(eval("div(7.5, 2)").unwrap(), Value::Real(3.0));
+    assert_eq!(eval("mod(-1.5, 1)").unwrap(), Value::Real(0.5));
+    assert!(eval("div(1, 0)").is_err());
+    let components = IndexMap::new();
+    assert_eq!(
+        type_of(&expression("mod(7, 2)"), &components),
+        Type::Integer
+    );
+    assert_eq!(type_of(&expression("mod(7.0, 2)"), &components), Type::Real);
+}
+
+/// Returns `expr` with its constant ranges expanded.
+fn expand_ranges(expr: &str) -> String {
+    let mut expr = expression(expr);
+    expr.accept(&mut RangeExpander::default());
+    format!("{:?}", expr)
+}
+
+#[test]
+fn constant_ranges_expand_to_arrays() {
+    assert_eq!(
+        expand_ranges("1:3"),
+        "[UnsignedInteger(\"1\"), UnsignedInteger(\"2\"), UnsignedInteger(\"3\")]"
+    );
+    assert_eq!(
+        expand_ranges("0:0.5:1"),
+        "[UnsignedReal(\"0.0\"), UnsignedReal(\"0.5\"), UnsignedReal(\"1.0\")]"
+    );
+    assert_eq!(expand_ranges("3:1"), "[]");
+    assert!(expand_ranges("1:n").starts_with("Range"));
+}
+
+#[test]
+fn well_known_constants_are_resolved() {
+    let Value::Real(two_pi) = eval("2 * Modelica.Constants.pi").unwrap() else {
+        panic!("expected a Real");
+    };
+    assert!((two_pi - 6.283185).abs() < 1e-6);
+    assert_eq!(
+        eval("Modelica.Constants.e").unwrap(),
+        Value::Real(std::f64::consts::E)
+    );
+    let err = eval("Modelica.Constants.tau").unwrap_err();
+    assert!(
+        err.to_string().contains("Modelica.Constants.tau"),
+        "{}",
+        err
+    );
+
+    // in a model, the constants are substituted while flattening
+    let def = Parser::new()
+        .parse(
+            "model M
+               Real x;
+             equation
+               x = Modelica.Constants.unknown;
+             end M;",
+            "test.mo",
+        )
+        .unwrap();
+    let err = flatten(&def).unwrap_err();
+    assert!(
+        err.to_string().contains("Modelica.Constants.unknown"),
+        "{}",
+        err
+    );
+}
+
+/// Adds the components of class `M` to an evaluator.
+fn evaluator(src: &str) -> anyhow::Result<ConstEvaluator> {
+    let def = Parser::new().parse(src, "test.mo").unwrap();
+    let mut evaluator = ConstEvaluator::default();
+    evaluator.add_components(&def.class_list["M"].components)?;
+    Ok(evaluator)
+}
+
+#[test]
+fn parameters_are_evaluated_in_dependency_order() {
+    let evaluator = evaluator(
+        "model M
+           parameter Real c = b * 2;
+           parameter Real b = a + 1;
+           parameter Real a = 1;
+         end M;",
+    )
+    .unwrap();
+    assert_eq!(evaluator.eval(&expression("a")).unwrap(), Value::Integer(1));
+    assert_eq!(evaluator.eval(&expression("b")).unwrap(), Value::Integer(2));
+    assert_eq!(evaluator.eval(&expression("c")).unwrap(), Value::Integer(4));
+}
+
+#[test]
+fn cyclic_parameters_are_an_error() {
+    let err = evaluator(
+        "model M
+           parameter Real a = b;
+           parameter Real b = a;
+         end M;",
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("cyclic parameter dependency a -> b -> a"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn relational_and_logical_operators_are_boolean() {
+    let components = IndexMap::new();
+    for expr in ["1 < 2", "1.5 >= 2", "1 == 2", "true and false", "not true"] {
+        assert_eq!(type_of(&expression(expr), &components), Type::Boolean);
+    }
+}
+
+#[test]
+fn constants_of_other_classes_are_resolved() {
+    let def = Parser::new()
+        .parse(
+            "model M
+               Real v;
+             equation
+               der(v) = -P.g * P.Inner.k;
+             end M;
+             package P
+               constant Real g = 9.81;
+               package Inner
+                 constant Real k = 2 * g;
+               end Inner;
+             end P;",
+            "test.mo",
+        )
+        .unwrap();
+    let env = def.constant_env();
+    assert_eq!(env["P.g"], 9.81);
+    assert_eq!(env["P.Inner.k"], 19.62);
+    assert!(!env.contains_key("g"));
+
+    let fclass = flatten(&def).unwrap();
+    assert!(
+        fclass
+            .to_flat_modelica()
+            .contains("der(v) = -(9.81 * 19.62);")
+    );
+}
+
+/// Prints RPN operations, `Binary` and `Unary` by their operator.
+fn rpn(expr: &str) -> Vec<String> {
+    expression(expr)
+        .to_rpn()
+        .unwrap()
+        .iter()
+        .map(|op| match op {
+            RpnOp::Literal(value) => format!("{:?}", value),
+            RpnOp::Variable(name) => name.clone(),
+            RpnOp::Unary(op) => format!("{:?}", op),
+            RpnOp::Binary(op) => format!("{:?}", op),
+            RpnOp::Call { name, args } => format!("{}/{}", name, args),
+            RpnOp::Select => "select".to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn rpn_respects_precedence() {
+    assert_eq!(
+        rpn("a + b * c"),
+        ["a", "b", "c", r#"Mul("*")"#, r#"Add("+")"#]
+    );
+    assert_eq!(
+        rpn("(a + b) * c"),
+        ["a", "b", r#"Add("+")"#, "c", r#"Mul("*")"#]
+    );
+}
+
+#[test]
+fn rpn_of_calls_and_if_expressions() {
+    assert_eq!(
+        rpn("if x > 0 then sin(x) else -1"),
+        [
+            "x",
+            "Integer(0)",
+            r#"Gt(">")"#,
+            "x",
+            "sin/1",
+            "Integer(1)",
+            r#"Minus("-")"#,
+            "select"
+        ]
+    );
+}
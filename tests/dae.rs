@@ -0,0 +1,1181 @@
+use rumoca::compile;
+use rumoca::dae::ast::Dae;
+use rumoca::dae::sparsity::ColumnKind;
+use rumoca::ir::ast::{Component, ComponentReference, Equation, Expression, StateSelect};
+use rumoca::ir::create_dae::create_dae_with_options;
+use rumoca::ir::flatten::{FlattenOptions, flatten, flatten_with_options};
+use rumoca::parser::Parser;
+
+/// Returns the names of the given DAE variables.
+fn names(components: &[Component]) -> Vec<&str> {
+    components.iter().map(|comp| comp.name.as_str()).collect()
+}
+
+fn dae(src: &str) -> Dae {
+    compile(src, "M").expect("failed to compile")
+}
+
+#[test]
+fn time_is_the_independent_variable() {
+    let dae = dae("model M
+                     Real x;
+                   equation
+                     der(x) = time;
+                   end M;");
+    assert_eq!(dae.t.name, "time");
+    assert_eq!(names(&dae.x), ["x"]);
+    assert!(names(&dae.y).is_empty());
+    assert!(!names(&dae.p).contains(&"time"));
+    assert!(dae.components().contains_key("time"));
+}
+
+#[test]
+fn pre_of_when_assigned_variable_is_collected() {
+    let dae = dae("model M
+                     Real z;
+                     Integer n;
+                     Boolean c;
+                   equation
+                     c = time > 1;
+                     when c then
+                       z = pre(z) + 1;
+                       n = pre(n) + 1;
+                     end when;
+                   end M;");
+    assert_eq!(names(&dae.z), ["z"]);
+    assert_eq!(names(&dae.pre_z), ["pre_z"]);
+    assert_eq!(names(&dae.m), ["n"]);
+    assert_eq!(names(&dae.pre_m), ["pre_n"]);
+}
+
+#[test]
+fn pre_of_continuous_variable_is_an_error() {
+    let err = compile(
+        "model M
+           Real x;
+           Real y;
+         equation
+           der(x) = pre(y);
+           y = x;
+         end M;",
+        "M",
+    )
+    .unwrap_err();
+    assert!(
+        format!("{:#}", err)
+            .contains("pre(y) outside of a when equation requires 'y' to be discrete"),
+        "{:#}",
+        err
+    );
+}
+
+#[test]
+fn bipartite_dot_has_a_node_per_equation_and_unknown() {
+    let dae = dae("model M
+                     Real x;
+                     Real y;
+                   equation
+                     der(x) = -y;
+                     y = 2*x;
+                   end M;");
+    let dot = dae.to_bipartite_dot();
+    for line in [
+        "        \"fx[0]\" [shape=box];\n",
+        "        \"fx[1]\" [shape=box];\n",
+        "        \"der_x\" [shape=ellipse];\n",
+        "        \"y\" [shape=ellipse];\n",
+        "    \"fx[0]\" -- \"der_x\";\n",
+        "    \"fx[0]\" -- \"y\";\n",
+        "    \"fx[1]\" -- \"y\";\n",
+    ] {
+        assert!(dot.contains(line), "{}", dot);
+    }
+    assert!(!dot.contains("\"x\""), "{}", dot);
+
+    let dot = dae.to_bipartite_dot_with_blocks(&[vec![1], vec![0]]);
+    assert!(dot.contains("\"fx[1]\" [shape=box, style=filled, fillcolor=lightblue];"));
+    assert!(dot.contains("\"fx[0]\" [shape=box, style=filled, fillcolor=lightgreen];"));
+}
+
+#[test]
+fn enumeration_variable_is_integer_coded_discrete() {
+    let dae = dae("model M
+                     type Mode = enumeration(off, low \"slow\", high);
+                     Mode mode(start = Mode.off);
+                     Real x;
+                   equation
+                     der(x) = if mode == Mode.high then 1 else 0;
+                     when time > 1 then
+                       mode = Mode.high;
+                     end when;
+                   end M;");
+    assert_eq!(names(&dae.m), ["mode"]);
+    assert_eq!(names(&dae.x), ["x"]);
+    let coding: Vec<(&str, i64)> = dae.enumerations["Mode"]
+        .iter()
+        .map(|(literal, ordinal)| (literal.as_str(), *ordinal))
+        .collect();
+    assert_eq!(coding, [("off", 1), ("low", 2), ("high", 3)]);
+    assert_eq!(format!("{:?}", dae.m[0].start), "UnsignedInteger(\"1\")");
+    assert!(format!("{:?}", dae.fx[0]).contains("rhs: UnsignedInteger(\"3\")"));
+    assert!(format!("{:?}", dae.fm[0]).contains("lhs: \"mode\", rhs: UnsignedInteger(\"3\")"));
+}
+
+#[test]
+fn nominal_and_state_select_reach_the_dae() {
+    let dae = dae("model M
+                     Real x(nominal = 1e3, stateSelect = StateSelect.prefer);
+                     Real y(nominal = 2);
+                   equation
+                     der(x) = -x;
+                     y = x;
+                   end M;");
+    assert_eq!(format!("{:?}", dae.x[0].nominal), "UnsignedReal(\"1e3\")");
+    assert_eq!(dae.x[0].state_select, StateSelect::Prefer);
+    assert_eq!(format!("{:?}", dae.y[0].nominal), "UnsignedInteger(\"2\")");
+    assert_eq!(dae.y[0].state_select, StateSelect::Default);
+}
+
+#[test]
+fn empty_model_gives_empty_dae() {
+    let def = Parser::new().parse("model M end M;", "test.mo").unwrap();
+    assert!(def.validate().is_empty());
+    let fclass = flatten(&def).unwrap();
+    assert!(fclass.components.is_empty());
+    assert!(fclass.equations.is_empty());
+
+    let dae = dae("model M end M;");
+    assert!(dae.components().keys().eq(["time"]));
+    assert!(dae.equations().is_empty());
+    assert!(dae.x.is_empty() && dae.y.is_empty() && dae.p.is_empty());
+}
+
+#[test]
+fn nothing_to_flatten_is_an_error() {
+    let def = Parser::new().parse("", "test.mo").unwrap();
+    let err = flatten(&def).unwrap_err();
+    assert_eq!(err.to_string(), "No class to flatten");
+}
+
+#[test]
+fn elsewhen_statements_fire_like_elsewhen_equations() {
+    let model = |section: &str, assign: &str| {
+        dae(&format!(
+            "model M
+               Integer n(start = 0);
+               Real x(start = 0);
+             equation
+               der(x) = 1;
+             {}
+               when x > 2 then
+                 n {} 2;
+               elsewhen x > 1 then
+                 n {} 1;
+               end when;
+             end M;",
+            section, assign, assign
+        ))
+    };
+    let equation = model("equation", "=");
+    let statement = model("algorithm", ":=");
+    assert_eq!(format!("{:?}", statement.fm), format!("{:?}", equation.fm));
+    assert_eq!(format!("{:?}", statement.fc), format!("{:?}", equation.fc));
+    // the elsewhen update is guarded by the condition of the when branch
+    assert_eq!(
+        format!("{:?}", statement.fm[1]),
+        "When([EquationBlock { cond: \"c0\", eqs: [] }, EquationBlock { cond: \"c1\", eqs: [Simple { lhs: \"n\", rhs: UnsignedInteger(\"1\"), origin: None }] }])"
+    );
+}
+
+#[test]
+fn unsupported_when_equation_is_an_error() {
+    let err = compile(
+        "model M
+           Integer n;
+           Real x;
+         equation
+           der(x) = 1;
+           when x > 1 then
+             if x > 2 then
+               n = 1;
+             else
+               n = 2;
+             end if;
+           end when;
+         end M;",
+        "M",
+    )
+    .unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("unsupported equation in when equation"),
+        "{:#}",
+        err
+    );
+}
+
+/// Returns the equations of class `M` as parsed, without flattening.
+fn parsed_equations(src: &str) -> Vec<rumoca::ir::ast::Equation> {
+    let def = Parser::new()
+        .parse(src, "test.mo")
+        .expect("failed to parse");
+    def.class_list["M"].equations.clone()
+}
+
+#[test]
+fn residual_form_subtracts_the_right_hand_side() {
+    let dae = dae("model M
+                     Real x;
+                     Real y;
+                   equation
+                     der(x) = -x;
+                     y = 0;
+                   end M;");
+    let residuals: Vec<String> = dae
+        .to_residual_form()
+        .unwrap()
+        .iter()
+        .map(|r| format!("{:?}", r))
+        .collect();
+    assert_eq!(residuals.len(), 2);
+    assert!(residuals[0].contains('-'), "{}", residuals[0]);
+    assert_eq!(residuals[1], "\"y\"");
+}
+
+#[test]
+fn residual_form_substitutes_for_indices() {
+    let mut dae = dae("model M
+                         parameter Integer n = 3;
+                       end M;");
+    dae.fx = parsed_equations(
+        "model M
+           Real x[3];
+         equation
+           for i in 1:n loop
+             x[i] = i;
+           end for;
+         end M;",
+    );
+    let residuals: Vec<String> = dae
+        .to_residual_form()
+        .unwrap()
+        .iter()
+        .map(|r| format!("{:?}", r))
+        .collect();
+    assert_eq!(residuals.len(), 3);
+    for (k, residual) in residuals.iter().enumerate() {
+        let subscript = format!(r#"UnsignedInteger(\\\"{}\\\")"#, k + 1);
+        assert!(residual.contains(&subscript), "{}", residual);
+        assert!(residual.ends_with(&format!(r#"rhs: UnsignedInteger("{}") }}"#, k + 1)));
+    }
+}
+
+#[test]
+fn residual_form_rejects_unbalanced_if_branches() {
+    let mut dae = dae("model M end M;");
+    dae.fx = parsed_equations(
+        "model M
+           Real x;
+           Real y;
+         equation
+           if time > 1 then
+             x = 1;
+             y = 2;
+           else
+             x = 0;
+           end if;
+         end M;",
+    );
+    let err = dae.to_residual_form().unwrap_err().to_string();
+    assert!(err.contains("same number of equations"), "{}", err);
+}
+
+#[test]
+fn residual_form_rejects_equations_without_residual() {
+    for eq in [
+        "connect(a, b);",
+        "when time > 1 then x = 1; end when;",
+        "assert(x > 0, \"positive\");",
+    ] {
+        let mut dae = dae("model M end M;");
+        dae.fx = parsed_equations(&format!("model M Real x; equation {} end M;", eq));
+        let err = dae.to_residual_form().unwrap_err().to_string();
+        assert!(
+            err.contains("cannot be written in residual form"),
+            "{}",
+            err
+        );
+    }
+}
+
+/// Returns the continuous equations as debug strings.
+fn fx(dae: &Dae) -> Vec<String> {
+    dae.fx.iter().map(|eq| format!("{:?}", eq)).collect()
+}
+
+#[test]
+fn later_algorithm_section_overwrites_earlier_assignment() {
+    let dae = dae("model M
+                     Real x;
+                     Real y;
+                   algorithm
+                     x := 1;
+                     y := x + 1;
+                   algorithm
+                     x := 3;
+                   end M;");
+    assert_eq!(
+        fx(&dae),
+        [
+            r#"Simple { lhs: "x", rhs: UnsignedInteger("3"), origin: None }"#,
+            r#"Simple { lhs: "y", rhs: Add("+") { lhs: UnsignedInteger("1"), rhs: UnsignedInteger("1") }, origin: None }"#,
+        ]
+    );
+}
+
+#[test]
+fn variable_read_before_assignment_has_its_previous_value() {
+    let dae = dae("model M
+                     Real x(start = 2);
+                     discrete Real z;
+                     Real a[2](start = {5, 6});
+                   algorithm
+                     x := x + 1;
+                     z := z + 1;
+                     a[1] := 1;
+                     a[2] := a[1] + a[2];
+                   end M;");
+    let fx = fx(&dae);
+    assert_eq!(
+        fx[..2],
+        [
+            r#"Simple { lhs: "x", rhs: Add("+") { lhs: UnsignedInteger("2"), rhs: UnsignedInteger("1") }, origin: None }"#,
+            r#"Simple { lhs: "z", rhs: Add("+") { lhs: "pre_z", rhs: UnsignedInteger("1") }, origin: None }"#,
+        ]
+    );
+    assert!(fx[3].ends_with(
+        r#"rhs: Add("+") { lhs: UnsignedInteger("1"), rhs: UnsignedInteger("6") }, origin: None }"#
+    ));
+}
+
+#[test]
+fn ode_has_structural_index_one() {
+    let dae = dae("model M
+                     Real x;
+                     Real y;
+                   equation
+                     der(x) = -x + y;
+                     y = 2 * x;
+                   end M;");
+    assert_eq!(dae.structural_index().unwrap(), 1);
+}
+
+/// A pendulum in cartesian coordinates, with the given modifications of the
+/// positions `x` and `y`.
+fn pendulum(x: &str, y: &str) -> Dae {
+    dae(&format!(
+        "model M
+           parameter Real L = 1;
+           parameter Real g = 9.81;
+           Real x({});
+           Real y({});
+           Real vx;
+           Real vy;
+           Real F;
+         equation
+           der(x) = vx;
+           der(y) = vy;
+           der(vx) = -F * x;
+           der(vy) = -F * y - g;
+           x^2 + y^2 = L^2;
+         end M;",
+        x, y
+    ))
+}
+
+#[test]
+fn pendulum_has_structural_index_three() {
+    let dae = pendulum("start = 1", "");
+    assert_eq!(dae.structural_index().unwrap(), 3);
+}
+
+#[test]
+fn pendulum_with_array_states_has_structural_index_three() {
+    let dae = dae("model M
+                     parameter Real L = 1;
+                     parameter Real g = 9.81;
+                     Real p[2](start = {1, 0});
+                     Real v[2];
+                     Real F;
+                   equation
+                     der(p) = v;
+                     der(v) = -F * p - {0, g};
+                     p * p = L^2;
+                   end M;");
+    assert_eq!(names(&dae.x), ["p[1]", "p[2]", "v[1]", "v[2]"]);
+    assert_eq!(
+        names(&dae.x_dot),
+        ["der_p[1]", "der_p[2]", "der_v[1]", "der_v[2]"]
+    );
+    assert_eq!(names(&dae.y), ["F"]);
+    assert_eq!(format!("{:?}", dae.x[0].start), r#"UnsignedInteger("1")"#);
+    assert_eq!(format!("{:?}", dae.x[1].start), r#"UnsignedInteger("0")"#);
+    assert_eq!(dae.structural_index().unwrap(), 3);
+}
+
+#[test]
+fn derivative_of_an_array_is_taken_elementwise() {
+    let dae = dae("model M
+                     Real x[2](start = {1, 2});
+                   equation
+                     der(x) = -x;
+                   end M;");
+    assert_eq!(names(&dae.x), ["x[1]", "x[2]"]);
+    assert_eq!(dae.fx.len(), 2);
+    assert_eq!(dae.structural_index().unwrap(), 1);
+}
+
+#[test]
+fn state_select_steers_the_dummy_derivatives() {
+    for (x, y, state) in [
+        ("stateSelect = StateSelect.always", "", "x"),
+        ("", "stateSelect = StateSelect.always", "y"),
+        ("stateSelect = StateSelect.never", "", "y"),
+    ] {
+        let states = pendulum(x, y).select_states().unwrap();
+        assert!(
+            states.contains(&state.to_string()),
+            "{}: {:?}",
+            state,
+            states
+        );
+        let other = if state == "x" { "y" } else { "x" };
+        assert!(
+            !states.contains(&other.to_string()),
+            "{}: {:?}",
+            state,
+            states
+        );
+    }
+}
+
+#[test]
+fn structurally_singular_system_is_an_error() {
+    let dae = dae("model M
+                     Real x;
+                     Real y;
+                   equation
+                     x = 1;
+                     x = 2;
+                   end M;");
+    let err = dae.structural_index().unwrap_err().to_string();
+    assert!(err.contains("structurally singular"), "{}", err);
+}
+
+#[test]
+fn reinit_keeps_the_state_and_records_the_reset() {
+    let dae = dae("model M
+                     Real x(start = 1);
+                   equation
+                     der(x) = -1;
+                     when x < 0 then
+                       reinit(x, 1);
+                     end when;
+                   end M;");
+    assert_eq!(names(&dae.x), ["x"]);
+    assert!(dae.z.is_empty());
+    assert_eq!(
+        format!("{:?}", dae.fz),
+        r#"[When([EquationBlock { cond: "c0", eqs: [FunctionCall { comp: "reinit", args: ["x", UnsignedInteger("1")] }] }])]"#
+    );
+    assert_eq!(
+        format!("{:?}", dae.fr),
+        r#"{"c0": Assignment { comp: "x", value: UnsignedInteger("1") }}"#
+    );
+}
+
+#[test]
+fn compile_runs_the_whole_pipeline() {
+    let dae = dae("model M
+                     Real x(start = 1);
+                   equation
+                     der(x) = -x;
+                   end M;");
+    assert_eq!(names(&dae.x), ["x"]);
+    assert_eq!(dae.fx.len(), 1);
+}
+
+#[test]
+fn compile_errors_name_the_failing_stage() {
+    let stage = |src: &str, model| compile(src, model).unwrap_err().to_string();
+    assert_eq!(stage("model M", "M"), "failed to parse the source");
+    assert_eq!(stage("model M end M;", "N"), "failed to flatten 'N'");
+    assert_eq!(
+        stage("model M Real time; end M;", "M"),
+        "failed to create the DAE of 'M'"
+    );
+}
+
+#[test]
+fn preserved_arrays_stay_single_variables() {
+    let def = Parser::new()
+        .parse(
+            "model M
+               parameter Real A[3, 3] = {{-1, 0, 0}, {0, -2, 0}, {0, 0, -3}};
+               Real x[3](start = {1, 2, 3});
+             equation
+               der(x) = A * x;
+             end M;",
+            "test.mo",
+        )
+        .unwrap();
+    let options = FlattenOptions {
+        preserve_arrays: true,
+        ..Default::default()
+    };
+    let mut fclass = flatten_with_options(&def, &options).unwrap();
+    let dae = create_dae_with_options(&mut fclass, &options).unwrap();
+    assert_eq!(names(&dae.x), ["x"]);
+    assert_eq!(names(&dae.x_dot), ["der_x"]);
+    assert_eq!(
+        format!("{:?}", dae.x[0].shape),
+        r#"[Expression(UnsignedInteger("3"))]"#
+    );
+    assert_eq!(
+        fx(&dae),
+        [r#"Simple { lhs: "der_x", rhs: Mul("*") { lhs: "A", rhs: "x" }, origin: None }"#]
+    );
+}
+
+#[test]
+fn algorithm_for_loop_is_unrolled_into_assignments() {
+    let dae = dae("model M
+                     Real y[3];
+                   algorithm
+                     for i in 1:3 loop
+                       y[i] := i;
+                     end for;
+                   end M;");
+    let fx = fx(&dae);
+    assert_eq!(fx.len(), 3, "{:?}", fx);
+    for (i, eq) in fx.iter().enumerate() {
+        assert!(
+            eq.ends_with(&format!(
+                r#"rhs: UnsignedInteger("{}"), origin: None }}"#,
+                i + 1
+            )),
+            "{}",
+            eq
+        );
+    }
+}
+
+#[test]
+fn when_equations_are_routed_by_the_assigned_type() {
+    let dae = dae("model M
+                     Real x(start = 1);
+                     discrete Real z;
+                     Boolean b;
+                   equation
+                     der(x) = -x;
+                     when x < 0.5 then
+                       z = pre(z) + 1;
+                       b = true;
+                     end when;
+                   end M;");
+    let fz: Vec<String> = dae.fz.iter().map(|eq| format!("{:?}", eq)).collect();
+    let fm: Vec<String> = dae.fm.iter().map(|eq| format!("{:?}", eq)).collect();
+    assert_eq!(fz.len(), 1, "{:?}", fz);
+    assert!(fz[0].contains(r#"Simple { lhs: "z""#), "{}", fz[0]);
+    assert_eq!(fm.len(), 1, "{:?}", fm);
+    assert!(fm[0].contains(r#"Simple { lhs: "b""#), "{}", fm[0]);
+}
+
+const DECAY_WITH_COUNTER: &str = "model M
+  Real x(start = 1);
+  Real y;
+  Integer n;
+equation
+  der(x) = -x;
+  y = x;
+  when x < 0.5 then
+    n = pre(n) + 1;
+  end when;
+end M;";
+
+fn reference(name: &str) -> rumoca::ir::ast::Expression {
+    rumoca::ir::ast::Expression::ComponentReference(name.parse().unwrap())
+}
+
+#[test]
+fn consistency_check_reports_pre_of_continuous_and_discrete_updates() {
+    let mut dae = dae(DECAY_WITH_COUNTER);
+    assert!(dae.check_consistency().is_empty());
+
+    // y = pre(x), with x continuous, and a continuous update of n
+    dae.fx.push(rumoca::ir::ast::Equation::Simple {
+        lhs: reference("y"),
+        rhs: reference("pre_x"),
+        origin: None,
+    });
+    dae.fx.push(rumoca::ir::ast::Equation::Simple {
+        lhs: reference("n"),
+        rhs: reference("x"),
+        origin: None,
+    });
+    let codes: Vec<String> = dae
+        .check_consistency()
+        .into_iter()
+        .map(|d| d.code)
+        .collect();
+    assert_eq!(codes, ["pre-of-continuous", "discrete-outside-when"]);
+}
+
+#[test]
+fn evaluated_parameter_is_inlined_and_dropped() {
+    let dae = dae("model M
+                     parameter Real k = 2 annotation(Evaluate = true);
+                     parameter Real g = 3;
+                     Real x;
+                   equation
+                     der(x) = -k * g * x;
+                   end M;");
+    assert_eq!(names(&dae.p), ["g"]);
+    assert_eq!(
+        fx(&dae),
+        [
+            r#"Simple { lhs: "der_x", rhs: Minus("-") { rhs: Mul("*") { lhs: Mul("*") { lhs: UnsignedInteger("2"), rhs: "g" }, rhs: "x" } }, origin: None }"#
+        ]
+    );
+}
+
+#[test]
+fn dependency_graph_follows_the_computation_order() {
+    let dae = dae("model M
+                     Real x(start = 1);
+                     Real y;
+                   equation
+                     y = 2*x;
+                     der(x) = -y;
+                   end M;");
+    assert_eq!(dae.dependency_graph_json(), r#"{"y":["x"],"der_x":["y"]}"#);
+}
+
+#[test]
+fn specialize_substitutes_parameter_values() {
+    let dae = dae("model M
+                     parameter Real k = 1;
+                     parameter Real g = 3;
+                     Real x(start = 1);
+                   equation
+                     der(x) = -k*x + g;
+                   end M;");
+    let params = std::collections::HashMap::from([("k".to_string(), 2.0)]);
+    let specialized = dae.specialize(&params);
+    assert_eq!(names(&specialized.p), ["g"]);
+    assert_eq!(
+        fx(&specialized),
+        [
+            r#"Simple { lhs: "der_x", rhs: Add("+") { lhs: Minus("-") { rhs: Mul("*") { lhs: UnsignedReal("2.0"), rhs: "x" } }, rhs: "g" }, origin: None }"#
+        ]
+    );
+
+    let all = std::collections::HashMap::from([("k".to_string(), 2.0), ("g".to_string(), 0.0)]);
+    assert!(dae.specialize(&all).p.is_empty());
+}
+
+#[test]
+fn specialize_substitutes_integer_alias_and_enumeration_parameters_as_integers() {
+    let dae = dae("model M
+                     type Count = Integer;
+                     type Mode = enumeration(off, on);
+                     parameter Count n = 1;
+                     parameter Mode mode = Mode.off;
+                     Real y;
+                   equation
+                     y = n + mode;
+                   end M;");
+    let params =
+        std::collections::HashMap::from([("n".to_string(), 3.0), ("mode".to_string(), 2.0)]);
+    let specialized = dae.specialize(&params);
+    assert!(specialized.p.is_empty());
+    assert_eq!(
+        fx(&specialized),
+        [r#"Simple { lhs: "y", rhs: UnsignedInteger("5"), origin: None }"#]
+    );
+}
+
+#[test]
+fn display_names_are_the_dotted_flattened_names() {
+    let dae = compile(
+        "model M
+           A a;
+           Real y;
+         equation
+           y = a_x;
+         end M;
+         model A
+           Real x;
+         equation
+           der(x) = -x;
+         end A;",
+        "M",
+    )
+    .expect("failed to compile");
+    let names = dae.display_names();
+    let display = |name: &str| names[&ComponentReference::new(name)].as_str();
+    assert_eq!(display("a_x"), "a.x");
+    assert_eq!(display("der_a_x"), "der(a.x)");
+    assert_eq!(display("y"), "y");
+    assert_eq!(display("time"), "time");
+}
+
+#[test]
+fn display_name_of_a_nested_component_has_every_level() {
+    let dae = Dae {
+        y: vec![Component {
+            name: "a_b_x".to_string(),
+            qualified_name: Some("a.b.x".to_string()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let names = dae.display_names();
+    assert_eq!(names[&ComponentReference::new("a_b_x")], "a.b.x");
+}
+
+#[test]
+fn remove_tautologies_keeps_equations_implying_a_value() {
+    let mut dae = Dae {
+        fx: parsed_equations(
+            "model M
+               Real a;
+             equation
+               a = a;
+               a = -a;
+             end M;",
+        ),
+        ..Default::default()
+    };
+    assert_eq!(dae.remove_tautologies(), 1);
+    assert_eq!(
+        fx(&dae),
+        [r#"Simple { lhs: "a", rhs: Minus("-") { rhs: "a" }, origin: None }"#]
+    );
+}
+
+#[test]
+fn equivalent_conditions_share_one_condition_variable() {
+    let dae = dae("model M
+                     Real x;
+                     discrete Real y;
+                     discrete Real z;
+                   equation
+                     der(x) = 1;
+                     when x > 0 then
+                       y = 1;
+                     end when;
+                     when 0 < x then
+                       z = 2;
+                     end when;
+                   end M;");
+    assert_eq!(names(&dae.c), ["c0"]);
+    assert_eq!(dae.fc.len(), 1);
+    for eq in &dae.fz {
+        assert!(format!("{:?}", eq).contains(r#"cond: "c0""#), "{:?}", eq);
+    }
+    assert_eq!(dae.fz.len(), 2);
+}
+
+#[test]
+fn output_equations_are_indexed() {
+    let dae = dae("model M
+                     Real x(start = 1);
+                     output Real y;
+                     Real z;
+                   equation
+                     der(x) = -x;
+                     z = x + 1;
+                     y = 2*x;
+                   end M;");
+    assert_eq!(dae.fo.len(), 1);
+    let index = dae.fo["y"];
+    assert_eq!(
+        format!("{:?}", dae.fx[index]),
+        r#"Simple { lhs: "y", rhs: Mul("*") { lhs: UnsignedInteger("2"), rhs: "x" }, origin: None }"#
+    );
+}
+
+#[test]
+fn slice_assignments_are_split_per_element() {
+    let dae = dae("model M
+                     Real y[3];
+                     Real A[2, 2];
+                   algorithm
+                     y[1:2] := {3, 4};
+                     y[3] := 5;
+                     A[2, :] := {6, 7};
+                   end M;");
+    let assignments: Vec<String> = dae
+        .fx
+        .iter()
+        .map(|eq| match eq {
+            Equation::Simple {
+                lhs: Expression::ComponentReference(comp),
+                rhs,
+                ..
+            } => format!("{} = {:?}", comp, rhs),
+            _ => panic!("expected a simple equation, got {:?}", eq),
+        })
+        .collect();
+    assert_eq!(
+        assignments,
+        [
+            r#"y[1] = UnsignedInteger("3")"#,
+            r#"y[2] = UnsignedInteger("4")"#,
+            r#"y[3] = UnsignedInteger("5")"#,
+            r#"A[2, 1] = UnsignedInteger("6")"#,
+            r#"A[2, 2] = UnsignedInteger("7")"#,
+        ]
+    );
+}
+
+#[test]
+fn unit_quantity_and_display_unit_are_captured_and_exported() {
+    let dae = dae("model M
+                     Real p(quantity = \"Pressure\", unit = \"Pa\", displayUnit = \"bar\");
+                     Real n;
+                   equation
+                     p = 1e5;
+                     n = 1;
+                   end M;");
+    let p = &dae.y[0];
+    assert_eq!(p.quantity.as_deref(), Some("Pressure"));
+    assert_eq!(p.unit.as_deref(), Some("Pa"));
+    assert_eq!(p.display_unit.as_deref(), Some("bar"));
+    assert_eq!(
+        dae.variables_json(),
+        r#"{"n":{"field":"y","type":"Real"},"p":{"displayUnit":"bar","field":"y","quantity":"Pressure","type":"Real","unit":"Pa"}}"#
+    );
+
+    let err = compile(
+        "model M
+           Real p(unit = 1);
+         equation
+           p = 1;
+         end M;",
+        "M",
+    )
+    .unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("unit of 'p' must be a string"),
+        "{:#}",
+        err
+    );
+}
+
+#[test]
+fn der_calls_become_derivative_variables() {
+    let dae = dae("model M
+                     Real x(start = 1);
+                     Real v;
+                   equation
+                     der(der(x)) = -x;
+                     v = der(x);
+                   end M;");
+    assert_eq!(names(&dae.x), ["x", "der_x"]);
+    assert_eq!(names(&dae.x_dot), ["der_x", "der_der_x"]);
+    assert_eq!(
+        fx(&dae),
+        [
+            r#"Simple { lhs: "der_der_x", rhs: Minus("-") { rhs: "x" }, origin: None }"#,
+            r#"Simple { lhs: "v", rhs: "der_x", origin: None }"#,
+        ]
+    );
+}
+
+#[test]
+fn jacobian_sparsity_marks_derivative_and_state_columns() {
+    let dae = dae("model M
+                     Real x(start = 1);
+                     Real y;
+                   equation
+                     der(x) = -x;
+                     y = 2;
+                   end M;");
+    let pattern = dae.jacobian_sparsity().unwrap();
+    assert_eq!(pattern.columns, ["der_x", "x", "y"]);
+    assert_eq!(
+        pattern.kinds,
+        [
+            ColumnKind::Derivative,
+            ColumnKind::State,
+            ColumnKind::Algebraic
+        ]
+    );
+    let der_x = pattern.column("der_x").unwrap();
+    let x = pattern.column("x").unwrap();
+    let y = pattern.column("y").unwrap();
+    assert!(pattern.is_nonzero(0, der_x));
+    assert!(pattern.is_nonzero(0, x));
+    assert!(!pattern.is_nonzero(0, y));
+    assert_eq!(pattern.rows[1], [y]);
+    assert_eq!(pattern.nnz(), 3);
+}
+
+#[test]
+fn only_top_level_inputs_and_outputs_are_the_interface() {
+    let src = "block G
+                 input Real v;
+                 output Real w;
+               equation
+                 w = 2 * v;
+               end G;
+               model M
+                 input Real u;
+                 output Real y;
+                 G g;
+               equation
+                 g.v = u;
+                 y = g.w;
+               end M;";
+    let dae = compile(src, "M").expect("failed to compile");
+    assert_eq!(names(&dae.u), ["u"]);
+    assert!(names(&dae.y).contains(&"g_v"));
+    assert_eq!(dae.fo.keys().collect::<Vec<_>>(), ["y"]);
+}
+
+#[test]
+fn reinit_of_a_parameter_is_an_error_with_its_location() {
+    let src = "model M
+  parameter Real k = 1;
+  Real x(start = 1);
+equation
+  der(x) = -k;
+  when x < 0 then
+    reinit(k, 0);
+  end when;
+end M;";
+    let err = compile(src, "M").unwrap_err();
+    assert_eq!(
+        err.root_cause().to_string(),
+        "reinit of 'k' at <source>:7:12 requires a continuous state"
+    );
+}
+
+#[test]
+fn constant_if_in_initial_equations_contributes_its_branch() {
+    let dae = dae("model M
+                     parameter Boolean steadyState = true;
+                     parameter Boolean fixed = false;
+                     Real x(start = 1);
+                     Real y;
+                   initial equation
+                     if steadyState then
+                       der(x) = 0;
+                     else
+                       x = 2;
+                     end if;
+                     if fixed then
+                       y = 1;
+                     elseif time > 0 then
+                       y = 2;
+                     end if;
+                   equation
+                     der(x) = -x + y;
+                     y = 3;
+                   end M;");
+    let fi: Vec<_> = dae.fi.iter().map(|eq| format!("{:?}", eq)).collect();
+    assert_eq!(
+        fi,
+        [
+            r#"Simple { lhs: "der_x", rhs: UnsignedInteger("0"), origin: None }"#,
+            r#"If { cond_blocks: [EquationBlock { cond: Gt(">") { lhs: "time", rhs: UnsignedInteger("0") }, eqs: [Simple { lhs: "y", rhs: UnsignedInteger("2"), origin: None }] }], else_block: None }"#,
+        ]
+    );
+}
+
+#[test]
+fn initialization_system_has_start_and_initial_equations() {
+    let dae = dae("model M
+                     Real x(start = 1);
+                     Real y(start = 2);
+                   initial equation
+                     der(x) = 0;
+                   equation
+                     der(x) = -x + y;
+                     y = 3;
+                   end M;");
+    let init = dae.initialization_system();
+    assert_eq!(names(&init.unknowns), ["x", "der_x", "y"]);
+    let equations: Vec<_> = init
+        .equations
+        .iter()
+        .map(|eq| format!("{:?}", eq))
+        .collect();
+    assert_eq!(
+        equations,
+        [
+            r#"Simple { lhs: "der_x", rhs: Add("+") { lhs: Minus("-") { rhs: "x" }, rhs: "y" }, origin: None }"#,
+            r#"Simple { lhs: "y", rhs: UnsignedInteger("3"), origin: None }"#,
+            r#"Simple { lhs: "x", rhs: UnsignedInteger("1"), origin: None }"#,
+            r#"Simple { lhs: "der_x", rhs: UnsignedInteger("0"), origin: None }"#,
+        ]
+    );
+    assert_eq!(init.guesses.keys().collect::<Vec<_>>(), ["y"]);
+}
+
+#[test]
+fn initialization_system_has_updates_active_initially() {
+    let dae = dae("model M
+                     discrete Real n;
+                     discrete Real m;
+                   equation
+                     when initial() then
+                       n = 1;
+                     end when;
+                     when time > 1 then
+                       m = 2;
+                     end when;
+                   end M;");
+    let equations: Vec<_> = dae
+        .initialization_system()
+        .equations
+        .iter()
+        .map(|eq| format!("{:?}", eq))
+        .collect();
+    assert_eq!(
+        equations,
+        [r#"Simple { lhs: "n", rhs: UnsignedInteger("1"), origin: None }"#]
+    );
+}
+
+#[test]
+fn fixed_starts_are_equations_and_unfixed_starts_are_guesses() {
+    let dae = dae("model M
+                     Real x(start = 1, fixed = true);
+                     Real v(start = 2, fixed = false);
+                     Real y(start = 3, fixed = true);
+                     Real z(start = 4);
+                   equation
+                     der(x) = v;
+                     der(v) = -x;
+                     y = 2 * x;
+                     z = y;
+                   end M;");
+    let init = dae.initialization_system();
+    let starts: Vec<_> = init.equations[dae.fx.len()..]
+        .iter()
+        .map(|eq| format!("{:?}", eq))
+        .collect();
+    assert_eq!(
+        starts,
+        [
+            r#"Simple { lhs: "x", rhs: UnsignedInteger("1"), origin: None }"#,
+            r#"Simple { lhs: "y", rhs: UnsignedInteger("3"), origin: None }"#,
+        ]
+    );
+    assert_eq!(init.guesses.keys().collect::<Vec<_>>(), ["v", "z"]);
+}
+
+#[test]
+fn grounded_node_gives_a_removable_redundant_equation() {
+    let mut dae = dae("connector Pin
+                         Real v;
+                         flow Real i;
+                       end Pin;
+                       model M
+                         Pin a;
+                         Pin b;
+                       equation
+                         connect(a, b);
+                         a.v = 0;
+                         b.v = 0;
+                         a.i = 1;
+                       end M;");
+    let aliases = dae.eliminate_aliases();
+    assert_eq!(aliases.keys().collect::<Vec<_>>(), ["a_v", "b_v", "a_i"]);
+    // the connection equation a_v = b_v of the grounded node
+    assert_eq!(dae.redundant_equations(), [0]);
+    let redundancy = dae.remove_redundant_equations().unwrap();
+    assert_eq!(redundancy.removed.len(), 1);
+    assert_eq!(redundancy.warning, None);
+    assert_eq!(dae.fx.len(), 1);
+    assert_eq!(names(&dae.y), ["b_i"]);
+}
+
+#[test]
+fn removing_redundant_equations_of_a_balanced_system_warns() {
+    let mut dae = dae("model M
+                         Real x;
+                         Real y;
+                       equation
+                         x = y;
+                         2 * x = 2 * y;
+                       end M;");
+    dae.eliminate_aliases();
+    let redundancy = dae.remove_redundant_equations().unwrap();
+    assert_eq!(redundancy.removed.len(), 1);
+    assert_eq!(
+        redundancy.warning.as_deref(),
+        Some("removing 1 redundant equation(s) leaves 0 equation(s) for 1 unknown(s)")
+    );
+}
+
+#[test]
+fn both_when_triggers_are_event_conditions() {
+    let dae = dae("model M
+                     Real x(start = 1);
+                     discrete Real a;
+                     discrete Real b;
+                   equation
+                     der(x) = -x;
+                     when x < 0.5 then
+                       a = 1;
+                     end when;
+                     when time > 2 then
+                       b = 2;
+                     end when;
+                   end M;");
+    let conditions: Vec<_> = dae
+        .event_conditions()
+        .iter()
+        .map(|cond| format!("{:?}", cond))
+        .collect();
+    assert_eq!(
+        conditions,
+        [
+            r#"Lt("<") { lhs: "x", rhs: UnsignedReal("0.5") }"#,
+            r#"Gt(">") { lhs: "time", rhs: UnsignedInteger("2") }"#,
+        ]
+    );
+}
+
+#[test]
+fn equation_between_whole_arrays_is_split() {
+    let dae = dae("model M
+                     Real x[2];
+                     Real y[2](each start = 1);
+                   equation
+                     x = y;
+                     der(y) = -y;
+                   end M;");
+    assert_eq!(names(&dae.x), ["y[1]", "y[2]"]);
+    assert_eq!(names(&dae.y), ["x[1]", "x[2]"]);
+    let cref = |expr: &Expression| match expr {
+        Expression::ComponentReference(cref) => cref.to_string(),
+        expr => format!("{:?}", expr),
+    };
+    let sides: Vec<(String, String)> = dae
+        .fx
+        .iter()
+        .map(|eq| match eq {
+            Equation::Simple { lhs, rhs, .. } => (cref(lhs), cref(rhs)),
+            eq => panic!("unexpected equation {:?}", eq),
+        })
+        .collect();
+    assert_eq!(sides.len(), 4);
+    assert_eq!(sides[0], ("x[1]".to_string(), "y[1]".to_string()));
+    assert_eq!(sides[1], ("x[2]".to_string(), "y[2]".to_string()));
+    assert_eq!(sides[2].0, "der_y[1]");
+    assert_eq!(sides[3].0, "der_y[2]");
+}
@@ -0,0 +1,1167 @@
+use rumoca::ir::ast::{ClassDefinition, Component, Equation, Expression, Visibility};
+use rumoca::ir::connections::{connection_sets, expand_connections};
+use rumoca::ir::flatten::{FlattenOptions, flatten, flatten_with_options};
+use rumoca::parser::Parser;
+
+fn flat(src: &str) -> anyhow::Result<ClassDefinition> {
+    let def = Parser::new()
+        .parse(src, "test.mo")
+        .expect("failed to parse");
+    flatten(&def)
+}
+
+/// Returns the equations of the flattened class printed as flat Modelica.
+fn equations(fclass: &ClassDefinition) -> Vec<String> {
+    fclass
+        .to_flat_modelica()
+        .lines()
+        .skip_while(|line| line.trim() != "equation")
+        .skip(1)
+        .take_while(|line| !line.starts_with("end ") && line.starts_with("  "))
+        .map(|line| line.trim().to_string())
+        .collect()
+}
+
+#[test]
+fn for_equation_unrolls_recurrence() {
+    let fclass = flat(
+        "model M
+           parameter Integer n = 4;
+           Real x[n];
+         equation
+           x[1] = 1;
+           for i in 2:n loop
+             x[i] = x[i-1];
+           end for;
+         end M;",
+    )
+    .unwrap();
+    assert_eq!(
+        equations(&fclass),
+        ["x[1] = 1;", "x[2] = x[1];", "x[3] = x[2];", "x[4] = x[3];"]
+    );
+}
+
+#[test]
+fn for_equation_folds_subscript_arithmetic() {
+    let fclass = flat(
+        "model M
+           Real x[5];
+           Real y[6];
+         equation
+           for i in 1:2, j in 0:1 loop
+             x[2*i + j] = y[i * 3 - j];
+           end for;
+         end M;",
+    )
+    .unwrap();
+    assert_eq!(
+        equations(&fclass),
+        [
+            "x[2] = y[3];",
+            "x[3] = y[2];",
+            "x[4] = y[6];",
+            "x[5] = y[5];"
+        ]
+    );
+}
+
+#[test]
+fn for_equation_with_variable_range_is_an_error() {
+    let err = flat(
+        "model M
+           Real n;
+           Real x[2];
+         equation
+           n = 2;
+           for i in 1:n loop
+             x[i] = i;
+           end for;
+         end M;",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("failed to expand for equation"));
+}
+
+#[test]
+fn for_equation_of_component_class_is_unrolled() {
+    let fclass = flat(
+        "model M
+           C c;
+         end M;
+         model C
+           Real v[2];
+         equation
+           for i in 1:2 loop
+             v[i] = i;
+           end for;
+         end C;",
+    )
+    .unwrap();
+    assert_eq!(equations(&fclass), ["c_v[1] = 1;", "c_v[2] = 2;"]);
+}
+
+#[test]
+fn inherited_duplicate_must_be_identical() {
+    let base = "
+         model Base
+           Real x;
+         end Base;";
+    let fclass = flat(&format!(
+        "model M
+           extends Base;
+           Real x;
+         equation
+           x = 1;
+         end M;{}",
+        base
+    ))
+    .unwrap();
+    assert_eq!(fclass.components.len(), 1);
+
+    for extends_first in [false, true] {
+        let def = Parser::new()
+            .parse(
+                &format!(
+                    "model M
+                       extends Base;
+                       Integer x;
+                     end M;{}",
+                    base
+                ),
+                "test.mo",
+            )
+            .unwrap();
+        let options = FlattenOptions {
+            extends_first,
+            ..Default::default()
+        };
+        let err = flatten_with_options(&def, &options).unwrap_err();
+        assert!(
+            err.to_string().contains("Duplicate declaration of 'x'"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn start_values_follow_precedence() {
+    let fclass = flat(
+        "model M
+           type T = Real(start = 2);
+           Real x(start = 5);
+           Real y;
+           Integer n;
+           T a;
+           T b(start = 3);
+           T c = 4;
+         equation
+           der(x) = -x;
+           y = n + a + b + c;
+           n = 1;
+         end M;",
+    )
+    .unwrap();
+    let start = |name: &str| format!("{:?}", fclass.components[name].start);
+    assert_eq!(start("x"), "UnsignedInteger(\"5\")");
+    assert_eq!(start("y"), "UnsignedReal(\"0.0\")");
+    assert_eq!(start("n"), "UnsignedInteger(\"0\")");
+    assert_eq!(start("a"), "UnsignedInteger(\"2\")");
+    assert_eq!(start("b"), "UnsignedInteger(\"3\")");
+    assert_eq!(start("c"), "UnsignedInteger(\"4\")");
+}
+
+#[test]
+fn cat_concatenates_literals_and_array_components() {
+    let fclass = flat(
+        "model M
+           Real x[2];
+           Real y[3];
+           Real z[3];
+         equation
+           x = {4, 5};
+           y = cat(1, {1, 2}, {3});
+           z = cat(1, x, {3});
+         end M;",
+    )
+    .unwrap();
+    assert_eq!(
+        equations(&fclass),
+        [
+            "x[1] = 4;",
+            "x[2] = 5;",
+            "y[1] = 1;",
+            "y[2] = 2;",
+            "y[3] = 3;",
+            "z[1] = x[1];",
+            "z[2] = x[2];",
+            "z[3] = 3;"
+        ]
+    );
+}
+
+#[test]
+fn cat_checks_dimensions() {
+    let err = flat(
+        "model M
+           Real y[2, 2];
+         equation
+           y = cat(1, {{1, 2}}, {{3}});
+         end M;",
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("cannot concatenate along dimension 1"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn extends_modifications_override_inherited_bindings() {
+    let src = "
+        model D
+          extends B(k = 2);
+        end D;
+        model E
+          extends B;
+        end E;
+        model B
+          extends A(k = 3, x(start = 5));
+        end B;
+        model A
+          parameter Real k = 1;
+          Real x;
+        equation
+          der(x) = -k * x;
+        end A;
+        model F
+          extends A(n = 1);
+        end F;";
+    let def = Parser::new().parse(src, "test.mo").unwrap();
+    let flat = |main_class: &str, extends_first: bool| {
+        let options = FlattenOptions {
+            extends_first,
+            main_class: Some(main_class.to_string()),
+            ..Default::default()
+        };
+        flatten_with_options(&def, &options)
+    };
+    let binding =
+        |fclass: &ClassDefinition, name: &str| format!("{:?}", fclass.components[name].start);
+
+    // the outermost modification wins along the inheritance chain
+    let d = flat("D", true).unwrap();
+    assert_eq!(binding(&d, "k"), "UnsignedInteger(\"2\")");
+    assert_eq!(binding(&d, "x"), "UnsignedInteger(\"5\")");
+    let e = flat("E", true).unwrap();
+    assert_eq!(binding(&e, "k"), "UnsignedInteger(\"3\")");
+
+    // without inlining, the modifications of the direct base class apply
+    let b = flat("B", false).unwrap();
+    assert_eq!(binding(&b, "k"), "UnsignedInteger(\"3\")");
+    assert_eq!(binding(&b, "x"), "UnsignedInteger(\"5\")");
+
+    for extends_first in [false, true] {
+        let err = flat("F", extends_first).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Modified component 'n' not found in class 'A'"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn chained_connects_form_one_connection_set() {
+    let src = "
+        model M
+          Pin a;
+          Pin b;
+          Pin c;
+        equation
+          connect(a, b);
+          connect(b, c);
+        end M;
+        connector Pin
+          Real v;
+          flow Real i;
+        end Pin;";
+    let def = Parser::new().parse(src, "test.mo").unwrap();
+    let sets = connection_sets(&def.class_list["M"].equations);
+    assert_eq!(sets.len(), 1);
+    let names: Vec<String> = sets[0].iter().map(|cref| cref.to_string()).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+
+    let fclass = flatten(&def).unwrap();
+    assert_eq!(
+        equations(&fclass),
+        ["a_v = b_v;", "a_v = c_v;", "((-a_i) - b_i) - c_i = 0;"]
+    );
+}
+
+#[test]
+fn flattened_equations_keep_source_locations() {
+    let fclass = flat(
+        "model M
+           C c;
+         end M;
+         model C
+           Real v;
+           Real w;
+         equation
+           v = 1;
+           w = v;
+         end C;",
+    )
+    .unwrap();
+    let lines: Vec<(String, u32)> = fclass
+        .equations
+        .iter()
+        .flat_map(|eq| match eq {
+            Equation::Simple { lhs, rhs, .. } => vec![lhs, rhs],
+            _ => vec![],
+        })
+        .filter_map(|expr| match expr {
+            Expression::ComponentReference(cref) => {
+                Some((cref.to_string(), cref.parts[0].ident.location.start_line))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            ("c_v".to_string(), 8),
+            ("c_w".to_string(), 9),
+            ("c_v".to_string(), 9)
+        ]
+    );
+}
+
+#[test]
+fn flat_modelica_reparses_into_the_same_class() {
+    let fclass = flat(
+        "model M
+           Sub a;
+           Sub b(k = 3);
+           Real y;
+         equation
+           y = a.x + b.x;
+         end M;
+         model Sub
+           parameter Real k = 2;
+           Real x(start = 1);
+         equation
+           der(x) = -k * x;
+         end Sub;",
+    )
+    .unwrap();
+    let text = fclass.to_flat_modelica();
+    let reparsed = flat(&text).unwrap();
+    assert_eq!(reparsed.to_flat_modelica(), text);
+    let names: Vec<&String> = reparsed.components.keys().collect();
+    assert_eq!(names, fclass.components.keys().collect::<Vec<_>>());
+    assert_eq!(
+        format!("{:?}", reparsed.equations),
+        format!("{:?}", fclass.equations).replace('.', "_")
+    );
+}
+
+#[test]
+fn connecting_signals_solves_for_the_input() {
+    let fclass = flat(
+        "model M
+           Source src;
+           Sink snk;
+         equation
+           connect(src.y, snk.u);
+         end M;
+         connector RealInput = input Real;
+         connector RealOutput = output Real;
+         block Source
+           RealOutput y;
+         equation
+           y = time;
+         end Source;
+         block Sink
+           RealInput u;
+         end Sink;",
+    )
+    .unwrap();
+    assert_eq!(equations(&fclass), ["src_y = time;", "snk_u = src_y;"]);
+}
+
+#[test]
+fn connectors_extending_connectors_are_not_variables() {
+    let def = Parser::new()
+        .parse(
+            "model M
+               Plug a;
+               Plug b;
+               Pin c;
+             equation
+               connect(a, b);
+               connect(b, c);
+             end M;
+             connector Pin
+               Real v;
+               flow Real i;
+             end Pin;
+             connector Plug
+               extends Pin;
+             end Plug;",
+            "test.mo",
+        )
+        .unwrap();
+    let options = FlattenOptions {
+        extends_first: true,
+        ..Default::default()
+    };
+    let fclass = flatten_with_options(&def, &options).unwrap();
+    assert_eq!(
+        equations(&fclass),
+        ["a_v = b_v;", "a_v = c_v;", "((-a_i) - b_i) - c_i = 0;"]
+    );
+}
+
+const NESTED: &str = "model M
+                        A a;
+                      end M;
+                      model A
+                        B b;
+                      end A;
+                      model B
+                        Real x;
+                      end B;";
+
+#[test]
+fn hierarchy_deeper_than_the_limit_is_an_error() {
+    let def = Parser::new().parse(NESTED, "test.mo").unwrap();
+    let options = |max_depth| FlattenOptions {
+        max_depth,
+        ..Default::default()
+    };
+    assert!(flatten_with_options(&def, &options(2)).is_ok());
+    let err = flatten_with_options(&def, &options(1))
+        .unwrap_err()
+        .to_string();
+    assert_eq!(err, "Hierarchy exceeds the maximum depth of 1 at class 'B'");
+}
+
+#[test]
+fn recursive_model_is_an_error() {
+    let err = flat(
+        "model M
+           A a;
+         end M;
+         model A
+           A inner_a;
+         end A;",
+    )
+    .unwrap_err()
+    .to_string();
+    assert_eq!(
+        err,
+        "Hierarchy exceeds the maximum depth of 100 at class 'A'"
+    );
+}
+
+#[test]
+fn connect_equations_are_kept_unless_expanded() {
+    let def = Parser::new()
+        .parse(
+            "model M
+               Pin a;
+               Pin b;
+             equation
+               connect(a, b);
+             end M;
+             connector Pin
+               Real v;
+               flow Real i;
+             end Pin;",
+            "test.mo",
+        )
+        .unwrap();
+    let options = FlattenOptions {
+        expand_connects: false,
+        ..Default::default()
+    };
+    let mut fclass = flatten_with_options(&def, &options).unwrap();
+    assert!(matches!(
+        fclass.equations.as_slice(),
+        [Equation::Connect { .. }]
+    ));
+    let dae = rumoca::ir::create_dae::create_dae(&mut fclass).unwrap();
+    assert!(matches!(dae.fx.as_slice(), [Equation::Connect { .. }]));
+
+    let expanded = flatten(&def).unwrap();
+    assert_eq!(equations(&expanded), ["a_v = b_v;", "(-a_i) - b_i = 0;"]);
+}
+
+const REPLACEABLE: &str = "
+record Narrow
+  Real a;
+end Narrow;
+record Wide
+  Real a;
+  Real b;
+end Wide;
+record Other
+  Real z;
+end Other;
+model Base
+  replaceable Narrow p;
+end Base;
+model C
+  extends Base;
+end C;
+model S
+  replaceable Narrow p;
+end S;
+model Outer
+  S sub;
+end Outer;";
+
+fn flat_with(model: &str) -> anyhow::Result<ClassDefinition> {
+    flat(&format!("{}\n{}", model, REPLACEABLE))
+}
+
+fn type_of(fclass: &ClassDefinition, name: &str) -> String {
+    fclass.components[name].type_name.to_string()
+}
+
+#[test]
+fn replaceable_component_keeps_its_default_type() {
+    let fclass = flat_with("model M S s; end M;").unwrap();
+    assert_eq!(type_of(&fclass, "s_p"), "Narrow");
+}
+
+#[test]
+fn redeclaration_replaces_the_component_type() {
+    let fclass = flat_with("model M S s(redeclare Wide p); end M;").unwrap();
+    assert_eq!(type_of(&fclass, "s_p"), "Wide");
+}
+
+#[test]
+fn redeclaration_must_satisfy_the_constraint() {
+    let err = flat_with("model M S s(redeclare Other p); end M;").unwrap_err();
+    assert!(err.to_string().contains("does not satisfy"), "{}", err);
+}
+
+#[test]
+fn inherited_replaceable_component_is_redeclared() {
+    let fclass = flat_with("model M C c(redeclare Wide p); end M;").unwrap();
+    assert_eq!(type_of(&fclass, "c_p"), "Wide");
+}
+
+#[test]
+fn extends_modification_redeclares_the_base_component() {
+    let fclass = flat_with("model M extends Base(redeclare Wide p); end M;").unwrap();
+    assert_eq!(type_of(&fclass, "p"), "Wide");
+}
+
+#[test]
+fn nested_redeclaration_is_kept_by_the_subcomponent() {
+    let fclass = flat_with("model M Outer o(sub(redeclare Wide p)); end M;").unwrap();
+    let sub = &fclass.components["o_sub"];
+    assert_eq!(sub.redeclarations["p"].type_name.to_string(), "Wide");
+
+    let err = flat_with("model M Outer o(sub(redeclare Other p)); end M;").unwrap_err();
+    assert!(err.to_string().contains("does not satisfy"), "{}", err);
+}
+
+const CONDITIONAL: &str = "
+connector Pin
+  Real v;
+  flow Real i;
+end Pin;
+model C
+  parameter Boolean use = false;
+  parameter Real k = 1;
+  Real v if use;
+  Real w;
+equation
+  w = k;
+end C;";
+
+#[test]
+fn disabled_conditional_component_is_removed_with_its_connections() {
+    let fclass = flat(&format!(
+        "model M
+           parameter Boolean enable = false;
+           Pin a;
+           Pin b if enable;
+         equation
+           connect(a, b);
+           a.v = 1;
+         end M;
+         {}",
+        CONDITIONAL
+    ))
+    .unwrap();
+    let names: Vec<&str> = fclass.components.keys().map(|k| k.as_str()).collect();
+    assert_eq!(names, ["enable", "a_i", "a_v"]);
+    assert_eq!(equations(&fclass), ["a_v = 1;"]);
+}
+
+#[test]
+fn removed_component_used_outside_connect_is_an_error() {
+    let err = flat(
+        "model M
+           Real x if false;
+           Real y;
+         equation
+           y = x;
+         end M;",
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("removed by its condition"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn instance_modifications_apply_before_conditions() {
+    let fclass = flat(&format!(
+        "model M
+           C c(use = true, k = 3);
+           C d;
+         end M;
+         {}",
+        CONDITIONAL
+    ))
+    .unwrap();
+    assert!(fclass.components.contains_key("c_v"));
+    assert!(!fclass.components.contains_key("d_v"));
+    let start = |name: &str| format!("{:?}", fclass.components[name].start);
+    assert_eq!(start("c_k"), r#"UnsignedInteger("3")"#);
+    assert_eq!(start("d_k"), r#"UnsignedInteger("1")"#);
+}
+
+#[test]
+fn matrix_times_vector_is_one_dot_product_per_row() {
+    let fclass = flat(
+        "model M
+           parameter Real A[2, 2] = {{1, 2}, {3, 4}};
+           Real x[2];
+           Real y[2];
+         equation
+           y = A * x;
+           x = A .* {{1, 0}, {0, 1}} * {1, 1};
+         end M;",
+    )
+    .unwrap();
+    assert_eq!(
+        equations(&fclass),
+        [
+            "y[1] = (A[1, 1] * x[1]) + (A[1, 2] * x[2]);",
+            "y[2] = (A[2, 1] * x[1]) + (A[2, 2] * x[2]);",
+            "x[1] = ((A[1, 1] * 1) * 1) + ((A[1, 2] * 0) * 1);",
+            "x[2] = ((A[2, 1] * 0) * 1) + ((A[2, 2] * 1) * 1);",
+        ]
+    );
+}
+
+#[test]
+fn derivative_of_an_array_is_split_per_element() {
+    let fclass = flat(
+        "model M
+           Real x[2];
+         equation
+           der(x) = -x;
+         end M;",
+    )
+    .unwrap();
+    assert_eq!(
+        equations(&fclass),
+        ["der(x[1]) = -x[1];", "der(x[2]) = -x[2];"]
+    );
+}
+
+#[test]
+fn for_equation_iterates_over_boolean_and_enumeration_ranges() {
+    let fclass = flat(
+        "model M
+           type Mode = enumeration(off, on);
+           Real y[2];
+           Real z[2];
+           Real w[2];
+         equation
+           for b in {false, true} loop
+             y[if b then 2 else 1] = if b then 1 else 0;
+           end for;
+           for b in Boolean loop
+             z[if b then 2 else 1] = 1;
+           end for;
+           for m in Mode loop
+             w[Integer(m)] = 2;
+           end for;
+         end M;",
+    )
+    .unwrap();
+    assert_eq!(
+        equations(&fclass),
+        [
+            "y[1] = if false then 1 else 0;",
+            "y[2] = if true then 1 else 0;",
+            "z[1] = 1;",
+            "z[2] = 1;",
+            "w[Integer(Mode.off)] = 2;",
+            "w[Integer(Mode.on)] = 2;",
+        ]
+    );
+}
+
+#[test]
+fn connect_generated_equations_carry_the_connect_location() {
+    let fclass = flat(
+        "model M
+           Pin a;
+           Pin b;
+         equation
+           a.v = 1;
+           connect(a, b);
+         end M;
+         connector Pin
+           Real v;
+           flow Real i;
+         end Pin;",
+    )
+    .unwrap();
+    let generated: Vec<(String, u32)> = fclass
+        .equations
+        .iter()
+        .filter_map(|eq| match eq {
+            Equation::Simple {
+                lhs,
+                origin: Some(origin),
+                ..
+            } => Some((format!("{:?}", lhs), origin.start_line)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        generated,
+        [
+            (r#""a_v""#.to_string(), 6),
+            (
+                r#"Sub("-") { lhs: Minus("-") { rhs: "a_i" }, rhs: "b_i" }"#.to_string(),
+                6
+            )
+        ]
+    );
+    // the written equation has no origin
+    assert!(matches!(
+        fclass.equations[0],
+        Equation::Simple { origin: None, .. }
+    ));
+}
+
+#[test]
+fn package_member_refers_to_a_sibling_by_short_name() {
+    let fclass = flat_options(
+        "package Lib
+           package Sub
+             type Voltage = Real(unit = \"V\");
+             model Source
+               Voltage v;
+               parameter Real u = 2;
+             equation
+               v = u;
+             end Source;
+           end Sub;
+           model Top
+             Sub.Source s;
+           end Top;
+         end Lib;",
+        "Lib.Top",
+    )
+    .unwrap();
+    let names: Vec<&str> = fclass.components.keys().map(|k| k.as_str()).collect();
+    assert_eq!(names, ["s_u", "s_v"]);
+    let v = &fclass.components["s_v"];
+    assert_eq!(v.type_name.to_string(), "Real");
+    assert_eq!(format!("{:?}", v.modifications["unit"]), r#"String("V")"#);
+    assert_eq!(equations(&fclass), ["s_v = s_u;"]);
+}
+
+#[test]
+fn component_of_a_nested_class_of_the_main_class_is_expanded() {
+    let fclass = flat(
+        "model M
+           model S
+             Real y;
+           equation
+             y = 1;
+           end S;
+           S s;
+         end M;",
+    )
+    .unwrap();
+    let names: Vec<&str> = fclass.components.keys().map(|k| k.as_str()).collect();
+    assert_eq!(names, ["s_y"]);
+    assert_eq!(equations(&fclass), ["s_y = 1;"]);
+    let err = flat("model M Missing m; end M;").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Class 'Missing' of component 'm' not found"
+    );
+}
+
+fn flat_options(src: &str, main_class: &str) -> anyhow::Result<ClassDefinition> {
+    let def = Parser::new()
+        .parse(src, "test.mo")
+        .expect("failed to parse");
+    flatten_with_options(
+        &def,
+        &FlattenOptions {
+            main_class: Some(main_class.to_string()),
+            ..Default::default()
+        },
+    )
+}
+
+#[test]
+fn tautological_equations_are_removed() {
+    let fclass = flat(
+        "model M
+           Real a;
+           Real b[2];
+         equation
+           a = a;
+           a = -a;
+           for i in 1:2 loop
+             b[i] = b[i];
+           end for;
+           b[1] = 1;
+           b[2] = 2;
+         end M;",
+    )
+    .unwrap();
+    assert_eq!(equations(&fclass), ["a = -a;", "b[1] = 1;", "b[2] = 2;"]);
+}
+
+#[test]
+fn type_extending_a_type_alias_inherits_its_attributes() {
+    let fclass = flat(
+        "model M
+           PositiveVoltage v;
+         equation
+           v = 1;
+         end M;
+         type Voltage = Real(unit = \"V\");
+         type PositiveVoltage
+           extends Voltage(min = 0);
+         end PositiveVoltage;",
+    )
+    .unwrap();
+    let v = &fclass.components["v"];
+    assert_eq!(v.type_name.to_string(), "Real");
+    let attributes: Vec<&str> = v.modifications.keys().map(|k| k.as_str()).collect();
+    assert_eq!(attributes, ["min", "unit"]);
+    assert_eq!(equations(&fclass), ["v = 1;"]);
+}
+
+#[test]
+fn flows_of_a_connection_set_sum_to_zero_in_one_equation() {
+    let fclass = flat(
+        "model M
+           Pin a;
+           Pin b;
+           Pin c;
+         equation
+           connect(a, b);
+           connect(a, c);
+         end M;
+         connector Pin
+           Real v;
+           flow Real i;
+         end Pin;",
+    )
+    .unwrap();
+    assert_eq!(
+        equations(&fclass),
+        ["a_v = b_v;", "a_v = c_v;", "((-a_i) - b_i) - c_i = 0;"]
+    );
+}
+
+#[test]
+fn flows_of_inside_connectors_are_added() {
+    let def = Parser::new()
+        .parse(
+            "model M
+               Pin p;
+               R r1;
+               R r2;
+             equation
+               connect(p, r1.p);
+               connect(r1.p, r2.p);
+             end M;
+             connector Pin
+               Real v;
+               flow Real i;
+             end Pin;
+             model R
+               Pin p;
+             end R;",
+            "test.mo",
+        )
+        .unwrap();
+    let class = &def.class_list["M"];
+    // the components of the connectors, as flattening them gives
+    let mut fclass = ClassDefinition::default();
+    for name in ["p_v", "p_i", "r1_p_v", "r1_p_i", "r2_p_v", "r2_p_i"] {
+        fclass.components.insert(
+            name.to_string(),
+            Component {
+                name: name.to_string(),
+                ..Default::default()
+            },
+        );
+    }
+    expand_connections(&mut fclass, class, &def.class_list, &class.equations).unwrap();
+    assert_eq!(
+        equations(&fclass),
+        [
+            "p_v = r1_p_v;",
+            "p_v = r2_p_v;",
+            "((-p_i) + r1_p_i) + r2_p_i = 0;"
+        ]
+    );
+}
+
+#[test]
+fn redeclared_package_changes_the_constants_of_medium() {
+    let src = "package PartialMedium
+                 constant Real X = 1;
+               end PartialMedium;
+               package Air
+                 extends PartialMedium;
+                 constant Real X = 3;
+               end Air;
+               model Pipe
+                 replaceable package Medium = PartialMedium;
+                 Real x;
+               equation
+                 x = Medium.X;
+               end Pipe;
+               model M
+                 Pipe a;
+                 Pipe b(redeclare package Medium = Air);
+               end M;";
+    let fclass = flat_options(src, "M").unwrap();
+    assert_eq!(equations(&fclass), ["a_x = 1;", "b_x = 3;"]);
+}
+
+#[test]
+fn redeclared_package_must_satisfy_the_constraint() {
+    let src = "package PartialMedium
+                 constant Real X = 1;
+               end PartialMedium;
+               package Other
+                 constant Real Y = 3;
+               end Other;
+               model Pipe
+                 replaceable package Medium = PartialMedium;
+                 Real x;
+               equation
+                 x = Medium.X;
+               end Pipe;
+               model M
+                 Pipe b(redeclare package Medium = Other);
+               end M;";
+    let err = flat_options(src, "M").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Redeclared class 'Other' of 'Medium' does not satisfy the constraint 'PartialMedium'"
+    );
+}
+
+#[test]
+fn package_cannot_be_the_flattened_class() {
+    let src = "package P
+                 model M
+                   Real x;
+                 equation
+                   x = 1;
+                 end M;
+               end P;";
+    let err = flat_options(src, "P").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Class 'P' is a package and cannot be flattened for simulation, select a model or block"
+    );
+    let fclass = flat_options(src, "P.M").unwrap();
+    assert_eq!(equations(&fclass), ["x = 1;"]);
+}
+
+#[test]
+fn partial_class_is_only_flattened_as_a_base() {
+    let src = "partial model Base
+                 Real x;
+               end Base;
+               model M
+                 extends Base;
+               equation
+                 x = 1;
+               end M;";
+    let def = Parser::new()
+        .parse(src, "test.mo")
+        .expect("failed to parse");
+    assert!(def.class_list["Base"].partial);
+    let err = flat_options(src, "Base").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Class 'Base' is partial and cannot be flattened for simulation, select a class extending it"
+    );
+    let fclass = flat_options(src, "M").unwrap();
+    assert!(fclass.components.contains_key("x"));
+    assert_eq!(equations(&fclass), ["x = 1;"]);
+}
+
+#[test]
+fn component_of_a_der_type_is_linked_to_its_variable() {
+    let fclass = flat_options(
+        "type Velocity = der(Real, x);
+         model M
+           Real x(start = 0);
+           Velocity v;
+         equation
+           v = 1;
+         end M;",
+        "M",
+    )
+    .unwrap();
+    assert_eq!(fclass.components["v"].type_name.to_string(), "Real");
+    assert_eq!(equations(&fclass), ["v = 1;", "v = der(x);"]);
+    let err = flat_options(
+        "type Gradient = der(Real, x, y);
+         model M
+           Real x;
+           Real y;
+           Gradient g;
+         end M;",
+        "M",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Component 'g' of type 'Gradient' derives 2 variables, a component may only be the derivative of one"
+    );
+}
+
+#[test]
+fn dimensions_of_a_type_alias_are_added_to_the_component() {
+    let fclass = flat_options(
+        "type Vector3 = Real[3];
+         model M
+           Vector3 v[2];
+         end M;",
+        "M",
+    )
+    .unwrap();
+    assert!(fclass.to_flat_modelica().contains("Real v[2, 3];"));
+}
+
+#[test]
+fn flattened_subcomponents_can_keep_their_hierarchy() {
+    let def = Parser::new()
+        .parse(
+            "model Sub
+               Real x;
+             end Sub;
+             model M
+               Sub sub;
+               Real y;
+             end M;",
+            "test.mo",
+        )
+        .expect("failed to parse");
+    let flat_with = |hierarchy| {
+        let options = FlattenOptions {
+            main_class: Some("M".to_string()),
+            hierarchy,
+            ..Default::default()
+        };
+        flatten_with_options(&def, &options).unwrap()
+    };
+    let fclass = flat_with(true);
+    assert_eq!(fclass.components["sub_x"].hierarchy, ["sub"]);
+    assert!(fclass.components["y"].hierarchy.is_empty());
+    assert!(flat_with(false).components["sub_x"].hierarchy.is_empty());
+}
+
+#[test]
+fn subcomponents_of_protected_components_are_protected() {
+    let fclass = flat_options(
+        "model Sub
+           Real x;
+         end Sub;
+         model M
+           Sub a;
+         protected
+           Sub b;
+         end M;",
+        "M",
+    )
+    .unwrap();
+    assert_eq!(fclass.components["a_x"].visibility, Visibility::Public);
+    assert_eq!(fclass.components["b_x"].visibility, Visibility::Protected);
+}
+
+#[test]
+fn array_constructors_and_transpose_are_scalarized() {
+    let fclass = flat(
+        "model M
+           Real z[3];
+           Real b[3, 2];
+           Real e[2, 2];
+         equation
+           z = zeros(3);
+           b = transpose({{1, 2, 3}, {4, 5, 6}});
+           e = identity(2);
+         end M;",
+    )
+    .unwrap();
+    assert_eq!(
+        equations(&fclass),
+        [
+            "z[1] = 0;",
+            "z[2] = 0;",
+            "z[3] = 0;",
+            "b[1, 1] = 1;",
+            "b[1, 2] = 4;",
+            "b[2, 1] = 2;",
+            "b[2, 2] = 5;",
+            "b[3, 1] = 3;",
+            "b[3, 2] = 6;",
+            "e[1, 1] = 1;",
+            "e[1, 2] = 0;",
+            "e[2, 1] = 0;",
+            "e[2, 2] = 1;",
+        ]
+    );
+}
+
+#[test]
+fn ragged_array_literals_are_rejected() {
+    for equation in [
+        "b = transpose({{1, 2}, {3}});",
+        "b = transpose({{1, 2}, {3, {4}}});",
+        "b = {{1, 2}, {3}} * {1, 2};",
+        "b = {1, 2} * {{1, 2}, {3}};",
+    ] {
+        let err = flat(&format!("model M Real b[2, 2]; equation {equation} end M;")).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("array is not rectangular"),
+            "{equation}: {err:#}"
+        );
+    }
+}
@@ -0,0 +1,728 @@
+use rumoca::ir::ast::{
+    Causality, ClassDefinition, Equation, Expression, OpBinary, Statement, StoredDefinition,
+    TerminalType, Variability, Visibility,
+};
+use rumoca::ir::diff::StructuralDiff;
+use rumoca::parser::{ParseOptions, Parser, for_each_class, parse_str};
+
+fn parse(src: &str) -> StoredDefinition {
+    Parser::new()
+        .parse(src, "test.mo")
+        .expect("failed to parse")
+}
+
+fn class<'a>(def: &'a StoredDefinition, name: &str) -> &'a ClassDefinition {
+    def.class_list.get(name).expect("missing class")
+}
+
+#[test]
+fn for_equation_with_single_index() {
+    let def = parse(
+        "model M
+           Real x[3];
+         equation
+           for i in 1:3 loop
+             x[i] = i;
+           end for;
+         end M;",
+    );
+    let Equation::For { indices, equations } = &class(&def, "M").equations[0] else {
+        panic!("expected a for equation");
+    };
+    assert_eq!(indices.len(), 1);
+    assert_eq!(indices[0].ident.text, "i");
+    let Expression::Range { start, step, end } = &indices[0].range else {
+        panic!("expected a range, found {:?}", indices[0].range);
+    };
+    assert!(step.is_none());
+    for (bound, text) in [(start, "1"), (end, "3")] {
+        let Expression::Terminal {
+            terminal_type: TerminalType::UnsignedInteger,
+            token,
+        } = bound.as_ref()
+        else {
+            panic!("expected an integer bound, found {:?}", bound);
+        };
+        assert_eq!(token.text, text);
+    }
+    assert_eq!(equations.len(), 1);
+    let Equation::Simple { lhs, rhs, .. } = &equations[0] else {
+        panic!("expected a simple equation");
+    };
+    let Expression::ComponentReference(cref) = lhs else {
+        panic!("expected a component reference, found {:?}", lhs);
+    };
+    assert_eq!(cref.to_string(), "x[i]");
+    let Expression::ComponentReference(index) = rhs else {
+        panic!("expected the loop index, found {:?}", rhs);
+    };
+    assert_eq!(index.to_string(), "i");
+}
+
+#[test]
+fn for_equation_with_several_indices() {
+    let def = parse(
+        "model M
+           parameter Integer n = 2;
+           Real a[n, 3];
+         equation
+           for i in 1:n, j in 1:3 loop
+             a[i, j] = i + j;
+             for k in {1, 2} loop
+             end for;
+           end for;
+         end M;",
+    );
+    let Equation::For { indices, equations } = &class(&def, "M").equations[0] else {
+        panic!("expected a for equation");
+    };
+    let names: Vec<&str> = indices.iter().map(|i| i.ident.text.as_str()).collect();
+    assert_eq!(names, ["i", "j"]);
+    let Expression::Range { end, .. } = &indices[0].range else {
+        panic!("expected a range, found {:?}", indices[0].range);
+    };
+    assert!(matches!(end.as_ref(), Expression::ComponentReference(n) if n.to_string() == "n"));
+    assert_eq!(equations.len(), 2);
+    let Equation::For { indices, equations } = &equations[1] else {
+        panic!("expected a nested for equation");
+    };
+    assert!(matches!(&indices[0].range, Expression::Array { elements } if elements.len() == 2));
+    assert!(equations.is_empty());
+}
+
+#[test]
+fn duplicate_component_is_an_error() {
+    let err = Parser::new()
+        .parse(
+            "model M
+               Real x;
+               Real x;
+             end M;",
+            "test.mo",
+        )
+        .unwrap_err();
+    let message = format!("{}", err);
+    assert!(
+        message.contains("Duplicate declaration of 'x' at test.mo:3:"),
+        "{}",
+        message
+    );
+    assert!(
+        message.contains("first declared at test.mo:2:"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn duplicate_across_sections_is_an_error() {
+    for src in [
+        "model M
+           Real x;
+         protected
+           Real x;
+         end M;",
+        "model M
+           model x
+           end x;
+         public
+           Real x;
+         end M;",
+    ] {
+        let err = Parser::new().parse(src, "test.mo").unwrap_err();
+        assert!(
+            format!("{}", err).contains("Duplicate declaration of 'x'"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn rename_class_updates_references() {
+    let mut def = parse(
+        "model Base
+           Real x;
+         end Base;
+         model M
+           extends Base;
+           Base b;
+         end M;",
+    );
+    def.rename_class("Base", "Parent").unwrap();
+    let names: Vec<&str> = def.class_list.keys().map(|name| name.as_str()).collect();
+    assert_eq!(names, ["Parent", "M"]);
+    assert_eq!(class(&def, "Parent").name.text, "Parent");
+    let m = class(&def, "M");
+    assert_eq!(m.extends[0].comp.to_string(), "Parent");
+    assert_eq!(m.components["b"].type_name.to_string(), "Parent");
+
+    let err = def.rename_class("M", "Parent").unwrap_err();
+    assert!(err.to_string().contains("Class 'Parent' already exists"));
+    assert!(def.rename_class("Base", "Other").is_err());
+}
+
+#[test]
+fn if_and_while_statements() {
+    let def = parse(
+        "model M
+           Real x;
+           Real y;
+         algorithm
+           if x > 1 then
+             y := 1;
+           elseif x > 0 then
+             y := 2;
+             y := y + 1;
+           else
+             y := 3;
+           end if;
+           while x > 0 loop
+             x := x - 1;
+           end while;
+         end M;",
+    );
+    let stmts = &class(&def, "M").algorithms[0];
+    let Statement::If {
+        cond_blocks,
+        else_block,
+    } = &stmts[0]
+    else {
+        panic!("expected an if statement, found {:?}", stmts[0]);
+    };
+    let lengths: Vec<usize> = cond_blocks.iter().map(|b| b.stmts.len()).collect();
+    assert_eq!(lengths, [1, 2]);
+    assert_eq!(else_block.as_ref().map(|b| b.len()), Some(1));
+    let Statement::While(block) = &stmts[1] else {
+        panic!("expected a while statement, found {:?}", stmts[1]);
+    };
+    assert!(matches!(
+        &block.cond,
+        Expression::Binary {
+            op: OpBinary::Gt(_),
+            ..
+        }
+    ));
+    assert_eq!(block.stmts.len(), 1);
+}
+
+#[test]
+fn reused_parser_gives_identical_results() {
+    let src = "model M
+                 parameter Real k = 2;
+                 Real x(start = 1);
+               equation
+                 der(x) = -k * x;
+               end M;";
+    let mut parser = Parser::new();
+    let first = parser.parse(src, "test.mo").unwrap();
+    let second = parser.parse(src, "test.mo").unwrap();
+    assert_eq!(first, second);
+
+    // a failed parse leaves nothing behind for the next one
+    assert!(parser.parse("model N Real y end N;", "test.mo").is_err());
+    let third = parser.parse(src, "test.mo").unwrap();
+    assert_eq!(first, third);
+    assert_eq!(third.class_list.len(), 1);
+}
+
+#[test]
+fn when_equations_and_statements_have_the_same_blocks() {
+    let def = parse(
+        "model M
+           Integer n;
+           Real x;
+         equation
+           when x > 3 then
+             n = 3;
+           elsewhen x > 2 then
+             n = 2;
+           elsewhen x > 1 then
+             n = 1;
+           end when;
+         algorithm
+           when x > 3 then
+             n := 3;
+           elsewhen x > 2 then
+             n := 2;
+           elsewhen x > 1 then
+             n := 1;
+           end when;
+         end M;",
+    );
+    let m = class(&def, "M");
+    let Equation::When(eq_blocks) = &m.equations[0] else {
+        panic!("expected a when equation");
+    };
+    let Statement::When(stmt_blocks) = &m.algorithms[0][0] else {
+        panic!("expected a when statement");
+    };
+    assert_eq!(eq_blocks.len(), 3);
+    assert_eq!(stmt_blocks.len(), eq_blocks.len());
+    for (eq_block, stmt_block) in eq_blocks.iter().zip(stmt_blocks) {
+        assert_eq!(
+            format!("{:?}", eq_block.cond),
+            format!("{:?}", stmt_block.cond)
+        );
+        assert_eq!(eq_block.eqs.len(), stmt_block.stmts.len());
+    }
+}
+
+#[test]
+fn comments_are_kept_with_the_following_declaration() {
+    let src = "model M
+  // the gain
+  parameter Real k = 2;
+  /* the state */ Real x;
+equation
+  der(x) = -k*x;
+end M;";
+    assert!(parse(src).comments.is_empty());
+
+    let options = ParseOptions {
+        keep_comments: true,
+        ..Default::default()
+    };
+    let def = parse_str(src, "test.mo", &options).unwrap();
+    assert_eq!(def.comments.len(), 2);
+    let m = class(&def, "M");
+    // the declaration of k starts at the `parameter` keyword
+    let Variability::Parameter(parameter) = &m.components["k"].variability else {
+        panic!("expected a parameter");
+    };
+    let before_k: Vec<&str> = def
+        .comments_before(&parameter.location)
+        .iter()
+        .map(|c| c.token.text.trim_end())
+        .collect();
+    assert_eq!(before_k, ["// the gain"]);
+    let x_type = &m.components["x"].type_name.name[0];
+    let before_x: Vec<&str> = def
+        .comments_before(&x_type.location)
+        .iter()
+        .map(|c| c.token.text.as_str())
+        .collect();
+    assert_eq!(before_x, ["/* the state */"]);
+}
+
+#[test]
+fn components_of_nested_classes_have_their_path() {
+    let def = parse(
+        "package P
+           constant Real c = 1;
+           model Inner
+             Real x;
+             model Deep
+               Real y;
+             end Deep;
+           end Inner;
+         end P;",
+    );
+    let paths: Vec<String> = class(&def, "P")
+        .components_recursive()
+        .map(|(path, _)| path)
+        .collect();
+    assert_eq!(paths, ["c", "Inner.x", "Inner.Deep.y"]);
+}
+
+#[test]
+fn oversized_source_is_rejected_before_parsing() {
+    let options = ParseOptions {
+        max_bytes: 32,
+        ..Default::default()
+    };
+    // not valid Modelica, the size is checked first
+    let err = parse_str(&"x".repeat(33), "big.mo", &options).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "big.mo: source of 33 bytes exceeds the limit of 32 bytes"
+    );
+
+    let def = parse_str("model M end M;", "small.mo", &options).unwrap();
+    assert!(def.class_list.contains_key("M"));
+}
+
+#[test]
+fn supported_model_round_trips() {
+    let src = "within Lib;
+package P \"the package\"
+  import SI = Modelica.SIunits;
+  type Voltage = Real(unit = \"V\") \"a\" + \"voltage\";
+  type E = enumeration(a \"first\", b);
+  partial model Base
+    parameter Real k(min = 0) = 2 \"gain\";
+    replaceable Real r constrainedby Real;
+  protected
+    Real hidden;
+  end Base;
+  model M
+    extends Base(k = 3);
+    Voltage v(start = 1) = 0.0 if k > 1;
+    E e = E.a;
+    Real x[2](start = 1);
+  initial equation
+    x[1] = 0;
+  equation
+    der(x) = -k*x;
+    when time > 1 then
+      reinit(x[1], 0);
+    end when;
+  algorithm
+    v := if k > 1 then 1 else 2;
+  annotation(experiment(StopTime = 1));
+  end M;
+end P;";
+    let printed = rumoca::round_trip(src).unwrap();
+    assert!(printed.starts_with("within Lib;\npackage P \"the package\"\n"));
+    assert!(printed.contains("  type Voltage = Real(unit = \"V\") \"a\" + \"voltage\";\n"));
+    assert!(printed.contains("    Voltage v(start = 1) = 0.0 if k > 1;\n"));
+    assert!(printed.contains("  protected\n    Real hidden;\n"));
+    assert!(printed.contains("    der(x) = -(k * x);\n"));
+    assert!(printed.contains("    annotation(experiment.StopTime = 1);\n  end M;\n"));
+    assert!(parse(&printed).diff(&parse(src)).is_empty());
+}
+
+#[test]
+fn unsupported_construct_is_reported() {
+    let err = rumoca::round_trip(
+        "model M
+           Real a;
+           Real b;
+         equation
+           (a, b) = f(1);
+         end M;",
+    )
+    .unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("failed to parse the source"),
+        "{:#}",
+        err
+    );
+}
+
+#[test]
+fn structural_diff_ignores_formatting() {
+    let def = parse("model M Real x(start = 1); equation der(x) = -x; end M;");
+    let reformatted = parse(
+        "model M
+           Real x(start = 1);
+         equation
+           der(x) = -x;
+         end M;",
+    );
+    assert!(def.diff(&reformatted).is_empty());
+
+    let changed = parse(
+        "model M
+           Real x(start = 2);
+           Real y;
+         equation
+           der(x) = -x;
+         end M;",
+    );
+    let diffs: Vec<String> = def.diff(&changed).iter().map(|d| d.to_string()).collect();
+    assert_eq!(diffs, ["'M.x' is changed", "'M.y' is added"]);
+}
+
+#[test]
+fn for_each_class_visits_every_class_of_every_file() {
+    let root = std::env::temp_dir().join(format!("rumoca_for_each_class_{}", std::process::id()));
+    std::fs::create_dir_all(root.join("Lib")).unwrap();
+    std::fs::write(
+        root.join("Lib/A.mo"),
+        "within Lib;
+         package A
+           model B
+             Real x;
+           end B;
+         end A;",
+    )
+    .unwrap();
+    std::fs::write(
+        root.join("C.mo"),
+        "model C
+           Real y;
+         end C;",
+    )
+    .unwrap();
+    std::fs::write(root.join("notes.txt"), "not a model").unwrap();
+    let mut visited = Vec::new();
+    let result = for_each_class(&root, |path, _| visited.push(path.to_string()));
+    std::fs::remove_dir_all(&root).unwrap();
+    result.unwrap();
+    assert_eq!(visited, ["C", "Lib.A", "Lib.A.B"]);
+}
+
+#[test]
+fn rumoca_vendor_annotations_are_hints() {
+    let def = parse(
+        r#"function f
+             input Real x;
+             output Real y;
+           algorithm
+             y := 2 * x;
+             annotation(__rumoca(inline = true), __OtherTool(fast = true));
+           end f;
+           model M
+             Real x annotation(__rumoca(solver = "rk4"), Evaluate = true);
+           end M;"#,
+    );
+    let f = class(&def, "f");
+    assert_eq!(f.hints.keys().collect::<Vec<_>>(), ["inline"]);
+    assert_eq!(format!("{:?}", f.hints["inline"]), r#"Bool("true")"#);
+    let x = &class(&def, "M").components["x"];
+    assert_eq!(x.hints.keys().collect::<Vec<_>>(), ["solver"]);
+    assert!(x.annotation.contains_key("Evaluate"));
+}
+
+#[test]
+fn class_extends_captures_the_base_and_its_modifications() {
+    let def = parse(
+        r#"class A
+             extends B(x = 1);
+           end A;
+           package P
+             class extends B(x = 2) "redefined B"
+               Real y;
+             end B;
+           end P;"#,
+    );
+    for (class, value) in [
+        (class(&def, "A"), "1"),
+        (&class(&def, "P").classes["B"], "2"),
+    ] {
+        let [extend] = class.extends.as_slice() else {
+            panic!("expected a single extends clause");
+        };
+        assert_eq!(extend.comp.to_string(), "B");
+        assert_eq!(
+            format!("{:?}", extend.modifications),
+            format!(r#"{{"x": UnsignedInteger("{}")}}"#, value)
+        );
+    }
+    let b = &class(&def, "P").classes["B"];
+    assert!(b.components.contains_key("y"));
+    assert_eq!(b.description[0].text, "redefined B");
+}
+
+#[test]
+fn der_class_specifiers_record_the_class_and_its_variables() {
+    let def = parse(
+        r#"function F
+             input Real x;
+             input Real y;
+             output Real z;
+           algorithm
+             z := x * y;
+           end F;
+           function dFdx = der(F, x) "partial derivative";
+           function dFdxy = der(F, x, y);"#,
+    );
+    let variables = |name: &str| -> Vec<String> {
+        let der = class(&def, name).der_class.as_ref().unwrap();
+        assert_eq!(der.class.to_string(), "F");
+        der.variables.iter().map(|var| var.text.clone()).collect()
+    };
+    assert_eq!(variables("dFdx"), ["x"]);
+    assert_eq!(variables("dFdxy"), ["x", "y"]);
+    assert_eq!(
+        class(&def, "dFdx").description[0].text,
+        "partial derivative"
+    );
+    assert!(class(&def, "F").der_class.is_none());
+}
+
+#[test]
+fn enumerations_keep_their_literals_in_order() {
+    let def = parse(
+        r#"type Color = enumeration(red "warm", green, blue);
+           model M
+             type E = enumeration(:);
+           end M;"#,
+    );
+    let color = class(&def, "Color");
+    let literals: Vec<_> = color
+        .enumeration_literals
+        .iter()
+        .map(|literal| literal.ident.text.as_str())
+        .collect();
+    assert_eq!(literals, ["red", "green", "blue"]);
+    assert_eq!(color.enumeration_literals[0].description[0].text, "warm");
+    assert!(color.enumeration_literals[1].description.is_empty());
+    assert!(!color.unspecified_enumeration);
+    let e = &class(&def, "M").classes["E"];
+    assert!(e.unspecified_enumeration);
+    assert!(e.enumeration_literals.is_empty());
+}
+
+#[test]
+fn short_type_class_keeps_its_base_prefix_and_modifications() {
+    let def = parse(
+        r#"type Voltage = Real(unit = "V") "electric potential";
+           connector RealInput = input Real;
+           type Vector3 = Real[3];"#,
+    );
+    let voltage = class(&def, "Voltage");
+    let [base] = voltage.extends.as_slice() else {
+        panic!("expected the base type");
+    };
+    assert_eq!(base.comp.to_string(), "Real");
+    assert_eq!(
+        format!("{:?}", base.modifications),
+        r#"{"unit": String("V")}"#
+    );
+    assert_eq!(voltage.description[0].text, "electric potential");
+    assert!(matches!(
+        class(&def, "RealInput").causality,
+        Causality::Input(..)
+    ));
+    assert_eq!(class(&def, "Vector3").shape.len(), 1);
+}
+
+#[test]
+fn operator_record_indexes_its_overloads() {
+    let def = parse(
+        "operator record Complex
+           Real re;
+           Real im;
+           encapsulated operator function '+'
+             input Complex a;
+             input Complex b;
+             output Complex c;
+           algorithm
+             c := Complex(a.re + b.re, a.im + b.im);
+           end '+';
+           encapsulated operator '-'
+             function negate
+               input Complex a;
+               output Complex c;
+             algorithm
+               c := Complex(-a.re, -a.im);
+             end negate;
+             function subtract
+               input Complex a;
+               input Complex b;
+               output Complex c;
+             algorithm
+               c := Complex(a.re - b.re, a.im - b.im);
+             end subtract;
+           end '-';
+         end Complex;",
+    );
+    let complex = class(&def, "Complex");
+    assert!(complex.operator);
+    let overloads = complex.operator_overloads();
+    let overloads: Vec<(&str, Vec<&str>)> = overloads
+        .iter()
+        .map(|(symbol, functions)| {
+            let names = functions.iter().map(|f| f.name.text.as_str()).collect();
+            (symbol.as_str(), names)
+        })
+        .collect();
+    assert_eq!(
+        overloads,
+        [("+", vec!["'+'"]), ("-", vec!["negate", "subtract"])]
+    );
+    assert!(complex.classes["'+'"].operator);
+}
+
+#[test]
+fn public_sections_are_merged_into_the_class() {
+    let def = parse(
+        "model M
+           Real x;
+         public
+           import SI = Modelica.SIunits;
+           extends Base;
+           model Sub
+           end Sub;
+           Real y;
+         equation
+           x = y;
+         public
+           Real z;
+         end M;",
+    );
+    let m = class(&def, "M");
+    assert_eq!(m.components.keys().collect::<Vec<_>>(), ["x", "y", "z"]);
+    assert!(m.classes.contains_key("Sub"));
+    assert_eq!(m.extends[0].comp.to_string(), "Base");
+    assert_eq!(m.imports.len(), 1);
+    assert_eq!(m.equations.len(), 1);
+}
+
+#[test]
+fn protected_elements_are_recorded_as_protected() {
+    let def = parse(
+        "model M
+           Real x;
+         protected
+           Real y;
+           model Sub
+           end Sub;
+         public
+           Real z;
+         end M;",
+    );
+    let m = class(&def, "M");
+    let visibility = |name: &str| m.components[name].visibility.clone();
+    assert_eq!(visibility("x"), Visibility::Public);
+    assert_eq!(visibility("y"), Visibility::Protected);
+    assert_eq!(visibility("z"), Visibility::Public);
+    assert_eq!(m.classes["Sub"].visibility, Visibility::Protected);
+}
+
+#[test]
+fn changed_parameter_value_is_one_changed_diff() {
+    let src = "model M
+                 parameter Real k = 1;
+                 Real x;
+               equation
+                 x = k;
+               end M;";
+    let changed = src.replace("k = 1", "k = 2");
+    let diffs = parse(src).diff(&parse(&changed));
+    assert_eq!(diffs, [StructuralDiff::Changed("M.k".to_string())]);
+    let removed = src.replace("Real x;", "");
+    assert_eq!(
+        parse(src).diff(&parse(&removed)),
+        [StructuralDiff::Removed("M.x".to_string())]
+    );
+}
+
+#[test]
+fn imports_keep_their_alias_and_path() {
+    let def = parse(
+        "model M
+           import Modelica.SIunits;
+           import SI = Modelica.SIunits;
+           import Modelica.Constants.*;
+           import Modelica.Math.{sin, cos};
+         end M;",
+    );
+    let imports: Vec<_> = class(&def, "M")
+        .imports
+        .iter()
+        .map(|import| {
+            (
+                import.alias.as_ref().map(|alias| alias.text.as_str()),
+                import.path.to_string(),
+                import
+                    .names
+                    .iter()
+                    .map(|name| name.text.as_str())
+                    .collect::<Vec<_>>(),
+                import.unqualified,
+            )
+        })
+        .collect();
+    assert_eq!(
+        imports,
+        [
+            (None, "Modelica.SIunits".to_string(), vec![], false),
+            (Some("SI"), "Modelica.SIunits".to_string(), vec![], false),
+            (None, "Modelica.Constants".to_string(), vec![], true),
+            (None, "Modelica.Math".to_string(), vec!["sin", "cos"], false),
+        ]
+    );
+}
@@ -0,0 +1,289 @@
+use rumoca::ir::ast::StoredDefinition;
+use rumoca::parser::Parser;
+
+fn parse(src: &str) -> StoredDefinition {
+    Parser::new()
+        .parse(src, "test.mo")
+        .expect("failed to parse")
+}
+
+#[test]
+fn validate_reports_unknown_type_and_unbalanced_class() {
+    let def = parse(
+        "model M
+           Unknown u;
+           Real x;
+           Real y;
+         equation
+           x = 1;
+         end M;",
+    );
+    let diagnostics = def.validate();
+    let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    // the class cannot be flattened, so its balance is not checked
+    assert_eq!(
+        messages,
+        ["Type 'Unknown' of component 'u' in class 'M' is not defined"]
+    );
+    assert_eq!(diagnostics[0].location.start_line, 2);
+    assert_eq!(
+        codes(
+            "model M
+               Real x;
+               Real y;
+             equation
+               x = 1;
+             end M;"
+        ),
+        ["unbalanced"]
+    );
+}
+
+/// Returns the codes of the diagnostics of `src`.
+fn codes(src: &str) -> Vec<String> {
+    parse(src).validate().into_iter().map(|d| d.code).collect()
+}
+
+#[test]
+fn validate_requires_boolean_conditions() {
+    let equation = |cond: &str| {
+        format!(
+            "model M
+               Real x;
+               Real y;
+             equation
+               x = 1;
+               if {} then
+                 y = 1;
+               else
+                 y = 2;
+               end if;
+             end M;",
+            cond
+        )
+    };
+    let def = parse(&equation("3"));
+    let diagnostics = def.validate();
+    let condition = diagnostics
+        .iter()
+        .find(|d| d.code == "non-boolean-condition")
+        .expect("missing condition diagnostic");
+    assert!(condition.message.contains("must be Boolean, found Integer"));
+    assert_eq!(condition.location.start_line, 6);
+    assert!(!codes(&equation("x > 0")).contains(&"non-boolean-condition".to_string()));
+
+    let statement = |cond: &str| {
+        format!(
+            "model M
+               Real x;
+               Real y;
+             algorithm
+               x := 1;
+               if {} then
+                 y := 1;
+               elseif x < 0 then
+                 y := 3;
+               else
+                 y := 2;
+               end if;
+               while {} loop
+                 x := x - 1;
+               end while;
+             end M;",
+            cond, cond
+        )
+    };
+    let count = |codes: Vec<String>| {
+        codes
+            .iter()
+            .filter(|code| *code == "non-boolean-condition")
+            .count()
+    };
+    assert_eq!(count(codes(&statement("3"))), 2);
+    assert_eq!(count(codes(&statement("x > 0"))), 0);
+}
+
+#[test]
+fn multi_output_call_only_in_tuple_assignment() {
+    let function = "
+        function f
+          input Real u;
+          output Real y1;
+          output Real y2;
+        algorithm
+          y1 := u;
+          y2 := 2 * u;
+        end f;";
+    let def = parse(&format!(
+        "model M
+           input Real u;
+           Real x;
+           Real a;
+           Real b;
+         equation
+           x = f(u) + 1;
+         algorithm
+           (a, b) := f(u);
+         end M;{}",
+        function
+    ));
+    let diagnostics: Vec<_> = def
+        .validate()
+        .into_iter()
+        .filter(|d| d.code == "multi-output-call")
+        .collect();
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert!(
+        diagnostics[0]
+            .message
+            .contains("Function 'f' has 2 outputs and can only be called in a tuple assignment")
+    );
+    assert_eq!(diagnostics[0].location.start_line, 7);
+
+    // a list of outputs is not an expression
+    let err = Parser::new()
+        .parse(
+            &format!(
+                "model M
+                   input Real u;
+                   Real a;
+                   Real b;
+                 equation
+                   (a, b) = f(u);
+                 end M;{}",
+                function
+            ),
+            "test.mo",
+        )
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("a list of outputs is only supported"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn logical_operators_require_boolean_operands() {
+    let model = |expr: &str| {
+        format!(
+            "model M
+               parameter Boolean x = true;
+               parameter Real y = 1;
+               Boolean b;
+             equation
+               b = {};
+             end M;",
+            expr
+        )
+    };
+    assert!(codes(&model("x and (y > 0)")).is_empty());
+    assert!(codes(&model("not x or y <= 1")).is_empty());
+    assert_eq!(codes(&model("x and 3")), ["non-boolean-operand"]);
+    assert_eq!(codes(&model("not y")), ["non-boolean-operand"]);
+    assert_eq!(
+        codes(&model("1 and 2")),
+        ["non-boolean-operand", "non-boolean-operand"]
+    );
+}
+
+#[test]
+fn diagnostics_serialize_to_json() {
+    let diagnostics = parse(
+        "model M
+           Unknown u;
+         end M;",
+    )
+    .validate();
+    let json: serde_json::Value =
+        serde_json::from_str(&rumoca::ir::validate::diagnostics_to_json(&diagnostics)).unwrap();
+    let first = &json[0];
+    assert_eq!(first["severity"], "error");
+    assert_eq!(first["code"], "undefined-type");
+    assert_eq!(first["location"]["start_line"], 2);
+    assert_eq!(first["location"]["start_column"], 12);
+    assert!(first["message"].as_str().unwrap().contains("Unknown"));
+}
+
+#[test]
+fn balance_counts_array_elements_and_bindings() {
+    for src in [
+        "model M
+           Real x[3](each start = 1);
+         equation
+           der(x) = -x;
+         end M;",
+        "model M
+           parameter Integer n = 2;
+           Real x[n, 2];
+         equation
+           der(x) = -x;
+         end M;",
+        "model M
+           Real x(start = 1);
+           Real y = 2 * x;
+         equation
+           der(x) = -x;
+         end M;",
+        "model M
+           Real x[2](start = {1, 2});
+           Real y[2] = 2 * x;
+         equation
+           der(x) = -x;
+         end M;",
+    ] {
+        assert!(!codes(src).contains(&"unbalanced".to_string()), "{}", src);
+    }
+    let diagnostics = parse(
+        "model M
+           Real x[3];
+           Real y = 1;
+         equation
+           der(x) = -x;
+           y = 2;
+         end M;",
+    )
+    .validate();
+    let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages, ["Class 'M' has 5 equation(s) and 4 unknown(s)"]);
+}
+
+#[test]
+fn nested_and_qualified_types_are_defined() {
+    let src = "package P
+                 package Sub
+                   connector Pin
+                     Real v;
+                     flow Real i;
+                   end Pin;
+                   model Part
+                     Pin a;
+                   end Part;
+                 end Sub;
+                 model Base
+                 end Base;
+                 model M
+                   extends Base;
+                   type Mode = enumeration(a, b);
+                   Mode m;
+                   P.Sub.Pin p;
+                   Sub.Part part;
+                 end M;
+               end P;";
+    assert!(!codes(src).contains(&"undefined-type".to_string()));
+    let diagnostics = parse(
+        "package P
+           model M
+             Sub.Missing s;
+           end M;
+         end P;",
+    )
+    .validate();
+    let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert!(
+        messages.contains(&"Type 'Sub.Missing' of component 's' in class 'M' is not defined"),
+        "{:?}",
+        messages
+    );
+}